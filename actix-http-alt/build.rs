@@ -0,0 +1,19 @@
+use std::env;
+
+// native-tls negotiates ALPN on server side with its openssl and schannel backends. The
+// security-framework backend used on apple platforms ignores ALPN protocols of acceptor.
+//
+// `native_tls_alpn` cfg is emitted for targets where native-tls uses a backend negotiating ALPN.
+// It can also be passed through `RUSTFLAGS` for backends not known here.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    if env::var_os("CARGO_FEATURE_NATIVE_TLS").is_none() {
+        return;
+    }
+
+    let vendor = env::var("CARGO_CFG_TARGET_VENDOR").unwrap_or_default();
+    if vendor != "apple" {
+        println!("cargo:rustc-cfg=native_tls_alpn");
+    }
+}
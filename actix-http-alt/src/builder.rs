@@ -1,4 +1,4 @@
-use std::{future::Future, marker::PhantomData};
+use std::{future::Future, marker::PhantomData, sync::Arc};
 
 use actix_server_alt::net::Stream as ServerStream;
 use actix_service_alt::ServiceFactory;
@@ -10,10 +10,15 @@ use super::body::{RequestBody, ResponseBody};
 use super::config::{HttpServiceConfig, DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT};
 use super::error::{BodyError, HttpServiceError};
 use super::expect::ExpectHandler;
-use super::response::ResponseError;
+use super::response::{ErrorFormatter, ResponseError};
 use super::service::HttpService;
+use super::shutdown::ShutdownHandle;
+use super::stats::{ConnectionStats, OnConnectionClose};
 use super::tls::{self, TlsStream};
 use super::upgrade::UpgradeHandler;
+use super::util::date::Clock;
+#[cfg(feature = "rustls")]
+use super::vhost::VirtualHosts;
 
 /// HttpService Builder type.
 /// Take in generic types of ServiceFactory for http and tls.
@@ -23,6 +28,9 @@ pub struct HttpServiceBuilder<F, ReqB, FE, FU, FA, const READ_BUF_LIMIT: usize,
     pub(crate) upgrade: Option<FU>,
     pub(crate) tls_factory: FA,
     pub(crate) config: HttpServiceConfig<READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
+    pub(crate) on_close: Option<OnConnectionClose>,
+    pub(crate) shutdown: Option<ShutdownHandle>,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
     pub(crate) _body: PhantomData<ReqB>,
 }
 
@@ -61,6 +69,9 @@ impl<F>
             upgrade: None,
             tls_factory: tls::TlsAcceptorService::default(),
             config,
+            on_close: None,
+            shutdown: None,
+            clock: None,
             _body: PhantomData,
         }
     }
@@ -95,6 +106,9 @@ impl<F>
             upgrade: None,
             tls_factory: tls::NoOpTlsAcceptorService,
             config: HttpServiceConfig::default(),
+            on_close: None,
+            shutdown: None,
+            clock: None,
             _body: PhantomData,
         }
     }
@@ -129,6 +143,9 @@ impl<F>
             upgrade: None,
             tls_factory: tls::NoOpTlsAcceptorService,
             config: HttpServiceConfig::default(),
+            on_close: None,
+            shutdown: None,
+            clock: None,
             _body: PhantomData,
         }
     }
@@ -151,6 +168,52 @@ impl<F>
     }
 }
 
+#[cfg(feature = "rustls")]
+impl<F>
+    HttpServiceBuilder<
+        VirtualHosts<F>,
+        RequestBody,
+        ExpectHandler<VirtualHosts<F>>,
+        UpgradeHandler,
+        tls::TlsAcceptorService,
+        DEFAULT_READ_BUF_LIMIT,
+        DEFAULT_WRITE_BUF_LIMIT,
+    >
+{
+    /// Construct a new Service Builder serving given virtual hosts over rustls.
+    ///
+    /// Both server config and service of a connection are selected by the server name from
+    /// tls handshake.
+    pub fn virtual_hosts(hosts: VirtualHosts<F>) -> Self {
+        let acceptor = tls::rustls::TlsAcceptorService::virtual_hosts(hosts.default_config(), hosts.router());
+        Self::new(hosts).rustls(acceptor)
+    }
+}
+
+impl<F, ReqB, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
+    HttpServiceBuilder<F, ReqB, ExpectHandler<F>, FU, FA, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+{
+    /// Reject request expecting `100 Continue` with `413 Payload Too Large` when its
+    /// `content-length` is larger than given limit. Body of rejected request is never sent.
+    ///
+    /// Only Http/1 requests with `expect: 100-continue` header are checked. Service must still
+    /// limit the body it reads from other requests.
+    #[cfg(feature = "http1")]
+    pub fn expect_max_body_size(self, limit: u64) -> Self {
+        self.expect_handler(|expect| expect.max_body_size(limit))
+    }
+
+    /// Configure the default [ExpectHandler]. (e.g. add predicate or reject with 417)
+    #[cfg(feature = "http1")]
+    pub fn expect_handler<M>(mut self, f: M) -> Self
+    where
+        M: FnOnce(ExpectHandler<F>) -> ExpectHandler<F>,
+    {
+        self.expect = f(self.expect);
+        self
+    }
+}
+
 impl<F, ReqB, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
     HttpServiceBuilder<F, ReqB, FE, FU, FA, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
 {
@@ -164,10 +227,55 @@ impl<F, ReqB, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: us
             upgrade: self.upgrade,
             tls_factory: self.tls_factory,
             config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: PhantomData,
         }
     }
 
+    /// Format error responses generated by dispatchers with given formatter.
+    ///
+    /// See [ErrorFormatter](crate::ErrorFormatter) for the constraints on formatted response.
+    pub fn error_formatter(mut self, formatter: ErrorFormatter) -> Self {
+        self.config.error_formatter = Some(formatter);
+        self
+    }
+
+    /// Serve plain text Http/1 connections with io-uring.
+    ///
+    /// See [HttpServiceConfig::io_uring](crate::config::HttpServiceConfig::io_uring).
+    #[cfg(feature = "io-uring")]
+    pub fn io_uring(mut self) -> Self {
+        self.config = self.config.io_uring();
+        self
+    }
+
+    /// Call given function with [ConnectionStats] of every connection when it's closed.
+    pub fn on_connection_close<C>(mut self, on_close: C) -> Self
+    where
+        C: Fn(ConnectionStats) + Send + Sync + 'static,
+    {
+        self.on_close = Some(Arc::new(on_close));
+        self
+    }
+
+    /// Drain services constructed by this builder with given [ShutdownHandle] instead of one
+    /// handle per service. Shutdown of all worker threads can then be triggered and watched with
+    /// a single handle.
+    pub fn shutdown_handle(mut self, handle: ShutdownHandle) -> Self {
+        self.shutdown = Some(handle);
+        self
+    }
+
+    /// Read time of `date` header and timer deadlines from given [Clock] instead of system clock.
+    ///
+    /// See [ManualClock](crate::util::ManualClock) for testing.
+    pub fn clock<C: Clock>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
     #[cfg(feature = "http1")]
     pub fn expect<FE2, ResB>(
         self,
@@ -183,6 +291,9 @@ impl<F, ReqB, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: us
             upgrade: self.upgrade,
             tls_factory: self.tls_factory,
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: PhantomData,
         }
     }
@@ -202,6 +313,9 @@ impl<F, ReqB, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: us
             upgrade: Some(upgrade),
             tls_factory: self.tls_factory,
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: PhantomData,
         }
     }
@@ -210,50 +324,200 @@ impl<F, ReqB, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: us
 impl<F, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
     HttpServiceBuilder<F, RequestBody, FE, FU, FA, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
 {
+    /// Accept openssl connections with given acceptor or acceptor service.
     #[cfg(feature = "openssl")]
-    pub fn openssl(
+    pub fn openssl<T>(
         self,
-        acceptor: tls::openssl::TlsAcceptor,
-    ) -> HttpServiceBuilder<F, RequestBody, FE, FU, tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT> {
+        acceptor: T,
+    ) -> HttpServiceBuilder<F, RequestBody, FE, FU, tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+    where
+        T: Into<tls::openssl::TlsAcceptorService>,
+    {
         HttpServiceBuilder {
             factory: self.factory,
             expect: self.expect,
             upgrade: self.upgrade,
-            tls_factory: tls::TlsAcceptorService::OpenSsl(tls::openssl::TlsAcceptorService::new(acceptor)),
+            tls_factory: tls::TlsAcceptorService::OpenSsl(acceptor.into()),
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: PhantomData,
         }
     }
 
+    /// Accept rustls connections with given server config or acceptor service.
     #[cfg(feature = "rustls")]
-    pub fn rustls(
+    pub fn rustls<T>(
         self,
-        config: tls::rustls::RustlsConfig,
-    ) -> HttpServiceBuilder<F, RequestBody, FE, FU, tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT> {
+        acceptor: T,
+    ) -> HttpServiceBuilder<F, RequestBody, FE, FU, tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+    where
+        T: Into<tls::rustls::TlsAcceptorService>,
+    {
         HttpServiceBuilder {
             factory: self.factory,
             expect: self.expect,
             upgrade: self.upgrade,
-            tls_factory: tls::TlsAcceptorService::Rustls(tls::rustls::TlsAcceptorService::new(config)),
+            tls_factory: tls::TlsAcceptorService::Rustls(acceptor.into()),
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: PhantomData,
         }
     }
 
+    /// Accept native-tls connections with given acceptor or acceptor service.
     #[cfg(feature = "native-tls")]
-    pub fn native_tls(
+    pub fn native_tls<T>(
         self,
-        acceptor: tls::native_tls::TlsAcceptor,
-    ) -> HttpServiceBuilder<F, RequestBody, FE, FU, tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT> {
+        acceptor: T,
+    ) -> HttpServiceBuilder<F, RequestBody, FE, FU, tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+    where
+        T: Into<tls::native_tls::TlsAcceptorService>,
+    {
         HttpServiceBuilder {
             factory: self.factory,
             expect: self.expect,
             upgrade: self.upgrade,
-            tls_factory: tls::TlsAcceptorService::NativeTls(tls::native_tls::TlsAcceptorService::new(acceptor)),
+            tls_factory: tls::TlsAcceptorService::NativeTls(acceptor.into()),
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: PhantomData,
         }
     }
+
+    /// Accept tls connections with a [TlsAccept](tls::TlsAccept) implementation.
+    ///
+    /// This is how tls stacks not bundled with this crate can be used.
+    pub fn tls_accept<T>(
+        self,
+        acceptor: T,
+    ) -> HttpServiceBuilder<F, RequestBody, FE, FU, tls::CustomTlsAcceptorService<T>, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+    where
+        T: tls::TlsAccept<ServerStream>,
+    {
+        HttpServiceBuilder {
+            factory: self.factory,
+            expect: self.expect,
+            upgrade: self.upgrade,
+            tls_factory: tls::CustomTlsAcceptorService::new(acceptor),
+            config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
+            _body: PhantomData,
+        }
+    }
+}
+
+impl<F, ReqB, FE, FU, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
+    HttpServiceBuilder<F, ReqB, FE, FU, tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+{
+    /// Detect plaintext http connections on tls listener and handle them with given behavior.
+    ///
+    /// Tls connections are accepted by current tls acceptor as usual.
+    pub fn plaintext(
+        self,
+        plaintext: tls::Plaintext,
+    ) -> HttpServiceBuilder<F, ReqB, FE, FU, tls::DetectTlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT> {
+        HttpServiceBuilder {
+            factory: self.factory,
+            expect: self.expect,
+            upgrade: self.upgrade,
+            tls_factory: tls::DetectTlsAcceptorService::new(self.tls_factory, plaintext),
+            config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
+            _body: PhantomData,
+        }
+    }
+
+    /// Override ALPN protocols advertised by tls acceptor. Protocols are in the order of preference.
+    ///
+    /// By default the protocols supported by enabled features are advertised when tls config
+    /// does not have any. Existing protocols are kept with a warning when no tls acceptor is
+    /// configured or ALPN protocols of it can not be overridden.
+    #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+    pub fn alpn(mut self, protos: &[&str]) -> Self {
+        self.tls_factory = self.tls_factory.alpn(protos);
+        self
+    }
+
+    /// Observe the outcome and duration of every tls handshake. e.g. for collecting metrics.
+    #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+    pub fn on_handshake<H>(mut self, f: H) -> Self
+    where
+        H: Fn(Result<&tls::TlsInfo, tls::HandshakeError>, std::time::Duration) + Send + Sync + 'static,
+    {
+        self.tls_factory = self.tls_factory.on_handshake(f);
+        self
+    }
+
+    /// Write tls session secrets to given key log destination for debugging encrypted traffic.
+    ///
+    /// Key logging is off unless enabled by this method. It stays off with a warning when no tls
+    /// acceptor is configured or it does not support key logging.
+    #[cfg(any(feature = "openssl", feature = "rustls"))]
+    pub fn keylog(mut self, keylog: tls::KeyLog) -> Self {
+        self.tls_factory = self.tls_factory.keylog(keylog);
+        self
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl<F, ReqB, FE, FU, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
+    HttpServiceBuilder<F, ReqB, FE, FU, tls::openssl::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+{
+    /// Override ALPN protocols advertised by openssl acceptor. Protocols are in the order of preference.
+    ///
+    /// Only take effect when the acceptor is constructed with `TlsAcceptorService::from_builder`.
+    pub fn alpn(mut self, protos: &[&str]) -> Self {
+        self.tls_factory = self.tls_factory.alpn(protos);
+        self
+    }
+
+    /// Write tls session secrets of openssl acceptor to given key log destination.
+    ///
+    /// Only take effect when the acceptor is constructed with `TlsAcceptorService::from_builder`.
+    pub fn keylog(mut self, keylog: tls::KeyLog) -> Self {
+        self.tls_factory = self.tls_factory.keylog(keylog);
+        self
+    }
+}
+
+#[cfg(feature = "native-tls")]
+impl<F, ReqB, FE, FU, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
+    HttpServiceBuilder<F, ReqB, FE, FU, tls::native_tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+{
+    /// Override ALPN protocols advertised by native-tls acceptor. Protocols are in the order of preference.
+    ///
+    /// Only take effect when the acceptor is constructed with `TlsAcceptorService::from_builder`.
+    pub fn alpn(mut self, protos: &[&str]) -> Self {
+        self.tls_factory = self.tls_factory.alpn(protos);
+        self
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl<F, ReqB, FE, FU, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
+    HttpServiceBuilder<F, ReqB, FE, FU, tls::rustls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+{
+    /// Override ALPN protocols advertised by rustls acceptor. Protocols are in the order of preference.
+    pub fn alpn(mut self, protos: &[&str]) -> Self {
+        self.tls_factory = self.tls_factory.alpn(protos);
+        self
+    }
+
+    /// Write tls session secrets of rustls acceptor to given key log destination.
+    pub fn keylog(mut self, keylog: tls::KeyLog) -> Self {
+        self.tls_factory = self.tls_factory.keylog(keylog);
+        self
+    }
 }
 
 impl<F, ResB, E, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> ServiceFactory<ServerStream>
@@ -296,6 +560,9 @@ where
         let service = self.factory.new_service(cfg);
         let tls_acceptor = self.tls_factory.new_service(());
         let config = self.config;
+        let on_close = self.on_close.clone();
+        let shutdown = self.shutdown.clone().unwrap_or_default();
+        let clock = self.clock.clone();
 
         async move {
             let expect = expect.await?;
@@ -306,7 +573,9 @@ where
             let service = service.await?;
             let tls_acceptor = tls_acceptor.await?;
 
-            Ok(HttpService::new(config, service, expect, upgrade, tls_acceptor))
+            let service = HttpService::with_shutdown_handle(config, service, expect, upgrade, tls_acceptor, shutdown);
+
+            Ok(service.on_close(on_close).clock(clock))
         }
     }
 }
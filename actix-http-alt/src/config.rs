@@ -1,5 +1,10 @@
 use std::time::Duration;
 
+use http::StatusCode;
+
+use super::response::ErrorFormatter;
+use super::util::date::DEFAULT_REFRESH;
+
 /// The default maximum read buffer size. If the head gets this big and
 /// a message is still not complete, a `TooLarge` error is triggered.
 ///
@@ -15,9 +20,20 @@ pub const DEFAULT_WRITE_BUF_LIMIT: usize = 8192 + 4096 * 100;
 #[derive(Copy, Clone)]
 pub struct HttpServiceConfig<const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> {
     pub(crate) http1_pipeline: bool,
+    #[cfg(feature = "http2")]
+    pub(crate) http2_budget: usize,
     pub(crate) keep_alive_timeout: Duration,
     pub(crate) first_request_timeout: Duration,
     pub(crate) tls_accept_timeout: Duration,
+    pub(crate) drain_timeout: Duration,
+    pub(crate) request_timeout: Option<Duration>,
+    pub(crate) service_ready_timeout: Duration,
+    pub(crate) request_timeout_status: StatusCode,
+    pub(crate) catch_panic: bool,
+    pub(crate) error_formatter: Option<ErrorFormatter>,
+    pub(crate) date_refresh: Option<Duration>,
+    #[cfg(feature = "io-uring")]
+    pub(crate) io_uring: bool,
 }
 
 impl Default for HttpServiceConfig<DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT> {
@@ -30,9 +46,20 @@ impl HttpServiceConfig<DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT> {
     pub const fn new() -> Self {
         Self {
             http1_pipeline: false,
+            #[cfg(feature = "http2")]
+            http2_budget: 128,
             keep_alive_timeout: Duration::from_secs(5),
             first_request_timeout: Duration::from_secs(5),
             tls_accept_timeout: Duration::from_secs(3),
+            drain_timeout: Duration::from_secs(30),
+            request_timeout: None,
+            service_ready_timeout: Duration::from_secs(5),
+            request_timeout_status: StatusCode::SERVICE_UNAVAILABLE,
+            catch_panic: true,
+            error_formatter: None,
+            date_refresh: Some(DEFAULT_REFRESH),
+            #[cfg(feature = "io-uring")]
+            io_uring: false,
         }
     }
 }
@@ -43,6 +70,17 @@ impl<const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> HttpServiceConfi
         self
     }
 
+    /// Units of work a Http/2 connection does before yielding to other connections on the same
+    /// worker thread. A unit is one read from the connection or one accepted stream. Default to 128.
+    ///
+    /// Lower value reduces latency of other connections when one connection has many ready
+    /// streams at the cost of throughput of that connection.
+    #[cfg(feature = "http2")]
+    pub fn http2_budget(mut self, units: usize) -> Self {
+        self.http2_budget = units;
+        self
+    }
+
     pub fn keep_alive_timeout(mut self, dur: Duration) -> Self {
         self.keep_alive_timeout = dur;
         self
@@ -58,14 +96,109 @@ impl<const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> HttpServiceConfi
         self
     }
 
+    /// Upper bound of time for service call and response body streaming of a request.
+    /// Counted from the time service is ready to be called and can be overridden per request with
+    /// [RequestTimeout](crate::RequestTimeout) extension. Disabled by default.
+    ///
+    /// When response is not started the service call is dropped and a response with
+    /// [request_timeout_status](Self::request_timeout_status) is sent. Otherwise the response
+    /// body is dropped and the Http/1 connection is closed or the Http/2 and Http/3 stream is reset.
+    pub fn request_timeout(mut self, dur: Duration) -> Self {
+        self.request_timeout = Some(dur);
+        self
+    }
+
+    /// Status code of response sent when request timeout is reached. Default to 503.
+    pub fn request_timeout_status(mut self, status: StatusCode) -> Self {
+        self.request_timeout_status = status;
+        self
+    }
+
+    /// Time a request waits for service to be ready before it's rejected with 503 and a
+    /// `retry-after` header of the same duration in seconds.
+    ///
+    /// Http/2 and Http/3 dispatchers do not accept the next stream until service is ready or this
+    /// duration has passed.
+    pub fn service_ready_timeout(mut self, dur: Duration) -> Self {
+        self.service_ready_timeout = dur;
+        self
+    }
+
+    /// Time given to live connections to finish after
+    /// [ShutdownHandle::shutdown](crate::ShutdownHandle::shutdown) is called.
+    /// Connections still open afterwards are dropped.
+    pub fn drain_timeout(mut self, dur: Duration) -> Self {
+        self.drain_timeout = dur;
+        self
+    }
+
+    /// Interval cached `date` header and connection timers are refreshed at. Default to 500
+    /// milli seconds.
+    ///
+    /// Keep alive and other connection timeouts fire up to one interval late.
+    ///
+    /// # Panics
+    /// When `dur` is less than 100 milli seconds.
+    pub fn date_refresh_interval(mut self, dur: Duration) -> Self {
+        assert!(
+            dur >= Duration::from_millis(100),
+            "date refresh interval can not be less than 100 milli seconds"
+        );
+        self.date_refresh = Some(dur);
+        self
+    }
+
+    /// Refresh cached `date` header on access when it's older than one second instead of in
+    /// background. Background task only runs to fire connection timeouts while there is live
+    /// connection.
+    ///
+    /// Suitable for service with little traffic.
+    pub fn date_refresh_on_demand(mut self) -> Self {
+        self.date_refresh = None;
+        self
+    }
+
+    /// Let panic from service call unwind the connection task instead of responding with
+    /// internal server error.
+    ///
+    /// By default a panicking Http/1 request is responded with 500 and the connection is closed
+    /// afterwards. A panicking Http/2 or Http/3 stream is responded with 500 and the connection
+    /// stops accepting new streams. Both responses go through
+    /// [error_formatter](crate::HttpServiceBuilder::error_formatter) when it's set.
+    pub fn disable_catch_panic(mut self) -> Self {
+        self.catch_panic = false;
+        self
+    }
+
+    /// Serve plain text Http/1 connections with io-uring when worker thread runs on io-uring
+    /// runtime. Tls connections and worker threads fell back to epoll runtime keep using epoll.
+    ///
+    /// Io-uring runtime is enabled by `actix_server_alt::Builder::io_uring`.
+    #[cfg(feature = "io-uring")]
+    pub fn io_uring(mut self) -> Self {
+        self.io_uring = true;
+        self
+    }
+
     pub fn max_read_buf_size<const READ_BUF_LIMIT_2: usize>(
         self,
     ) -> HttpServiceConfig<READ_BUF_LIMIT_2, WRITE_BUF_LIMIT> {
         HttpServiceConfig {
             http1_pipeline: self.http1_pipeline,
+            #[cfg(feature = "http2")]
+            http2_budget: self.http2_budget,
             keep_alive_timeout: self.keep_alive_timeout,
             first_request_timeout: self.first_request_timeout,
             tls_accept_timeout: self.tls_accept_timeout,
+            drain_timeout: self.drain_timeout,
+            request_timeout: self.request_timeout,
+            service_ready_timeout: self.service_ready_timeout,
+            request_timeout_status: self.request_timeout_status,
+            catch_panic: self.catch_panic,
+            error_formatter: self.error_formatter,
+            date_refresh: self.date_refresh,
+            #[cfg(feature = "io-uring")]
+            io_uring: self.io_uring,
         }
     }
 
@@ -74,9 +207,20 @@ impl<const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> HttpServiceConfi
     ) -> HttpServiceConfig<READ_BUF_LIMIT, WRITE_BUF_LIMIT_2> {
         HttpServiceConfig {
             http1_pipeline: self.http1_pipeline,
+            #[cfg(feature = "http2")]
+            http2_budget: self.http2_budget,
             keep_alive_timeout: self.keep_alive_timeout,
             first_request_timeout: self.first_request_timeout,
             tls_accept_timeout: self.tls_accept_timeout,
+            drain_timeout: self.drain_timeout,
+            request_timeout: self.request_timeout,
+            service_ready_timeout: self.service_ready_timeout,
+            request_timeout_status: self.request_timeout_status,
+            catch_panic: self.catch_panic,
+            error_formatter: self.error_formatter,
+            date_refresh: self.date_refresh,
+            #[cfg(feature = "io-uring")]
+            io_uring: self.io_uring,
         }
     }
 }
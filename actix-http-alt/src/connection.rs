@@ -0,0 +1,357 @@
+//! Connection level data shared by all requests served on the same connection.
+//!
+//! After a connection is accepted (and tls handshake finished) [OnConnect] is called once on
+//! the connection's stream type and the collected [ConnectionData] is attached to the
+//! extensions of every request from that connection.
+
+use std::{
+    fmt,
+    net::SocketAddr,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use actix_server_alt::net::{Stream as ServerStream, TcpStream};
+use http::Extensions;
+
+use super::tls::TlsInfo;
+
+/// A collection of data gathered from a connection.
+#[derive(Clone)]
+pub struct ConnectionData {
+    id: ConnectionId,
+    addrs: Option<ConnectionAddrs>,
+    peer_certs: Option<PeerCertificates>,
+    vhost: Option<VirtualHost>,
+    tls_info: Option<Arc<TlsInfo>>,
+    extensions: Option<ConnectionExtensions>,
+}
+
+impl Default for ConnectionData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnectionData {
+    /// Construct with a newly assigned [ConnectionId].
+    pub fn new() -> Self {
+        Self {
+            id: ConnectionId::next(),
+            addrs: None,
+            peer_certs: None,
+            vhost: None,
+            tls_info: None,
+            extensions: None,
+        }
+    }
+
+    /// Collect connection data from given io type.
+    pub fn from_io<T: OnConnect>(io: &T) -> Self {
+        let mut data = Self::new();
+        io.on_connect(&mut data);
+        data
+    }
+
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    pub fn set_addrs(&mut self, addrs: ConnectionAddrs) {
+        self.addrs = Some(addrs);
+    }
+
+    pub fn addrs(&self) -> Option<ConnectionAddrs> {
+        self.addrs
+    }
+
+    pub fn set_peer_certificates(&mut self, certs: PeerCertificates) {
+        self.peer_certs = Some(certs);
+    }
+
+    pub fn peer_certificates(&self) -> Option<&PeerCertificates> {
+        self.peer_certs.as_ref()
+    }
+
+    pub fn set_tls_info<I: Into<Arc<TlsInfo>>>(&mut self, info: I) {
+        self.tls_info = Some(info.into());
+    }
+
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.tls_info.as_deref()
+    }
+
+    pub fn set_virtual_host(&mut self, vhost: VirtualHost) {
+        self.vhost = Some(vhost);
+    }
+
+    pub fn virtual_host(&self) -> Option<&VirtualHost> {
+        self.vhost.as_ref()
+    }
+
+    pub fn set_extensions(&mut self, extensions: ConnectionExtensions) {
+        self.extensions = Some(extensions);
+    }
+
+    pub fn extensions(&self) -> Option<&ConnectionExtensions> {
+        self.extensions.as_ref()
+    }
+
+    /// Insert connection data to extensions of a request.
+    pub(crate) fn insert_into(&self, extensions: &mut Extensions) {
+        extensions.insert(self.id);
+
+        if let Some(addrs) = self.addrs {
+            extensions.insert(addrs);
+        }
+
+        if let Some(ref certs) = self.peer_certs {
+            extensions.insert(certs.clone());
+        }
+
+        if let Some(ref info) = self.tls_info {
+            extensions.insert(info.clone());
+        }
+
+        if let Some(ref vhost) = self.vhost {
+            extensions.insert(vhost.clone());
+        }
+
+        if let Some(ref ext) = self.extensions {
+            extensions.insert(ext.clone());
+        }
+    }
+}
+
+/// Process wide unique id of a connection. Dispatchers prefix their logs with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Socket addresses of a connection captured when it's accepted.
+///
+/// # Examples:
+///
+/// ```rust
+/// # use actix_http_alt::connection::ConnectionAddrs;
+/// # use http::Request;
+/// fn client_ip<B>(req: &Request<B>) -> Option<std::net::IpAddr> {
+///     req.extensions().get::<ConnectionAddrs>()?.peer().map(|addr| addr.ip())
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionAddrs {
+    /// Tcp or Quic connection.
+    ///
+    /// For Quic the local address is the one udp endpoint bound to.
+    Inet { peer: SocketAddr, local: SocketAddr },
+    /// Unix domain socket connection. Peer of it is unnamed in most cases and not collected.
+    Uds,
+}
+
+impl ConnectionAddrs {
+    /// Collect addresses from a stream accepted by server. `None` when they can not be queried.
+    pub fn from_stream(io: &ServerStream) -> Option<Self> {
+        match *io {
+            ServerStream::Tcp(ref tcp) => Self::from_tcp(tcp),
+            #[cfg(feature = "http3")]
+            ServerStream::Udp(ref udp) => Some(Self::Inet {
+                peer: udp.peer_addr(),
+                local: udp.local_addr(),
+            }),
+            #[cfg(unix)]
+            ServerStream::Unix(_) => Some(Self::Uds),
+        }
+    }
+
+    /// Collect addresses from io type implementing [OnConnect].
+    pub(crate) fn from_io<T: OnConnect>(io: &T) -> Option<Self> {
+        // id is not used. avoid taking one from the counter.
+        let mut data = ConnectionData {
+            id: ConnectionId(0),
+            addrs: None,
+            peer_certs: None,
+            vhost: None,
+            tls_info: None,
+            extensions: None,
+        };
+        io.on_connect(&mut data);
+        data.addrs
+    }
+
+    fn from_tcp(tcp: &TcpStream) -> Option<Self> {
+        Some(Self::Inet {
+            peer: tcp.peer_addr().ok()?,
+            local: tcp.local_addr().ok()?,
+        })
+    }
+
+    /// Address of remote peer. `None` for unix domain socket.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        match *self {
+            Self::Inet { peer, .. } => Some(peer),
+            Self::Uds => None,
+        }
+    }
+
+    /// Local address connection is accepted on. `None` for unix domain socket.
+    pub fn local(&self) -> Option<SocketAddr> {
+        match *self {
+            Self::Inet { local, .. } => Some(local),
+            Self::Uds => None,
+        }
+    }
+}
+
+/// A helper trait for collecting connection level data from certain types.
+pub trait OnConnect {
+    fn on_connect(&self, data: &mut ConnectionData);
+}
+
+impl OnConnect for ServerStream {
+    #[inline]
+    fn on_connect(&self, data: &mut ConnectionData) {
+        if let Some(addrs) = ConnectionAddrs::from_stream(self) {
+            data.set_addrs(addrs);
+        }
+    }
+}
+
+impl OnConnect for TcpStream {
+    #[inline]
+    fn on_connect(&self, data: &mut ConnectionData) {
+        if let Some(addrs) = ConnectionAddrs::from_tcp(self) {
+            data.set_addrs(addrs);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl OnConnect for actix_server_alt::net::UnixStream {
+    #[inline]
+    fn on_connect(&self, data: &mut ConnectionData) {
+        data.set_addrs(ConnectionAddrs::Uds);
+    }
+}
+
+/// Extensions produced once per connection (e.g. by a tls acceptor after handshake) and shared by
+/// all requests from that connection.
+#[derive(Clone)]
+pub struct ConnectionExtensions(Arc<Extensions>);
+
+impl ConnectionExtensions {
+    pub fn new(extensions: Extensions) -> Self {
+        Self(Arc::new(extensions))
+    }
+}
+
+impl Deref for ConnectionExtensions {
+    type Target = Extensions;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Virtual host selected for a connection by server name indication of tls handshake.
+#[derive(Clone, Debug)]
+pub struct VirtualHost {
+    pattern: Option<Arc<str>>,
+    #[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+    index: usize,
+}
+
+impl VirtualHost {
+    #[cfg(feature = "rustls")]
+    pub(crate) fn new(pattern: Option<Arc<str>>, index: usize) -> Self {
+        Self { pattern, index }
+    }
+
+    /// Host pattern the server name matched. `None` for the default host.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_deref()
+    }
+
+    /// Check if connection is served by the default host.
+    pub fn is_default(&self) -> bool {
+        self.pattern.is_none()
+    }
+
+    #[cfg(feature = "rustls")]
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+}
+
+/// Certificate chain presented by client during tls handshake.
+///
+/// Certificates are in DER format and the first one is the client's own certificate.
+/// Only available on connections with client certificate verification enabled.
+#[derive(Clone, Debug)]
+pub struct PeerCertificates(Arc<[Vec<u8>]>);
+
+impl PeerCertificates {
+    pub fn new(chain: Vec<Vec<u8>>) -> Self {
+        Self(chain.into())
+    }
+
+    /// DER bytes of client's own certificate.
+    pub fn leaf(&self) -> Option<&[u8]> {
+        self.0.first().map(|der| der.as_slice())
+    }
+
+    /// DER bytes of full certificate chain.
+    pub fn chain(&self) -> &[Vec<u8>] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "x509")]
+impl PeerCertificates {
+    /// Subject of client's own certificate in RFC 4514 string form.
+    pub fn subject(&self) -> Option<String> {
+        let (_, cert) = x509_parser::parse_x509_certificate(self.leaf()?).ok()?;
+        Some(cert.subject().to_string())
+    }
+
+    /// DNS names listed in subject alternative name extension of client's own certificate.
+    pub fn subject_alt_names(&self) -> Vec<String> {
+        use x509_parser::extensions::GeneralName;
+
+        self.leaf()
+            .and_then(|der| x509_parser::parse_x509_certificate(der).ok())
+            .and_then(|(_, cert)| {
+                let san = cert.subject_alternative_name().ok()??;
+                let names = san
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match *name {
+                        GeneralName::DNSName(dns) => Some(dns.to_owned()),
+                        _ => None,
+                    })
+                    .collect();
+                Some(names)
+            })
+            .unwrap_or_default()
+    }
+}
@@ -2,25 +2,39 @@ use std::{
     error::Error,
     fmt::{self, Debug, Display, Formatter},
     io,
+    net::SocketAddr,
 };
 
 use log::error;
 
 use super::protocol::Protocol;
+use super::tls::TlsError;
 
 /// HttpService layer error.
+///
+/// Each timer of dispatchers has its own timeout variant. Whether a response is attempted
+/// before closing the connection is documented on the variant.
 pub enum HttpServiceError {
     Ignored,
     ServiceReady,
-    Timeout(TimeoutError),
     UnknownProtocol(Protocol),
     Body(BodyError),
-    #[cfg(feature = "openssl")]
-    Openssl(super::tls::openssl::OpensslError),
-    #[cfg(feature = "rustls")]
-    Rustls(super::tls::rustls::RustlsError),
-    #[cfg(feature = "native-tls")]
-    NativeTls(super::tls::native_tls::NativeTlsError),
+    /// Http/2 connection did not answer keep alive ping in time. Idle Http/1 connection is closed
+    /// with `Ok(())` when reaching keep alive timeout.
+    KeepAliveExpired,
+    /// Request head did not arrive in time. Including Http/2 connection preface.
+    /// Http/1 responds with 408 when part of a request head is received. Otherwise closed silently.
+    RequestHeadTimeout,
+    /// Tls handshake did not finish in time. Closed silently. Carry peer address when available.
+    TlsHandshakeTimeout(Option<SocketAddr>),
+    /// Service call and response body did not finish within request timeout.
+    /// Response with configured status is attempted when response is not started.
+    /// Otherwise Http/1 connection is closed.
+    ServiceCallTimeout,
+    /// Connection did not finish in time after shutdown is started. Closed silently.
+    DrainTimeout,
+    /// Tls handshake failed. Carry peer address when available.
+    HandshakeFailed(Option<SocketAddr>, TlsError),
     #[cfg(feature = "http1")]
     H1(super::h1::Error),
     // Http/2 error happen in HttpService handle.
@@ -31,12 +45,23 @@ pub enum HttpServiceError {
     H3(super::h3::Error),
 }
 
-/// time out error from async task that run for too long.
-#[derive(Debug)]
-pub enum TimeoutError {
-    TlsAccept,
-    #[cfg(feature = "http2")]
-    H2Handshake,
+/// Category of [HttpServiceError].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Connection closed on purpose and error detail is dropped.
+    Ignored,
+    /// Service failed readiness check.
+    ServiceReady,
+    /// Connection or handshake did not finish in time.
+    Timeout,
+    /// Tls handshake failed.
+    Tls,
+    /// Io error from connection. Usually caused by client going away.
+    Io,
+    /// Malformed request or protocol violation.
+    Protocol,
+    /// Request or response body failed.
+    Body,
 }
 
 impl Debug for HttpServiceError {
@@ -44,15 +69,15 @@ impl Debug for HttpServiceError {
         match *self {
             Self::Ignored => write!(f, "Error detail is ignored."),
             Self::ServiceReady => write!(f, "Service is not ready"),
-            Self::Timeout(ref timeout) => write!(f, "{:?} is timed out", timeout),
             Self::UnknownProtocol(ref protocol) => write!(f, "Protocol: {:?} is not supported", protocol),
             Self::Body(ref e) => write!(f, "{:?}", e),
-            #[cfg(feature = "openssl")]
-            Self::Openssl(ref e) => write!(f, "{:?}", e),
-            #[cfg(feature = "rustls")]
-            Self::Rustls(ref e) => write!(f, "{:?}", e),
-            #[cfg(feature = "native-tls")]
-            Self::NativeTls(ref e) => write!(f, "{:?}", e),
+            Self::TlsHandshakeTimeout(Some(ref peer)) => write!(f, "Tls handshake from {} is timed out", peer),
+            Self::TlsHandshakeTimeout(None) => write!(f, "Tls handshake is timed out"),
+            Self::KeepAliveExpired | Self::RequestHeadTimeout | Self::ServiceCallTimeout | Self::DrainTimeout => {
+                Display::fmt(self, f)
+            }
+            Self::HandshakeFailed(Some(ref peer), ref e) => write!(f, "Tls handshake from {} failed: {:?}", peer, e),
+            Self::HandshakeFailed(None, ref e) => write!(f, "Tls handshake failed: {:?}", e),
             #[cfg(feature = "http1")]
             Self::H1(ref e) => write!(f, "{:?}", e),
             #[cfg(feature = "http2")]
@@ -63,10 +88,133 @@ impl Debug for HttpServiceError {
     }
 }
 
+impl Display for HttpServiceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Ignored => write!(f, "Error detail is ignored"),
+            Self::ServiceReady => write!(f, "Service is not ready"),
+            Self::UnknownProtocol(ref protocol) => write!(f, "Protocol: {:?} is not supported", protocol),
+            Self::Body(ref e) => write!(f, "Body error: {}", e),
+            Self::KeepAliveExpired => write!(f, "Keep alive is timed out"),
+            Self::RequestHeadTimeout => write!(f, "Request head is timed out"),
+            Self::TlsHandshakeTimeout(Some(ref peer)) => write!(f, "Tls handshake from {} is timed out", peer),
+            Self::TlsHandshakeTimeout(None) => write!(f, "Tls handshake is timed out"),
+            Self::ServiceCallTimeout => write!(f, "Service call is timed out"),
+            Self::DrainTimeout => write!(f, "Connection drain is timed out"),
+            Self::HandshakeFailed(Some(ref peer), ref e) => write!(f, "Tls handshake from {} failed: {}", peer, e),
+            Self::HandshakeFailed(None, ref e) => write!(f, "Tls handshake failed: {}", e),
+            #[cfg(feature = "http1")]
+            Self::H1(ref e) => write!(f, "Http/1 error: {}", e),
+            #[cfg(feature = "http2")]
+            Self::H2(ref e) => write!(f, "Http/2 error: {}", e),
+            #[cfg(feature = "http3")]
+            Self::H3(ref e) => write!(f, "Http/3 error: {}", e),
+        }
+    }
+}
+
+impl Error for HttpServiceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Body(ref e) => Some(e),
+            Self::HandshakeFailed(_, ref e) => Some(e),
+            #[cfg(feature = "http1")]
+            Self::H1(ref e) => Some(e),
+            #[cfg(feature = "http2")]
+            Self::H2(ref e) => Some(e),
+            #[cfg(feature = "http3")]
+            Self::H3(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl HttpServiceError {
+    /// Category of error. Useful for choosing log level.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Self::Ignored => ErrorKind::Ignored,
+            Self::ServiceReady => ErrorKind::ServiceReady,
+            Self::KeepAliveExpired
+            | Self::RequestHeadTimeout
+            | Self::TlsHandshakeTimeout(_)
+            | Self::ServiceCallTimeout
+            | Self::DrainTimeout => ErrorKind::Timeout,
+            Self::UnknownProtocol(_) => ErrorKind::Protocol,
+            Self::Body(_) => ErrorKind::Body,
+            Self::HandshakeFailed(..) => ErrorKind::Tls,
+            #[cfg(feature = "http1")]
+            Self::H1(ref e) => match *e {
+                super::h1::Error::Closed | super::h1::Error::Io(_) => ErrorKind::Io,
+                super::h1::Error::Body(_) => ErrorKind::Body,
+                super::h1::Error::Proto(_) => ErrorKind::Protocol,
+                super::h1::Error::ServiceReady => ErrorKind::ServiceReady,
+                super::h1::Error::RequestHeadTimeout | super::h1::Error::ServiceCallTimeout => ErrorKind::Timeout,
+            },
+            #[cfg(feature = "http2")]
+            Self::H2(ref e) => match *e {
+                super::h2::Error::H2(ref e) if e.is_io() => ErrorKind::Io,
+                super::h2::Error::H2(_) => ErrorKind::Protocol,
+                super::h2::Error::Body(_) => ErrorKind::Body,
+                super::h2::Error::KeepAliveExpired => ErrorKind::Timeout,
+            },
+            #[cfg(feature = "http3")]
+            Self::H3(ref e) => match *e {
+                super::h3::Error::Connection(_) => ErrorKind::Io,
+                super::h3::Error::H3(_) => ErrorKind::Protocol,
+                super::h3::Error::Body(_) => ErrorKind::Body,
+            },
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        self.kind() == ErrorKind::Timeout
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.kind() == ErrorKind::Tls
+    }
+
+    pub fn is_io(&self) -> bool {
+        self.kind() == ErrorKind::Io
+    }
+
+    pub fn is_protocol(&self) -> bool {
+        self.kind() == ErrorKind::Protocol
+    }
+
+    pub fn is_body(&self) -> bool {
+        self.kind() == ErrorKind::Body
+    }
+
+    /// Category of malformed Http/1 message when error is caused by one.
+    #[cfg(feature = "http1")]
+    pub fn h1_parse(&self) -> Option<&super::h1::Parse> {
+        match *self {
+            Self::H1(super::h1::Error::Proto(super::h1::ProtoError::Parse(ref e))) => Some(e),
+            _ => None,
+        }
+    }
+
     pub fn log(self) {
-        // TODO: add logging for different error types.
-        error!("HttpService Error: {:?}", self);
+        match self {
+            // handshake failures are logged with rate limit by http services where they happen.
+            Self::TlsHandshakeTimeout(_) | Self::HandshakeFailed(..) => {}
+            e => error!("HttpService Error: {:?}", e),
+        }
+    }
+
+    /// Construct handshake timeout error of given peer.
+    pub(crate) fn handshake_timeout(peer: Option<SocketAddr>) -> Self {
+        Self::TlsHandshakeTimeout(peer)
+    }
+
+    /// Attach peer address to handshake failure.
+    pub(crate) fn with_peer(self, peer: Option<SocketAddr>) -> Self {
+        match self {
+            Self::HandshakeFailed(_, e) => Self::HandshakeFailed(peer, e),
+            e => e,
+        }
     }
 }
 
@@ -102,6 +250,30 @@ impl From<io::Error> for BodyError {
     }
 }
 
+#[cfg(feature = "websocket")]
+impl From<http_ws::ProtocolError> for BodyError {
+    fn from(e: http_ws::ProtocolError) -> Self {
+        match e {
+            http_ws::ProtocolError::Io(e) => Self::Io(e),
+            e => Self::Std(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl<E> From<http_ws::DecodeError<E>> for BodyError
+where
+    BodyError: From<E>,
+{
+    fn from(e: http_ws::DecodeError<E>) -> Self {
+        match e {
+            http_ws::DecodeError::Protocol(e) => e.into(),
+            http_ws::DecodeError::Stream(e) => e.into(),
+            http_ws::DecodeError::Timeout => Self::Io(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
 impl From<Box<dyn Error>> for BodyError {
     fn from(e: Box<dyn Error>) -> Self {
         Self::Std(e)
@@ -119,3 +291,26 @@ impl From<()> for HttpServiceError {
         Self::Ignored
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn source_and_kind() {
+        let e = HttpServiceError::from(BodyError::from(io::Error::new(io::ErrorKind::UnexpectedEof, "eof")));
+
+        assert_eq!(e.kind(), ErrorKind::Body);
+        assert!(e.is_body());
+        assert_eq!(e.to_string(), "Body error: eof");
+        assert_eq!(e.source().unwrap().to_string(), "eof");
+
+        assert!(HttpServiceError::TlsHandshakeTimeout(None).is_timeout());
+        assert!(HttpServiceError::KeepAliveExpired.is_timeout());
+        assert_eq!(
+            HttpServiceError::RequestHeadTimeout.to_string(),
+            "Request head is timed out"
+        );
+        assert!(HttpServiceError::Ignored.source().is_none());
+    }
+}
@@ -1,14 +1,46 @@
-//! Default expect handler. Pass through request unconditionally.
+//! Default expect handler. Check request head before `100 Continue` is sent.
 
 use std::{
+    fmt,
     future::Future,
     marker::PhantomData,
+    rc::Rc,
     task::{Context, Poll},
 };
 
 use actix_service_alt::{Service, ServiceFactory};
+use http::{header::CONTENT_LENGTH, HeaderMap, Method, Request, Response, StatusCode, Uri};
 
-pub struct ExpectHandler<F>(PhantomData<F>);
+use crate::body::ResponseBody;
+use crate::response::ResponseError;
+
+type Predicate = Rc<dyn Fn(&Method, &Uri, &HeaderMap) -> bool>;
+
+/// Expect service called with request carrying `expect: 100-continue` header before its body is
+/// sent.
+///
+/// Pass through request unconditionally by default. With [ExpectHandler::max_body_size] request
+/// declaring larger `content-length` is rejected with `413 Payload Too Large`. With
+/// [ExpectHandler::predicate] request failing the check is rejected with `417 Expectation Failed`.
+///
+/// Client does not send body of rejected request so the connection stays keep-alive.
+pub struct ExpectHandler<F> {
+    max_body_size: Option<u64>,
+    status: StatusCode,
+    predicate: Option<Predicate>,
+    _factory: PhantomData<F>,
+}
+
+impl<F> Clone for ExpectHandler<F> {
+    fn clone(&self) -> Self {
+        Self {
+            max_body_size: self.max_body_size,
+            status: self.status,
+            predicate: self.predicate.clone(),
+            _factory: PhantomData,
+        }
+    }
+}
 
 impl<F> Default for ExpectHandler<F> {
     fn default() -> Self {
@@ -18,32 +50,81 @@ impl<F> Default for ExpectHandler<F> {
 
 impl<F> ExpectHandler<F> {
     pub fn new() -> Self {
-        Self(PhantomData)
+        Self {
+            max_body_size: None,
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            predicate: None,
+            _factory: PhantomData,
+        }
+    }
+
+    /// Reject request with `content-length` header larger than given limit.
+    pub fn max_body_size(mut self, limit: u64) -> Self {
+        self.max_body_size = Some(limit);
+        self
+    }
+
+    /// Reject too large request with `417 Expectation Failed` instead of `413 Payload Too Large`.
+    pub fn expectation_failed(mut self) -> Self {
+        self.status = StatusCode::EXPECTATION_FAILED;
+        self
+    }
+
+    /// Reject request when given function returns false. (e.g. authorization header is missing or
+    /// path is not allowed to upload)
+    pub fn predicate<P>(mut self, predicate: P) -> Self
+    where
+        P: Fn(&Method, &Uri, &HeaderMap) -> bool + 'static,
+    {
+        self.predicate = Some(Rc::new(predicate));
+        self
+    }
+
+    fn check<B>(&self, req: &Request<B>) -> Result<(), ExpectRejected> {
+        if let Some(limit) = self.max_body_size {
+            let len = req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+
+            if matches!(len, Some(len) if len > limit) {
+                return Err(ExpectRejected(self.status));
+            }
+        }
+
+        match self.predicate {
+            Some(ref predicate) if !predicate(req.method(), req.uri(), req.headers()) => {
+                Err(ExpectRejected(StatusCode::EXPECTATION_FAILED))
+            }
+            _ => Ok(()),
+        }
     }
 }
 
-impl<F, Req> ServiceFactory<Req> for ExpectHandler<F>
+impl<F, ReqB> ServiceFactory<Request<ReqB>> for ExpectHandler<F>
 where
-    F: ServiceFactory<Req>,
+    F: ServiceFactory<Request<ReqB>>,
 {
-    type Response = Req;
-    type Error = F::Error;
+    type Response = Request<ReqB>;
+    type Error = ExpectRejected;
     type Config = ();
     type Service = Self;
     type InitError = F::InitError;
     type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
 
     fn new_service(&self, _: Self::Config) -> Self::Future {
-        async { Ok(Self::new()) }
+        let this = self.clone();
+        async { Ok(this) }
     }
 }
 
-impl<F, Req> Service<Req> for ExpectHandler<F>
+impl<F, ReqB> Service<Request<ReqB>> for ExpectHandler<F>
 where
-    F: ServiceFactory<Req>,
+    F: ServiceFactory<Request<ReqB>>,
 {
-    type Response = Req;
-    type Error = F::Error;
+    type Response = Request<ReqB>;
+    type Error = ExpectRejected;
     type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
 
     #[inline]
@@ -51,7 +132,118 @@ where
         Poll::Ready(Ok(()))
     }
 
-    fn call(&self, req: Req) -> Self::Future<'_> {
-        async move { Ok(req) }
+    fn call(&self, req: Request<ReqB>) -> Self::Future<'_> {
+        let res = self.check(&req).map(|_| req);
+        async move { res }
+    }
+}
+
+/// Error of request rejected by [ExpectHandler]. Converted to response with the status code.
+#[derive(Debug)]
+pub struct ExpectRejected(StatusCode);
+
+impl ExpectRejected {
+    pub fn status_code(&self) -> StatusCode {
+        self.0
+    }
+}
+
+impl fmt::Display for ExpectRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            StatusCode::PAYLOAD_TOO_LARGE => f.write_str("request body too large"),
+            _ => f.write_str("expectation failed"),
+        }
+    }
+}
+
+impl std::error::Error for ExpectRejected {}
+
+impl<B> ResponseError<Response<ResponseBody<B>>> for ExpectRejected {
+    fn status_code(&self) -> StatusCode {
+        self.0
+    }
+
+    fn response_error(&mut self) -> Response<ResponseBody<B>> {
+        ResponseError::<Response<ResponseBody<B>>>::response(self)
+    }
+}
+
+#[cfg(all(test, feature = "http1"))]
+mod test {
+    use super::*;
+
+    use http::header::AUTHORIZATION;
+    use tokio::task::LocalSet;
+
+    use crate::builder::HttpServiceBuilder;
+    use crate::util::{
+        echo,
+        testing::{duplex, serve, H1Client, TestStream},
+    };
+
+    #[tokio::test]
+    async fn reject_before_continue() {
+        LocalSet::new()
+            .run_until(async {
+                let builder = HttpServiceBuilder::h1(echo()).expect_handler(|expect| {
+                    expect
+                        .max_body_size(4)
+                        .predicate(|_, _, headers| headers.contains_key(AUTHORIZATION))
+                });
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+                let (res, _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+                    let mut res = Vec::new();
+
+                    for head in [
+                        "POST / HTTP/1.1\r\nHost: localhost\r\nAuthorization: a\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+                        "POST / HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\n",
+                    ] {
+                        // client does not send body after rejection.
+                        client.send(head).await.unwrap();
+                        res.push(client.response().await.unwrap());
+                    }
+
+                    // connection is still usable.
+                    client
+                        .send("POST / HTTP/1.1\r\nHost: localhost\r\nAuthorization: a\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\n")
+                        .await
+                        .unwrap();
+                    res.push(client.response().await.unwrap());
+                    client.send("body").await.unwrap();
+                    res.push(client.response().await.unwrap());
+
+                    res
+                })
+                .await;
+
+                assert_eq!(res[0].status(), StatusCode::PAYLOAD_TOO_LARGE);
+                assert_eq!(res[1].status(), StatusCode::EXPECTATION_FAILED);
+                for res in &res[..2] {
+                    assert!(res.headers().get("connection").is_none());
+                }
+                assert_eq!(res[2].status(), StatusCode::CONTINUE);
+                assert_eq!(res[3].status(), StatusCode::OK);
+                assert_eq!(res[3].body(), "body");
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn expectation_failed() {
+        let expect = ExpectHandler::<crate::util::Echo>::new()
+            .max_body_size(0)
+            .expectation_failed();
+        let service = ServiceFactory::<Request<()>>::new_service(&expect, ()).await.unwrap();
+
+        let req = Request::post("/").header(CONTENT_LENGTH, "1").body(()).unwrap();
+        let err = service.call(req).await.unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::EXPECTATION_FAILED);
+
+        // request without content-length is passed.
+        assert!(service.call(Request::new(())).await.is_ok());
     }
 }
@@ -1,5 +1,18 @@
-use std::{ops::Deref, rc::Rc};
+use std::{ops::Deref, rc::Rc, task::Poll, time::Duration};
 
+use actix_service_alt::Service;
+
+use super::shutdown::ShutdownHandle;
+use super::util::{
+    poll_fn::poll_fn,
+    timer::{Timer, TimerWheel},
+};
+
+/// Services and state shared by all connections of a service instance.
+///
+/// Constructed once per [HttpService](crate::HttpService) (one per worker thread). Connections
+/// borrow it and only Http/2 and Http/3 streams clone it as they are spawned as separate tasks.
+/// Connection specific state belongs to dispatchers and must not be added here.
 pub(crate) struct HttpFlow<S, X, U>(Rc<HttpFlowInner<S, X, U>>);
 
 impl<S, X, U> Clone for HttpFlow<S, X, U> {
@@ -20,16 +33,48 @@ pub(crate) struct HttpFlowInner<S, X, U> {
     pub(crate) service: S,
     pub(crate) expect: X,
     pub(crate) upgrade: Option<U>,
+    pub(crate) shutdown: ShutdownHandle,
 }
 
 impl<S, X, U> HttpFlow<S, X, U> {
-    pub fn new(service: S, expect: X, upgrade: Option<U>) -> Self {
+    pub fn new(service: S, expect: X, upgrade: Option<U>, shutdown: ShutdownHandle) -> Self {
         let inner = HttpFlowInner {
             service,
             expect,
             upgrade,
+            shutdown: shutdown.subscribe(),
         };
 
         Self(Rc::new(inner))
     }
 }
+
+/// Outcome of waiting for service readiness.
+pub(crate) enum Readiness {
+    Ready,
+    /// Service stayed not ready longer than the given duration.
+    Timeout,
+    /// Service failed readiness check.
+    Failed,
+}
+
+impl<S, X, U> HttpFlowInner<S, X, U> {
+    /// Wait for service readiness before calling it. Give up after given duration.
+    pub(crate) async fn ready<Req>(&self, wheel: &Rc<TimerWheel>, dur: Duration) -> Readiness
+    where
+        S: Service<Req>,
+    {
+        // timer is only registered when service is not ready right away.
+        let mut timer = None::<Timer>;
+
+        poll_fn(|cx| match self.service.poll_ready(cx) {
+            Poll::Ready(Ok(_)) => Poll::Ready(Readiness::Ready),
+            Poll::Ready(Err(_)) => Poll::Ready(Readiness::Failed),
+            Poll::Pending => timer
+                .get_or_insert_with(|| wheel.sleep(dur))
+                .poll_expired(cx)
+                .map(|_| Readiness::Timeout),
+        })
+        .await
+    }
+}
@@ -1,4 +1,8 @@
-use std::io;
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+    io,
+};
 
 use super::proto::ProtoError;
 
@@ -8,11 +12,42 @@ use crate::error::{BodyError, HttpServiceError};
 pub enum Error {
     /// Closed error should be treated as success and transform to Ok(())
     Closed,
+    /// Request head did not arrive in time.
+    RequestHeadTimeout,
+    /// Service call and response body did not finish within request timeout.
+    ServiceCallTimeout,
+    /// Service failed readiness check.
+    ServiceReady,
     Body(BodyError),
     Io(io::Error),
     Proto(ProtoError),
 }
 
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Closed => f.write_str("Connection closed"),
+            Self::RequestHeadTimeout => f.write_str("Request head is timed out"),
+            Self::ServiceCallTimeout => f.write_str("Service call is timed out"),
+            Self::ServiceReady => f.write_str("Service is not ready"),
+            Self::Body(ref e) => write!(f, "{}", e),
+            Self::Io(ref e) => write!(f, "{}", e),
+            Self::Proto(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::Closed | Self::RequestHeadTimeout | Self::ServiceCallTimeout | Self::ServiceReady => None,
+            Self::Body(ref e) => Some(e),
+            Self::Io(ref e) => Some(e),
+            Self::Proto(ref e) => Some(e),
+        }
+    }
+}
+
 impl From<BodyError> for Error {
     fn from(e: BodyError) -> Self {
         Self::Body(e)
@@ -37,6 +72,11 @@ impl From<io::Error> for Error {
 
 impl From<Error> for HttpServiceError {
     fn from(e: Error) -> Self {
-        Self::H1(e)
+        match e {
+            Error::RequestHeadTimeout => Self::RequestHeadTimeout,
+            Error::ServiceCallTimeout => Self::ServiceCallTimeout,
+            Error::ServiceReady => Self::ServiceReady,
+            e => Self::H1(e),
+        }
     }
 }
@@ -4,9 +4,13 @@ mod error;
 mod proto;
 mod service;
 
+pub(crate) use self::proto::read_response;
+#[cfg(feature = "io-uring")]
+pub(crate) use self::proto::BufIo;
 pub(crate) use self::proto::Dispatcher;
 
 pub use self::body::RequestBody;
 pub use self::builder::H1ServiceBuilder;
 pub use self::error::Error;
+pub use self::proto::{ClientBody, ClientConnection, ClientError, Parse, ProtoError};
 pub use self::service::H1Service;
@@ -7,9 +7,20 @@ use bytes::{Buf, Bytes, BytesMut};
 
 use crate::util::buf_list::BufList;
 
+// spare capacity reserved for the first read of a connection.
+const INIT_READ_SIZE: usize = 8 * 1024;
+
+/// Read buffer with adaptive read size.
+///
+/// Io reads into spare capacity of [BytesMut] through [BufMut](bytes::BufMut) so memory is not
+/// zeroed before read. Spare capacity reserved for next read doubles when a read fills it and
+/// halves after two consecutive reads using less than half of it. A large upload is read in few
+/// big reads while an idle keep alive connection holds a small buffer.
 pub(super) struct ReadBuf<const READ_BUF_LIMIT: usize> {
     advanced: bool,
     buf: BytesMut,
+    next: usize,
+    decrease_now: bool,
 }
 
 impl<const READ_BUF_LIMIT: usize> ReadBuf<READ_BUF_LIMIT> {
@@ -17,6 +28,32 @@ impl<const READ_BUF_LIMIT: usize> ReadBuf<READ_BUF_LIMIT> {
         Self {
             advanced: false,
             buf: BytesMut::new(),
+            next: INIT_READ_SIZE,
+            decrease_now: false,
+        }
+    }
+
+    /// Reserve spare capacity for next read. Reservation never goes beyond read buffer limit.
+    #[inline]
+    pub(super) fn reserve(&mut self) {
+        let additional = self.next.min(READ_BUF_LIMIT.saturating_sub(self.buf.len()));
+        self.buf.reserve(additional);
+    }
+
+    /// Adjust size of next reservation with bytes of last read.
+    pub(super) fn record(&mut self, n: usize) {
+        if n >= self.next {
+            self.next = self.next.saturating_mul(2).min(READ_BUF_LIMIT.max(INIT_READ_SIZE));
+            self.decrease_now = false;
+        } else if n < self.next / 2 && self.next > INIT_READ_SIZE {
+            if self.decrease_now {
+                self.next /= 2;
+                self.decrease_now = false;
+            } else {
+                self.decrease_now = true;
+            }
+        } else {
+            self.decrease_now = false;
         }
     }
 
@@ -156,3 +193,41 @@ impl<B: Buf> Buf for EncodedBuf<B> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_size() {
+        let mut buf = ReadBuf::<{ 64 * 1024 }>::new();
+
+        buf.reserve();
+        assert!(buf.buf.capacity() >= INIT_READ_SIZE);
+
+        // full reads grow up to read buffer limit.
+        for size in [8, 16, 32, 64, 64] {
+            assert_eq!(buf.next, size * 1024);
+            buf.record(buf.next);
+        }
+
+        // one small read does not shrink.
+        buf.record(1);
+        assert_eq!(buf.next, 64 * 1024);
+        buf.record(40 * 1024);
+        buf.record(1);
+        assert_eq!(buf.next, 64 * 1024);
+
+        // shrink on consecutive small reads and never below initial size.
+        for size in [32, 16, 8, 8] {
+            buf.record(1);
+            buf.record(1);
+            assert_eq!(buf.next, size * 1024);
+        }
+
+        // reservation is bound by read buffer limit.
+        buf.buf.extend_from_slice(&[0; 60 * 1024]);
+        buf.reserve();
+        assert!(buf.buf.capacity() - buf.buf.len() >= 4 * 1024);
+    }
+}
@@ -0,0 +1,538 @@
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures_core::{ready, Stream};
+use http::{Method, Request, Response, StatusCode};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::io::poll_read_buf;
+
+use crate::error::BodyError;
+use crate::util::poll_fn::poll_fn;
+
+use super::buf::WriteBuf;
+use super::decode::{decode_response_head, RequestBodyItem, TransferDecoding};
+use super::encode::{encode_request_head, TransferEncoding};
+use super::error::ProtoError;
+
+/// Max size of response head.
+const READ_BUF_LIMIT: usize = 1024 * 1024;
+
+/// Write buffer is drained before more request body is encoded so there is no backpressure.
+const WRITE_BUF_LIMIT: usize = 64 * 1024;
+
+/// Client side of a Http/1 connection over any [AsyncRead] + [AsyncWrite] type.
+///
+/// One request is sent at a time. Connection can be taken back from [ClientBody] for next request
+/// when response is finished and both sides want to keep it alive. There is no pooling, redirect
+/// or tls setup.
+///
+/// # Example:
+/// ```rust,no_run
+/// # async fn send() -> Result<(), Box<dyn std::error::Error>> {
+/// use actix_http_alt::{h1::ClientConnection, http::Request, RequestBody};
+/// use actix_server_alt::net::TcpStream;
+///
+/// let stream = TcpStream::connect("127.0.0.1:8080").await?;
+///
+/// let req = Request::get("http://127.0.0.1:8080/").body(RequestBody::None)?;
+/// let res = ClientConnection::new(stream).send(req).await?;
+/// assert!(res.status().is_success());
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClientConnection<Io> {
+    io: Io,
+    read_buf: BytesMut,
+    write_buf: WriteBuf<WRITE_BUF_LIMIT>,
+}
+
+impl<Io> ClientConnection<Io>
+where
+    Io: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(io: Io) -> Self {
+        Self {
+            io,
+            read_buf: BytesMut::new(),
+            write_buf: WriteBuf::new(false),
+        }
+    }
+
+    /// Send request and wait for head of response.
+    ///
+    /// Request body is encoded according to content-length or chunked transfer-encoding header
+    /// of request. Request with neither of them is sent without body. Body of upgrade and CONNECT
+    /// requests is sent as is after the head. Host header is added from uri when it's missing.
+    ///
+    /// Request body is sent along with reading response and keeps being sent by [ClientBody]
+    /// after response head is returned. Interim 1xx responses other than 101 are skipped.
+    pub async fn send<B, E>(mut self, req: Request<B>) -> Result<Response<ClientBody<Io, B>>, ClientError>
+    where
+        B: Stream<Item = Result<Bytes, E>> + Unpin,
+        BodyError: From<E>,
+    {
+        let (parts, body) = req.into_parts();
+
+        let (encoder, keep_alive) = encode_request_head(&parts, &mut self.write_buf).map_err(ClientError::Request)?;
+
+        let mut body = ClientBody {
+            conn: self,
+            req_body: Some((body, encoder)),
+            decoder: TransferDecoding::length(0),
+            method: parts.method,
+            keep_alive,
+            done: false,
+        };
+
+        let res = poll_fn(|cx| body.poll_head(cx)).await?;
+
+        Ok(res.map(|_| body))
+    }
+}
+
+/// Body of response received by [ClientConnection]. It also sends the remaining request body.
+pub struct ClientBody<Io, B> {
+    conn: ClientConnection<Io>,
+    // request body is dropped after it's finished.
+    req_body: Option<(B, TransferEncoding)>,
+    decoder: TransferDecoding,
+    method: Method,
+    keep_alive: bool,
+    done: bool,
+}
+
+impl<Io, B, E> ClientBody<Io, B>
+where
+    Io: AsyncRead + AsyncWrite + Unpin,
+    B: Stream<Item = Result<Bytes, E>> + Unpin,
+    BodyError: From<E>,
+{
+    /// Check if there is no more data to receive.
+    pub fn is_end_stream(&self) -> bool {
+        self.done || self.decoder == TransferDecoding::length(0)
+    }
+
+    /// Take back connection when response is finished and it can be reused for next request.
+    pub fn into_connection(self) -> Option<ClientConnection<Io>> {
+        let reusable = self.keep_alive
+            && self.is_end_stream()
+            && self.req_body.is_none()
+            && matches!(self.conn.write_buf, WriteBuf::Flat(ref buf) if buf.is_empty());
+
+        if reusable {
+            Some(self.conn)
+        } else {
+            None
+        }
+    }
+
+    fn poll_head(&mut self, cx: &mut Context<'_>) -> Poll<Result<Response<()>, ClientError>> {
+        loop {
+            self.poll_send(cx)?;
+
+            while let Some((res, decoder, keep_alive)) =
+                decode_response_head::<READ_BUF_LIMIT>(&mut self.conn.read_buf, &self.method)
+                    .map_err(ClientError::Response)?
+            {
+                // skip interim response. e.g. 100 continue.
+                if res.status().is_informational() && res.status() != StatusCode::SWITCHING_PROTOCOLS {
+                    continue;
+                }
+
+                self.decoder = decoder;
+                self.keep_alive &= keep_alive;
+
+                return Poll::Ready(Ok(res));
+            }
+
+            if ready!(self.poll_read(cx))? == 0 {
+                return Poll::Ready(Err(ClientError::Closed));
+            }
+        }
+    }
+
+    /// Write request body as much as possible without waiting.
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Result<(), ClientError> {
+        let conn = &mut self.conn;
+
+        loop {
+            let buf = match conn.write_buf {
+                WriteBuf::Flat(ref mut buf) => buf,
+                WriteBuf::List(_) => unreachable!("ClientConnection does not use vectored write"),
+            };
+
+            if buf.has_remaining() {
+                while buf.has_remaining() {
+                    match Pin::new(&mut conn.io).poll_write(cx, buf.chunk())? {
+                        Poll::Ready(0) => return Err(ClientError::Closed),
+                        Poll::Ready(n) => buf.advance(n),
+                        Poll::Pending => return Ok(()),
+                    }
+                }
+
+                // flush is best effort. it would be polled again when more is written.
+                let _ = Pin::new(&mut conn.io).poll_flush(cx)?;
+            }
+
+            let (body, encoder) = match self.req_body {
+                Some(ref mut req_body) => req_body,
+                None => return Ok(()),
+            };
+
+            match Pin::new(body).poll_next(cx) {
+                Poll::Ready(Some(Ok(bytes))) => {
+                    encoder
+                        .encode(bytes, &mut conn.write_buf)
+                        .map_err(|e| ClientError::Body(e.into()))?;
+                }
+                Poll::Ready(Some(Err(e))) => return Err(ClientError::Body(e.into())),
+                Poll::Ready(None) => {
+                    // error when body is shorter than content-length.
+                    encoder
+                        .encode_eof(&mut conn.write_buf)
+                        .map_err(|e| ClientError::Body(e.into()))?;
+                    self.req_body = None;
+                }
+                Poll::Pending => return Ok(()),
+            }
+        }
+    }
+
+    fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize, ClientError>> {
+        let conn = &mut self.conn;
+        conn.read_buf.reserve(4096);
+        poll_read_buf(Pin::new(&mut conn.io), cx, &mut conn.read_buf).map_err(Into::into)
+    }
+}
+
+impl<Io, B, E> Stream for ClientBody<Io, B>
+where
+    Io: AsyncRead + AsyncWrite + Unpin,
+    B: Stream<Item = Result<Bytes, E>> + Unpin,
+    BodyError: From<E>,
+{
+    type Item = Result<Bytes, ClientError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            if let Err(e) = this.poll_send(cx) {
+                this.done = true;
+                return Poll::Ready(Some(Err(e)));
+            }
+
+            match this.decoder.decode(&mut this.conn.read_buf) {
+                Ok(Some(RequestBodyItem::Chunk(bytes))) => return Poll::Ready(Some(Ok(bytes))),
+                Ok(Some(RequestBodyItem::Eof)) => {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(ClientError::Response(e))));
+                }
+            }
+
+            match ready!(this.poll_read(cx)) {
+                Ok(0) => {
+                    this.done = true;
+                    this.keep_alive = false;
+
+                    return if this.decoder.is_until_close() {
+                        Poll::Ready(None)
+                    } else {
+                        Poll::Ready(Some(Err(ClientError::Closed)))
+                    };
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+/// Read one whole response from io. Interim responses are returned as they are and bytes after
+/// head of 101 response are left in read buffer.
+///
+/// Used by [H1Client](crate::util::testing::H1Client) which writes raw requests.
+pub(crate) async fn read_response<Io>(
+    io: &mut Io,
+    read_buf: &mut BytesMut,
+    method: &Method,
+) -> Result<Response<Bytes>, ClientError>
+where
+    Io: AsyncRead + Unpin,
+{
+    let (res, mut decoder) = loop {
+        if let Some((res, decoder, _)) =
+            decode_response_head::<READ_BUF_LIMIT>(read_buf, method).map_err(ClientError::Response)?
+        {
+            break (res, decoder);
+        }
+
+        read_buf.reserve(4096);
+        if poll_fn(|cx| poll_read_buf(Pin::new(&mut *io), cx, read_buf)).await? == 0 {
+            return Err(ClientError::Closed);
+        }
+    };
+
+    if res.status() == StatusCode::SWITCHING_PROTOCOLS {
+        return Ok(res.map(|_| Bytes::new()));
+    }
+
+    let mut body = BytesMut::new();
+
+    loop {
+        match decoder.decode(read_buf).map_err(ClientError::Response)? {
+            Some(RequestBodyItem::Chunk(bytes)) => body.extend_from_slice(&bytes),
+            Some(RequestBodyItem::Eof) => return Ok(res.map(|_| body.freeze())),
+            None => {
+                read_buf.reserve(4096);
+                if poll_fn(|cx| poll_read_buf(Pin::new(&mut *io), cx, read_buf)).await? == 0 {
+                    return if decoder.is_until_close() {
+                        Ok(res.map(|_| body.freeze()))
+                    } else {
+                        Err(ClientError::Closed)
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Error of [ClientConnection] and [ClientBody].
+///
+/// Connection can not be reused after any of them.
+#[derive(Debug)]
+pub enum ClientError {
+    /// Connection is closed before response is finished.
+    Closed,
+    /// Io error of connection.
+    Io(io::Error),
+    /// Request head can not be encoded. Nothing is sent.
+    Request(ProtoError),
+    /// Response head or body is malformed.
+    Response(ProtoError),
+    /// Request body failed or does not match its content-length.
+    Body(BodyError),
+}
+
+impl ClientError {
+    /// Check if error is caused by connection instead of request or response.
+    pub fn is_connection(&self) -> bool {
+        matches!(*self, Self::Closed | Self::Io(_))
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Closed => f.write_str("Connection closed"),
+            Self::Io(ref e) => write!(f, "{}", e),
+            Self::Request(ref e) => write!(f, "Invalid request: {}", e),
+            Self::Response(ref e) => write!(f, "Invalid response: {}", e),
+            Self::Body(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::Closed => None,
+            Self::Io(ref e) => Some(e),
+            Self::Request(ref e) | Self::Response(ref e) => Some(e),
+            Self::Body(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe => Self::Closed,
+            _ => Self::Io(e),
+        }
+    }
+}
+
+impl From<ClientError> for BodyError {
+    fn from(e: ClientError) -> Self {
+        match e {
+            ClientError::Io(e) => Self::Io(e),
+            e => Self::Std(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::VecDeque;
+
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use crate::body::RequestBody;
+
+    use super::super::error::Parse;
+
+    struct Chunks(VecDeque<&'static str>);
+
+    impl Stream for Chunks {
+        type Item = Result<Bytes, BodyError>;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.get_mut().0.pop_front().map(|s| Ok(Bytes::from(s))))
+        }
+    }
+
+    async fn read_body<B, E>(body: &mut ClientBody<DuplexStream, B>) -> Result<Vec<u8>, ClientError>
+    where
+        B: Stream<Item = Result<Bytes, E>> + Unpin,
+        BodyError: From<E>,
+    {
+        let mut buf = Vec::new();
+        while let Some(bytes) = poll_fn(|cx| Pin::new(&mut *body).poll_next(cx)).await {
+            buf.extend_from_slice(&bytes?);
+        }
+        Ok(buf)
+    }
+
+    // read from server side of duplex until given bytes are received.
+    async fn read_until(server: &mut DuplexStream, end: &str) -> String {
+        let mut buf = Vec::new();
+        while !buf.ends_with(end.as_bytes()) {
+            let mut chunk = [0; 64];
+            let n = server.read(&mut chunk).await.unwrap();
+            assert_ne!(n, 0);
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[tokio::test]
+    async fn keep_alive() {
+        let (client, mut server) = duplex(1024);
+
+        server
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+            .await
+            .unwrap();
+
+        let req = Request::get("http://localhost/a").body(RequestBody::None).unwrap();
+        let res = ClientConnection::new(client).send(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            read_until(&mut server, "\r\n\r\n").await,
+            "GET /a HTTP/1.1\r\nhost: localhost\r\n\r\n"
+        );
+
+        let mut body = res.into_body();
+        assert_eq!(read_body(&mut body).await.unwrap(), b"hello");
+        let conn = body.into_connection().unwrap();
+
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n5\r\nworld\r\n0\r\n\r\n")
+            .await
+            .unwrap();
+
+        let req = Request::post("/b")
+            .header("transfer-encoding", "chunked")
+            .body(Chunks(vec!["foo", "bar"].into()))
+            .unwrap();
+        let mut body = conn.send(req).await.unwrap().into_body();
+        assert_eq!(read_body(&mut body).await.unwrap(), b"world");
+        assert!(body.into_connection().is_some());
+
+        assert_eq!(
+            read_until(&mut server, "0\r\n\r\n").await,
+            "POST /b HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n3\r\nfoo\r\n3\r\nbar\r\n0\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn body_length() {
+        // response to HEAD has no body.
+        let (client, mut server) = duplex(1024);
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n")
+            .await
+            .unwrap();
+
+        let req = Request::head("/").body(RequestBody::None).unwrap();
+        let body = ClientConnection::new(client).send(req).await.unwrap().into_body();
+        assert!(body.is_end_stream());
+        assert!(body.into_connection().is_some());
+
+        // body without length is read until close and connection can not be reused.
+        let (client, mut server) = duplex(1024);
+        server.write_all(b"HTTP/1.0 200 OK\r\n\r\nuntil close").await.unwrap();
+
+        let req = Request::get("/").body(RequestBody::None).unwrap();
+        let mut body = ClientConnection::new(client).send(req).await.unwrap().into_body();
+        drop(server);
+        assert_eq!(read_body(&mut body).await.unwrap(), b"until close");
+        assert!(body.into_connection().is_none());
+
+        // request body shorter than content-length.
+        let (client, _server) = duplex(1024);
+        let req = Request::post("/")
+            .header("content-length", "10")
+            .body(Chunks(vec!["foo"].into()))
+            .unwrap();
+        let err = ClientConnection::new(client).send(req).await.err().unwrap();
+        assert!(matches!(err, ClientError::Body(_)));
+        assert!(!err.is_connection());
+    }
+
+    #[tokio::test]
+    async fn error() {
+        let (client, mut server) = duplex(1024);
+        server.write_all(b"HTTP/1.1 abc OK\r\n\r\n").await.unwrap();
+
+        let req = Request::get("/").body(RequestBody::None).unwrap();
+        let err = ClientConnection::new(client).send(req).await.err().unwrap();
+        assert!(matches!(
+            err,
+            ClientError::Response(ProtoError::Parse(Parse::StatusLine))
+        ));
+        assert!(!err.is_connection());
+
+        let (client, mut server) = duplex(1024);
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nhello")
+            .await
+            .unwrap();
+
+        let req = Request::get("/").body(RequestBody::None).unwrap();
+        let mut body = ClientConnection::new(client).send(req).await.unwrap().into_body();
+        drop(server);
+        let err = read_body(&mut body).await.err().unwrap();
+        assert!(matches!(err, ClientError::Closed));
+        assert!(err.is_connection());
+
+        let (client, _server) = duplex(1024);
+        let req = Request::get("/")
+            .header("transfer-encoding", "gzip")
+            .body(RequestBody::None)
+            .unwrap();
+        let err = ClientConnection::new(client).send(req).await.err().unwrap();
+        assert!(matches!(err, ClientError::Request(_)));
+    }
+}
@@ -1,7 +1,9 @@
-use std::{io, task::Poll};
+use std::task::Poll;
 
 use bytes::{Buf, Bytes, BytesMut};
 
+use super::error::Parse;
+
 #[derive(Debug, Clone, PartialEq)]
 pub(super) enum Kind {
     /// Coder used when a Content-Length header is passed with a positive integer.
@@ -67,7 +69,7 @@ impl ChunkedState {
         body: &mut BytesMut,
         size: &mut u64,
         buf: &mut Option<Bytes>,
-    ) -> Poll<io::Result<ChunkedState>> {
+    ) -> Poll<Result<ChunkedState, Parse>> {
         use self::ChunkedState::*;
         match *self {
             Size => ChunkedState::read_size(body, size),
@@ -83,66 +85,56 @@ impl ChunkedState {
         }
     }
 
-    fn read_size(rdr: &mut BytesMut, size: &mut u64) -> Poll<io::Result<ChunkedState>> {
+    fn read_size(rdr: &mut BytesMut, size: &mut u64) -> Poll<Result<ChunkedState, Parse>> {
         let radix = 16;
-        match byte!(rdr) {
-            b @ b'0'..=b'9' => {
-                *size *= radix;
-                *size += u64::from(b - b'0');
-            }
-            b @ b'a'..=b'f' => {
-                *size *= radix;
-                *size += u64::from(b + 10 - b'a');
-            }
-            b @ b'A'..=b'F' => {
-                *size *= radix;
-                *size += u64::from(b + 10 - b'A');
-            }
+        let digit = match byte!(rdr) {
+            b @ b'0'..=b'9' => b - b'0',
+            b @ b'a'..=b'f' => b + 10 - b'a',
+            b @ b'A'..=b'F' => b + 10 - b'A',
             b'\t' | b' ' => return Poll::Ready(Ok(ChunkedState::SizeLws)),
             b';' => return Poll::Ready(Ok(ChunkedState::Extension)),
             b'\r' => return Poll::Ready(Ok(ChunkedState::SizeLf)),
-            _ => {
-                return Poll::Ready(Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Invalid chunk size line: Invalid Size",
-                )));
-            }
+            _ => return Poll::Ready(Err(Parse::ChunkSize)),
+        };
+
+        // chunk size overflowing u64 is treated as invalid.
+        match size
+            .checked_mul(radix)
+            .and_then(|size| size.checked_add(u64::from(digit)))
+        {
+            Some(new) => *size = new,
+            None => return Poll::Ready(Err(Parse::ChunkSize)),
         }
+
         Poll::Ready(Ok(ChunkedState::Size))
     }
 
-    fn read_size_lws(rdr: &mut BytesMut) -> Poll<io::Result<ChunkedState>> {
+    fn read_size_lws(rdr: &mut BytesMut) -> Poll<Result<ChunkedState, Parse>> {
         match byte!(rdr) {
             // LWS can follow the chunk size, but no more digits can come
             b'\t' | b' ' => Poll::Ready(Ok(ChunkedState::SizeLws)),
             b';' => Poll::Ready(Ok(ChunkedState::Extension)),
             b'\r' => Poll::Ready(Ok(ChunkedState::SizeLf)),
-            _ => Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid chunk size linear white space",
-            ))),
+            _ => Poll::Ready(Err(Parse::ChunkSize)),
         }
     }
 
-    fn read_extension(rdr: &mut BytesMut) -> Poll<io::Result<ChunkedState>> {
+    fn read_extension(rdr: &mut BytesMut) -> Poll<Result<ChunkedState, Parse>> {
         match byte!(rdr) {
             b'\r' => Poll::Ready(Ok(ChunkedState::SizeLf)),
             _ => Poll::Ready(Ok(ChunkedState::Extension)), // no supported extensions
         }
     }
 
-    fn read_size_lf(rdr: &mut BytesMut, size: &mut u64) -> Poll<io::Result<ChunkedState>> {
+    fn read_size_lf(rdr: &mut BytesMut, size: &mut u64) -> Poll<Result<ChunkedState, Parse>> {
         match byte!(rdr) {
             b'\n' if *size > 0 => Poll::Ready(Ok(ChunkedState::Body)),
             b'\n' if *size == 0 => Poll::Ready(Ok(ChunkedState::EndCr)),
-            _ => Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid chunk size LF",
-            ))),
+            _ => Poll::Ready(Err(Parse::ChunkSize)),
         }
     }
 
-    fn read_body(rdr: &mut BytesMut, rem: &mut u64, buf: &mut Option<Bytes>) -> Poll<io::Result<ChunkedState>> {
+    fn read_body(rdr: &mut BytesMut, rem: &mut u64, buf: &mut Option<Bytes>) -> Poll<Result<ChunkedState, Parse>> {
         let len = rdr.len() as u64;
         if len == 0 {
             Poll::Ready(Ok(ChunkedState::Body))
@@ -164,37 +156,65 @@ impl ChunkedState {
         }
     }
 
-    fn read_body_cr(rdr: &mut BytesMut) -> Poll<io::Result<ChunkedState>> {
+    fn read_body_cr(rdr: &mut BytesMut) -> Poll<Result<ChunkedState, Parse>> {
         match byte!(rdr) {
             b'\r' => Poll::Ready(Ok(ChunkedState::BodyLf)),
-            _ => Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid chunk body CR",
-            ))),
+            _ => Poll::Ready(Err(Parse::ChunkDelimiter)),
         }
     }
 
-    fn read_body_lf(rdr: &mut BytesMut) -> Poll<io::Result<ChunkedState>> {
+    fn read_body_lf(rdr: &mut BytesMut) -> Poll<Result<ChunkedState, Parse>> {
         match byte!(rdr) {
             b'\n' => Poll::Ready(Ok(ChunkedState::Size)),
-            _ => Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid chunk body LF",
-            ))),
+            _ => Poll::Ready(Err(Parse::ChunkDelimiter)),
         }
     }
 
-    fn read_end_cr(rdr: &mut BytesMut) -> Poll<io::Result<ChunkedState>> {
+    fn read_end_cr(rdr: &mut BytesMut) -> Poll<Result<ChunkedState, Parse>> {
         match byte!(rdr) {
             b'\r' => Poll::Ready(Ok(ChunkedState::EndLf)),
-            _ => Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid chunk end CR"))),
+            _ => Poll::Ready(Err(Parse::ChunkDelimiter)),
         }
     }
 
-    fn read_end_lf(rdr: &mut BytesMut) -> Poll<io::Result<ChunkedState>> {
+    fn read_end_lf(rdr: &mut BytesMut) -> Poll<Result<ChunkedState, Parse>> {
         match byte!(rdr) {
             b'\n' => Poll::Ready(Ok(ChunkedState::End)),
-            _ => Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid chunk end LF"))),
+            _ => Poll::Ready(Err(Parse::ChunkDelimiter)),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chunk_size() {
+        let mut buf = BytesMut::from(&b"1f\r\n"[..]);
+        let mut size = 0;
+        let mut state = ChunkedState::Size;
+        while state == ChunkedState::Size {
+            state = match state.step(&mut buf, &mut size, &mut None) {
+                Poll::Ready(res) => res.unwrap(),
+                Poll::Pending => panic!("chunk size line is complete"),
+            };
+        }
+        assert_eq!(size, 31);
+
+        let mut buf = BytesMut::from(&b"fffffffffffffffff\r\n"[..]);
+        let mut size = 0;
+        let res = loop {
+            match ChunkedState::Size.step(&mut buf, &mut size, &mut None) {
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(e)) => break e,
+                Poll::Pending => panic!("chunk size must overflow"),
+            }
+        };
+        assert_eq!(res, Parse::ChunkSize);
+
+        let mut buf = BytesMut::from(&b"x"[..]);
+        let res = ChunkedState::Size.step(&mut buf, &mut 0, &mut None);
+        assert!(matches!(res, Poll::Ready(Err(Parse::ChunkSize))));
+    }
+}
@@ -1,9 +1,11 @@
-use std::{io, task::Poll};
+use std::task::Poll;
 
 use bytes::{Buf, Bytes, BytesMut};
 use http::{
-    header::{HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, EXPECT, TRANSFER_ENCODING, UPGRADE},
-    Method, Request, Uri, Version,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, EXPECT, HOST, TRANSFER_ENCODING, UPGRADE,
+    },
+    Method, Request, Response, StatusCode, Uri, Version,
 };
 use httparse::{Header, Status, EMPTY_HEADER};
 
@@ -14,6 +16,53 @@ use super::error::{Parse, ProtoError};
 /// No particular reason. Copied from `actix-http` crate.
 const MAX_HEADERS: usize = 96;
 
+/// Max length of uri accepted by http crate.
+const MAX_URI_LEN: usize = (u16::MAX - 1) as usize;
+
+/// Codings a transfer-encoding header can list before the final chunked coding. They are left
+/// to service and only chunked is decoded.
+const TRANSFER_CODINGS: &[&str] = &["gzip", "x-gzip", "deflate", "compress", "x-compress", "identity"];
+
+/// Check list of transfer codings from a transfer-encoding header. `chunked` is set when codings
+/// seen so far end with chunked. Codings of multiple headers form one list.
+///
+/// Chunked must be the final coding and appear only once. Otherwise the length of body can not be
+/// determined. Codings not known are rejected with `501 Not Implemented`.
+fn check_transfer_codings(value: &str, chunked: &mut bool) -> Result<(), ProtoError> {
+    for coding in value
+        .split(',')
+        .map(|coding| coding.trim())
+        .filter(|coding| !coding.is_empty())
+    {
+        if *chunked {
+            return Err(ProtoError::Parse(Parse::HeaderValue(TRANSFER_ENCODING)));
+        }
+
+        if coding.eq_ignore_ascii_case("chunked") {
+            *chunked = true;
+        } else if !TRANSFER_CODINGS.iter().any(|known| coding.eq_ignore_ascii_case(known)) {
+            return Err(ProtoError::Parse(Parse::UnsupportedTransferEncoding));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check if buffer starts with a HTTP/0.9 simple request line. (method and uri without version)
+fn is_simple_request(buf: &[u8]) -> bool {
+    let line = match buf.iter().position(|b| *b == b'\n') {
+        Some(idx) => &buf[..idx],
+        None => return false,
+    };
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    let mut parts = line.split(|b| *b == b' ');
+    matches!(
+        (parts.next(), parts.next(), parts.next()),
+        (Some(method), Some(uri), None) if !method.is_empty() && !uri.is_empty()
+    )
+}
+
 impl Context<'_> {
     // decode head and generate request and body decoder.
     pub(super) fn decode_head<const READ_BUF_LIMIT: usize>(
@@ -24,7 +73,13 @@ impl Context<'_> {
 
         let mut req = httparse::Request::new(&mut headers);
 
-        match req.parse(buf)? {
+        let status = match req.parse(buf) {
+            Ok(status) => status,
+            Err(httparse::Error::Token) if is_simple_request(buf) => return Err(ProtoError::Parse(Parse::Version)),
+            Err(e) => return Err(e.into()),
+        };
+
+        match status {
             Status::Complete(len) => {
                 // Important: reset context state for new request.
                 self.reset();
@@ -36,7 +91,11 @@ impl Context<'_> {
                     self.set_connect_method();
                 }
 
-                let uri = req.path.unwrap().parse::<Uri>()?;
+                let path = req.path.unwrap();
+                if path.len() > MAX_URI_LEN {
+                    return Err(ProtoError::Parse(Parse::UriTooLong));
+                }
+                let uri = path.parse::<Uri>()?;
 
                 // Set connection type when doing version match.
                 let version = if req.version.unwrap() == 1 {
@@ -62,6 +121,8 @@ impl Context<'_> {
                 headers.reserve(headers_len);
 
                 let mut decoder = TransferDecoding::eof();
+                // transfer-encoding is present and if its codings end with chunked.
+                let mut chunked = None;
 
                 // write headers to headermap and update request states.
                 for idx in &header_idx[..headers_len] {
@@ -71,25 +132,18 @@ impl Context<'_> {
                     match name {
                         TRANSFER_ENCODING => {
                             if version != Version::HTTP_11 {
-                                return Err(ProtoError::Parse(Parse::Header));
+                                return Err(ProtoError::Parse(Parse::HeaderValue(TRANSFER_ENCODING)));
                             }
 
-                            let chunked = value
-                                .to_str()
-                                .map_err(|_| ProtoError::Parse(Parse::Header))?
-                                .trim()
-                                .eq_ignore_ascii_case("chunked");
-
-                            if chunked {
-                                decoder.reset(TransferDecoding::chunked())?;
-                            }
+                            let value = value.to_str().map_err(|_| Parse::HeaderValue(TRANSFER_ENCODING))?;
+                            check_transfer_codings(value, chunked.get_or_insert(false))?;
                         }
                         CONTENT_LENGTH => {
                             let len = value
                                 .to_str()
-                                .map_err(|_| ProtoError::Parse(Parse::Header))?
+                                .map_err(|_| Parse::HeaderValue(CONTENT_LENGTH))?
                                 .parse::<u64>()
-                                .map_err(|_| ProtoError::Parse(Parse::Header))?;
+                                .map_err(|_| Parse::HeaderValue(CONTENT_LENGTH))?;
 
                             if len != 0 {
                                 decoder.reset(TransferDecoding::length(len))?;
@@ -118,6 +172,16 @@ impl Context<'_> {
                     headers.append(name, value);
                 }
 
+                if version == Version::HTTP_11 && !headers.contains_key(HOST) {
+                    return Err(ProtoError::Parse(Parse::MissingHost));
+                }
+
+                match chunked {
+                    Some(true) => decoder.reset(TransferDecoding::chunked())?,
+                    Some(false) => return Err(ProtoError::Parse(Parse::HeaderValue(TRANSFER_ENCODING))),
+                    None => {}
+                }
+
                 if method == Method::CONNECT {
                     self.set_ctype(ConnectionType::Upgrade);
                     decoder = TransferDecoding::plain_chunked();
@@ -144,6 +208,146 @@ impl Context<'_> {
     }
 }
 
+/// Decode head of response received by client connection. Interim 1xx responses other than 101
+/// are returned as is and caller should decode again for the final one.
+///
+/// The returned bool is false when connection can not be reused after the response.
+pub(super) fn decode_response_head<const READ_BUF_LIMIT: usize>(
+    buf: &mut BytesMut,
+    method: &Method,
+) -> Result<Option<(Response<()>, TransferDecoding, bool)>, ProtoError> {
+    let mut headers = [EMPTY_HEADER; MAX_HEADERS];
+
+    let mut res = httparse::Response::new(&mut headers);
+
+    let status = match res.parse(buf) {
+        Ok(status) => status,
+        Err(httparse::Error::Token) | Err(httparse::Error::NewLine) | Err(httparse::Error::Status) => {
+            return Err(ProtoError::Parse(Parse::StatusLine))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    match status {
+        Status::Complete(len) => {
+            let status = StatusCode::from_u16(res.code.unwrap()).map_err(|_| Parse::StatusLine)?;
+
+            let version = if res.version.unwrap() == 1 {
+                Version::HTTP_11
+            } else {
+                Version::HTTP_10
+            };
+
+            let mut keep_alive = version == Version::HTTP_11;
+
+            let mut header_idx = [HeaderIndex::new(); MAX_HEADERS];
+
+            HeaderIndex::record(buf, res.headers, &mut header_idx);
+
+            let headers_len = res.headers.len();
+
+            let slice = buf.split_to(len).freeze();
+
+            let mut headers = HeaderMap::with_capacity(headers_len);
+
+            // None means body is delimited by closing connection.
+            let mut decoder = None;
+
+            for idx in &header_idx[..headers_len] {
+                let name = HeaderName::from_bytes(&slice[idx.name.0..idx.name.1]).unwrap();
+                let value = HeaderValue::from_maybe_shared(slice.slice(idx.value.0..idx.value.1)).unwrap();
+
+                match name {
+                    TRANSFER_ENCODING => {
+                        let chunked = value
+                            .to_str()
+                            .map_err(|_| Parse::HeaderValue(TRANSFER_ENCODING))?
+                            .rsplit(',')
+                            .next()
+                            .map(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+                            .unwrap_or(false);
+
+                        // chunked must be the final coding. Otherwise body is read until close.
+                        if chunked {
+                            decoder
+                                .get_or_insert_with(TransferDecoding::eof)
+                                .reset(TransferDecoding::chunked())?;
+                        } else {
+                            decoder = Some(TransferDecoding::eof());
+                        }
+                    }
+                    CONTENT_LENGTH => {
+                        let len = value
+                            .to_str()
+                            .map_err(|_| Parse::HeaderValue(CONTENT_LENGTH))?
+                            .parse::<u64>()
+                            .map_err(|_| Parse::HeaderValue(CONTENT_LENGTH))?;
+
+                        decoder
+                            .get_or_insert_with(TransferDecoding::eof)
+                            .reset(TransferDecoding::length(len))?;
+                    }
+                    CONNECTION => {
+                        if let Ok(value) = value.to_str() {
+                            for value in value.split(',').map(str::trim) {
+                                if value.eq_ignore_ascii_case("keep-alive") {
+                                    keep_alive = true;
+                                } else if value.eq_ignore_ascii_case("close") {
+                                    keep_alive = false;
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                headers.append(name, value);
+            }
+
+            let decoder = match status {
+                StatusCode::SWITCHING_PROTOCOLS => {
+                    keep_alive = false;
+                    TransferDecoding::plain_chunked()
+                }
+                s if *method == Method::CONNECT && s.is_success() => {
+                    keep_alive = false;
+                    TransferDecoding::plain_chunked()
+                }
+                s if s.is_informational() || s == StatusCode::NO_CONTENT || s == StatusCode::NOT_MODIFIED => {
+                    TransferDecoding::length(0)
+                }
+                _ if *method == Method::HEAD => TransferDecoding::length(0),
+                _ => match decoder {
+                    Some(decoder) if decoder.is_eof() => {
+                        keep_alive = false;
+                        decoder
+                    }
+                    Some(decoder) => decoder,
+                    None => {
+                        keep_alive = false;
+                        TransferDecoding::eof()
+                    }
+                },
+            };
+
+            let mut res = Response::new(());
+
+            *res.status_mut() = status;
+            *res.version_mut() = version;
+            *res.headers_mut() = headers;
+
+            Ok(Some((res, decoder, keep_alive)))
+        }
+        Status::Partial => {
+            if buf.remaining() >= READ_BUF_LIMIT {
+                Err(ProtoError::Parse(Parse::HeaderTooLarge))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 struct HeaderIndex {
     name: (usize, usize),
@@ -210,11 +414,17 @@ impl TransferDecoding {
         matches!(self.kind, Kind::Eof)
     }
 
+    /// Check if body ends when connection is closed.
+    #[inline(always)]
+    pub fn is_until_close(&self) -> bool {
+        matches!(self.kind, Kind::Eof | Kind::PlainChunked)
+    }
+
     #[inline(always)]
     pub fn reset(&mut self, other: Self) -> Result<(), ProtoError> {
         match (&self.kind, &other.kind) {
             (Kind::DecodeChunked(..), Kind::Length(..)) | (Kind::Length(..), Kind::DecodeChunked(..)) => {
-                Err(ProtoError::Parse(Parse::Header))
+                Err(ProtoError::Parse(Parse::AmbiguousLength))
             }
             _ => {
                 *self = other;
@@ -232,7 +442,7 @@ pub enum RequestBodyItem {
 }
 
 impl TransferDecoding {
-    pub(super) fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<RequestBodyItem>> {
+    pub(super) fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RequestBodyItem>, ProtoError> {
         match self.kind {
             Kind::Length(ref mut remaining) => {
                 if *remaining == 0 {
@@ -260,7 +470,7 @@ impl TransferDecoding {
                     *state = match state.step(src, size, &mut buf) {
                         Poll::Pending => return Ok(None),
                         Poll::Ready(Ok(state)) => state,
-                        Poll::Ready(Err(e)) => return Err(e),
+                        Poll::Ready(Err(e)) => return Err(e.into()),
                     };
                     if *state == ChunkedState::End {
                         return Ok(Some(RequestBodyItem::Eof));
@@ -7,38 +7,59 @@ use std::{
     time::Duration,
 };
 
-use actix_server_alt::net::AsyncReadWrite;
 use actix_service_alt::Service;
 use bytes::{Buf, Bytes};
 use futures_core::Stream;
-use http::{response::Parts, Request, Response};
-use log::trace;
+use http::{response::Parts, Request, Response, StatusCode, Version};
+use log::{trace, warn};
 use pin_project::pin_project;
-use tokio::{io::Interest, pin, select};
+use tokio::{pin, select};
 
 use crate::body::ResponseBody;
 use crate::config::HttpServiceConfig;
+use crate::connection::{ConnectionAddrs, ConnectionData, OnConnect};
 use crate::error::BodyError;
-use crate::flow::HttpFlowInner;
+use crate::flow::{HttpFlowInner, Readiness};
 use crate::h1::{
     body::{RequestBody, RequestBodySender},
     error::Error,
 };
-use crate::response::{self, ResponseError};
-use crate::util::{date::Date, keep_alive::KeepAlive, poll_fn::poll_fn};
+use crate::protocol::{Protocol, RequestProtocol};
+use crate::response::{self, ErrorContext, ErrorFormatter, ResponseError};
+use crate::stats::{RequestStats, StatsRecorder};
+use crate::timeout::RequestTimer;
+use crate::util::{
+    catch_unwind::{CatchUnwind, Panic},
+    date::Date,
+    keep_alive::KeepAlive,
+    log_context::LogContext,
+    poll_fn::poll_fn,
+    trace::TraceSpan,
+};
 
 use super::buf::{ReadBuf, WriteBuf};
 use super::context::{ConnectionType, Context};
 use super::decode::{RequestBodyItem, TransferDecoding};
 use super::encode::TransferEncoding;
 use super::error::{Parse, ProtoError};
+use super::io::BufIo;
 
 /// Http/1 dispatcher
 pub(crate) struct Dispatcher<'a, St, S, ReqB, X, U, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> {
     io: Io<'a, St, READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
     timer: Pin<&'a mut KeepAlive>,
     ka_dur: Duration,
+    catch_panic: bool,
+    request_timeout: Option<Duration>,
+    request_timeout_status: StatusCode,
+    ready_dur: Duration,
+    error_formatter: Option<ErrorFormatter>,
     ctx: Context<'a>,
+    conn_data: ConnectionData,
+    log_ctx: LogContext,
+    span: TraceSpan,
+    // span of request being handled.
+    req_span: Option<TraceSpan>,
     flow: &'a HttpFlowInner<S, X, U>,
     _phantom: PhantomData<ReqB>,
 }
@@ -47,11 +68,12 @@ struct Io<'a, St, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> {
     io: &'a mut St,
     read_buf: ReadBuf<READ_BUF_LIMIT>,
     write_buf: WriteBuf<WRITE_BUF_LIMIT>,
+    stats: &'a StatsRecorder,
 }
 
 impl<St, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> Io<'_, St, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
 where
-    St: AsyncReadWrite,
+    St: BufIo,
 {
     /// read until blocked/read backpressure and advance readbuf.
     fn try_read(&mut self) -> Result<(), Error> {
@@ -63,9 +85,12 @@ where
             Ok(())
         } else {
             loop {
+                read_buf.reserve();
                 match self.io.try_read_buf(read_buf.buf_mut()) {
                     Ok(0) => return Err(Error::Closed),
-                    Ok(_) => {
+                    Ok(n) => {
+                        self.stats.read(n);
+                        read_buf.record(n);
                         read_buf.advance(true);
 
                         if read_buf.backpressure() {
@@ -84,48 +109,15 @@ where
     /// Return false when write is finished.(Did not blocked)
     fn try_write(&mut self) -> Result<bool, Error> {
         match self.write_buf {
-            WriteBuf::List(ref mut list) => {
-                let queue = list.list_mut();
-                while queue.remaining() > 0 {
-                    let mut iovs = [io::IoSlice::new(&[]); 64];
-                    let len = queue.chunks_vectored(&mut iovs);
-                    match self.io.try_write_vectored(&iovs[..len]) {
-                        Ok(0) => return Err(Error::Closed),
-                        Ok(n) => queue.advance(n),
-                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                            return Ok(true);
-                        }
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-            }
-            WriteBuf::Flat(ref mut buf) => {
-                let mut written = 0;
-                let len = buf.len();
-
-                while written < len {
-                    match self.io.try_write(&buf[written..]) {
-                        Ok(0) => return Err(Error::Closed),
-                        Ok(n) => written += n,
-                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                            buf.advance(written);
-                            return Ok(true);
-                        }
-                        Err(e) => return Err(e.into()),
-                    }
-                }
-
-                buf.clear();
-            }
+            WriteBuf::List(ref mut list) => try_write_buf(self.io, list.list_mut(), self.stats),
+            WriteBuf::Flat(ref mut buf) => try_write_buf(self.io, buf, self.stats),
         }
-
-        Ok(false)
     }
 
     /// Block task and read.
     #[inline(always)]
     async fn read(&mut self) -> Result<(), Error> {
-        let _ = self.io.ready(Interest::READABLE).await?;
+        poll_fn(|cx| self.io.poll_read_ready(cx)).await?;
         self.try_read()
     }
 
@@ -133,9 +125,9 @@ where
     #[inline(always)]
     async fn drain_write(&mut self) -> Result<(), Error> {
         while self.try_write()? {
-            let _ = self.io.ready(Interest::WRITABLE).await?;
+            poll_fn(|cx| self.io.poll_write_ready(cx)).await?;
         }
-        poll_fn(|cx| Pin::new(&mut *self.io).poll_flush(cx)).await?;
+        poll_fn(|cx| self.io.poll_flush(cx)).await?;
         Ok(())
     }
 
@@ -155,24 +147,26 @@ where
                     // TODO: read error here should be treated as partial close.
                     // Which means body_handle should treat error as finished read.
                     // pass the partial buffer to service call and let it decide what to do.
-                    'read: while self.io.poll_read_ready(cx)?.is_ready() {
-                        let _ = self.try_read()?;
-
-                        let buf = &mut self.read_buf;
-
-                        if buf.advanced() {
-                            while let Some(item) = handle.decoder.decode(buf.buf_mut())? {
-                                new = true;
-                                match item {
-                                    RequestBodyItem::Chunk(bytes) => handle.sender.feed_data(bytes),
-                                    RequestBodyItem::Eof => {
-                                        handle.sender.feed_eof();
-                                        done = true;
-                                        break 'read;
-                                    }
+                    'read: loop {
+                        // decode bytes already buffered before waiting for read. They could be
+                        // read along with request head or previous body chunks.
+                        while let Some(item) = handle.decoder.decode(self.read_buf.buf_mut())? {
+                            new = true;
+                            match item {
+                                RequestBodyItem::Chunk(bytes) => handle.sender.feed_data(bytes),
+                                RequestBodyItem::Eof => {
+                                    handle.sender.feed_eof();
+                                    done = true;
+                                    break 'read;
                                 }
                             }
                         }
+
+                        if !self.io.poll_read_ready(cx)?.is_ready() {
+                            break;
+                        }
+
+                        self.try_read()?;
                     }
 
                     // remove body handle when client sent eof chunk.
@@ -198,6 +192,23 @@ where
     }
 }
 
+/// Return true when write is blocked and need wait.
+fn try_write_buf<St, B>(io: &mut St, buf: &mut B, stats: &StatsRecorder) -> Result<bool, Error>
+where
+    St: BufIo,
+    B: Buf,
+{
+    loop {
+        match io.try_write_buf(buf) {
+            Ok(0) if buf.has_remaining() => return Err(Error::Closed),
+            Ok(0) => return Ok(false),
+            Ok(n) => stats.written(n),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
 impl<'a, St, S, ReqB, ResB, E, X, U, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
     Dispatcher<'a, St, S, ReqB, X, U, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
 where
@@ -212,7 +223,7 @@ where
     ResB: Stream<Item = Result<Bytes, E>>,
     BodyError: From<E>,
 
-    St: AsyncReadWrite,
+    St: BufIo + OnConnect,
 {
     pub(crate) fn new(
         io: &'a mut St,
@@ -220,7 +231,26 @@ where
         config: HttpServiceConfig<READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
         flow: &'a HttpFlowInner<S, X, U>,
         date: &'a Date,
+        addrs: Option<ConnectionAddrs>,
+        stats: &'a StatsRecorder,
     ) -> Self {
+        let mut conn_data = ConnectionData::from_io(&*io);
+        if let Some(addrs) = addrs {
+            conn_data.set_addrs(addrs);
+        }
+
+        let protocol = if conn_data.tls_info().is_some() {
+            Protocol::Http1Tls
+        } else {
+            Protocol::Http1
+        };
+
+        stats.set_id(conn_data.id());
+        stats.set_protocol(protocol);
+        let peer = addrs.and_then(|addrs| addrs.peer());
+        let log_ctx = LogContext::new(conn_data.id(), peer);
+        let span = TraceSpan::connection(conn_data.id(), peer, protocol);
+
         let is_vectored = if config.http1_pipeline {
             false
         } else {
@@ -231,13 +261,23 @@ where
             io,
             read_buf: ReadBuf::new(),
             write_buf: WriteBuf::new(is_vectored),
+            stats,
         };
 
         Self {
             io,
             timer,
             ka_dur: config.keep_alive_timeout,
+            catch_panic: config.catch_panic,
+            request_timeout: config.request_timeout,
+            request_timeout_status: config.request_timeout_status,
+            ready_dur: config.service_ready_timeout,
+            error_formatter: config.error_formatter,
             ctx: Context::new(date),
+            conn_data,
+            log_ctx,
+            span,
+            req_span: None,
             flow,
             _phantom: PhantomData,
         }
@@ -253,7 +293,10 @@ where
                     let (body_handle, body) = RequestBodyHandle::new_pair(decoder);
 
                     let (parts, _) = req.into_parts();
-                    let req = Request::from_parts(parts, body);
+                    let mut req = Request::from_parts(parts, body);
+                    self.conn_data.insert_into(req.extensions_mut());
+                    let protocol = RequestProtocol::http1(req.version(), self.conn_data.tls_info().is_some());
+                    req.extensions_mut().insert(protocol);
 
                     return Some(Ok((req, body_handle)));
                 }
@@ -266,21 +309,80 @@ where
     }
 
     fn encode_head(&mut self, parts: Parts, body: &ResponseBody<ResB>) -> Result<(), Error> {
+        // close connection after response when service is shutting down.
+        if self.flow.shutdown.is_shutdown() {
+            self.ctx.set_force_close();
+        }
+
+        self.io.stats.response(parts.status);
+        if let Some(ref span) = self.req_span {
+            span.record_status(parts.status);
+        }
         let size = body.size();
         self.ctx.encode_head(parts, size, &mut self.io.write_buf)?;
         Ok(())
     }
 
     pub(crate) async fn run(mut self) -> Result<(), Error> {
+        let span = self.span.clone();
+        let res = span.instrument(self.run_inner()).await;
+
+        // connection is closed in the middle of a request.
+        if let (Err(ref e), Some(span)) = (&res, self.req_span.take()) {
+            span.record_error(e);
+        }
+
+        match res {
+            Ok(_) | Err(Error::Closed) => {}
+            Err(ref e) => warn!("{}: {}", self.log_ctx, e),
+        }
+
+        res
+    }
+
+    async fn run_inner(&mut self) -> Result<(), Error> {
         loop {
-            'req: while let Some(res) = self.decode_head() {
+            while let Some(res) = self.decode_head() {
                 match res {
-                    Ok((req, mut body_handle)) => {
+                    Ok((mut req, mut body_handle)) => {
+                        let req_stats = self.io.stats.request();
+                        let span = self.span.request(&req);
+                        self.req_span = Some(span.clone());
+
                         // have new request. update timer deadline.
                         let now = self.ctx.date.get().now() + self.ka_dur;
                         self.timer.as_mut().update(now);
 
-                        let (parts, res_body) = self.request_handler(req, &mut body_handle).await?.into_parts();
+                        match self.flow.ready(self.timer.wheel(), self.ready_dur).await {
+                            Readiness::Ready => {}
+                            // reject request and close connection instead of queueing it.
+                            Readiness::Timeout => {
+                                self.encode_unavailable(&req)?;
+                                break;
+                            }
+                            Readiness::Failed => {
+                                self.encode_unavailable(&req)?;
+                                self.io.drain_write().await?;
+                                return Err(Error::ServiceReady);
+                            }
+                        }
+
+                        let request_timer =
+                            RequestTimer::new(self.timer.wheel(), self.request_timeout, req.extensions_mut());
+                        pin!(request_timer);
+
+                        let res = select! {
+                            biased;
+                            res = span.instrument(self.request_handler(req, &mut body_handle)) => res?,
+                            _ = request_timer.as_mut() => {
+                                // service call is dropped. respond and close connection.
+                                self.encode_request_timeout()?;
+                                self.io.drain_write().await?;
+                                return Err(Error::ServiceCallTimeout);
+                            }
+                        };
+
+                        let (parts, res_body) = res.into_parts();
 
                         self.encode_head(parts, &res_body)?;
 
@@ -294,36 +396,52 @@ where
                             // borrow every state so it can iter.
                             let handler = ResponseHandler {
                                 res_body: res_body.as_mut(),
+                                req_stats: &req_stats,
                                 encoder,
                                 body_handle: &mut body_handle,
                                 io: &mut self.io,
                                 ctx: &mut self.ctx,
                             };
 
-                            match handler.await? {
+                            let res = select! {
+                                biased;
+                                res = span.instrument(handler) => res?,
+                                // response is started. connection is closed without finishing it.
+                                _ = request_timer.as_mut() => return Err(Error::ServiceCallTimeout),
+                            };
+
+                            match res {
                                 ResponseHandlerResult::Ok => break 'res,
                                 // write buffer grows too big. drain it.
                                 ResponseHandlerResult::WriteBackpressure => {
                                     trace!("Write buffer limit reached. Enter backpressure.");
-                                    self.io.drain_write().await?;
+                                    select! {
+                                        biased;
+                                        res = self.io.drain_write() => res?,
+                                        _ = request_timer.as_mut() => return Err(Error::ServiceCallTimeout),
+                                    }
                                     trace!("Write buffer empty. Recover from backpressure.");
                                 }
                             }
                         }
+
+                        self.log_ctx.clear_request();
+                        self.req_span = None;
+
+                        // drop pipelined requests when shutting down.
+                        if self.flow.shutdown.is_shutdown() {
+                            break;
+                        }
                     }
-                    Err(ProtoError::Parse(Parse::HeaderTooLarge)) => {
-                        // Header is too large to be parsed.
+                    Err(ProtoError::Parse(e)) => {
+                        // Request head can not be decoded. Respond with status code of the failure.
                         // Close the connection after sending error response as it's pointless
                         // to read the remaining bytes inside connection.
-                        self.ctx.set_force_close();
-
-                        let (parts, res_body) = response::header_too_large().into_parts();
-
-                        self.encode_head(parts, &res_body)?;
+                        self.encode_canned(e.status_code(), Some(&e))?;
+                        self.io.drain_write().await?;
 
-                        break 'req;
+                        return Err(ProtoError::Parse(e).into());
                     }
-                    // TODO: handle error that are meant to be a response.
                     Err(e) => return Err(e.into()),
                 };
             }
@@ -333,52 +451,126 @@ where
             match self.ctx.ctype() {
                 ConnectionType::Init => {
                     if self.ctx.is_force_close() {
-                        trace!("Connection error. Shutting down");
+                        trace!("{}: Connection error. Shutting down", self.log_ctx);
                         return Ok(());
                     } else {
                         // use timer to detect slow connection.
                         select! {
                             biased;
                             res = self.io.read() => res?,
+                            _ = self.flow.shutdown.wait() => {
+                                trace!("{}: Service shutdown. Closing idle connection", self.log_ctx);
+                                return Ok(());
+                            }
                             _ = self.timer.as_mut() => {
-                                trace!("Slow Connection detected. Shutting down");
-                                return Ok(())
+                                trace!("{}: Slow Connection detected. Shutting down", self.log_ctx);
+                                return self.request_head_timeout().await;
                             }
                         }
                     }
                 }
                 ConnectionType::KeepAlive => {
                     if self.ctx.is_force_close() {
-                        trace!("Connection is keep-alive but meet a force close condition. Shutting down");
+                        trace!(
+                            "{}: Connection is keep-alive but meet a force close condition. Shutting down",
+                            self.log_ctx
+                        );
                         return Ok(());
                     } else {
                         select! {
                             biased;
                             res = self.io.read() => res?,
+                            _ = self.flow.shutdown.wait() => {
+                                trace!("{}: Service shutdown. Closing idle connection", self.log_ctx);
+                                return Ok(());
+                            }
                             _ = self.timer.as_mut() => {
-                                trace!("Connection keep-alive timeout. Shutting down");
+                                // part of next request head is received.
+                                if self.io.read_buf.len() > 0 {
+                                    trace!("{}: Slow Connection detected. Shutting down", self.log_ctx);
+                                    return self.request_head_timeout().await;
+                                }
+
+                                // idle connection expiring is the normal end of keep-alive.
+                                trace!("{}: Connection keep-alive timeout. Shutting down", self.log_ctx);
                                 return Ok(());
                             }
                         }
                     }
                 }
                 ConnectionType::Upgrade | ConnectionType::Close => {
-                    trace!("Connection not keep-alive. Shutting down");
+                    trace!("{}: Connection not keep-alive. Shutting down", self.log_ctx);
                     return Ok(());
                 }
             }
         }
     }
 
+    /// Respond with 408 when part of request head is received. Otherwise close silently.
+    async fn request_head_timeout(&mut self) -> Result<(), Error> {
+        if self.io.read_buf.len() > 0 {
+            self.encode_canned(StatusCode::REQUEST_TIMEOUT, None)?;
+            self.io.drain_write().await?;
+        }
+
+        Err(Error::RequestHeadTimeout)
+    }
+
+    /// Encode error response generated by dispatcher. Connection is closed afterwards.
+    fn encode_canned(&mut self, status: StatusCode, parse: Option<&Parse>) -> Result<(), Error> {
+        let mut ctx = ErrorContext::new(Version::HTTP_11);
+        if let Some(parse) = parse {
+            ctx = ctx.parse(parse);
+        }
+
+        let res = response::canned(self.error_formatter, status, ctx);
+        self.encode_canned_response(res)
+    }
+
+    /// Encode response of request timed out before response is started.
+    fn encode_request_timeout(&mut self) -> Result<(), Error> {
+        // request is always set before service call.
+        let (method, uri, version) = self.log_ctx.request().unwrap();
+        let ctx = ErrorContext::new(version).request(method, uri);
+        let res = response::canned(self.error_formatter, self.request_timeout_status, ctx);
+        self.encode_canned_response(res)
+    }
+
+    /// Encode 503 response of request rejected when service is not ready.
+    fn encode_unavailable(&mut self, req: &Request<ReqB>) -> Result<(), Error> {
+        let ctx = ErrorContext::new(req.version()).request(req.method(), req.uri());
+        let res = response::unavailable(self.error_formatter, self.ready_dur, ctx);
+        self.encode_canned_response(res)
+    }
+
+    fn encode_canned_response(&mut self, res: Response<ResponseBody<ResB>>) -> Result<(), Error> {
+        self.ctx.set_force_close();
+
+        let (parts, res_body) = res.into_parts();
+
+        self.encode_head(parts, &res_body)?;
+
+        let mut encoder = res_body.encoder(self.ctx.ctype());
+        if let ResponseBody::Bytes { bytes } = res_body {
+            encoder.encode(bytes, &mut self.io.write_buf)?;
+        }
+        encoder.encode_eof(&mut self.io.write_buf)?;
+
+        Ok(())
+    }
+
     async fn request_handler(
         &mut self,
         mut req: Request<ReqB>,
         body_handle: &mut Option<RequestBodyHandle>,
     ) -> Result<Response<ResponseBody<ResB>>, Error> {
+        self.log_ctx.set_request(&req);
+
         if self.ctx.is_expect_header() {
             match self.flow.expect.call(req).await {
                 Ok(expect_res) => {
                     // encode continue
+                    self.io.stats.response(StatusCode::CONTINUE);
                     self.ctx.encode_continue(&mut self.io.write_buf);
 
                     // use drain write to make sure continue is sent to client.
@@ -387,24 +579,54 @@ where
 
                     req = expect_res;
                 }
-                Err(ref mut e) => return Ok(ResponseError::response_error(e)),
+                Err(ref mut e) => {
+                    // continue is not sent and client does not send body. connection can be reused
+                    // for next request unless part of body is already read.
+                    *body_handle = None;
+                    if self.io.read_buf.len() > 0 {
+                        self.ctx.set_force_close();
+                    }
+                    return Ok(ResponseError::response_error(e));
+                }
             }
         };
 
-        RequestHandler {
-            fut: self.flow.service.call(req),
+        let res = RequestHandler {
+            fut: CatchUnwind::new(self.flow.service.call(req), self.catch_panic),
             body_handle,
             io: &mut self.io,
             ctx: &mut self.ctx,
         }
-        .await
+        .await?;
+
+        match res {
+            Ok(res) => Ok(res),
+            Err(panic) => {
+                panic.log(&self.log_ctx);
+                if let Some(ref span) = self.req_span {
+                    span.record_error(&"service panicked");
+                }
+
+                // service state is unknown after panic. close the connection after response.
+                self.ctx.set_force_close();
+
+                // request is always set before service call.
+                let (method, uri, version) = self.log_ctx.request().unwrap();
+                let ctx = ErrorContext::new(version).request(method, uri);
+                Ok(response::canned(
+                    self.error_formatter,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ctx,
+                ))
+            }
+        }
     }
 }
 
 #[pin_project]
 struct RequestHandler<'a, 'b, St, Fut, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> {
     #[pin]
-    fut: Fut,
+    fut: CatchUnwind<Fut>,
     body_handle: &'a mut Option<RequestBodyHandle>,
     io: &'a mut Io<'b, St, READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
     ctx: &'a mut Context<'b>,
@@ -416,9 +638,9 @@ where
     Fut: Future<Output = Result<Response<ResponseBody<ResB>>, E>>,
     E: ResponseError<Response<ResponseBody<ResB>>>,
 
-    St: AsyncReadWrite,
+    St: BufIo,
 {
-    type Output = Result<Response<ResponseBody<ResB>>, Error>;
+    type Output = Result<Result<Response<ResponseBody<ResB>>, Panic>, Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
         let mut this = self.project();
@@ -426,7 +648,7 @@ where
         loop {
             match this.fut.as_mut().poll(cx) {
                 Poll::Ready(res) => {
-                    let res = res.unwrap_or_else(|ref mut e| ResponseError::response_error(e));
+                    let res = res.map(|res| res.unwrap_or_else(|ref mut e| ResponseError::response_error(e)));
                     return Poll::Ready(Ok(res));
                 }
                 // service call is pending. could be waiting for more read.
@@ -442,6 +664,7 @@ where
 
 struct ResponseHandler<'a, 'b, St, ResB, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> {
     res_body: Pin<&'a mut ResponseBody<ResB>>,
+    req_stats: &'a RequestStats,
     encoder: &'a mut TransferEncoding,
     body_handle: &'a mut Option<RequestBodyHandle>,
     io: &'a mut Io<'b, St, READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
@@ -459,7 +682,7 @@ where
     ResB: Stream<Item = Result<Bytes, E>>,
     BodyError: From<E>,
 
-    St: AsyncReadWrite,
+    St: BufIo,
 {
     type Output = Result<ResponseHandlerResult, Error>;
 
@@ -470,6 +693,7 @@ where
             match this.res_body.as_mut().poll_next(cx) {
                 Poll::Ready(Some(bytes)) => {
                     let bytes = bytes?;
+                    this.req_stats.body(bytes.len());
                     this.encoder.encode(bytes, &mut this.io.write_buf)?;
                 }
                 Poll::Ready(None) => {
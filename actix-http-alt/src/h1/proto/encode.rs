@@ -2,9 +2,10 @@ use std::{cmp, io};
 
 use bytes::{BufMut, Bytes, BytesMut};
 use http::{
-    header::{CONNECTION, CONTENT_LENGTH, DATE, TRANSFER_ENCODING},
+    header::{CONNECTION, CONTENT_LENGTH, DATE, HOST, TRANSFER_ENCODING},
+    request,
     response::Parts,
-    StatusCode, Version,
+    Method, StatusCode, Version,
 };
 use log::{debug, warn};
 
@@ -59,7 +60,9 @@ impl Context<'_> {
 
         // decide if content-length or transfer-encoding header would be skipped.
         let mut skip_len = match (status, version) {
-            (StatusCode::SWITCHING_PROTOCOLS, _) => false,
+            // Sending content-length or transfer-encoding header on 1xx response is forbidden
+            // in RFC 7230.
+            (StatusCode::SWITCHING_PROTOCOLS, _) => true,
             // Sending content-length or transfer-encoding header on 2xx response
             // to CONNECT is forbidden in RFC 7231.
             (s, _) if self.is_connect_method() && s.is_success() => true,
@@ -95,7 +98,7 @@ impl Context<'_> {
                 }
                 CONNECTION if self.is_force_close() => continue,
                 CONNECTION => {
-                    for val in value.to_str().map_err(|_| Parse::HeaderValue)?.split(',') {
+                    for val in value.to_str().map_err(|_| Parse::HeaderValue(CONNECTION))?.split(',') {
                         let val = val.trim();
 
                         if val.eq_ignore_ascii_case("close") {
@@ -152,6 +155,128 @@ impl Context<'_> {
     }
 }
 
+/// Encode head of request sent by client connection and generate request body encoder.
+///
+/// Body is encoded according to content-length or chunked transfer-encoding header of request.
+/// Request with neither of them is sent without body. Upgrade and CONNECT requests send body as
+/// is after the head. The returned bool is false when connection can not be reused afterwards.
+pub(super) fn encode_request_head<const WRITE_BUF_LIMIT: usize>(
+    parts: &request::Parts,
+    buf: &mut WriteBuf<WRITE_BUF_LIMIT>,
+) -> Result<(TransferEncoding, bool), ProtoError> {
+    match *buf {
+        WriteBuf::List(ref mut list) => {
+            let buf = list.buf_mut();
+
+            let res = encode_request_head_inner(parts, buf)?;
+
+            let bytes = buf.split().freeze();
+            list.list_mut().push(EncodedBuf::Buf(bytes));
+
+            Ok(res)
+        }
+        WriteBuf::Flat(ref mut buf) => encode_request_head_inner(parts, buf),
+    }
+}
+
+fn encode_request_head_inner(
+    parts: &request::Parts,
+    buf: &mut BytesMut,
+) -> Result<(TransferEncoding, bool), ProtoError> {
+    let is_connect = parts.method == Method::CONNECT;
+
+    buf.put_slice(parts.method.as_str().as_bytes());
+    buf.put_slice(b" ");
+
+    if is_connect {
+        let authority = parts.uri.authority().ok_or(Parse::Uri)?;
+        buf.put_slice(authority.as_str().as_bytes());
+    } else {
+        let path = parts.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        buf.put_slice(path.as_bytes());
+    }
+
+    buf.put_slice(b" HTTP/1.1\r\n");
+
+    let mut encoder = None;
+    let mut keep_alive = true;
+    let mut upgrade = is_connect;
+    let mut skip_host = false;
+
+    for (name, value) in parts.headers.iter() {
+        match *name {
+            CONTENT_LENGTH => {
+                if encoder.is_some() {
+                    return Err(ProtoError::Parse(Parse::AmbiguousLength));
+                }
+
+                let len = value
+                    .to_str()
+                    .map_err(|_| Parse::HeaderValue(CONTENT_LENGTH))?
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| Parse::HeaderValue(CONTENT_LENGTH))?;
+
+                encoder = Some(TransferEncoding::length(len));
+            }
+            TRANSFER_ENCODING => {
+                if encoder.is_some() {
+                    return Err(ProtoError::Parse(Parse::AmbiguousLength));
+                }
+
+                let chunked = value
+                    .to_str()
+                    .map_err(|_| Parse::HeaderValue(TRANSFER_ENCODING))?
+                    .trim()
+                    .eq_ignore_ascii_case("chunked");
+
+                if !chunked {
+                    return Err(ProtoError::Parse(Parse::UnsupportedTransferEncoding));
+                }
+
+                encoder = Some(TransferEncoding::chunked());
+            }
+            CONNECTION => {
+                for val in value.to_str().map_err(|_| Parse::HeaderValue(CONNECTION))?.split(',') {
+                    let val = val.trim();
+
+                    if val.eq_ignore_ascii_case("close") {
+                        keep_alive = false;
+                    } else if val.eq_ignore_ascii_case("upgrade") {
+                        upgrade = true;
+                    }
+                }
+            }
+            HOST => skip_host = true,
+            _ => {}
+        }
+
+        buf.put_slice(name.as_str().as_bytes());
+        buf.put_slice(b": ");
+        buf.put_slice(value.as_bytes());
+        buf.put_slice(b"\r\n");
+    }
+
+    if !skip_host {
+        if let Some(authority) = parts.uri.authority() {
+            buf.put_slice(b"host: ");
+            buf.put_slice(authority.as_str().as_bytes());
+            buf.put_slice(b"\r\n");
+        }
+    }
+
+    buf.put_slice(b"\r\n");
+
+    // connection belongs to the upgraded protocol afterwards.
+    if upgrade {
+        return Ok((TransferEncoding::plain_chunked(), false));
+    }
+
+    let encoder = encoder.unwrap_or_else(|| TransferEncoding::length(0));
+
+    Ok((encoder, keep_alive))
+}
+
 fn encode_version_status_reason<B: BufMut>(buf: &mut B, version: Version, status: StatusCode) {
     // encode version, status code and reason
     match (version, status) {
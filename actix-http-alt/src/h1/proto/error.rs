@@ -1,25 +1,143 @@
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+};
+
+use http::{header::HeaderName, StatusCode};
+
+/// Http/1 protocol error.
 #[derive(Debug)]
 pub enum ProtoError {
-    // crate level parse error.
+    /// Malformed request or response.
     Parse(Parse),
-    // error from httparse crate.
-    HttpParse(httparse::Error),
-    // error from http crate.
+    /// Error from http crate.
     Http(http::Error),
 }
 
-/// Failure on parsing.
-#[derive(Debug)]
+/// Category of malformed message. Detailed enough to tell which part of a request is at fault.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Parse {
+    /// Request line is malformed. e.g. missing space or invalid token.
+    RequestLine,
+    /// Method in request line is invalid.
+    Method,
+    /// Uri in request line is invalid.
+    Uri,
+    /// Uri in request line is too long.
+    UriTooLong,
+    /// Http version in request line is invalid or not supported. Including HTTP/0.9 request
+    /// line without version.
+    Version,
+    /// Http/1.1 request without host header.
+    MissingHost,
+    /// Header name or value contains invalid bytes.
     Header,
+    /// Request has more headers than allowed.
+    TooManyHeaders,
+    /// Request head exceeds read buffer limit.
     HeaderTooLarge,
+    /// Value of given header is invalid.
+    HeaderValue(HeaderName),
+    /// Both content-length and chunked transfer-encoding are present.
+    AmbiguousLength,
+    /// Transfer-encoding lists a coding not known.
+    UnsupportedTransferEncoding,
+    /// Size line of chunked body is invalid.
+    ChunkSize,
+    /// Missing CRLF after chunk data or at the end of chunked body.
+    ChunkDelimiter,
+    /// Response carries a status code that can not be sent.
     StatusCode,
-    HeaderValue,
+    /// Status line of response received by client is malformed.
+    StatusLine,
+}
+
+impl Display for ProtoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Parse(ref e) => write!(f, "{}", e),
+            Self::Http(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ProtoError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::Parse(ref e) => Some(e),
+            Self::Http(ref e) => Some(e),
+        }
+    }
+}
+
+impl Display for Parse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg = match *self {
+            Self::RequestLine => "invalid request line",
+            Self::Method => "invalid method",
+            Self::Uri => "invalid uri",
+            Self::UriTooLong => "uri too long",
+            Self::Version => "invalid version",
+            Self::MissingHost => "missing host header",
+            Self::Header => "invalid header",
+            Self::TooManyHeaders => "too many headers",
+            Self::HeaderTooLarge => "header too large",
+            Self::HeaderValue(ref name) => return write!(f, "invalid {} header value", name),
+            Self::AmbiguousLength => "both content-length and chunked transfer-encoding present",
+            Self::UnsupportedTransferEncoding => "unsupported transfer-encoding",
+            Self::ChunkSize => "invalid chunk size",
+            Self::ChunkDelimiter => "invalid chunk delimiter",
+            Self::StatusCode => "invalid status code",
+            Self::StatusLine => "invalid status line",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl error::Error for Parse {}
+
+impl Parse {
+    /// Status code of error response for this failure.
+    ///
+    /// Dispatcher responds with it when request head can not be decoded and passes it to
+    /// [ErrorFormatter](crate::ErrorFormatter) when there is one.
+    pub fn status_code(&self) -> StatusCode {
+        match *self {
+            Self::UriTooLong => StatusCode::URI_TOO_LONG,
+            Self::HeaderTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::UnsupportedTransferEncoding => StatusCode::NOT_IMPLEMENTED,
+            Self::Version => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            Self::StatusCode => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::StatusLine => StatusCode::BAD_GATEWAY,
+            Self::RequestLine
+            | Self::Method
+            | Self::Uri
+            | Self::MissingHost
+            | Self::Header
+            | Self::TooManyHeaders
+            | Self::HeaderValue(_)
+            | Self::AmbiguousLength
+            | Self::ChunkSize
+            | Self::ChunkDelimiter => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl From<httparse::Error> for Parse {
+    fn from(e: httparse::Error) -> Self {
+        match e {
+            httparse::Error::HeaderName | httparse::Error::HeaderValue => Self::Header,
+            httparse::Error::TooManyHeaders => Self::TooManyHeaders,
+            httparse::Error::Version => Self::Version,
+            httparse::Error::Token | httparse::Error::NewLine | httparse::Error::Status => Self::RequestLine,
+        }
+    }
 }
 
 impl From<httparse::Error> for ProtoError {
     fn from(e: httparse::Error) -> Self {
-        Self::HttpParse(e)
+        Self::Parse(e.into())
     }
 }
 
@@ -30,14 +148,14 @@ impl From<http::Error> for ProtoError {
 }
 
 impl From<http::method::InvalidMethod> for ProtoError {
-    fn from(e: http::method::InvalidMethod) -> Self {
-        Self::Http(e.into())
+    fn from(_: http::method::InvalidMethod) -> Self {
+        Self::Parse(Parse::Method)
     }
 }
 
 impl From<http::uri::InvalidUri> for ProtoError {
-    fn from(e: http::uri::InvalidUri) -> Self {
-        Self::Http(e.into())
+    fn from(_: http::uri::InvalidUri) -> Self {
+        Self::Parse(Parse::Uri)
     }
 }
 
@@ -46,3 +164,27 @@ impl From<Parse> for ProtoError {
         Self::Parse(e)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status_code() {
+        assert_eq!(Parse::UriTooLong.status_code(), StatusCode::URI_TOO_LONG);
+        assert_eq!(
+            Parse::HeaderTooLarge.status_code(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+        assert_eq!(
+            Parse::UnsupportedTransferEncoding.status_code(),
+            StatusCode::NOT_IMPLEMENTED
+        );
+        assert_eq!(Parse::Version.status_code(), StatusCode::HTTP_VERSION_NOT_SUPPORTED);
+        assert_eq!(Parse::MissingHost.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            ProtoError::from(httparse::Error::Version).to_string(),
+            "invalid version"
+        );
+    }
+}
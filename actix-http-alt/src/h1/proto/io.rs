@@ -0,0 +1,77 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use actix_server_alt::net::AsyncReadWrite;
+use bytes::{Buf, BytesMut};
+
+/// Io type of Http/1 dispatcher.
+///
+/// Reads go into and writes come from dispatcher's own buffers so a completion based io can take
+/// them for the duration of an operation instead of copying.
+pub(crate) trait BufIo: Unpin {
+    /// Read into `buf` and return number of bytes read. `Ok(0)` means io is closed.
+    fn try_read_buf(&mut self, buf: &mut BytesMut) -> io::Result<usize>;
+
+    /// Take bytes from `buf` for write and return number of bytes written.
+    ///
+    /// Bytes taken by a write that is not finished yet are counted by a later call. `Ok(0)` is
+    /// returned when `buf` is empty and no write is pending.
+    fn try_write_buf<B: Buf>(&mut self, buf: &mut B) -> io::Result<usize>;
+
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    fn is_write_vectored(&self) -> bool;
+}
+
+impl<St> BufIo for St
+where
+    St: AsyncReadWrite,
+{
+    #[inline]
+    fn try_read_buf(&mut self, buf: &mut BytesMut) -> io::Result<usize> {
+        AsyncReadWrite::try_read_buf(self, buf)
+    }
+
+    fn try_write_buf<B: Buf>(&mut self, buf: &mut B) -> io::Result<usize> {
+        let n = match buf.remaining() {
+            0 => return Ok(0),
+            n if n == buf.chunk().len() => self.try_write(buf.chunk())?,
+            _ => {
+                let mut iovs = [io::IoSlice::new(&[]); 64];
+                let len = buf.chunks_vectored(&mut iovs);
+                self.try_write_vectored(&iovs[..len])?
+            }
+        };
+
+        buf.advance(n);
+
+        Ok(n)
+    }
+
+    #[inline]
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncReadWrite::poll_read_ready(self, cx)
+    }
+
+    #[inline]
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncReadWrite::poll_write_ready(self, cx)
+    }
+
+    #[inline]
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_flush(Pin::new(self), cx)
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        tokio::io::AsyncWrite::is_write_vectored(self)
+    }
+}
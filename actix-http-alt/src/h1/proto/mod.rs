@@ -2,12 +2,18 @@
 //! aiming to be correct and fast with only safe code.
 
 mod buf;
+mod client;
 mod codec;
 mod context;
 mod decode;
 mod dispatcher;
 mod encode;
 mod error;
+mod io;
 
+pub(crate) use client::read_response;
+pub use client::{ClientBody, ClientConnection, ClientError};
 pub(crate) use dispatcher::Dispatcher;
-pub(crate) use error::ProtoError;
+pub use error::{Parse, ProtoError};
+#[cfg(feature = "io-uring")]
+pub(crate) use io::BufIo;
@@ -8,12 +8,17 @@ use actix_service_alt::Service;
 use bytes::Bytes;
 use futures_core::{ready, Stream};
 use http::{Request, Response};
-use tokio::{pin, select};
+use tokio::pin;
 
 use crate::body::ResponseBody;
-use crate::error::{BodyError, HttpServiceError, TimeoutError};
+use crate::connection::{ConnectionAddrs, OnConnect};
+use crate::error::{BodyError, HttpServiceError};
 use crate::response::ResponseError;
 use crate::service::HttpService;
+use crate::tls::{
+    handshake::{self, accept_timeout},
+    HandshakeReason,
+};
 use crate::util::keep_alive::KeepAlive;
 
 use super::body::RequestBody;
@@ -42,8 +47,8 @@ where
     E: 'static,
     BodyError: From<E>,
 
-    St: AsyncReadWrite,
-    TlsSt: AsyncReadWrite,
+    St: AsyncReadWrite + OnConnect,
+    TlsSt: AsyncReadWrite + OnConnect,
 {
     type Response = ();
     type Error = HttpServiceError;
@@ -73,31 +78,353 @@ where
 
     fn call(&self, io: St) -> Self::Future<'_> {
         async move {
-            // tls accept timer.
-            let accept_dur = self.config.tls_accept_timeout;
-            let deadline = self.date.get().get().now() + accept_dur;
-            let timer = KeepAlive::new(deadline);
-            pin!(timer);
-
-            select! {
-                biased;
-                res = self.tls_acceptor.call(io) => {
-                    let mut io = res?;
-
-                    // update timer to first request duration.
-                    let request_dur = self.config.first_request_timeout;
-                    let deadline = self.date.get().get().now() + request_dur;
-                    timer.as_mut().update(deadline);
-
-                    let dispatcher = Dispatcher::new(&mut io, timer.as_mut(), self.config, &*self.flow, self.date.get());
-
-                    match dispatcher.run().await {
-                        Ok(_) | Err(Error::Closed) => Ok(()),
-                        Err(e) => Err(e.into()),
+            let stats = self.stats();
+            let addrs = ConnectionAddrs::from_io(&io);
+            let peer = addrs.and_then(|addrs| addrs.peer());
+
+            let fut = async {
+                // tls accept timer.
+                let accept_dur = self.config.tls_accept_timeout;
+                let deadline = self.date.get().get().now() + accept_dur;
+                let timer = KeepAlive::new(self.date.wheel(), deadline);
+                pin!(timer);
+
+                match accept_timeout(self.tls_acceptor.call(io), timer.as_mut()).await {
+                    Some(res) => {
+                        let mut io = match res {
+                            Ok(io) => io,
+                            Err(e) => {
+                                let e = HttpServiceError::from(e).with_peer(peer);
+                                if let HttpServiceError::HandshakeFailed(peer, ref err) = e {
+                                    handshake::log(peer, err.reason(), err);
+                                }
+                                return Err(e);
+                            }
+                        };
+
+                        // update timer to first request duration.
+                        let request_dur = self.config.first_request_timeout;
+                        let deadline = self.date.get().get().now() + request_dur;
+                        timer.as_mut().update(deadline);
+
+                        let dispatcher = Dispatcher::new(
+                            &mut io,
+                            timer.as_mut(),
+                            self.config,
+                            &*self.flow,
+                            self.date.get(),
+                            addrs,
+                            &stats,
+                        );
+
+                        match dispatcher.run().await {
+                            Ok(_) | Err(Error::Closed) => Ok(()),
+                            Err(e) => Err(e.into()),
+                        }
+                    }
+                    None => {
+                        handshake::log(peer, HandshakeReason::Timeout, &"timed out");
+                        Err(HttpServiceError::handshake_timeout(peer))
                     }
                 }
-                _ = timer.as_mut() => Err(HttpServiceError::Timeout(TimeoutError::TlsAccept)),
-            }
+            };
+
+            let res = self.drain(fut).await;
+
+            self.report_stats(&stats, &res);
+
+            res
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use actix_service_alt::{fn_service, ServiceFactory};
+    use http::{Method, StatusCode};
+    use tokio::task::LocalSet;
+
+    use crate::builder::HttpServiceBuilder;
+    use crate::util::{
+        testing::{duplex, serve, DuplexConfig, H1Client, TestStream},
+        ManualClock,
+    };
+
+    async fn handler(req: Request<RequestBody>) -> Result<Response<ResponseBody<RequestBody>>, Infallible> {
+        match req.uri().path() {
+            "/echo" => Ok(Response::new(ResponseBody::stream(req.into_body()))),
+            path => Ok(Response::new(Bytes::copy_from_slice(path.as_bytes()).into())),
+        }
+    }
+
+    async fn service() -> impl Service<TestStream, Response = (), Error = HttpServiceError> {
+        let builder = HttpServiceBuilder::h1(fn_service(handler));
+        ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn keep_alive() {
+        LocalSet::new()
+            .run_until(async {
+                let service = service().await;
+
+                // every read returns one byte.
+                let (client, io) = DuplexConfig::new().chunk(1).pair();
+
+                let (_, res) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+
+                    for path in ["/a", "/b"].iter() {
+                        let req = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+                        client.send(req).await.unwrap();
+                        let res = client.response().await.unwrap();
+                        assert_eq!(res.status(), StatusCode::OK);
+                        assert_eq!(res.headers()["content-length"], "2");
+                        assert_eq!(res.body(), path);
+                    }
+
+                    client
+                        .send("HEAD /c HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let res = client.response_to(&Method::HEAD).await.unwrap();
+                    assert_eq!(res.headers()["content-length"], "2");
+                    assert!(res.body().is_empty());
+                })
+                .await;
+
+                // connection is closed by client.
+                assert!(res.is_ok());
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn pipelining() {
+        LocalSet::new()
+            .run_until(async {
+                let service = service().await;
+
+                let (client, io) = duplex();
+
+                let (res, _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+
+                    client
+                        .send(
+                            "GET /a HTTP/1.1\r\nHost: localhost\r\n\r\n\
+                             POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello\
+                             GET /c HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                        )
+                        .await
+                        .unwrap();
+
+                    let mut bodies = Vec::new();
+                    for _ in 0..3 {
+                        bodies.push(client.response().await.unwrap().into_body());
+                    }
+
+                    // server closes connection after last request.
+                    assert!(client.read_to_end().await.unwrap().is_empty());
+
+                    bodies
+                })
+                .await;
+
+                assert_eq!(res, ["/a", "hello", "/c"]);
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn chunked() {
+        LocalSet::new()
+            .run_until(async {
+                let service = service().await;
+
+                let (client, io) = DuplexConfig::new().chunk(3).pair();
+
+                let (_, res) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+
+                    client
+                        .send(
+                            "POST /echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+                             5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n",
+                        )
+                        .await
+                        .unwrap();
+
+                    // response body of unknown size is chunked.
+                    let res = client.response().await.unwrap();
+                    assert_eq!(res.headers()["transfer-encoding"], "chunked");
+                    assert_eq!(res.body(), "hello world");
+
+                    // malformed chunk size.
+                    client
+                        .send("POST /echo HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nx\r\n")
+                        .await
+                        .unwrap();
+                    client.read_to_end().await.unwrap()
+                })
+                .await;
+
+                assert!(res.is_err());
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn transfer_codings() {
+        LocalSet::new()
+            .run_until(async {
+                let service = service().await;
+
+                for (head, status) in [
+                    (
+                        "POST /echo HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n",
+                        StatusCode::OK,
+                    ),
+                    (
+                        "POST /echo HTTP/1.1\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n",
+                        StatusCode::OK,
+                    ),
+                    (
+                        "POST /echo HTTP/1.1\r\nTransfer-Encoding: br, chunked\r\n",
+                        StatusCode::NOT_IMPLEMENTED,
+                    ),
+                    (
+                        "POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n",
+                        StatusCode::BAD_REQUEST,
+                    ),
+                    (
+                        "POST /echo HTTP/1.1\r\nTransfer-Encoding: gzip\r\n",
+                        StatusCode::BAD_REQUEST,
+                    ),
+                    ("GET /\r\n", StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+                ] {
+                    let (client, io) = duplex();
+
+                    let (res, _) = serve(&service, io, async move {
+                        let mut client = H1Client::new(client);
+                        client
+                            .send(format!("{}Host: localhost\r\n\r\n5\r\nhello\r\n0\r\n\r\n", head))
+                            .await
+                            .unwrap();
+                        client.response().await.unwrap()
+                    })
+                    .await;
+
+                    assert_eq!(res.status(), status, "{}", head);
+                    if status == StatusCode::OK {
+                        // only chunked is decoded. other codings are left to service.
+                        assert_eq!(res.body(), "hello");
+                    }
+                }
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn date() {
+        LocalSet::new()
+            .run_until(async {
+                let clock = ManualClock::with_system_time(UNIX_EPOCH);
+                let builder = HttpServiceBuilder::h1(fn_service(handler)).clock(clock.clone());
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+
+                let (_, res) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+
+                    client.send("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+                    let res = client.response().await.unwrap();
+                    assert_eq!(res.headers()["date"], "Thu, 01 Jan 1970 00:00:00 GMT");
+
+                    // cached date is refreshed right away.
+                    clock.advance(Duration::from_secs(1));
+                    tokio::task::yield_now().await;
+
+                    client.send("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+                    let res = client.response().await.unwrap();
+                    assert_eq!(res.headers()["date"], "Thu, 01 Jan 1970 00:00:01 GMT");
+                })
+                .await;
+
+                assert!(res.is_ok());
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn keep_alive_expire() {
+        LocalSet::new()
+            .run_until(async {
+                let clock = ManualClock::new();
+                let builder = HttpServiceBuilder::h1(fn_service(handler)).clock(clock.clone());
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+
+                let (_, res) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+
+                    client.send("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+                    assert_eq!(client.response().await.unwrap().status(), StatusCode::OK);
+
+                    // idle connection is closed once clock passes keep alive timeout.
+                    clock.advance(Duration::from_secs(6));
+                    assert!(client.read_to_end().await.unwrap().is_empty());
+                })
+                .await;
+
+                assert!(res.is_ok());
+            })
+            .await
+    }
+
+    #[cfg(feature = "rustls")]
+    #[tokio::test]
+    async fn handshake_error_peer() {
+        use std::sync::Arc;
+
+        use tokio::{
+            io::AsyncWriteExt,
+            net::{TcpListener, TcpStream},
+        };
+        use tokio_rustls::rustls::{NoClientAuth, ServerConfig};
+
+        LocalSet::new()
+            .run_until(async {
+                let config = Arc::new(ServerConfig::new(NoClientAuth::new()));
+                let builder = HttpServiceBuilder::h1(fn_service(handler)).rustls(config);
+                let service = ServiceFactory::<TcpStream>::new_service(&builder, ()).await.unwrap();
+
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+                let (io, _) = listener.accept().await.unwrap();
+
+                // plain text request is not a valid client hello.
+                client
+                    .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .await
+                    .unwrap();
+
+                match service.call(io).await {
+                    Err(HttpServiceError::HandshakeFailed(peer, _)) => {
+                        assert_eq!(peer, Some(client.local_addr().unwrap()))
+                    }
+                    _ => panic!("handshake must fail"),
+                }
+            })
+            .await
+    }
+}
@@ -12,6 +12,13 @@ use crate::error::BodyError;
 /// Request body type for Http/2 specifically.
 pub struct RequestBody(RecvStream);
 
+impl RequestBody {
+    /// Check if peer has ended the stream without sending more data.
+    pub(crate) fn is_end_stream(&self) -> bool {
+        self.0.is_end_stream()
+    }
+}
+
 impl Stream for RequestBody {
     type Item = Result<Bytes, BodyError>;
 
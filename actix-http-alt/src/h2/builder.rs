@@ -8,6 +8,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::body::ResponseBody;
 use crate::builder::HttpServiceBuilder;
+use crate::connection::OnConnect;
 use crate::error::{BodyError, HttpServiceError};
 use crate::response::ResponseError;
 
@@ -23,46 +24,64 @@ impl<F, FE, FU, FA, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>
     HttpServiceBuilder<F, RequestBody, FE, FU, FA, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
 {
     #[cfg(feature = "openssl")]
-    pub fn openssl(
+    pub fn openssl<T>(
         self,
-        acceptor: crate::tls::openssl::TlsAcceptor,
-    ) -> H2ServiceBuilder<F, FE, FU, crate::tls::openssl::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT> {
+        acceptor: T,
+    ) -> H2ServiceBuilder<F, FE, FU, crate::tls::openssl::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+    where
+        T: Into<crate::tls::openssl::TlsAcceptorService>,
+    {
         H2ServiceBuilder {
             factory: self.factory,
             expect: self.expect,
             upgrade: self.upgrade,
-            tls_factory: crate::tls::openssl::TlsAcceptorService::new(acceptor),
+            tls_factory: acceptor.into(),
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: std::marker::PhantomData,
         }
     }
 
     #[cfg(feature = "rustls")]
-    pub fn rustls(
+    pub fn rustls<T>(
         self,
-        config: crate::tls::rustls::RustlsConfig,
-    ) -> H2ServiceBuilder<F, FE, FU, crate::tls::rustls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT> {
+        acceptor: T,
+    ) -> H2ServiceBuilder<F, FE, FU, crate::tls::rustls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+    where
+        T: Into<crate::tls::rustls::TlsAcceptorService>,
+    {
         H2ServiceBuilder {
             factory: self.factory,
             expect: self.expect,
             upgrade: self.upgrade,
-            tls_factory: crate::tls::rustls::TlsAcceptorService::new(config),
+            tls_factory: acceptor.into(),
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: std::marker::PhantomData,
         }
     }
 
     #[cfg(feature = "native-tls")]
-    pub fn native_tls(
+    pub fn native_tls<T>(
         self,
-        acceptor: crate::tls::native_tls::TlsAcceptor,
-    ) -> H2ServiceBuilder<F, FE, FU, crate::tls::native_tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT> {
+        acceptor: T,
+    ) -> H2ServiceBuilder<F, FE, FU, crate::tls::native_tls::TlsAcceptorService, READ_BUF_LIMIT, WRITE_BUF_LIMIT>
+    where
+        T: Into<crate::tls::native_tls::TlsAcceptorService>,
+    {
         H2ServiceBuilder {
             factory: self.factory,
             expect: self.expect,
             upgrade: self.upgrade,
-            tls_factory: crate::tls::native_tls::TlsAcceptorService::new(acceptor),
+            tls_factory: acceptor.into(),
             config: self.config,
+            on_close: self.on_close,
+            shutdown: self.shutdown,
+            clock: self.clock,
             _body: std::marker::PhantomData,
         }
     }
@@ -86,8 +105,8 @@ where
     E: 'static,
     BodyError: From<E>,
 
-    St: AsyncRead + AsyncWrite + Unpin,
-    TlsSt: AsyncRead + AsyncWrite + Unpin,
+    St: AsyncRead + AsyncWrite + Unpin + OnConnect,
+    TlsSt: AsyncRead + AsyncWrite + Unpin + OnConnect,
 {
     type Response = ();
     type Error = HttpServiceError;
@@ -100,12 +119,17 @@ where
         let service = self.factory.new_service(cfg);
         let tls_acceptor = self.tls_factory.new_service(());
         let config = self.config;
+        let on_close = self.on_close.clone();
+        let shutdown = self.shutdown.clone().unwrap_or_default();
+        let clock = self.clock.clone();
 
         async move {
             let service = service.await?;
             let tls_acceptor = tls_acceptor.await?;
 
-            Ok(H2Service::new(config, service, (), None, tls_acceptor))
+            let service = H2Service::with_shutdown_handle(config, service, (), None, tls_acceptor, shutdown);
+
+            Ok(service.on_close(on_close).clock(clock))
         }
     }
 }
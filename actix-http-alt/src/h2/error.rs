@@ -1,3 +1,8 @@
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+};
+
 use crate::error::{BodyError, HttpServiceError};
 
 #[derive(Debug)]
@@ -5,6 +10,28 @@ pub enum Error {
     // error from h2 crate.
     H2(::h2::Error),
     Body(BodyError),
+    /// Ping pong is not answered within keep alive timeout.
+    KeepAliveExpired,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::H2(ref e) => write!(f, "{}", e),
+            Self::Body(ref e) => write!(f, "{}", e),
+            Self::KeepAliveExpired => f.write_str("Keep alive is timed out"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::H2(ref e) => Some(e),
+            Self::Body(ref e) => Some(e),
+            Self::KeepAliveExpired => None,
+        }
+    }
 }
 
 impl From<::h2::Error> for Error {
@@ -21,7 +48,10 @@ impl From<BodyError> for Error {
 
 impl From<Error> for HttpServiceError {
     fn from(e: Error) -> Self {
-        Self::H2(e)
+        match e {
+            Error::KeepAliveExpired => Self::KeepAliveExpired,
+            e => Self::H2(e),
+        }
     }
 }
 
@@ -3,6 +3,7 @@ use std::{
     future::Future,
     marker::PhantomData,
     pin::Pin,
+    rc::Rc,
     task::{Context, Poll},
     time::Duration,
 };
@@ -14,27 +15,46 @@ use ::h2::{
 use actix_service_alt::Service;
 use bytes::Bytes;
 use futures_core::{ready, Stream};
-use http::{header::CONTENT_LENGTH, HeaderValue, Request, Response, Version};
-use log::trace;
+use http::{header::CONTENT_LENGTH, HeaderValue, Request, Response, StatusCode, Version};
+use log::{trace, warn};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     pin, select,
+    sync::Notify,
 };
 
 use crate::body::{ResponseBody, ResponseBodySize};
-use crate::error::{BodyError, HttpServiceError};
-use crate::flow::HttpFlow;
+use crate::config::HttpServiceConfig;
+use crate::connection::ConnectionData;
+use crate::error::{BodyError, ErrorKind, HttpServiceError};
+use crate::flow::{HttpFlow, Readiness};
 use crate::h2::{body::RequestBody, error::Error};
-use crate::response::ResponseError;
-use crate::util::{date::Date, keep_alive::KeepAlive, poll_fn::poll_fn};
+use crate::protocol::{Protocol, RequestProtocol};
+use crate::response::{self, ErrorContext, ErrorFormatter, ResponseError};
+use crate::stats::{RequestStats, StatsRecorder};
+use crate::timeout::RequestTimer;
+use crate::util::{
+    budget::Budget, catch_unwind::CatchUnwind, date::Date, keep_alive::KeepAlive, log_context::LogContext,
+    poll_fn::poll_fn, trace::TraceSpan,
+};
 
 /// Http/2 dispatcher
 pub(crate) struct Dispatcher<'a, TlsSt, S, ReqB, X, U> {
     io: &'a mut Connection<TlsSt, Bytes>,
     keep_alive: Pin<&'a mut KeepAlive>,
     ka_dur: Duration,
+    catch_panic: bool,
+    request_timeout: Option<Duration>,
+    request_timeout_status: StatusCode,
+    ready_dur: Duration,
+    error_formatter: Option<ErrorFormatter>,
+    conn_data: ConnectionData,
+    log_ctx: LogContext,
+    span: TraceSpan,
     flow: &'a HttpFlow<S, X, U>,
     date: &'a Date,
+    stats: StatsRecorder,
+    budget: Budget,
     _req_body: PhantomData<ReqB>,
 }
 
@@ -54,35 +74,86 @@ where
     TlsSt: AsyncRead + AsyncWrite + Unpin,
     ReqB: From<RequestBody> + 'static,
 {
-    pub(crate) fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new<const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>(
         io: &'a mut Connection<TlsSt, Bytes>,
         keep_alive: Pin<&'a mut KeepAlive>,
-        ka_dur: Duration,
+        config: HttpServiceConfig<READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
+        conn_data: ConnectionData,
         flow: &'a HttpFlow<S, X, U>,
         date: &'a Date,
+        stats: &StatsRecorder,
+        budget: &Budget,
     ) -> Self {
+        stats.set_id(conn_data.id());
+        stats.set_protocol(Protocol::Http2);
+
+        let peer = conn_data.addrs().and_then(|addrs| addrs.peer());
+        let log_ctx = LogContext::new(conn_data.id(), peer);
+        let span = TraceSpan::connection(conn_data.id(), peer, Protocol::Http2);
+
         Self {
             io,
             keep_alive,
-            ka_dur,
+            ka_dur: config.keep_alive_timeout,
+            catch_panic: config.catch_panic,
+            request_timeout: config.request_timeout,
+            request_timeout_status: config.request_timeout_status,
+            ready_dur: config.service_ready_timeout,
+            error_formatter: config.error_formatter,
+            conn_data,
+            log_ctx,
+            span,
             flow,
             date,
+            stats: stats.clone(),
+            budget: budget.clone(),
             _req_body: PhantomData,
         }
     }
 
     pub(crate) async fn run(self) -> Result<(), Error> {
+        let log_ctx = self.log_ctx.clone();
+        let budget = self.budget.clone();
+        let span = self.span.clone();
+
+        let res = budget.run(span.instrument(self.run_inner())).await;
+
+        match res {
+            Ok(_) | Err(Error::KeepAliveExpired) => {}
+            Err(ref e) => warn!("{}: {}", log_ctx, e),
+        }
+
+        res
+    }
+
+    async fn run_inner(self) -> Result<(), Error> {
         let Self {
             io,
             mut keep_alive,
             ka_dur,
+            catch_panic,
+            request_timeout,
+            request_timeout_status,
+            ready_dur,
+            error_formatter,
+            conn_data,
+            log_ctx,
+            span,
             flow,
             date,
+            stats,
+            budget,
             ..
         } = self;
 
         let ping_pong = io.ping_pong().unwrap();
 
+        let protocol = RequestProtocol::http2(conn_data.tls_info().is_some());
+
+        // request and readiness timers of streams share the wheel of keep alive timer.
+        let wheel = keep_alive.wheel().clone();
+
         // reset timer to keep alive.
         let deadline = date.get().now() + ka_dur;
         keep_alive.as_mut().update(deadline);
@@ -96,38 +167,155 @@ where
             ka_dur,
         };
 
+        // stop accepting new streams and let in flight ones finish when service is shutting down
+        // or failed readiness check.
+        let mut draining = false;
+        let ready_failed = Rc::new(Notify::new());
+
+        // wait for service readiness before accepting next stream. streams arriving meanwhile are
+        // queued by h2 up to max concurrent streams.
+        let ready = flow.ready::<Request<ReqB>>(&wheel, ready_dur);
+        pin!(ready);
+        let mut readiness = None;
+
         loop {
+            // readiness is checked again while waiting for next stream after it's timed out.
+            let check_ready = matches!(readiness, None | Some(Readiness::Timeout));
+            let accept = readiness.is_some();
+
             select! {
-                opt = io.accept() => match opt {
+                _ = flow.shutdown.wait(), if !draining => {
+                    trace!("{}: Service shutdown. Sending GOAWAY", log_ctx);
+                    draining = true;
+                    io.graceful_shutdown();
+                }
+                _ = ready_failed.notified(), if !draining => {
+                    trace!("{}: Service is not ready. Sending GOAWAY", log_ctx);
+                    draining = true;
+                    io.graceful_shutdown();
+                }
+                res = &mut ready, if check_ready => {
+                    if let Readiness::Timeout = res {
+                        ready.set(flow.ready(&wheel, ready_dur));
+                    }
+                    readiness = Some(res);
+                }
+                opt = poll_fn(|cx| match accept {
+                    true => io.poll_accept(cx),
+                    // connection is driven without accepting new stream when service is not ready.
+                    false => io.poll_closed(cx).map(|res| res.err().map(Err)),
+                }) => match opt {
                     Some(res) => {
                         let (req, tx) = res?;
+                        let req_stats = stats.request();
                         // Convert http::Request body type to crate::h2::Body
                         // and reconstruct as HttpRequest.
                         let (parts, body) = req.into_parts();
                         let body = ReqB::from(RequestBody::from(body));
-                        let req = Request::from_parts(parts, body);
+                        let mut req = Request::from_parts(parts, body);
+                        conn_data.insert_into(req.extensions_mut());
+                        req.extensions_mut().insert(protocol);
+
+                        let log_ctx = log_ctx.with_request(&req);
+                        let req_span = span.request(&req);
+
+                        // stream is only accepted after readiness check.
+                        let readiness = readiness.take().unwrap();
+                        ready.set(flow.ready(&wheel, ready_dur));
+
+                        if !matches!(readiness, Readiness::Ready) {
+                            // reject stream instead of queueing it.
+                            if let Readiness::Failed = readiness {
+                                warn!("{}: {}", log_ctx, HttpServiceError::ServiceReady);
+                                req_span.record_error(&HttpServiceError::ServiceReady);
+                                req_stats.error(ErrorKind::ServiceReady);
+                                ready_failed.notify_one();
+                            }
 
-                        let flow = HttpFlow::clone(flow);
+                            let (method, uri, _) = log_ctx.request().unwrap();
+                            let ctx = ErrorContext::new(Version::HTTP_2).request(method, uri);
+                            let res = Ok::<_, S::Error>(response::unavailable(error_formatter, ready_dur, ctx));
 
-                        tokio::task::spawn_local(async move {
-                            let fut = flow.service.call(req);
-                            if let Err(e) = h2_handler(fut, tx).await {
-                                HttpServiceError::from(e).log();
+                            tokio::task::spawn_local(req_span.clone().instrument(async move {
+                                if let Err(e) = h2_handler(res, tx, &req_stats, &req_span).await {
+                                    warn!("{}: {}", log_ctx, e);
+                                    req_span.record_error(&e);
+                                }
+                            }));
+
+                            continue;
+                        }
+
+                        let flow = HttpFlow::clone(flow);
+                        let ready_failed = ready_failed.clone();
+                        let wheel = wheel.clone();
+
+                        tokio::task::spawn_local(req_span.clone().instrument(async move {
+                            let request_timer = RequestTimer::new(&wheel, request_timeout, req.extensions_mut());
+                            pin!(request_timer);
+
+                            let fut = CatchUnwind::new(flow.service.call(req), catch_panic);
+                            let res = select! {
+                                biased;
+                                res = fut => res,
+                                // service call is dropped. respond with canned response.
+                                _ = request_timer.as_mut() => {
+                                    warn!("{}: {}", log_ctx, HttpServiceError::ServiceCallTimeout);
+                                    req_span.record_error(&HttpServiceError::ServiceCallTimeout);
+                                    req_stats.error(ErrorKind::Timeout);
+                                    let (method, uri, _) = log_ctx.request().unwrap();
+                                    let ctx = ErrorContext::new(Version::HTTP_2).request(method, uri);
+                                    Ok(Ok::<_, S::Error>(response::canned(error_formatter, request_timeout_status, ctx)))
+                                }
+                            };
+
+                            let res = match res {
+                                Ok(res) => res,
+                                Err(panic) => {
+                                    panic.log(&log_ctx);
+                                    req_span.record_error(&"service panicked");
+
+                                    // service state is unknown after panic. drain the connection.
+                                    ready_failed.notify_one();
+
+                                    let (method, uri, _) = log_ctx.request().unwrap();
+                                    let ctx = ErrorContext::new(Version::HTTP_2).request(method, uri);
+                                    Ok(response::canned(error_formatter, StatusCode::INTERNAL_SERVER_ERROR, ctx))
+                                }
+                            };
+
+                            select! {
+                                biased;
+                                res = h2_handler(res, tx, &req_stats, &req_span) => if let Err(e) = res {
+                                    warn!("{}: {}", log_ctx, e);
+                                    req_span.record_error(&e);
+                                },
+                                // stream is reset when dropped unfinished.
+                                _ = request_timer.as_mut() => {
+                                    warn!("{}: {}", log_ctx, HttpServiceError::ServiceCallTimeout);
+                                    req_span.record_error(&HttpServiceError::ServiceCallTimeout);
+                                    req_stats.error(ErrorKind::Timeout);
+                                }
                             }
-                        });
+                        }));
+
+                        // give other connections on the same thread a chance to make progress.
+                        if !budget.consume() {
+                            tokio::task::yield_now().await;
+                        }
                     },
                     None => return Ok(())
                 },
                 res = &mut ping_pong => {
                     res?;
 
-                    trace!("Connection keep-alive timeout. Shutting down");
+                    trace!("{}: Connection keep-alive timeout. Shutting down", log_ctx);
 
                     io.graceful_shutdown();
 
                     poll_fn(|cx| io.poll_closed(cx)).await?;
 
-                    return Ok(())
+                    return Err(Error::KeepAliveExpired)
                 }
             }
         }
@@ -185,15 +373,19 @@ impl Future for H2PingPong<'_> {
     }
 }
 
-async fn h2_handler<Fut, B, BE, E>(fut: Fut, mut tx: SendResponse<Bytes>) -> Result<(), Error>
+async fn h2_handler<B, BE, E>(
+    res: Result<Response<ResponseBody<B>>, E>,
+    mut tx: SendResponse<Bytes>,
+    stats: &RequestStats,
+    span: &TraceSpan,
+) -> Result<(), Error>
 where
-    Fut: Future<Output = Result<Response<ResponseBody<B>>, E>>,
     E: ResponseError<Response<ResponseBody<B>>>,
     B: Stream<Item = Result<Bytes, BE>>,
     BodyError: From<BE>,
 {
-    // resolve service call. map error to response.
-    let res = fut.await.unwrap_or_else(|ref mut e| ResponseError::response_error(e));
+    // map service call error to response.
+    let res = res.unwrap_or_else(|ref mut e| ResponseError::response_error(e));
 
     // split response to header and body.
     let (res, body) = res.into_parts();
@@ -202,6 +394,9 @@ where
     // set response version.
     *res.version_mut() = Version::HTTP_2;
 
+    stats.response(res.status());
+    span.record_status(res.status());
+
     // set content length header when it's absent.
     if !res.headers().contains_key(CONTENT_LENGTH) {
         if let ResponseBodySize::Sized(n) = body.size() {
@@ -232,6 +427,7 @@ where
 
                         let len = chunk.len();
                         let bytes = chunk.split_to(cmp::min(cap, len));
+                        stats.body(bytes.len());
 
                         stream.send_data(bytes, false)?;
 
@@ -13,10 +13,19 @@ use tokio::{
 };
 
 use crate::body::ResponseBody;
-use crate::error::{BodyError, HttpServiceError, TimeoutError};
+use crate::connection::{ConnectionAddrs, ConnectionData, OnConnect};
+use crate::error::{BodyError, HttpServiceError};
 use crate::response::ResponseError;
 use crate::service::HttpService;
-use crate::util::keep_alive::KeepAlive;
+use crate::tls::{
+    handshake::{self, accept_timeout},
+    HandshakeReason,
+};
+use crate::util::{
+    budget::{Budget, BudgetIo},
+    keep_alive::KeepAlive,
+    stats_io::StatsIo,
+};
 
 use super::body::RequestBody;
 use super::proto::Dispatcher;
@@ -36,8 +45,8 @@ where
     E: 'static,
     BodyError: From<E>,
 
-    St: AsyncRead + AsyncWrite + Unpin,
-    TlsSt: AsyncRead + AsyncWrite + Unpin,
+    St: AsyncRead + AsyncWrite + Unpin + OnConnect,
+    TlsSt: AsyncRead + AsyncWrite + Unpin + OnConnect,
 
     HttpServiceError: From<A::Error>,
 {
@@ -60,37 +69,400 @@ where
 
     fn call(&self, io: St) -> Self::Future<'_> {
         async move {
-            // tls accept timer.
-            let accept_dur = self.config.tls_accept_timeout;
-            let deadline = self.date.get().get().now() + accept_dur;
-            let timer = KeepAlive::new(deadline);
-            pin!(timer);
-
-            select! {
-                biased;
-                res = self.tls_acceptor.call(io) => {
-                    let tls_stream = res?;
-
-                    // update timer to first request timeout.
-                    let request_dur = self.config.first_request_timeout;
-                    let deadline = self.date.get().get().now() + request_dur;
-                    timer.as_mut().update(deadline);
-
-                    select! {
-                        biased;
-                        res = ::h2::server::handshake(tls_stream) => {
-                            let mut conn = res?;
-
-                            let dispatcher = Dispatcher::new(&mut conn, timer.as_mut(), self.config.keep_alive_timeout, &self.flow, self.date.get());
-                            dispatcher.run().await?;
-
-                            Ok(())
+            let stats = self.stats();
+            let addrs = ConnectionAddrs::from_io(&io);
+            let peer = addrs.and_then(|addrs| addrs.peer());
+
+            let fut = async {
+                // tls accept timer.
+                let accept_dur = self.config.tls_accept_timeout;
+                let deadline = self.date.get().get().now() + accept_dur;
+                let timer = KeepAlive::new(self.date.wheel(), deadline);
+                pin!(timer);
+
+                match accept_timeout(self.tls_acceptor.call(io), timer.as_mut()).await {
+                    Some(res) => {
+                        let tls_stream = match res {
+                            Ok(tls_stream) => tls_stream,
+                            Err(e) => {
+                                let e = HttpServiceError::from(e).with_peer(peer);
+                                if let HttpServiceError::HandshakeFailed(peer, ref err) = e {
+                                    handshake::log(peer, err.reason(), err);
+                                }
+                                return Err(e);
+                            }
+                        };
+
+                        let conn_data = ConnectionData::from_io(&tls_stream);
+                        let budget = Budget::new(self.config.http2_budget);
+
+                        // update timer to first request timeout.
+                        let request_dur = self.config.first_request_timeout;
+                        let deadline = self.date.get().get().now() + request_dur;
+                        timer.as_mut().update(deadline);
+
+                        select! {
+                            biased;
+                            res = budget.run(::h2::server::handshake(BudgetIo::new(StatsIo::new(tls_stream, &stats), &budget))) => {
+                                let mut conn = res?;
+
+                                let dispatcher = Dispatcher::new(&mut conn, timer.as_mut(), self.config, conn_data, &self.flow, self.date.get(), &stats, &budget);
+                                dispatcher.run().await?;
+
+                                Ok(())
+                            }
+                            _ = timer.as_mut() => Err(HttpServiceError::RequestHeadTimeout)
                         }
-                        _ = timer.as_mut() => Err(HttpServiceError::Timeout(TimeoutError::H2Handshake))
+                    }
+                    None => {
+                        handshake::log(peer, HandshakeReason::Timeout, &"timed out");
+                        Err(HttpServiceError::handshake_timeout(peer))
                     }
                 }
-                _ = timer.as_mut() => Err(HttpServiceError::Timeout(TimeoutError::TlsAccept)),
+            };
+
+            let res = self.drain(fut).await;
+
+            self.report_stats(&stats, &res);
+
+            res
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{cell::RefCell, convert::Infallible, pin::Pin, rc::Rc, sync::Arc, time::Duration};
+
+    use actix_service_alt::{fn_service, ServiceFactory};
+    use http::StatusCode;
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        sync::Notify,
+        task::LocalSet,
+    };
+
+    use crate::builder::HttpServiceBuilder;
+    use crate::config::HttpServiceConfig;
+    use crate::connection::ConnectionAddrs;
+    use crate::protocol::RequestProtocol;
+    use crate::util::testing::{duplex, serve, H2Client, TestStream};
+
+    async fn handler(req: Request<RequestBody>) -> Result<Response<ResponseBody>, Infallible> {
+        let addrs = req.extensions().get::<ConnectionAddrs>().copied();
+        let protocol = req.extensions().get::<RequestProtocol>().copied();
+        Ok(Response::new(Bytes::from(format!("{:?} {:?}", protocol, addrs)).into()))
+    }
+
+    #[tokio::test]
+    async fn connection_addrs() {
+        LocalSet::new()
+            .run_until(async {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let local = listener.local_addr().unwrap();
+                let client = TcpStream::connect(local).await.unwrap();
+                let peer = client.local_addr().unwrap();
+                let (io, _) = listener.accept().await.unwrap();
+
+                let builder = HttpServiceBuilder::h2(fn_service(handler));
+                let service = ServiceFactory::<TcpStream>::new_service(&builder, ()).await.unwrap();
+
+                let request = async {
+                    let (mut client, conn) = ::h2::client::handshake(client).await.unwrap();
+                    tokio::task::spawn_local(conn);
+
+                    let req = Request::get("http://localhost/").body(()).unwrap();
+                    let (res, _) = client.send_request(req, true).unwrap();
+                    let mut body = res.await.unwrap().into_body();
+
+                    let mut res = Vec::new();
+                    while let Some(chunk) = body.data().await {
+                        res.extend_from_slice(&chunk.unwrap());
+                    }
+                    String::from_utf8(res).unwrap()
+                };
+
+                // server future ends when client dropped and closes the connection.
+                let (res, _) = tokio::join!(request, service.call(io));
+
+                let addrs = ConnectionAddrs::Inet { peer, local };
+                assert_eq!(res, format!("{:?} {:?}", Some(RequestProtocol::H2c), Some(addrs)));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        LocalSet::new()
+            .run_until(async {
+                let (client, io) = duplex();
+
+                // handler notifies when called and waits for release.
+                let called = Arc::new(Notify::new());
+                let release = Arc::new(Notify::new());
+                let (called2, release2) = (called.clone(), release.clone());
+                let builder = HttpServiceBuilder::h2(fn_service(move |_: Request<RequestBody>| {
+                    let (called, release) = (called2.clone(), release2.clone());
+                    async move {
+                        called.notify_one();
+                        release.notified().await;
+                        Ok::<Response<ResponseBody>, Infallible>(Response::new(Bytes::from("done").into()))
+                    }
+                }));
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let handle = service.shutdown_handle();
+
+                let (res, res2) = serve(&service, io, async {
+                    let client = H2Client::handshake(client).await.unwrap();
+
+                    let req = Request::get("http://localhost/").body(Bytes::new()).unwrap();
+                    let control = async {
+                        called.notified().await;
+                        handle.shutdown();
+                        release.notify_one();
+                    };
+
+                    // in flight stream finishes after GOAWAY.
+                    let (res, _) = tokio::join!(client.send(req), control);
+
+                    // connection is closed by server.
+                    client.closed().await.unwrap();
+
+                    res.unwrap().into_body()
+                })
+                .await;
+
+                assert_eq!(res, "done");
+                assert!(res2.is_ok());
+            })
+            .await
+    }
+
+    struct PendingBody;
+
+    impl Stream for PendingBody {
+        type Item = Result<Bytes, BodyError>;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Pending
+        }
+    }
+
+    async fn timeout_handler(req: Request<RequestBody>) -> Result<Response<ResponseBody<PendingBody>>, Infallible> {
+        match req.uri().path() {
+            "/body" => Ok(Response::new(ResponseBody::stream(PendingBody))),
+            _ => std::future::pending().await,
+        }
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        LocalSet::new()
+            .run_until(async {
+                let (client, io) = duplex();
+
+                let config = HttpServiceConfig::new().request_timeout(Duration::from_millis(10));
+                let builder = HttpServiceBuilder::h2(fn_service(timeout_handler)).config(config);
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                // server future ends when client dropped and closes the connection.
+                let (_, res) = serve(&service, io, async {
+                    let client = H2Client::handshake(client).await.unwrap();
+
+                    // service call is dropped and responded with 503.
+                    let req = Request::get("http://localhost/").body(Bytes::new()).unwrap();
+                    let res = client.send(req).await.unwrap();
+                    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+                    // stream is reset without finishing response body.
+                    let req = Request::get("http://localhost/body").body(Bytes::new()).unwrap();
+                    let err = client.send(req).await.unwrap_err();
+                    assert_eq!(err.reason(), Some(::h2::Reason::CANCEL));
+                })
+                .await;
+                assert!(res.is_ok());
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn error_formatter() {
+        fn formatter(status: StatusCode, ctx: crate::ErrorContext<'_>) -> Response<Bytes> {
+            let body = format!("{} {}", status.as_u16(), ctx.uri().unwrap().path());
+            Response::builder().status(status).body(Bytes::from(body)).unwrap()
+        }
+
+        LocalSet::new()
+            .run_until(async {
+                let (client, io) = duplex();
+
+                let config = HttpServiceConfig::new().request_timeout(Duration::from_millis(10));
+                let builder = HttpServiceBuilder::h2(fn_service(|req: Request<RequestBody>| async move {
+                    match req.uri().path() {
+                        "/panic" => panic!("service panicked"),
+                        _ => std::future::pending::<Result<Response<ResponseBody>, Infallible>>().await,
+                    }
+                }))
+                .config(config)
+                .error_formatter(formatter);
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (_, res) = serve(&service, io, async {
+                    let client = H2Client::handshake(client).await.unwrap();
+
+                    let req = Request::get("http://localhost/timeout").body(Bytes::new()).unwrap();
+                    let res = client.send(req).await.unwrap();
+                    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+                    assert_eq!(res.body(), "503 /timeout");
+
+                    // panicked service call is answered with 500 instead of stream reset.
+                    let req = Request::get("http://localhost/panic").body(Bytes::new()).unwrap();
+                    let res = client.send(req).await.unwrap();
+                    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+                    assert_eq!(res.body(), "500 /panic");
+
+                    // connection is drained after panic.
+                    client.closed().await.unwrap();
+                })
+                .await;
+                assert!(res.is_ok());
+            })
+            .await
+    }
+
+    // service fails readiness check when `fail` is true. Otherwise it's never ready.
+    #[derive(Clone, Copy)]
+    struct NotReady {
+        fail: bool,
+    }
+
+    impl ServiceFactory<Request<RequestBody>> for NotReady {
+        type Response = Response<ResponseBody>;
+        type Error = std::io::Error;
+        type Config = ();
+        type Service = Self;
+        type InitError = ();
+        type Future = std::future::Ready<Result<Self, ()>>;
+
+        fn new_service(&self, _: Self::Config) -> Self::Future {
+            std::future::ready(Ok(*self))
+        }
+    }
+
+    impl Service<Request<RequestBody>> for NotReady {
+        type Response = Response<ResponseBody>;
+        type Error = std::io::Error;
+        type Future<'f> = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            if self.fail {
+                Poll::Ready(Err(std::io::ErrorKind::Other.into()))
+            } else {
+                Poll::Pending
             }
         }
+
+        fn call(&self, _: Request<RequestBody>) -> Self::Future<'_> {
+            unreachable!("service is never ready")
+        }
+    }
+
+    #[tokio::test]
+    async fn service_not_ready() {
+        LocalSet::new()
+            .run_until(async {
+                for &fail in [false, true].iter() {
+                    let (client, io) = duplex();
+
+                    let config = HttpServiceConfig::new().service_ready_timeout(Duration::from_millis(10));
+                    let builder = HttpServiceBuilder::h2(NotReady { fail }).config(config);
+                    let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                    let (_, res) = serve(&service, io, async {
+                        let client = H2Client::handshake(client).await.unwrap();
+
+                        let req = Request::get("http://localhost/").body(Bytes::new()).unwrap();
+                        let res = client.send(req).await.unwrap();
+                        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+                        assert_eq!(res.headers()["retry-after"], "1");
+
+                        // connection is closed by server after readiness failure.
+                        if fail {
+                            client.closed().await.unwrap();
+                        }
+                    })
+                    .await;
+                    assert!(res.is_ok());
+                }
+            })
+            .await
+    }
+
+    // position of the quiet connection's request in the order requests are handled when it's
+    // served together with a busy connection having many ready streams.
+    async fn quiet_position(budget: usize) -> usize {
+        const BUSY: usize = 256;
+
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let order2 = order.clone();
+        let config = HttpServiceConfig::new().http2_budget(budget);
+        let builder = HttpServiceBuilder::h2(fn_service(move |req: Request<RequestBody>| {
+            order2.borrow_mut().push(req.uri().path() == "/quiet");
+            async { Ok::<Response<ResponseBody>, Infallible>(Response::new(ResponseBody::None)) }
+        }))
+        .config(config);
+        let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+        let (busy, busy_io) = duplex();
+        let (quiet, quiet_io) = duplex();
+
+        let (mut busy, conn) = ::h2::client::handshake(busy).await.unwrap();
+        tokio::task::spawn_local(conn);
+        let busy_res = (0..BUSY)
+            .map(|_| {
+                let req = Request::get("http://localhost/busy").body(()).unwrap();
+                busy.send_request(req, true).unwrap().0
+            })
+            .collect::<Vec<_>>();
+
+        let (mut quiet, conn) = ::h2::client::handshake(quiet).await.unwrap();
+        tokio::task::spawn_local(conn);
+        let req = Request::get("http://localhost/quiet").body(()).unwrap();
+        let quiet_res = quiet.send_request(req, true).unwrap().0;
+
+        // let all requests reach server before it starts.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let request = async move {
+            for res in busy_res {
+                res.await.unwrap();
+            }
+            quiet_res.await.unwrap();
+            // server futures end when clients dropped and close the connections.
+            drop((busy, quiet));
+        };
+
+        let (_, res, res2) = tokio::join!(request, service.call(busy_io), service.call(quiet_io));
+        assert!(res.is_ok());
+        assert!(res2.is_ok());
+
+        let order = order.borrow();
+        assert_eq!(order.len(), BUSY + 1);
+        order.iter().position(|quiet| *quiet).unwrap()
+    }
+
+    #[tokio::test]
+    async fn budget() {
+        LocalSet::new()
+            .run_until(async {
+                // busy connection yields and quiet connection is served early.
+                assert!(quiet_position(8).await < 32);
+                // without yielding quiet connection waits for all streams of busy connection.
+                assert_eq!(quiet_position(1024).await, 256);
+            })
+            .await
     }
 }
@@ -1,4 +1,4 @@
-use std::future::Future;
+use std::{future::Future, sync::Arc};
 
 use actix_server_alt::net::UdpStream;
 use actix_service_alt::ServiceFactory;
@@ -7,8 +7,10 @@ use futures_core::Stream;
 use http::{Request, Response};
 
 use crate::body::ResponseBody;
+use crate::config::{HttpServiceConfig, DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT};
 use crate::error::{BodyError, HttpServiceError};
 use crate::response::ResponseError;
+use crate::stats::{ConnectionStats, OnConnectionClose};
 
 use super::body::RequestBody;
 use super::service::H3Service;
@@ -17,6 +19,8 @@ use super::service::H3Service;
 /// Take in generic types of ServiceFactory for `quinn`.
 pub struct H3ServiceBuilder<F> {
     factory: F,
+    config: HttpServiceConfig<DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT>,
+    on_close: Option<OnConnectionClose>,
 }
 
 impl<F, B, E> H3ServiceBuilder<F>
@@ -30,7 +34,33 @@ where
 {
     /// Construct a new Service Builder with given service factory.
     pub fn new(factory: F) -> Self {
-        Self { factory }
+        Self {
+            factory,
+            config: HttpServiceConfig::new(),
+            on_close: None,
+        }
+    }
+
+    /// Use given configuration for constructed services.
+    ///
+    /// Buffer limits of config are ignored. Http/3 streams are buffered by `quinn`.
+    pub fn config<const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>(
+        mut self,
+        config: HttpServiceConfig<READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
+    ) -> Self {
+        self.config = config
+            .max_read_buf_size::<DEFAULT_READ_BUF_LIMIT>()
+            .max_write_buf_size::<DEFAULT_WRITE_BUF_LIMIT>();
+        self
+    }
+
+    /// Call given function with [ConnectionStats] of every connection when it's closed.
+    pub fn on_connection_close<C>(mut self, on_close: C) -> Self
+    where
+        C: Fn(ConnectionStats) + Send + Sync + 'static,
+    {
+        self.on_close = Some(Arc::new(on_close));
+        self
     }
 }
 
@@ -54,9 +84,44 @@ where
 
     fn new_service(&self, cfg: Self::Config) -> Self::Future {
         let service = self.factory.new_service(cfg);
-        async {
+        let config = self.config;
+        let on_close = self.on_close.clone();
+        async move {
             let service = service.await?;
-            Ok(H3Service::new(service))
+            Ok(H3Service::new(service).config(config).on_close(on_close))
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{convert::Infallible, time::Duration};
+
+    use actix_service_alt::fn_service;
+    use tokio::task::LocalSet;
+
+    async fn handler(_: Request<RequestBody>) -> Result<Response<ResponseBody>, Infallible> {
+        Ok(Response::new(ResponseBody::None))
+    }
+
+    #[tokio::test]
+    async fn config() {
+        LocalSet::new()
+            .run_until(async {
+                let config = HttpServiceConfig::new()
+                    .drain_timeout(Duration::from_secs(7))
+                    .max_read_buf_size::<1024>();
+
+                let service = H3ServiceBuilder::new(fn_service(handler))
+                    .config(config)
+                    .new_service(())
+                    .await
+                    .unwrap();
+
+                assert_eq!(service.config.drain_timeout, Duration::from_secs(7));
+            })
+            .await
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+};
+
 use h3_quinn::quinn::ConnectionError;
 
 use crate::error::{BodyError, HttpServiceError};
@@ -10,6 +15,26 @@ pub enum Error {
     Body(BodyError),
 }
 
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Connection(ref e) => write!(f, "{}", e),
+            Self::H3(ref e) => write!(f, "{}", e),
+            Self::Body(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::Connection(ref e) => Some(e),
+            Self::H3(ref e) => Some(e),
+            Self::Body(ref e) => Some(e),
+        }
+    }
+}
+
 impl From<::h3::Error> for Error {
     fn from(e: ::h3::Error) -> Self {
         Self::H3(e)
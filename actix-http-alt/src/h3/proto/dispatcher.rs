@@ -1,26 +1,42 @@
-use std::{future::Future, marker::PhantomData, rc::Rc};
+use std::{marker::PhantomData, rc::Rc, time::Duration};
 
 use actix_server_alt::net::UdpStream;
 use actix_service_alt::Service;
-use bytes::Bytes;
+use bytes::{Buf, Bytes};
 use futures_core::Stream;
 use futures_intrusive::sync::LocalMutex;
 use h3::{
     quic::SendStream,
     server::{self, RequestStream},
 };
-use http::{Request, Response};
+use http::{Request, Response, StatusCode, Version};
+use log::warn;
+use tokio::{pin, select, sync::Notify};
 
 use crate::body::ResponseBody;
-use crate::error::{BodyError, HttpServiceError};
-use crate::flow::HttpFlow;
+use crate::config::HttpServiceConfig;
+use crate::connection::{ConnectionAddrs, ConnectionData};
+use crate::error::{BodyError, ErrorKind, HttpServiceError};
+use crate::flow::{HttpFlow, Readiness};
 use crate::h3::{body::RequestBody, error::Error};
-use crate::response::ResponseError;
+use crate::protocol::{Protocol, RequestProtocol};
+use crate::response::{self, ErrorContext, ErrorFormatter, ResponseError};
+use crate::stats::{RequestStats, StatsRecorder};
+use crate::timeout::RequestTimer;
+use crate::util::{catch_unwind::CatchUnwind, log_context::LogContext, timer::TimerWheel, trace::TraceSpan};
 
 /// Http/3 dispatcher
 pub(crate) struct Dispatcher<'a, S, ReqB, X, U> {
     io: UdpStream,
     flow: &'a HttpFlow<S, X, U>,
+    wheel: &'a Rc<TimerWheel>,
+    catch_panic: bool,
+    request_timeout: Option<Duration>,
+    request_timeout_status: StatusCode,
+    ready_dur: Duration,
+    drain_dur: Duration,
+    error_formatter: Option<ErrorFormatter>,
+    stats: &'a StatsRecorder,
     _req_body: PhantomData<ReqB>,
 }
 
@@ -39,15 +55,50 @@ where
 
     ReqB: From<RequestBody> + 'static,
 {
-    pub(crate) fn new(io: UdpStream, flow: &'a HttpFlow<S, X, U>) -> Self {
+    pub(crate) fn new<const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize>(
+        io: UdpStream,
+        flow: &'a HttpFlow<S, X, U>,
+        wheel: &'a Rc<TimerWheel>,
+        config: HttpServiceConfig<READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
+        stats: &'a StatsRecorder,
+    ) -> Self {
         Self {
             io,
             flow,
+            wheel,
+            catch_panic: config.catch_panic,
+            request_timeout: config.request_timeout,
+            request_timeout_status: config.request_timeout_status,
+            ready_dur: config.service_ready_timeout,
+            drain_dur: config.drain_timeout,
+            error_formatter: config.error_formatter,
+            stats,
             _req_body: PhantomData,
         }
     }
 
     pub(crate) async fn run(self) -> Result<(), Error> {
+        let mut conn_data = ConnectionData::new();
+        conn_data.set_addrs(ConnectionAddrs::Inet {
+            peer: self.io.peer_addr(),
+            local: self.io.local_addr(),
+        });
+        let log_ctx = LogContext::new(conn_data.id(), Some(self.io.peer_addr()));
+        let span = TraceSpan::connection(conn_data.id(), Some(self.io.peer_addr()), Protocol::Http3);
+
+        self.stats.set_id(conn_data.id());
+        self.stats.set_protocol(Protocol::Http3);
+
+        let res = span.instrument(self.run_inner(&conn_data, &log_ctx, &span)).await;
+
+        if let Err(ref e) = res {
+            warn!("{}: {}", log_ctx, e);
+        }
+
+        res
+    }
+
+    async fn run_inner(self, conn_data: &ConnectionData, log_ctx: &LogContext, span: &TraceSpan) -> Result<(), Error> {
         // wait for connecting.
         let conn = self.io.connecting().await?;
 
@@ -55,8 +106,51 @@ where
         let conn = h3_quinn::Connection::new(conn);
         let mut conn = server::Connection::new(conn).await?;
 
-        // accept loop
-        while let Some((req, stream)) = conn.accept().await? {
+        let ready_failed = Rc::new(Notify::new());
+
+        // every request task holds a clone and notifies when it finishes.
+        let in_flight = Rc::new(Notify::new());
+
+        // wait for service readiness before accepting next request.
+        let ready = self.flow.ready::<Request<ReqB>>(self.wheel, self.ready_dur);
+        pin!(ready);
+        let mut readiness = None;
+
+        // accept loop. stop accepting new request when service is shutting down or failed
+        // readiness check. in flight requests are owned by their tasks and keep running until
+        // they finish or drain timeout is reached.
+        loop {
+            // readiness is checked again while waiting for next request after it's timed out.
+            let check_ready = matches!(readiness, None | Some(Readiness::Timeout));
+
+            let (req, stream) = select! {
+                biased;
+                _ = self.flow.shutdown.wait() => break,
+                _ = ready_failed.notified() => break,
+                res = &mut ready, if check_ready => {
+                    if let Readiness::Timeout = res {
+                        ready.set(self.flow.ready(self.wheel, self.ready_dur));
+                    }
+                    readiness = Some(res);
+                    continue;
+                }
+                res = conn.accept(), if readiness.is_some() => match res? {
+                    Some(res) => res,
+                    None => break,
+                },
+            };
+
+            // request is only accepted after readiness check.
+            let readiness = readiness.take().unwrap();
+            ready.set(self.flow.ready(self.wheel, self.ready_dur));
+
+            // stop accepting after the request is rejected.
+            if let Readiness::Failed = readiness {
+                ready_failed.notify_one();
+            }
+
+            let req_stats = self.stats.request();
+
             // Reconstruct HttpRequest to attach crate body type.
             let (parts, _) = req.into_parts();
 
@@ -64,47 +158,164 @@ where
             // TODO: may deadlock?
             let stream = Rc::new(LocalMutex::new(stream, true));
             let sender = stream.clone();
+            let stats = self.stats.clone();
             let body = async_stream::stream! {
                 while let Some(res) = sender.lock().await.recv_data().await.transpose() {
+                    if let Ok(ref buf) = res {
+                        stats.read(buf.remaining());
+                    }
                     yield res;
                 }
             };
             let body = ReqB::from(RequestBody(Box::pin(body)));
 
-            let req = Request::from_parts(parts, body);
+            let mut req = Request::from_parts(parts, body);
+            conn_data.insert_into(req.extensions_mut());
+            req.extensions_mut().insert(RequestProtocol::H3);
+
+            let log_ctx = log_ctx.with_request(&req);
+            let req_span = span.request(&req);
+            let catch_panic = self.catch_panic;
+            let request_timeout = self.request_timeout;
+            let request_timeout_status = self.request_timeout_status;
+            let ready_dur = self.ready_dur;
+            let error_formatter = self.error_formatter;
+            let ready_failed = ready_failed.clone();
+            let task_span = req_span.clone();
+            let shutdown = self.flow.shutdown.clone();
+            let drain_dur = self.drain_dur;
+            let drain_wheel = self.wheel.clone();
+            let in_flight = InFlight(in_flight.clone());
+            let wheel = self.wheel.clone();
 
             let flow = HttpFlow::clone(self.flow);
-            tokio::task::spawn_local(async move {
-                let fut = flow.service.call(req);
-                if let Err(e) = h3_handler(fut, stream).await {
-                    HttpServiceError::from(e).log();
+            let request = async move {
+                if !matches!(readiness, Readiness::Ready) {
+                    // reject request instead of queueing it.
+                    if let Readiness::Failed = readiness {
+                        warn!("{}: {}", log_ctx, HttpServiceError::ServiceReady);
+                        req_span.record_error(&HttpServiceError::ServiceReady);
+                        req_stats.error(ErrorKind::ServiceReady);
+                    }
+
+                    let (method, uri, _) = log_ctx.request().unwrap();
+                    let ctx = ErrorContext::new(Version::HTTP_3).request(method, uri);
+                    let res = Ok::<_, S::Error>(response::unavailable(error_formatter, ready_dur, ctx));
+                    if let Err(e) = h3_handler(res, stream, &req_stats, &req_span).await {
+                        warn!("{}: {}", log_ctx, e);
+                        req_span.record_error(&e);
+                    }
+                    return;
                 }
-            });
+
+                let request_timer = RequestTimer::new(&wheel, request_timeout, req.extensions_mut());
+                pin!(request_timer);
+
+                let fut = CatchUnwind::new(flow.service.call(req), catch_panic);
+                let res = select! {
+                    biased;
+                    res = fut => res,
+                    // service call is dropped. respond with canned response.
+                    _ = request_timer.as_mut() => {
+                        warn!("{}: {}", log_ctx, HttpServiceError::ServiceCallTimeout);
+                        req_span.record_error(&HttpServiceError::ServiceCallTimeout);
+                        req_stats.error(ErrorKind::Timeout);
+                        let (method, uri, _) = log_ctx.request().unwrap();
+                        let ctx = ErrorContext::new(Version::HTTP_3).request(method, uri);
+                        Ok(Ok::<_, S::Error>(response::canned(error_formatter, request_timeout_status, ctx)))
+                    }
+                };
+
+                let res = match res {
+                    Ok(res) => res,
+                    Err(panic) => {
+                        panic.log(&log_ctx);
+                        req_span.record_error(&"service panicked");
+
+                        // service state is unknown after panic. drain the connection.
+                        ready_failed.notify_one();
+
+                        let (method, uri, _) = log_ctx.request().unwrap();
+                        let ctx = ErrorContext::new(Version::HTTP_3).request(method, uri);
+                        Ok(response::canned(
+                            error_formatter,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            ctx,
+                        ))
+                    }
+                };
+
+                select! {
+                    biased;
+                    res = h3_handler(res, stream, &req_stats, &req_span) => if let Err(e) = res {
+                        warn!("{}: {}", log_ctx, e);
+                        req_span.record_error(&e);
+                    },
+                    // request stream is dropped without finishing and reset.
+                    _ = request_timer.as_mut() => {
+                        warn!("{}: {}", log_ctx, HttpServiceError::ServiceCallTimeout);
+                        req_span.record_error(&HttpServiceError::ServiceCallTimeout);
+                        req_stats.error(ErrorKind::Timeout);
+                    }
+                }
+            };
+
+            tokio::task::spawn_local(task_span.instrument(async move {
+                let _in_flight = in_flight;
+
+                // request is dropped and its stream is reset when drain timeout is reached.
+                select! {
+                    biased;
+                    _ = request => {}
+                    _ = shutdown.drain_expired(&drain_wheel, drain_dur) => {}
+                }
+            }));
+        }
+
+        // connection is live until in flight requests finish.
+        while Rc::strong_count(&in_flight) > 1 {
+            in_flight.notified().await;
         }
 
         Ok(())
     }
 }
 
-async fn h3_handler<Fut, C, B, BE, E>(fut: Fut, stream: Rc<LocalMutex<RequestStream<C>>>) -> Result<(), Error>
+struct InFlight(Rc<Notify>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.notify_one();
+    }
+}
+
+async fn h3_handler<C, B, BE, E>(
+    res: Result<Response<ResponseBody<B>>, E>,
+    stream: Rc<LocalMutex<RequestStream<C>>>,
+    stats: &RequestStats,
+    span: &TraceSpan,
+) -> Result<(), Error>
 where
-    Fut: Future<Output = Result<Response<ResponseBody<B>>, E>>,
     C: SendStream<Bytes>,
     E: ResponseError<Response<ResponseBody<B>>>,
     B: Stream<Item = Result<Bytes, BE>>,
     BodyError: From<BE>,
 {
-    let res = fut.await.unwrap_or_else(|ref mut e| ResponseError::response_error(e));
+    let res = res.unwrap_or_else(|ref mut e| ResponseError::response_error(e));
 
     let (res, body) = res.into_parts();
     let res = Response::from_parts(res, ());
 
+    stats.response(res.status());
+    span.record_status(res.status());
+
     stream.lock().await.send_response(res).await?;
 
     tokio::pin!(body);
 
     while let Some(res) = body.as_mut().next().await {
         let bytes = res?;
+        stats.written(bytes.len());
         stream.lock().await.send_data(bytes).await?;
     }
 
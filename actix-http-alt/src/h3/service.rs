@@ -8,17 +8,26 @@ use actix_service_alt::Service;
 use bytes::Bytes;
 use futures_core::Stream;
 use http::{Request, Response};
+use tokio::select;
 
 use super::proto::Dispatcher;
 use crate::body::ResponseBody;
+use crate::config::{HttpServiceConfig, DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT};
 use crate::error::{BodyError, HttpServiceError};
 use crate::flow::HttpFlow;
 use crate::response::ResponseError;
+use crate::shutdown::ShutdownHandle;
+use crate::stats::{OnConnectionClose, StatsRecorder};
+use crate::util::date::DateTimeTask;
 
 use super::body::RequestBody;
 
 pub struct H3Service<S> {
     flow: HttpFlow<S, (), ()>,
+    pub(crate) config: HttpServiceConfig<DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT>,
+    // only timer wheel of connections is used.
+    date: DateTimeTask,
+    on_close: Option<OnConnectionClose>,
 }
 
 impl<S> H3Service<S> {
@@ -26,9 +35,32 @@ impl<S> H3Service<S> {
     /// No upgrade/expect services allowed in Http/3.
     pub fn new(service: S) -> Self {
         Self {
-            flow: HttpFlow::new(service, (), None),
+            flow: HttpFlow::new(service, (), None, ShutdownHandle::new()),
+            config: HttpServiceConfig::new(),
+            date: DateTimeTask::new(None),
+            on_close: None,
         }
     }
+
+    /// Handle for draining connections served by this service.
+    ///
+    /// Http/3 connections stop accepting new requests after shutdown. In flight requests are
+    /// dropped when [drain timeout](crate::config::HttpServiceConfig::drain_timeout) is reached.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.flow.shutdown.clone()
+    }
+
+    pub(crate) fn config(mut self, config: HttpServiceConfig<DEFAULT_READ_BUF_LIMIT, DEFAULT_WRITE_BUF_LIMIT>) -> Self {
+        // timers tick at refresh interval of config.
+        self.date = DateTimeTask::new(config.date_refresh);
+        self.config = config;
+        self
+    }
+
+    pub(crate) fn on_close(mut self, on_close: Option<OnConnectionClose>) -> Self {
+        self.on_close = on_close;
+        self
+    }
 }
 
 impl<S, B, E> Service<UdpStream> for H3Service<S>
@@ -55,11 +87,32 @@ where
 
     fn call(&self, stream: UdpStream) -> Self::Future<'_> {
         async move {
-            let dispatcher = Dispatcher::new(stream, &self.flow);
+            let _guard = self.flow.shutdown.track();
+
+            if self.flow.shutdown.is_shutdown() {
+                return Ok(());
+            }
+
+            let stats = StatsRecorder::new(self.on_close.is_some());
+
+            let fut = async {
+                let dispatcher = Dispatcher::new(stream, &self.flow, self.date.wheel(), self.config, &stats);
+                dispatcher.run().await.map_err(HttpServiceError::from)
+            };
+
+            let res = select! {
+                biased;
+                res = fut => res,
+                _ = self.flow.shutdown.drain_expired(self.date.wheel(), self.config.drain_timeout) => Err(HttpServiceError::DrainTimeout),
+            };
 
-            dispatcher.run().await?;
+            if let Some(stats) = stats.finish(res.as_ref().err().map(HttpServiceError::kind)) {
+                if let Some(ref on_close) = self.on_close {
+                    on_close(stats);
+                }
+            }
 
-            Ok(())
+            res
         }
     }
 }
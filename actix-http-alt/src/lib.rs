@@ -7,6 +7,7 @@
 #![forbid(unsafe_code)]
 #![allow(incomplete_features)]
 #![feature(generic_associated_types, min_type_alias_impl_trait)]
+#![cfg_attr(test, feature(test))]
 
 mod body;
 mod builder;
@@ -16,7 +17,8 @@ mod flow;
 mod protocol;
 mod response;
 mod service;
-mod tls;
+mod shutdown;
+mod timeout;
 mod upgrade;
 
 #[cfg(feature = "http1")]
@@ -27,18 +29,24 @@ pub mod h2;
 pub mod h3;
 
 pub mod config;
+pub mod connection;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod stats;
+pub mod tls;
 pub mod util;
+#[cfg(feature = "rustls")]
+pub mod vhost;
 
 /// re-export http crate as module.
 pub use http;
 
 pub use body::{RequestBody, ResponseBody};
 pub use builder::HttpServiceBuilder;
-pub use error::{BodyError, HttpServiceError};
-pub use response::ResponseError;
+pub use error::{BodyError, ErrorKind, HttpServiceError};
+pub use expect::{ExpectHandler, ExpectRejected};
+pub use protocol::{Protocol, RequestProtocol};
+pub use response::{ErrorContext, ErrorFormatter, ErrorStatus, ResponseError};
 pub use service::HttpService;
-
-// temporary compile error for conflicted feature combination.
-#[cfg(not(feature = "http1"))]
-#[cfg(all(feature = "http2", feature = "native-tls"))]
-compile_error!("http2 feature can not use native-tls");
+pub use shutdown::{DrainWatcher, ShutdownHandle};
+pub use timeout::RequestTimeout;
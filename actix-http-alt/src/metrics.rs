@@ -0,0 +1,113 @@
+//! Metrics recorded by dispatchers through the [metrics](https://docs.rs/metrics) facade.
+//!
+//! Install a recorder of any backend (e.g. a Prometheus exporter) to collect them. Recording is
+//! a no op when there is no recorder installed.
+//!
+//! | Name | Type | Labels |
+//! |------|------|--------|
+//! | [REQUESTS_TOTAL] | counter | `protocol`, `status_class` |
+//! | [REQUEST_DURATION_SECONDS] | histogram | `protocol` |
+//! | [RESPONSE_SIZE_BYTES] | histogram | `protocol` |
+//! | [ACTIVE_CONNECTIONS] | gauge | |
+//! | [IN_FLIGHT_REQUESTS] | gauge | |
+//! | [ERRORS_TOTAL] | counter | `kind` |
+//! | [TLS_HANDSHAKES_TOTAL] | counter | `outcome` |
+//!
+//! Label values:
+//! - `protocol`: `http1`, `http1_tls`, `http2`, `http3`
+//! - `status_class`: `2xx`, `3xx`, `4xx`, `5xx`
+//! - `kind`: `service_ready`, `timeout`, `tls`, `io`, `protocol`, `body`
+//! - `outcome`: `success`, `failure`, `timeout`
+
+use http::StatusCode;
+use metrics_crate as metrics;
+
+use crate::error::ErrorKind;
+use crate::protocol::Protocol;
+
+/// Final responses sent. Interim 1xx responses are not counted.
+pub const REQUESTS_TOTAL: &str = "http_server_requests_total";
+
+/// Time from request head decoded to response body finished or dropped.
+pub const REQUEST_DURATION_SECONDS: &str = "http_server_request_duration_seconds";
+
+/// Bytes of response body sent.
+pub const RESPONSE_SIZE_BYTES: &str = "http_server_response_size_bytes";
+
+/// Connections being served.
+pub const ACTIVE_CONNECTIONS: &str = "http_server_active_connections";
+
+/// Requests being handled.
+pub const IN_FLIGHT_REQUESTS: &str = "http_server_in_flight_requests";
+
+/// Connections closed with error and requests timed out without closing their connection.
+pub const ERRORS_TOTAL: &str = "http_server_errors_total";
+
+/// Tls handshakes of connections accepted by [HttpService](crate::HttpService) with tls enabled.
+pub const TLS_HANDSHAKES_TOTAL: &str = "http_server_tls_handshakes_total";
+
+pub(crate) fn protocol_label(protocol: Option<Protocol>) -> &'static str {
+    match protocol {
+        Some(Protocol::Http1) => "http1",
+        Some(Protocol::Http1Tls) => "http1_tls",
+        Some(Protocol::Http2) => "http2",
+        Some(Protocol::Http3) => "http3",
+        None => "unknown",
+    }
+}
+
+fn status_class(status: StatusCode) -> Option<&'static str> {
+    match status.as_u16() / 100 {
+        2 => Some("2xx"),
+        3 => Some("3xx"),
+        4 => Some("4xx"),
+        5 => Some("5xx"),
+        _ => None,
+    }
+}
+
+fn kind_label(kind: ErrorKind) -> Option<&'static str> {
+    match kind {
+        ErrorKind::Ignored => None,
+        ErrorKind::ServiceReady => Some("service_ready"),
+        ErrorKind::Timeout => Some("timeout"),
+        ErrorKind::Tls => Some("tls"),
+        ErrorKind::Io => Some("io"),
+        ErrorKind::Protocol => Some("protocol"),
+        ErrorKind::Body => Some("body"),
+    }
+}
+
+pub(crate) fn response(protocol: &'static str, status: StatusCode) {
+    if let Some(class) = status_class(status) {
+        metrics::counter!(REQUESTS_TOTAL, "protocol" => protocol, "status_class" => class).increment(1);
+    }
+}
+
+pub(crate) fn request_start() {
+    metrics::gauge!(IN_FLIGHT_REQUESTS).increment(1.0);
+}
+
+pub(crate) fn request_end(protocol: &'static str, duration: f64, size: u64) {
+    metrics::gauge!(IN_FLIGHT_REQUESTS).decrement(1.0);
+    metrics::histogram!(REQUEST_DURATION_SECONDS, "protocol" => protocol).record(duration);
+    metrics::histogram!(RESPONSE_SIZE_BYTES, "protocol" => protocol).record(size as f64);
+}
+
+pub(crate) fn connection_open() {
+    metrics::gauge!(ACTIVE_CONNECTIONS).increment(1.0);
+}
+
+pub(crate) fn connection_close() {
+    metrics::gauge!(ACTIVE_CONNECTIONS).decrement(1.0);
+}
+
+pub(crate) fn error(kind: ErrorKind) {
+    if let Some(kind) = kind_label(kind) {
+        metrics::counter!(ERRORS_TOTAL, "kind" => kind).increment(1);
+    }
+}
+
+pub(crate) fn tls_handshake(outcome: &'static str) {
+    metrics::counter!(TLS_HANDSHAKES_TOTAL, "outcome" => outcome).increment(1);
+}
@@ -1,4 +1,5 @@
 use actix_server_alt::net::Stream;
+use http::Version;
 
 /// A collection of regular used http protocols
 #[derive(Copy, Clone, PartialOrd, PartialEq, Debug)]
@@ -28,3 +29,57 @@ impl AsProtocol for Stream {
         }
     }
 }
+
+/// Protocol a request arrived with. Inserted into extensions of every request by dispatchers.
+///
+/// Unlike [Version](http::Version) of a request it tells plain text and tls connections apart.
+/// Upgrading Http/1.1 connection to h2c is not supported. [H2c](RequestProtocol::H2c) is always
+/// Http/2 with prior knowledge.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RequestProtocol {
+    Http10,
+    Http10Tls,
+    Http11,
+    Http11Tls,
+    /// Http/2 over tls negotiated with alpn.
+    H2,
+    /// Http/2 over plain text.
+    H2c,
+    H3,
+}
+
+impl RequestProtocol {
+    /// Protocol of a Http/1 request. Http/0.9 requests are treated as Http/1.0.
+    #[cfg(feature = "http1")]
+    pub(crate) fn http1(version: Version, tls: bool) -> Self {
+        match (version, tls) {
+            (Version::HTTP_11, false) => Self::Http11,
+            (Version::HTTP_11, true) => Self::Http11Tls,
+            (_, false) => Self::Http10,
+            (_, true) => Self::Http10Tls,
+        }
+    }
+
+    #[cfg(feature = "http2")]
+    pub(crate) fn http2(tls: bool) -> Self {
+        if tls {
+            Self::H2
+        } else {
+            Self::H2c
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        match *self {
+            Self::Http10 | Self::Http10Tls => Version::HTTP_10,
+            Self::Http11 | Self::Http11Tls => Version::HTTP_11,
+            Self::H2 | Self::H2c => Version::HTTP_2,
+            Self::H3 => Version::HTTP_3,
+        }
+    }
+
+    /// Check if request arrived over tls. Http/3 is always over tls.
+    pub fn is_tls(&self) -> bool {
+        !matches!(*self, Self::Http10 | Self::Http11 | Self::H2c)
+    }
+}
@@ -1,50 +1,184 @@
-use std::{error, io};
+use std::{
+    convert::Infallible,
+    error,
+    fmt::{self, Debug, Display, Formatter},
+    io,
+};
 
 use bytes::Bytes;
-use http::{header, status::StatusCode, Response};
+use http::{header, status::StatusCode, Method, Response, Uri, Version};
 
 use super::body::ResponseBody;
 
 /// Helper trait for convert Service::Error type to Service::Response.
-// TODO: Add method to modify status code.
+///
+/// For error types implementing [Display] the [impl_response_error](crate::impl_response_error)
+/// macro can be used to implement this trait with a status code.
 pub trait ResponseError<Res> {
+    /// Status code of response converted from error. Default to 500.
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    /// Convert error to response. This is what dispatchers call on service errors.
     fn response_error(&mut self) -> Res;
+
+    /// Response with [status_code](ResponseError::status_code) and the Display output of
+    /// error as plain text body.
+    fn response<B>(&self) -> Response<ResponseBody<B>>
+    where
+        Self: Display,
+    {
+        text_response(self.status_code(), self.to_string().as_bytes())
+    }
 }
 
 impl<R, Res> ResponseError<Res> for Box<R>
 where
     R: ResponseError<Res> + ?Sized,
 {
+    fn status_code(&self) -> StatusCode {
+        R::status_code(&**self)
+    }
+
     fn response_error(&mut self) -> Res {
         R::response_error(&mut **self)
     }
 }
 
+/// Implement [ResponseError] for error types implementing [Display] with given status code.
+///
+/// # Example:
+/// ```rust
+/// use std::fmt;
+///
+/// use actix_http_alt::{http::StatusCode, impl_response_error};
+///
+/// #[derive(Debug)]
+/// struct NotFound;
+///
+/// impl fmt::Display for NotFound {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         f.write_str("not found")
+///     }
+/// }
+///
+/// impl_response_error!(NotFound, StatusCode::NOT_FOUND);
+/// ```
+#[macro_export]
+macro_rules! impl_response_error {
+    ($ty: ty, $status: expr) => {
+        impl<B> $crate::ResponseError<$crate::http::Response<$crate::ResponseBody<B>>> for $ty {
+            fn status_code(&self) -> $crate::http::StatusCode {
+                $status
+            }
+
+            fn response_error(&mut self) -> $crate::http::Response<$crate::ResponseBody<B>> {
+                $crate::ResponseError::<$crate::http::Response<$crate::ResponseBody<B>>>::response(self)
+            }
+        }
+    };
+}
+
 // implement ResponseError for common error types.
 
 impl<B> ResponseError<Response<ResponseBody<B>>> for Box<dyn error::Error> {
     fn response_error(&mut self) -> Response<ResponseBody<B>> {
-        internal_error(self.to_string().as_bytes())
+        internal_error(self)
     }
 }
 
 impl<B> ResponseError<Response<ResponseBody<B>>> for Box<dyn error::Error + Send> {
     fn response_error(&mut self) -> Response<ResponseBody<B>> {
-        internal_error(self.to_string().as_bytes())
+        internal_error(self)
+    }
+}
+
+impl<B> ResponseError<Response<ResponseBody<B>>> for Box<dyn error::Error + Send + Sync> {
+    fn response_error(&mut self) -> Response<ResponseBody<B>> {
+        internal_error(self)
     }
 }
 
 impl<B> ResponseError<Response<ResponseBody<B>>> for io::Error {
     fn response_error(&mut self) -> Response<ResponseBody<B>> {
-        internal_error(self.to_string().as_bytes())
+        internal_error(self)
+    }
+}
+
+impl<B> ResponseError<Response<ResponseBody<B>>> for http::Error {
+    fn response_error(&mut self) -> Response<ResponseBody<B>> {
+        internal_error(self)
+    }
+}
+
+impl<B> ResponseError<Response<ResponseBody<B>>> for Infallible {
+    fn response_error(&mut self) -> Response<ResponseBody<B>> {
+        match *self {}
+    }
+}
+
+/// Error paired with the status code of response converted from it.
+///
+/// # Example:
+/// ```rust
+/// use actix_http_alt::{http::StatusCode, ErrorStatus};
+///
+/// let res: Result<u8, _> = "256".parse::<u8>().map_err(|e| ErrorStatus(e, StatusCode::BAD_REQUEST));
+/// assert!(res.is_err());
+/// ```
+pub struct ErrorStatus<E>(pub E, pub StatusCode);
+
+impl<E> ErrorStatus<E> {
+    pub fn into_inner(self) -> E {
+        self.0
+    }
+}
+
+impl<E: Debug> Debug for ErrorStatus<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} ({})", self.0, self.1)
+    }
+}
+
+impl<E: Display> Display for ErrorStatus<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for ErrorStatus<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl<E: Display, B> ResponseError<Response<ResponseBody<B>>> for ErrorStatus<E> {
+    fn status_code(&self) -> StatusCode {
+        self.1
+    }
+
+    fn response_error(&mut self) -> Response<ResponseBody<B>> {
+        ResponseError::<Response<ResponseBody<B>>>::response(self)
     }
 }
 
-fn internal_error<B>(buf: &[u8]) -> Response<ResponseBody<B>> {
+/// 500 response. Display output of error is only written to body in debug build so internal
+/// detail does not leak in production.
+fn internal_error<B>(e: &dyn Display) -> Response<ResponseBody<B>> {
+    let msg = if cfg!(debug_assertions) {
+        e.to_string()
+    } else {
+        String::new()
+    };
+    text_response(StatusCode::INTERNAL_SERVER_ERROR, msg.as_bytes())
+}
+
+fn text_response<B>(status: StatusCode, buf: &[u8]) -> Response<ResponseBody<B>> {
     // TODO: write this to bytes mut directly.
     let bytes = Bytes::copy_from_slice(buf);
     Response::builder()
-        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .status(status)
         .header(
             header::CONTENT_TYPE,
             header::HeaderValue::from_static("text/plain; charset=utf-8"),
@@ -53,10 +187,237 @@ fn internal_error<B>(buf: &[u8]) -> Response<ResponseBody<B>> {
         .unwrap()
 }
 
-#[cfg(feature = "http1")]
-pub(super) fn header_too_large<B>() -> Response<ResponseBody<B>> {
-    Response::builder()
-        .status(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
-        .body(Bytes::new().into())
-        .unwrap()
+/// Formatter of error responses generated by dispatchers instead of service.
+/// (e.g. 431 for too large request head or 500 for panicked service call)
+///
+/// The status code of formatted response must match the given one and its headers must not
+/// exceed 8KiB. Otherwise the built in response with empty body is used. `connection`,
+/// `content-length` and `transfer-encoding` headers are ignored as they are managed by dispatcher.
+pub type ErrorFormatter = fn(StatusCode, ErrorContext<'_>) -> Response<Bytes>;
+
+/// Context of an error response generated by dispatcher.
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorContext<'a> {
+    method: Option<&'a Method>,
+    uri: Option<&'a Uri>,
+    version: Version,
+    #[cfg(feature = "http1")]
+    parse: Option<&'a crate::h1::Parse>,
+}
+
+impl<'a> ErrorContext<'a> {
+    pub(crate) fn new(version: Version) -> Self {
+        Self {
+            method: None,
+            uri: None,
+            version,
+            #[cfg(feature = "http1")]
+            parse: None,
+        }
+    }
+
+    pub(crate) fn request(mut self, method: &'a Method, uri: &'a Uri) -> Self {
+        self.method = Some(method);
+        self.uri = Some(uri);
+        self
+    }
+
+    /// Method of request. `None` when error happens before request head is parsed.
+    pub fn method(&self) -> Option<&Method> {
+        self.method
+    }
+
+    /// Uri of request. `None` when error happens before request head is parsed.
+    pub fn uri(&self) -> Option<&Uri> {
+        self.uri
+    }
+
+    /// Http version of connection.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    #[cfg(feature = "http1")]
+    pub(crate) fn parse(mut self, parse: &'a crate::h1::Parse) -> Self {
+        self.parse = Some(parse);
+        self
+    }
+
+    /// Decode failure of Http/1 request head that caused the error response. Status code of
+    /// response is [Parse::status_code](crate::h1::Parse::status_code).
+    #[cfg(feature = "http1")]
+    pub fn parse_error(&self) -> Option<&crate::h1::Parse> {
+        self.parse
+    }
+}
+
+const MAX_FORMATTED_HEAD: usize = 8192;
+
+/// Error response with given status. Formatted by formatter when there is one.
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub(crate) fn canned<B>(
+    formatter: Option<ErrorFormatter>,
+    status: StatusCode,
+    ctx: ErrorContext<'_>,
+) -> Response<ResponseBody<B>> {
+    formatter
+        .and_then(|f| std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(status, ctx))).ok())
+        .and_then(|res| sanitize(status, res))
+        .unwrap_or_else(|| Response::builder().status(status).body(Bytes::new().into()).unwrap())
+}
+
+/// 503 response for request rejected when service is not ready. Carry `retry-after` header of
+/// given duration rounded up to seconds.
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub(crate) fn unavailable<B>(
+    formatter: Option<ErrorFormatter>,
+    retry_after: std::time::Duration,
+    ctx: ErrorContext<'_>,
+) -> Response<ResponseBody<B>> {
+    let mut res = canned(formatter, StatusCode::SERVICE_UNAVAILABLE, ctx);
+    set_retry_after(&mut res, retry_after);
+    res
+}
+
+/// 429 response for request rejected by rate limit. Carry `retry-after` header of given duration
+/// rounded up to seconds.
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub(crate) fn too_many_requests<B>(
+    retry_after: std::time::Duration,
+    ctx: ErrorContext<'_>,
+) -> Response<ResponseBody<B>> {
+    let mut res = canned(None, StatusCode::TOO_MANY_REQUESTS, ctx);
+    set_retry_after(&mut res, retry_after);
+    res
+}
+
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+fn set_retry_after<B>(res: &mut Response<B>, retry_after: std::time::Duration) {
+    let mut secs = retry_after.as_secs();
+    if retry_after.subsec_nanos() > 0 || secs == 0 {
+        secs += 1;
+    }
+    res.headers_mut()
+        .insert(header::RETRY_AFTER, header::HeaderValue::from(secs));
+}
+
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+fn sanitize<B>(status: StatusCode, res: Response<Bytes>) -> Option<Response<ResponseBody<B>>> {
+    if res.status() != status {
+        return None;
+    }
+
+    let (mut parts, body) = res.into_parts();
+
+    for name in [header::CONNECTION, header::CONTENT_LENGTH, header::TRANSFER_ENCODING].iter() {
+        parts.headers.remove(name);
+    }
+
+    let len = parts
+        .headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len() + 4)
+        .sum::<usize>();
+
+    if len > MAX_FORMATTED_HEAD {
+        return None;
+    }
+
+    parts.version = Version::default();
+
+    Some(Response::from_parts(parts, body.into()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Forbidden;
+
+    impl fmt::Display for Forbidden {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("forbidden")
+        }
+    }
+
+    crate::impl_response_error!(Forbidden, StatusCode::FORBIDDEN);
+
+    #[test]
+    fn status_code() {
+        let res: Response<ResponseBody<()>> = Forbidden.response_error();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let mut e = Box::new(io::Error::from(io::ErrorKind::Other));
+        assert_eq!(
+            ResponseError::<Response<ResponseBody<()>>>::status_code(&e),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        let res: Response<ResponseBody<()>> = e.response_error();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn error_status() {
+        let mut e = ErrorStatus(Forbidden, StatusCode::UNAUTHORIZED);
+        let res: Response<ResponseBody<()>> = e.response_error();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(matches!(res.body(), ResponseBody::Bytes { bytes } if bytes == "forbidden"));
+
+        let mut e: Box<dyn error::Error + Send + Sync> = "boom".into();
+        let res: Response<ResponseBody<()>> = e.response_error();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn internal_error_body() {
+        // error detail is only written to body in debug build.
+        let detail = if cfg!(debug_assertions) { "secret" } else { "" };
+
+        let mut e = io::Error::new(io::ErrorKind::Other, "secret");
+        let res: Response<ResponseBody<()>> = e.response_error();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(matches!(res.body(), ResponseBody::Bytes { bytes } if bytes == detail));
+
+        let mut e: Box<dyn error::Error> = "secret".into();
+        let res: Response<ResponseBody<()>> = e.response_error();
+        assert!(matches!(res.body(), ResponseBody::Bytes { bytes } if bytes == detail));
+    }
+
+    #[cfg(feature = "http1")]
+    #[test]
+    fn canned_formatter() {
+        fn json(status: StatusCode, ctx: ErrorContext<'_>) -> Response<Bytes> {
+            let body = format!(
+                "{{\"status\":{},\"path\":\"{}\"}}",
+                status.as_u16(),
+                ctx.uri().unwrap().path()
+            );
+            Response::builder()
+                .status(status)
+                .header(header::CONNECTION, "keep-alive")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Bytes::from(body))
+                .unwrap()
+        }
+
+        fn wrong_status(_: StatusCode, _: ErrorContext<'_>) -> Response<Bytes> {
+            Response::new(Bytes::from_static(b"ok"))
+        }
+
+        let method = Method::GET;
+        let uri = Uri::from_static("/foo");
+        let ctx = ErrorContext::new(Version::HTTP_11).request(&method, &uri);
+
+        let res = canned::<()>(Some(json), StatusCode::INTERNAL_SERVER_ERROR, ctx);
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!res.headers().contains_key(header::CONNECTION));
+        assert!(matches!(res.body(), ResponseBody::Bytes { bytes } if bytes.len() == 28));
+
+        let res = canned::<()>(Some(wrong_status), StatusCode::INTERNAL_SERVER_ERROR, ctx);
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(matches!(res.body(), ResponseBody::Bytes { bytes } if bytes.is_empty()));
+    }
 }
@@ -1,6 +1,7 @@
 use std::{
     future::Future,
     marker::PhantomData,
+    sync::Arc,
     task::{Context, Poll},
 };
 
@@ -13,12 +14,21 @@ use tokio::{pin, select};
 
 use super::body::{RequestBody, ResponseBody};
 use super::config::HttpServiceConfig;
-use super::error::{BodyError, HttpServiceError, TimeoutError};
+use super::connection::ConnectionAddrs;
+use super::error::{BodyError, HttpServiceError};
 use super::flow::HttpFlow;
-use super::protocol::AsProtocol;
+use super::protocol::{AsProtocol, Protocol};
 use super::response::ResponseError;
-use super::tls::TlsStream;
-use super::util::{date::DateTimeTask, keep_alive::KeepAlive};
+use super::shutdown::ShutdownHandle;
+use super::stats::{HandshakeOutcome, OnConnectionClose, StatsRecorder};
+use super::tls::{
+    handshake::{self, accept_timeout},
+    HandshakeReason, TlsStream,
+};
+use super::util::{
+    date::{Clock, DateTimeTask},
+    keep_alive::KeepAlive,
+};
 
 /// General purpose http service
 pub struct HttpService<S, ReqB, X, U, A, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> {
@@ -26,6 +36,7 @@ pub struct HttpService<S, ReqB, X, U, A, const READ_BUF_LIMIT: usize, const WRIT
     pub(crate) date: DateTimeTask,
     pub(crate) flow: HttpFlow<S, X, U>,
     pub(crate) tls_acceptor: A,
+    pub(crate) on_close: Option<OnConnectionClose>,
     _body: PhantomData<ReqB>,
 }
 
@@ -39,15 +50,82 @@ impl<S, ReqB, X, U, A, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize
         expect: X,
         upgrade: Option<U>,
         tls_acceptor: A,
+    ) -> Self {
+        Self::with_shutdown_handle(config, service, expect, upgrade, tls_acceptor, ShutdownHandle::new())
+    }
+
+    /// Construct new Http Service draining with given [ShutdownHandle].
+    ///
+    /// Services sharing a handle are drained together. Useful when services are constructed and
+    /// called on connections accepted outside of this crate.
+    pub fn with_shutdown_handle(
+        config: HttpServiceConfig<READ_BUF_LIMIT, WRITE_BUF_LIMIT>,
+        service: S,
+        expect: X,
+        upgrade: Option<U>,
+        tls_acceptor: A,
+        shutdown: ShutdownHandle,
     ) -> Self {
         Self {
             config,
-            date: DateTimeTask::new(),
-            flow: HttpFlow::new(service, expect, upgrade),
+            date: DateTimeTask::new(config.date_refresh),
+            flow: HttpFlow::new(service, expect, upgrade, shutdown),
             tls_acceptor,
+            on_close: None,
             _body: PhantomData,
         }
     }
+
+    pub(crate) fn on_close(mut self, on_close: Option<OnConnectionClose>) -> Self {
+        self.on_close = on_close;
+        self
+    }
+
+    pub(crate) fn clock(mut self, clock: Option<Arc<dyn Clock>>) -> Self {
+        if let Some(clock) = clock {
+            self.date = DateTimeTask::with_clock(clock, self.config.date_refresh);
+        }
+        self
+    }
+
+    /// Handle for draining connections served by this service.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.flow.shutdown.clone()
+    }
+
+    /// Run a connection future. Connection accepted after shutdown is closed right away and live
+    /// connection is dropped when drain timeout is reached.
+    pub(crate) async fn drain<F>(&self, fut: F) -> Result<(), HttpServiceError>
+    where
+        F: Future<Output = Result<(), HttpServiceError>>,
+    {
+        let _guard = self.flow.shutdown.track();
+
+        if self.flow.shutdown.is_shutdown() {
+            return Ok(());
+        }
+
+        select! {
+            biased;
+            res = fut => res,
+            _ = self.flow.shutdown.drain_expired(self.date.wheel(), self.config.drain_timeout) => Err(HttpServiceError::DrainTimeout),
+        }
+    }
+
+    /// Recorder for a newly accepted connection. No op when there is no callback.
+    pub(crate) fn stats(&self) -> StatsRecorder {
+        StatsRecorder::new(self.on_close.is_some())
+    }
+
+    /// Call the callback with stats of a closed connection.
+    pub(crate) fn report_stats(&self, stats: &StatsRecorder, res: &Result<(), HttpServiceError>) {
+        let error = res.as_ref().err().map(HttpServiceError::kind);
+        if let Some(stats) = stats.finish(error) {
+            if let Some(ref on_close) = self.on_close {
+                on_close(stats);
+            }
+        }
+    }
 }
 
 impl<S, X, U, B, E, A, const READ_BUF_LIMIT: usize, const WRITE_BUF_LIMIT: usize> Service<ServerStream>
@@ -97,65 +175,779 @@ where
 
     fn call(&self, io: ServerStream) -> Self::Future<'_> {
         async move {
-            // tls accept timer.
-            let accept_dur = self.config.tls_accept_timeout;
-            let deadline = self.date.get().get().now() + accept_dur;
-            let timer = KeepAlive::new(deadline);
-            pin!(timer);
+            let stats = self.stats();
 
-            match io {
-                #[cfg(feature = "http3")]
-                ServerStream::Udp(udp) => {
-                    let dispatcher = super::h3::Dispatcher::new(udp, &self.flow);
+            let fut = async {
+                // tls accept timer.
+                let accept_dur = self.config.tls_accept_timeout;
+                let deadline = self.date.get().get().now() + accept_dur;
+                let timer = KeepAlive::new(self.date.wheel(), deadline);
+                pin!(timer);
 
-                    dispatcher.run().await?;
+                match io {
+                    #[cfg(feature = "http3")]
+                    ServerStream::Udp(udp) => {
+                        stats.set_protocol(Protocol::Http3);
 
-                    Ok(())
-                }
-                io => select! {
-                    biased;
-                    res = self.tls_acceptor.call(io) => {
-                        #[allow(unused_mut)]
-                        let mut tls_stream = res?;
-
-                        let protocol = tls_stream.as_protocol();
-
-                        // update timer to first request timeout.
-                        let request_dur = self.config.first_request_timeout;
-                        let deadline = self.date.get().get().now() + request_dur;
-                        timer.as_mut().update(deadline);
-
-                        match protocol {
-                            #[cfg(feature = "http1")]
-                            super::protocol::Protocol::Http1Tls | super::protocol::Protocol::Http1 => {
-                                let dispatcher = super::h1::Dispatcher::new(&mut tls_stream, timer.as_mut(), self.config, &*self.flow, self.date.get());
-
-                                match dispatcher.run().await {
-                                    Ok(_) | Err(super::h1::Error::Closed) => Ok(()),
-                                    Err(e) => Err(e.into()),
+                        let dispatcher =
+                            super::h3::Dispatcher::new(udp, &self.flow, self.date.wheel(), self.config, &stats);
+
+                        dispatcher.run().await?;
+
+                        Ok(())
+                    }
+                    io => {
+                        let addrs = ConnectionAddrs::from_stream(&io);
+                        let peer = addrs.and_then(|addrs| addrs.peer());
+
+                        match accept_timeout(self.tls_acceptor.call(io), timer.as_mut()).await {
+                            Some(res) => {
+                                #[allow(unused_mut)]
+                                let mut tls_stream = match res {
+                                    Ok(tls_stream) => tls_stream,
+                                    Err(e) => {
+                                        stats.tls_handshake(HandshakeOutcome::Failure);
+                                        let e = HttpServiceError::from(e).with_peer(peer);
+                                        if let HttpServiceError::HandshakeFailed(peer, ref err) = e {
+                                            handshake::log(peer, err.reason(), err);
+                                        }
+                                        return Err(e);
+                                    }
+                                };
+
+                                if !matches!(tls_stream, TlsStream::NoOp(_)) {
+                                    stats.tls_handshake(HandshakeOutcome::Success);
                                 }
-                            }
-                            #[cfg(feature = "http2")]
-                            super::protocol::Protocol::Http2 => {
-                                select! {
-                                    biased;
-                                    res = ::h2::server::handshake(tls_stream) => {
-                                        let mut conn = res?;
 
-                                        let dispatcher = super::h2::Dispatcher::new(&mut conn, timer.as_mut(), self.config.keep_alive_timeout, &self.flow, self.date.get());
-                                        dispatcher.run().await?;
+                                let protocol = tls_stream.as_protocol();
+                                stats.set_protocol(protocol);
+
+                                // update timer to first request timeout.
+                                let request_dur = self.config.first_request_timeout;
+                                let deadline = self.date.get().get().now() + request_dur;
+                                timer.as_mut().update(deadline);
+
+                                match protocol {
+                                    #[cfg(feature = "http1")]
+                                    Protocol::Http1Tls | Protocol::Http1 => {
+                                        #[cfg(feature = "io-uring")]
+                                        let mut tls_stream = match tls_stream {
+                                            TlsStream::NoOp(ServerStream::Tcp(tcp))
+                                                if self.config.io_uring && actix_server_alt::net::is_io_uring() =>
+                                            {
+                                                let mut io = super::util::uring_io::UringIo::new(tcp)
+                                                    .map_err(super::h1::Error::from)?;
+                                                let dispatcher = super::h1::Dispatcher::new(
+                                                    &mut io,
+                                                    timer.as_mut(),
+                                                    self.config,
+                                                    &*self.flow,
+                                                    self.date.get(),
+                                                    addrs,
+                                                    &stats,
+                                                );
 
-                                        Ok(())
+                                                return match dispatcher.run().await {
+                                                    Ok(_) | Err(super::h1::Error::Closed) => Ok(()),
+                                                    Err(e) => Err(e.into()),
+                                                };
+                                            }
+                                            tls_stream => tls_stream,
+                                        };
+
+                                        let dispatcher = super::h1::Dispatcher::new(
+                                            &mut tls_stream,
+                                            timer.as_mut(),
+                                            self.config,
+                                            &*self.flow,
+                                            self.date.get(),
+                                            addrs,
+                                            &stats,
+                                        );
+
+                                        match dispatcher.run().await {
+                                            Ok(_) | Err(super::h1::Error::Closed) => Ok(()),
+                                            Err(e) => Err(e.into()),
+                                        }
                                     }
-                                    _ = timer.as_mut() => Err(HttpServiceError::Timeout(TimeoutError::H2Handshake))
+                                    #[cfg(feature = "http2")]
+                                    Protocol::Http2 => {
+                                        let mut conn_data = super::connection::ConnectionData::from_io(&tls_stream);
+                                        if let Some(addrs) = addrs {
+                                            conn_data.set_addrs(addrs);
+                                        }
+
+                                        let budget = super::util::budget::Budget::new(self.config.http2_budget);
+
+                                        select! {
+                                            biased;
+                                            res = budget.run(::h2::server::handshake(super::util::budget::BudgetIo::new(super::util::stats_io::StatsIo::new(tls_stream, &stats), &budget))) => {
+                                                let mut conn = res?;
+
+                                                let dispatcher = super::h2::Dispatcher::new(&mut conn, timer.as_mut(), self.config, conn_data, &self.flow, self.date.get(), &stats, &budget);
+                                                dispatcher.run().await?;
+
+                                                Ok(())
+                                            }
+                                            _ = timer.as_mut() => Err(HttpServiceError::RequestHeadTimeout)
+                                        }
+                                    }
+                                    protocol => Err(HttpServiceError::UnknownProtocol(protocol)),
                                 }
                             }
-                            protocol => Err(HttpServiceError::UnknownProtocol(protocol))
+                            None => {
+                                stats.tls_handshake(HandshakeOutcome::Timeout);
+                                handshake::log(peer, HandshakeReason::Timeout, &"timed out");
+                                Err(HttpServiceError::handshake_timeout(peer))
+                            }
                         }
                     }
-                    _ = timer.as_mut() => Err(HttpServiceError::Timeout(TimeoutError::TlsAccept)),
-                },
+                }
+            };
+
+            let res = self.drain(fut).await;
+
+            self.report_stats(&stats, &res);
+
+            res
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use actix_service_alt::{fn_service, ServiceFactory};
+    use http::{StatusCode, Version};
+    use tokio::{
+        io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::Notify,
+        task::LocalSet,
+        time::timeout,
+    };
+
+    use crate::builder::HttpServiceBuilder;
+    use crate::connection::ConnectionAddrs;
+    use crate::protocol::RequestProtocol;
+    use crate::stats::{ConnectionStats, StatusCounts};
+    use crate::timeout::RequestTimeout;
+    use crate::util::{testing::tcp_pair, SystemClock};
+
+    async fn handler(req: Request<RequestBody>) -> Result<Response<ResponseBody>, Infallible> {
+        let addrs = req.extensions().get::<ConnectionAddrs>().copied();
+        Ok(Response::new(Bytes::from(format!("{:?}", addrs)).into()))
+    }
+
+    // write a request to client and read the whole response while io is served.
+    async fn serve<C, F>(mut client: C, io: ServerStream, on_close: F) -> String
+    where
+        C: AsyncRead + AsyncWrite + Unpin,
+        F: Fn(ConnectionStats) + Send + Sync + 'static,
+    {
+        let service = HttpServiceBuilder::new(fn_service(handler))
+            .on_connection_close(on_close)
+            .new_service(())
+            .await
+            .unwrap();
+
+        let request = async {
+            client
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut res = String::new();
+            client.read_to_string(&mut res).await.unwrap();
+            res
+        };
+
+        let (res, _) = tokio::join!(request, service.call(io));
+        res
+    }
+
+    #[tokio::test]
+    async fn connection_addrs_tcp() {
+        LocalSet::new()
+            .run_until(async {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let local = listener.local_addr().unwrap();
+                let client = TcpStream::connect(local).await.unwrap();
+                let peer = client.local_addr().unwrap();
+                let (io, _) = listener.accept().await.unwrap();
+
+                let res = serve(client, ServerStream::Tcp(io), |_| {}).await;
+
+                let addrs = ConnectionAddrs::Inet { peer, local };
+                assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+                assert!(res.ends_with(&format!("{:?}", Some(addrs))));
+            })
+            .await
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn connection_addrs_uds() {
+        LocalSet::new()
+            .run_until(async {
+                let (client, io) = tokio::net::UnixStream::pair().unwrap();
+
+                let res = serve(client, ServerStream::Unix(io), |_| {}).await;
+
+                assert!(res.ends_with(&format!("{:?}", Some(ConnectionAddrs::Uds))));
+            })
+            .await
+    }
+
+    #[cfg(feature = "http3")]
+    #[tokio::test]
+    async fn connection_addrs_udp() {
+        use actix_server_alt::net::UdpListenerBuilder;
+        use bytes::Buf;
+        use h3_quinn::quinn::{
+            Certificate, CertificateChain, ClientConfigBuilder, Endpoint, PrivateKey, ServerConfigBuilder,
+        };
+
+        use crate::util::poll_fn::poll_fn;
+
+        LocalSet::new()
+            .run_until(async {
+                let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+                let cert_der = cert.serialize_der().unwrap();
+                let key = PrivateKey::from_der(&cert.serialize_private_key_der()).unwrap();
+                let chain = CertificateChain::from_certs(vec![Certificate::from_der(&cert_der).unwrap()]);
+
+                let mut config = ServerConfigBuilder::default();
+                config.protocols(&[b"h3-29"]);
+                config.certificate(chain, key).unwrap();
+
+                let listener = UdpListenerBuilder::new("127.0.0.1:0".parse().unwrap(), config.build())
+                    .build()
+                    .unwrap();
+                let local = listener.local_addr();
+
+                let mut config = ClientConfigBuilder::default();
+                config.protocols(&[b"h3-29"]);
+                config
+                    .add_certificate_authority(Certificate::from_der(&cert_der).unwrap())
+                    .unwrap();
+
+                let mut builder = Endpoint::builder();
+                builder.default_client_config(config.build());
+                let (endpoint, _) = builder.bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+                let peer = endpoint.local_addr().unwrap();
+
+                let service = HttpServiceBuilder::new(fn_service(handler))
+                    .new_service(())
+                    .await
+                    .unwrap();
+
+                let request = async {
+                    let conn = endpoint.connect(&local, "localhost").unwrap().await.unwrap();
+                    let (mut driver, mut client) = h3::client::new(h3_quinn::Connection::new(conn)).await.unwrap();
+                    tokio::task::spawn_local(async move {
+                        let _ = poll_fn(|cx| driver.poll_close(cx)).await;
+                    });
+
+                    let req = Request::get("https://localhost/").body(()).unwrap();
+                    let mut stream = client.send_request(req).await.unwrap();
+                    stream.finish().await.unwrap();
+
+                    let res = stream.recv_response().await.unwrap();
+                    assert_eq!(res.status(), StatusCode::OK);
+
+                    let mut body = Vec::new();
+                    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+                        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+                    }
+
+                    // server side connection ends when client endpoint is closed.
+                    endpoint.close(0u32.into(), b"");
+
+                    String::from_utf8(body).unwrap()
+                };
+
+                let serve = async {
+                    let io = listener.accept().await.unwrap();
+                    service.call(ServerStream::Udp(io)).await
+                };
+
+                let (res, _) = tokio::join!(request, serve);
+
+                let addrs = ConnectionAddrs::Inet { peer, local };
+                assert_eq!(res, format!("{:?}", Some(addrs)));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn connection_stats() {
+        LocalSet::new()
+            .run_until(async {
+                let (client, io) = tcp_pair().await.unwrap();
+
+                let stats = Arc::new(Mutex::new(None));
+                let stats2 = stats.clone();
+                let res = serve(client, io, move |s| *stats2.lock().unwrap() = Some(s)).await;
+
+                let stats = stats.lock().unwrap().take().unwrap();
+                assert!(stats.id.is_some());
+                assert_eq!(stats.protocol, Some(Protocol::Http1));
+                assert_eq!(stats.requests, 1);
+                assert_eq!(
+                    stats.responses,
+                    StatusCounts {
+                        success: 1,
+                        ..Default::default()
+                    }
+                );
+                assert_eq!(stats.bytes_written, res.len() as u64);
+                assert!(stats.bytes_read > 0);
+                assert!(stats.error.is_none());
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn request_protocol() {
+        LocalSet::new()
+            .run_until(async {
+                let service = HttpServiceBuilder::new(fn_service(|req: Request<RequestBody>| async move {
+                    let protocol = req.extensions().get::<RequestProtocol>().copied();
+                    Ok::<Response<ResponseBody>, Infallible>(Response::new(
+                        Bytes::from(format!("{:?}", protocol)).into(),
+                    ))
+                }))
+                .new_service(())
+                .await
+                .unwrap();
+
+                for (req, protocol, version) in [
+                    (
+                        &b"GET / HTTP/1.0\r\nHost: localhost\r\n\r\n"[..],
+                        RequestProtocol::Http10,
+                        Version::HTTP_10,
+                    ),
+                    (
+                        &b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"[..],
+                        RequestProtocol::Http11,
+                        Version::HTTP_11,
+                    ),
+                ]
+                .iter()
+                {
+                    let (mut client, io) = tcp_pair().await.unwrap();
+
+                    let request = async {
+                        client.write_all(req).await.unwrap();
+                        let mut res = String::new();
+                        client.read_to_string(&mut res).await.unwrap();
+                        res
+                    };
+
+                    let (res, _) = tokio::join!(request, service.call(io));
+                    assert!(res.ends_with(&format!("{:?}", Some(protocol))));
+                    assert_eq!(protocol.version(), *version);
+                    assert!(!protocol.is_tls());
+                }
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn shutdown_in_flight() {
+        LocalSet::new()
+            .run_until(async {
+                let (mut client, io) = tcp_pair().await.unwrap();
+
+                // handler notifies when called and waits for release.
+                let called = Arc::new(Notify::new());
+                let release = Arc::new(Notify::new());
+                let (called2, release2) = (called.clone(), release.clone());
+                let service = HttpServiceBuilder::new(fn_service(move |_: Request<RequestBody>| {
+                    let (called, release) = (called2.clone(), release2.clone());
+                    async move {
+                        called.notify_one();
+                        release.notified().await;
+                        Ok::<Response<ResponseBody>, Infallible>(Response::new(ResponseBody::None))
+                    }
+                }))
+                .new_service(())
+                .await
+                .unwrap();
+
+                let handle = service.shutdown_handle();
+
+                let request = async {
+                    // keep alive request.
+                    client
+                        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                        .await
+                        .unwrap();
+
+                    called.notified().await;
+                    handle.shutdown();
+                    release.notify_one();
+
+                    let mut res = String::new();
+                    client.read_to_string(&mut res).await.unwrap();
+                    res
+                };
+
+                let (res, res2) = tokio::join!(request, service.call(io));
+
+                assert!(res2.is_ok());
+                assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+                assert!(res.contains("connection: close\r\n"));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn drain_watcher() {
+        LocalSet::new()
+            .run_until(async {
+                let called = Arc::new(Notify::new());
+                let release = Arc::new(Notify::new());
+                let (called2, release2) = (called.clone(), release.clone());
+                let handle = ShutdownHandle::new();
+                let builder = HttpServiceBuilder::new(fn_service(move |_: Request<RequestBody>| {
+                    let (called, release) = (called2.clone(), release2.clone());
+                    async move {
+                        called.notify_one();
+                        release.notified().await;
+                        Ok::<Response<ResponseBody>, Infallible>(Response::new(ResponseBody::None))
+                    }
+                }))
+                .shutdown_handle(handle.clone());
+
+                // services of different workers share the handle.
+                let service = builder.new_service(()).await.unwrap();
+                let service2 = builder.new_service(()).await.unwrap();
+
+                let (_idle, io) = tcp_pair().await.unwrap();
+                let (mut client, io2) = tcp_pair().await.unwrap();
+
+                let watcher = handle.drain_watcher();
+
+                let control = async {
+                    client
+                        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                        .await
+                        .unwrap();
+                    called.notified().await;
+                    assert_eq!(watcher.live_connections(), 2);
+
+                    service2.shutdown_handle().shutdown();
+                    assert!(service.shutdown_handle().is_shutdown());
+
+                    // in flight request is not finished.
+                    assert!(timeout(Duration::from_millis(50), watcher.drained()).await.is_err());
+                    assert_eq!(watcher.live_connections(), 1);
+
+                    release.notify_one();
+                    watcher.drained().await;
+                    assert_eq!(watcher.live_connections(), 0);
+
+                    let mut res = String::new();
+                    client.read_to_string(&mut res).await.unwrap();
+                    res
+                };
+
+                let (res, res2, res3) = tokio::join!(control, service.call(io), service2.call(io2));
+
+                assert!(res2.is_ok());
+                assert!(res3.is_ok());
+                assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn shutdown_generation() {
+        LocalSet::new()
+            .run_until(async {
+                let handle = ShutdownHandle::new();
+                let builder = HttpServiceBuilder::new(fn_service(handler)).shutdown_handle(handle.clone());
+
+                let service = builder.new_service(()).await.unwrap();
+                let watcher = handle.drain_watcher();
+                handle.shutdown();
+                assert!(service.shutdown_handle().is_shutdown());
+                watcher.drained().await;
+
+                // service constructed after shutdown keeps serving.
+                let service = builder.new_service(()).await.unwrap();
+                assert!(!service.shutdown_handle().is_shutdown());
+
+                let (mut client, io) = tcp_pair().await.unwrap();
+                let request = async {
+                    client
+                        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let mut res = String::new();
+                    client.read_to_string(&mut res).await.unwrap();
+                    res
+                };
+                let (res, res2) = tokio::join!(request, service.call(io));
+                assert!(res2.is_ok());
+                assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+                assert_eq!(watcher.live_connections(), 0);
+
+                // next shutdown drains it.
+                handle.shutdown();
+                assert!(service.shutdown_handle().is_shutdown());
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn shutdown_idle() {
+        LocalSet::new()
+            .run_until(async {
+                let builder = HttpServiceBuilder::new(fn_service(handler));
+                let service = builder.new_service(()).await.unwrap();
+
+                let (mut client, io) = tcp_pair().await.unwrap();
+
+                let handle = service.shutdown_handle();
+
+                // idle connection is closed once shutdown is started.
+                let shutdown = async {
+                    handle.shutdown();
+                    let mut res = String::new();
+                    client.read_to_string(&mut res).await.unwrap();
+                    res
+                };
+                let (res, res2) = tokio::join!(shutdown, service.call(io));
+                assert!(res.is_empty());
+                assert!(res2.is_ok());
+
+                // new connection is closed right away.
+                let (mut client, io) = tcp_pair().await.unwrap();
+                assert!(service.call(io).await.is_ok());
+                let mut res = String::new();
+                client.read_to_string(&mut res).await.unwrap();
+                assert!(res.is_empty());
+
+                // service constructed afterwards is not affected.
+                let service = builder.new_service(()).await.unwrap();
+                assert!(handle.is_shutdown());
+                assert!(!service.shutdown_handle().is_shutdown());
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn shutdown_drain_timeout() {
+        LocalSet::new()
+            .run_until(async {
+                let (mut client, io) = tcp_pair().await.unwrap();
+
+                let called = Arc::new(Notify::new());
+                let called2 = called.clone();
+                let config = HttpServiceConfig::new().drain_timeout(Duration::from_millis(10));
+                let service = HttpServiceBuilder::new(fn_service(move |_: Request<RequestBody>| {
+                    let called = called2.clone();
+                    async move {
+                        called.notify_one();
+                        std::future::pending::<Result<Response<ResponseBody>, Infallible>>().await
+                    }
+                }))
+                .config(config)
+                .new_service(())
+                .await
+                .unwrap();
+
+                let handle = service.shutdown_handle();
+
+                let request = async {
+                    client
+                        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                        .await
+                        .unwrap();
+                    called.notified().await;
+                    handle.shutdown();
+                };
+
+                let (_, res) = tokio::join!(request, service.call(io));
+
+                assert!(matches!(res, Err(HttpServiceError::DrainTimeout)));
+            })
+            .await
+    }
+
+    struct PendingBody;
+
+    impl Stream for PendingBody {
+        type Item = Result<Bytes, BodyError>;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Pending
+        }
+    }
+
+    async fn timeout_handler(req: Request<RequestBody>) -> Result<Response<ResponseBody<PendingBody>>, Infallible> {
+        if req.uri().path() == "/override" {
+            req.extensions()
+                .get::<RequestTimeout>()
+                .unwrap()
+                .set(Duration::from_millis(1));
+        }
+
+        match req.uri().path() {
+            "/body" => Ok(Response::new(ResponseBody::stream(PendingBody))),
+            _ => std::future::pending().await,
+        }
+    }
+
+    async fn timeout_request<S>(service: &S, path: &str) -> String
+    where
+        S: Service<ServerStream, Response = (), Error = HttpServiceError>,
+    {
+        let (mut client, io) = tcp_pair().await.unwrap();
+
+        let request = async {
+            let req = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+            client.write_all(req.as_bytes()).await.unwrap();
+            let mut res = String::new();
+            client.read_to_string(&mut res).await.unwrap();
+            res
+        };
+
+        let (res, res2) = tokio::join!(request, service.call(io));
+        assert!(matches!(res2, Err(HttpServiceError::ServiceCallTimeout)));
+        res
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        LocalSet::new()
+            .run_until(async {
+                let builder = |dur| {
+                    let config = HttpServiceConfig::new()
+                        .request_timeout(dur)
+                        .request_timeout_status(StatusCode::GATEWAY_TIMEOUT);
+                    HttpServiceBuilder::new(fn_service(timeout_handler)).config(config)
+                };
+
+                let service = builder(Duration::from_millis(10)).new_service(()).await.unwrap();
+
+                // service call is dropped and responded with configured status.
+                let res = timeout_request(&service, "/").await;
+                assert!(res.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"));
+                assert!(res.contains("connection: close\r\n"));
+
+                // connection is closed without finishing response body.
+                let res = timeout_request(&service, "/body").await;
+                assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+                assert!(res.ends_with("\r\n\r\n"));
+
+                // timeout is overridden by request extension.
+                let service = builder(Duration::from_secs(10)).new_service(()).await.unwrap();
+                let res = timeout_request(&service, "/override").await;
+                assert!(res.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"));
+            })
+            .await
+    }
+
+    // service fails readiness check when `fail` is true. Otherwise it's never ready.
+    #[derive(Clone, Copy)]
+    struct NotReady {
+        fail: bool,
+    }
+
+    impl ServiceFactory<Request<RequestBody>> for NotReady {
+        type Response = Response<ResponseBody>;
+        type Error = std::io::Error;
+        type Config = ();
+        type Service = Self;
+        type InitError = ();
+        type Future = std::future::Ready<Result<Self, ()>>;
+
+        fn new_service(&self, _: Self::Config) -> Self::Future {
+            std::future::ready(Ok(*self))
+        }
+    }
+
+    impl Service<Request<RequestBody>> for NotReady {
+        type Response = Response<ResponseBody>;
+        type Error = std::io::Error;
+        type Future<'f> = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            if self.fail {
+                Poll::Ready(Err(std::io::ErrorKind::Other.into()))
+            } else {
+                Poll::Pending
             }
         }
+
+        fn call(&self, _: Request<RequestBody>) -> Self::Future<'_> {
+            unreachable!("service is never ready")
+        }
+    }
+
+    #[tokio::test]
+    async fn service_not_ready() {
+        LocalSet::new()
+            .run_until(async {
+                for &fail in [false, true].iter() {
+                    let config = HttpServiceConfig::new().service_ready_timeout(Duration::from_millis(10));
+                    let service = HttpServiceBuilder::new(NotReady { fail })
+                        .config(config)
+                        .new_service(())
+                        .await
+                        .unwrap();
+
+                    let (mut client, io) = tcp_pair().await.unwrap();
+
+                    let request = async {
+                        client
+                            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                            .await
+                            .unwrap();
+                        let mut res = String::new();
+                        client.read_to_string(&mut res).await.unwrap();
+                        res
+                    };
+
+                    let (res, res2) = tokio::join!(request, service.call(io));
+
+                    // request is rejected and connection is closed.
+                    assert!(res.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+                    assert!(res.contains("retry-after: 1\r\n"));
+                    assert!(res.contains("connection: close\r\n"));
+                    assert_eq!(matches!(res2, Err(HttpServiceError::ServiceReady)), fail);
+                }
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn drop_service() {
+        LocalSet::new()
+            .run_until(async {
+                let builder = HttpServiceBuilder::new(fn_service(handler)).clock(SystemClock);
+
+                for _ in 0..64 {
+                    let service = builder.new_service(()).await.unwrap();
+                    tokio::task::yield_now().await;
+                    drop(service);
+                }
+
+                // date refresh tasks of dropped services are finished.
+                tokio::task::yield_now().await;
+                assert_eq!(Arc::strong_count(builder.clock.as_ref().unwrap()), 1);
+            })
+            .await
     }
 }
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use crate::util::timer::TimerWheel;
+
+/// Handle for draining live connections of a service. Obtained from
+/// [HttpService::shutdown_handle](crate::HttpService::shutdown_handle).
+///
+/// After [shutdown](ShutdownHandle::shutdown) is called live connections finish their in flight
+/// requests and close. Idle connections and connections accepted afterwards are closed right
+/// away. Connections still open after [drain timeout](crate::config::HttpServiceConfig::drain_timeout)
+/// are dropped.
+///
+/// Each service has its own handle unless one is shared through
+/// [HttpServiceBuilder::shutdown_handle](crate::HttpServiceBuilder::shutdown_handle). A shutdown
+/// only drains services constructed before it. Services constructed afterwards keep serving until
+/// the next shutdown.
+///
+/// # Examples
+/// ```rust
+/// # use actix_http_alt::ShutdownHandle;
+/// # async fn restart(handle: ShutdownHandle) {
+/// // hand the handle to services with HttpServiceBuilder::shutdown_handle. on restart signal:
+/// let watcher = handle.drain_watcher();
+/// handle.shutdown();
+/// // all connections of the services are closed. safe to exit.
+/// watcher.drained().await;
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shared: Arc<Shared>,
+    // number of shutdowns started before service of this handle was constructed.
+    generation: usize,
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                shutdowns: AtomicUsize::new(0),
+                live: Mutex::new(BTreeMap::new()),
+                notify: Notify::new(),
+            }),
+            generation: 0,
+        }
+    }
+
+    /// Start draining connections of services constructed before this call. Calling it again
+    /// before any new service is constructed has no effect.
+    ///
+    /// Safe to call from any thread. E.g. from a task waiting for a signal.
+    pub fn shutdown(&self) {
+        self.shared.shutdowns.fetch_add(1, Ordering::SeqCst);
+        self.shared.notify.notify_waiters();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shared.shutdowns.load(Ordering::SeqCst) > self.generation
+    }
+
+    /// Watcher for the end of draining connections.
+    pub fn drain_watcher(&self) -> DrainWatcher {
+        DrainWatcher {
+            shared: self.shared.clone(),
+            generation: self.generation,
+        }
+    }
+
+    /// Handle of a newly constructed service. It's only drained by shutdowns started afterwards.
+    pub(crate) fn subscribe(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            generation: self.shared.shutdowns.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Count a live connection until returned guard is dropped.
+    pub(crate) fn track(&self) -> ConnectionGuard {
+        *self.shared.live().entry(self.generation).or_insert(0) += 1;
+        ConnectionGuard {
+            shared: self.shared.clone(),
+            generation: self.generation,
+        }
+    }
+
+    /// Resolve when shutdown is started.
+    pub(crate) async fn wait(&self) {
+        loop {
+            // construct before checking so a notify in between is not missed.
+            let notified = self.shared.notify.notified();
+
+            if self.is_shutdown() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Resolve when shutdown is started and given drain timeout has passed. Timeout is counted
+    /// by given timer wheel of the service.
+    pub(crate) async fn drain_expired(&self, wheel: &Rc<TimerWheel>, dur: Duration) {
+        self.wait().await;
+        wheel.sleep(dur).await;
+    }
+}
+
+struct Shared {
+    shutdowns: AtomicUsize,
+    // live connection count of services keyed by their generation.
+    live: Mutex<BTreeMap<usize, usize>>,
+    notify: Notify,
+}
+
+impl Shared {
+    fn live(&self) -> MutexGuard<'_, BTreeMap<usize, usize>> {
+        self.live.lock().unwrap()
+    }
+}
+
+pub(crate) struct ConnectionGuard {
+    shared: Arc<Shared>,
+    generation: usize,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut live = self.shared.live();
+        let count = live.get_mut(&self.generation).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            live.remove(&self.generation);
+            drop(live);
+            self.shared.notify.notify_waiters();
+        }
+    }
+}
+
+/// Watcher obtained from [ShutdownHandle::drain_watcher].
+#[derive(Clone)]
+pub struct DrainWatcher {
+    shared: Arc<Shared>,
+    generation: usize,
+}
+
+impl DrainWatcher {
+    /// Number of live connections of services sharing the [ShutdownHandle].
+    pub fn live_connections(&self) -> usize {
+        self.shared.live().values().sum()
+    }
+
+    /// Resolve when shutdown is started and all connections of services constructed before it
+    /// are closed. Including connections dropped by drain timeout.
+    pub async fn drained(&self) {
+        loop {
+            // construct before checking so a notify in between is not missed.
+            let notified = self.shared.notify.notified();
+
+            let shutdowns = self.shared.shutdowns.load(Ordering::SeqCst);
+            if shutdowns > self.generation && self.shared.live().range(..shutdowns).next().is_none() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
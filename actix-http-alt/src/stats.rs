@@ -0,0 +1,449 @@
+//! Per connection counters reported to a user callback when connection is closed.
+//!
+//! Counters are only maintained when a callback is registered with
+//! [HttpServiceBuilder::on_connection_close](crate::HttpServiceBuilder::on_connection_close).
+//! With `metrics` feature the same recording points feed [metrics](crate::metrics).
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use http::StatusCode;
+
+use super::connection::ConnectionId;
+use super::error::ErrorKind;
+use super::protocol::Protocol;
+
+/// Callback called with [ConnectionStats] once a connection is closed.
+pub type OnConnectionClose = Arc<dyn Fn(ConnectionStats) + Send + Sync>;
+
+/// Statistics of a closed connection.
+#[derive(Clone, Debug)]
+pub struct ConnectionStats {
+    /// `None` when connection is closed before any dispatcher started. (e.g. tls handshake failed)
+    pub id: Option<ConnectionId>,
+    /// `None` when connection is closed before protocol is negotiated.
+    pub protocol: Option<Protocol>,
+    /// Number of requests received.
+    pub requests: u64,
+    /// Number of responses sent grouped by status class.
+    pub responses: StatusCounts,
+    /// Bytes read from connection after tls decryption.
+    ///
+    /// Http/3 only counts bytes of request and response body.
+    pub bytes_read: u64,
+    /// Bytes written to connection before tls encryption.
+    pub bytes_written: u64,
+    /// Time from connection accepted to closed.
+    pub duration: Duration,
+    /// Kind of error connection is closed with. `None` when closed normally.
+    pub error: Option<ErrorKind>,
+}
+
+/// Response counts by status class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatusCounts {
+    /// 1xx
+    pub informational: u64,
+    /// 2xx
+    pub success: u64,
+    /// 3xx
+    pub redirection: u64,
+    /// 4xx
+    pub client_error: u64,
+    /// 5xx
+    pub server_error: u64,
+}
+
+/// Recorder of counters shared by dispatcher and its request tasks. No op when disabled.
+#[derive(Clone)]
+pub(crate) struct StatsRecorder {
+    counters: Option<Rc<Counters>>,
+    #[cfg(feature = "metrics")]
+    metrics: Rc<ConnectionMetrics>,
+}
+
+struct Counters {
+    start: Instant,
+    id: Cell<Option<ConnectionId>>,
+    protocol: Cell<Option<Protocol>>,
+    requests: Cell<u64>,
+    // indexed by status class. 1xx at 0.
+    responses: [Cell<u64>; 5],
+    bytes_read: Cell<u64>,
+    bytes_written: Cell<u64>,
+}
+
+impl StatsRecorder {
+    pub(crate) fn new(enabled: bool) -> Self {
+        let counters = if enabled {
+            Some(Rc::new(Counters {
+                start: Instant::now(),
+                id: Cell::new(None),
+                protocol: Cell::new(None),
+                requests: Cell::new(0),
+                responses: Default::default(),
+                bytes_read: Cell::new(0),
+                bytes_written: Cell::new(0),
+            }))
+        } else {
+            None
+        };
+
+        Self {
+            counters,
+            #[cfg(feature = "metrics")]
+            metrics: Rc::new(ConnectionMetrics::new()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_id(&self, id: ConnectionId) {
+        if let Some(ref c) = self.counters {
+            c.id.set(Some(id));
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_protocol(&self, protocol: Protocol) {
+        if let Some(ref c) = self.counters {
+            c.protocol.set(Some(protocol));
+        }
+
+        #[cfg(feature = "metrics")]
+        self.metrics
+            .protocol
+            .set(crate::metrics::protocol_label(Some(protocol)));
+    }
+
+    /// Record a new request. Returned [RequestStats] lives until response of it is finished.
+    #[inline]
+    pub(crate) fn request(&self) -> RequestStats {
+        if let Some(ref c) = self.counters {
+            add(&c.requests, 1);
+        }
+
+        RequestStats::new(self)
+    }
+
+    #[inline]
+    pub(crate) fn response(&self, status: StatusCode) {
+        if let Some(ref c) = self.counters {
+            if let Some(count) = c.responses.get((status.as_u16() / 100) as usize - 1) {
+                add(count, 1);
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::response(self.metrics.protocol.get(), status);
+    }
+
+    #[inline]
+    pub(crate) fn read(&self, n: usize) {
+        if let Some(ref c) = self.counters {
+            add(&c.bytes_read, n as u64);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn written(&self, n: usize) {
+        if let Some(ref c) = self.counters {
+            add(&c.bytes_written, n as u64);
+        }
+    }
+
+    /// Record outcome of tls handshake.
+    #[inline]
+    pub(crate) fn tls_handshake(&self, _outcome: HandshakeOutcome) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::tls_handshake(match _outcome {
+            HandshakeOutcome::Success => "success",
+            HandshakeOutcome::Failure => "failure",
+            HandshakeOutcome::Timeout => "timeout",
+        });
+    }
+
+    /// Snapshot of counters. `None` when disabled.
+    ///
+    /// Must be called once when connection is closed.
+    pub(crate) fn finish(&self, error: Option<ErrorKind>) -> Option<ConnectionStats> {
+        #[cfg(feature = "metrics")]
+        if let Some(kind) = error {
+            crate::metrics::error(kind);
+        }
+
+        self.counters.as_ref().map(|c| ConnectionStats {
+            id: c.id.get(),
+            protocol: c.protocol.get(),
+            requests: c.requests.get(),
+            responses: StatusCounts {
+                informational: c.responses[0].get(),
+                success: c.responses[1].get(),
+                redirection: c.responses[2].get(),
+                client_error: c.responses[3].get(),
+                server_error: c.responses[4].get(),
+            },
+            bytes_read: c.bytes_read.get(),
+            bytes_written: c.bytes_written.get(),
+            duration: c.start.elapsed(),
+            error,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum HandshakeOutcome {
+    Success,
+    Failure,
+    Timeout,
+}
+
+/// Recorder of one request. Duration and response size are recorded when it's dropped.
+pub(crate) struct RequestStats {
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    stats: StatsRecorder,
+    #[cfg(feature = "metrics")]
+    protocol: &'static str,
+    #[cfg(feature = "metrics")]
+    start: Instant,
+    #[cfg(feature = "metrics")]
+    size: Cell<u64>,
+}
+
+impl RequestStats {
+    fn new(_stats: &StatsRecorder) -> Self {
+        #[cfg(feature = "metrics")]
+        crate::metrics::request_start();
+
+        Self {
+            #[cfg(any(feature = "http2", feature = "http3"))]
+            stats: _stats.clone(),
+            #[cfg(feature = "metrics")]
+            protocol: _stats.metrics.protocol.get(),
+            #[cfg(feature = "metrics")]
+            start: Instant::now(),
+            #[cfg(feature = "metrics")]
+            size: Cell::new(0),
+        }
+    }
+
+    /// Record response status on connection.
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    #[inline]
+    pub(crate) fn response(&self, status: StatusCode) {
+        self.stats.response(status);
+    }
+
+    /// Record bytes of response body. Bytes written to connection are counted separately.
+    #[inline]
+    pub(crate) fn body(&self, _n: usize) {
+        #[cfg(feature = "metrics")]
+        add(&self.size, _n as u64);
+    }
+
+    /// Record bytes of response body that are also counted as bytes written to connection.
+    #[cfg(feature = "http3")]
+    #[inline]
+    pub(crate) fn written(&self, n: usize) {
+        self.stats.written(n);
+        self.body(n);
+    }
+
+    /// Record an error that does not close the connection. e.g. timeout of one Http/2 stream.
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    #[inline]
+    pub(crate) fn error(&self, _kind: ErrorKind) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::error(_kind);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for RequestStats {
+    fn drop(&mut self) {
+        crate::metrics::request_end(self.protocol, self.start.elapsed().as_secs_f64(), self.size.get());
+    }
+}
+
+#[cfg(feature = "metrics")]
+struct ConnectionMetrics {
+    protocol: Cell<&'static str>,
+}
+
+#[cfg(feature = "metrics")]
+impl ConnectionMetrics {
+    fn new() -> Self {
+        crate::metrics::connection_open();
+        Self {
+            protocol: Cell::new(crate::metrics::protocol_label(None)),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for ConnectionMetrics {
+    fn drop(&mut self) {
+        crate::metrics::connection_close();
+    }
+}
+
+#[inline(always)]
+fn add(cell: &Cell<u64>, n: u64) {
+    cell.set(cell.get().wrapping_add(n));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recorder() {
+        let stats = StatsRecorder::new(false);
+        stats.request();
+        assert!(stats.finish(None).is_none());
+
+        let stats = StatsRecorder::new(true);
+        stats.set_protocol(Protocol::Http2);
+        stats.request();
+        stats.request();
+        stats.response(StatusCode::OK);
+        stats.response(StatusCode::NOT_FOUND);
+        stats.read(10);
+        stats.clone().written(20);
+
+        let res = stats.finish(Some(ErrorKind::Io)).unwrap();
+        assert!(res.id.is_none());
+        assert_eq!(res.protocol, Some(Protocol::Http2));
+        assert_eq!(res.requests, 2);
+        assert_eq!(
+            res.responses,
+            StatusCounts {
+                success: 1,
+                client_error: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!((res.bytes_read, res.bytes_written), (10, 20));
+        assert_eq!(res.error, Some(ErrorKind::Io));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        use std::sync::{Arc, Mutex};
+
+        use metrics_crate::{
+            Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString,
+            Unit,
+        };
+
+        // log of every metric update as "name{labels} value".
+        #[derive(Clone, Default)]
+        struct Log(Arc<Mutex<Vec<String>>>);
+
+        struct Handle(String, Log);
+
+        impl Handle {
+            fn push(&self, value: impl std::fmt::Display) {
+                (self.1).0.lock().unwrap().push(format!("{} {}", self.0, value));
+            }
+        }
+
+        impl CounterFn for Handle {
+            fn increment(&self, value: u64) {
+                self.push(value);
+            }
+
+            fn absolute(&self, _: u64) {}
+        }
+
+        impl GaugeFn for Handle {
+            fn increment(&self, value: f64) {
+                self.push(value);
+            }
+
+            fn decrement(&self, value: f64) {
+                self.push(-value);
+            }
+
+            fn set(&self, _: f64) {}
+        }
+
+        impl HistogramFn for Handle {
+            fn record(&self, value: f64) {
+                self.push(value);
+            }
+        }
+
+        impl Log {
+            fn handle(&self, key: &Key) -> Arc<Handle> {
+                let labels = key
+                    .labels()
+                    .map(|l| format!("{}={}", l.key(), l.value()))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                Arc::new(Handle(format!("{}{{{}}}", key.name(), labels), self.clone()))
+            }
+        }
+
+        impl Recorder for Log {
+            fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+            fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+                Counter::from_arc(self.handle(key))
+            }
+
+            fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+                Gauge::from_arc(self.handle(key))
+            }
+
+            fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+                Histogram::from_arc(self.handle(key))
+            }
+        }
+
+        let log = Log::default();
+
+        metrics_crate::with_local_recorder(&log, || {
+            let stats = StatsRecorder::new(false);
+            stats.set_protocol(Protocol::Http2);
+
+            let req = stats.request();
+            stats.response(StatusCode::CONTINUE);
+            stats.response(StatusCode::NOT_FOUND);
+            req.body(5);
+            drop(req);
+
+            stats.tls_handshake(HandshakeOutcome::Success);
+            assert!(stats.finish(Some(ErrorKind::Io)).is_none());
+        });
+
+        let log = log.0.lock().unwrap();
+        let log = log.iter().map(String::as_str).collect::<Vec<_>>();
+
+        assert_eq!(
+            log[..4],
+            [
+                "http_server_active_connections{} 1",
+                "http_server_in_flight_requests{} 1",
+                "http_server_requests_total{protocol=http2,status_class=4xx} 1",
+                "http_server_in_flight_requests{} -1",
+            ]
+        );
+        assert!(log[4].starts_with("http_server_request_duration_seconds{protocol=http2} "));
+        assert_eq!(
+            log[5..],
+            [
+                "http_server_response_size_bytes{protocol=http2} 5",
+                "http_server_tls_handshakes_total{outcome=success} 1",
+                "http_server_errors_total{kind=io} 1",
+                "http_server_active_connections{} -1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::ready;
+use http::Extensions;
+use tokio::time::Instant;
+
+use crate::util::timer::{Timer, TimerWheel};
+
+/// Request extension for overriding [request timeout](crate::config::HttpServiceConfig::request_timeout)
+/// of a single request.
+///
+/// Inserted by dispatcher when request timeout is configured. The timeout is counted from the
+/// time service is ready to be called and an override applies as long as the response is not
+/// finished.
+#[derive(Clone, Debug)]
+pub struct RequestTimeout(Arc<AtomicU64>);
+
+impl RequestTimeout {
+    fn new(dur: Duration) -> Self {
+        Self(Arc::new(AtomicU64::new(as_millis(dur))))
+    }
+
+    /// Current timeout of request.
+    pub fn get(&self) -> Duration {
+        Duration::from_millis(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Override timeout of request.
+    pub fn set(&self, dur: Duration) {
+        self.0.store(as_millis(dur), Ordering::Relaxed);
+    }
+}
+
+fn as_millis(dur: Duration) -> u64 {
+    dur.as_millis().min(u64::MAX as u128) as u64
+}
+
+/// Timer of request timeout. Never resolve when request timeout is not configured.
+/// Resolve only once and stay pending afterwards.
+///
+/// Timer is an entry of the connection's [TimerWheel] and fires up to one tick late.
+pub(crate) struct RequestTimer {
+    timer: Option<Timer>,
+    start: Instant,
+    dur: Duration,
+    timeout: Option<RequestTimeout>,
+}
+
+impl RequestTimer {
+    /// Construct timer with given timeout and insert [RequestTimeout] into request extensions
+    /// when there is one.
+    pub(crate) fn new(wheel: &Rc<TimerWheel>, dur: Option<Duration>, extensions: &mut Extensions) -> Self {
+        let start = wheel.now();
+
+        let timeout = dur.map(|dur| {
+            let timeout = RequestTimeout::new(dur);
+            extensions.insert(timeout.clone());
+            timeout
+        });
+
+        let dur = dur.unwrap_or_default();
+
+        Self {
+            // no wheel entry is held when there is no timeout.
+            timer: timeout.as_ref().map(|_| wheel.timer(start + dur)),
+            start,
+            dur,
+            timeout,
+        }
+    }
+}
+
+impl Future for RequestTimer {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        match (this.timeout.as_ref(), this.timer.as_ref()) {
+            (Some(timeout), Some(timer)) => {
+                // pick up override of request timeout.
+                let dur = timeout.get();
+                if dur != this.dur {
+                    this.dur = dur;
+                    timer.reset(this.start + dur);
+                }
+
+                ready!(timer.poll_expired(cx));
+
+                this.timeout = None;
+                this.timer = None;
+
+                Poll::Ready(())
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures_task::noop_waker_ref;
+
+    use crate::util::date::{Clock, ManualClock};
+
+    #[test]
+    fn request_timer() {
+        let clock = ManualClock::new();
+        let wheel = TimerWheel::new(Arc::new(clock.clone()), Duration::from_millis(500));
+        let cx = &mut Context::from_waker(noop_waker_ref());
+
+        let advance = |dur| {
+            clock.advance(dur);
+            wheel.advance(clock.now());
+        };
+
+        let mut ext = Extensions::new();
+        let mut timer = RequestTimer::new(&wheel, None, &mut ext);
+        assert!(ext.get::<RequestTimeout>().is_none());
+        advance(Duration::from_secs(60));
+        assert!(Pin::new(&mut timer).poll(cx).is_pending());
+
+        let mut ext = Extensions::new();
+        let mut timer = RequestTimer::new(&wheel, Some(Duration::from_secs(10)), &mut ext);
+
+        let timeout_ext = ext.get::<RequestTimeout>().unwrap();
+        assert_eq!(timeout_ext.get(), Duration::from_secs(10));
+        advance(Duration::from_secs(1));
+        assert!(Pin::new(&mut timer).poll(cx).is_pending());
+
+        // override is picked up on next poll.
+        timeout_ext.set(Duration::from_secs(1));
+        assert!(Pin::new(&mut timer).poll(cx).is_ready());
+        // resolve only once.
+        advance(Duration::from_secs(60));
+        assert!(Pin::new(&mut timer).poll(cx).is_pending());
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use actix_server_alt::net::{AsyncReadWrite, Stream as ServerStream};
+use actix_service_alt::{Service, ServiceFactory};
+use bytes::BufMut;
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
+
+use crate::connection::{ConnectionData, OnConnect, PeerCertificates};
+use crate::error::HttpServiceError;
+use crate::protocol::{AsProtocol, Protocol};
+
+use super::handshake::TlsError;
+
+/// Information of an established tls connection.
+///
+/// Http dispatchers select protocol from [alpn](TlsInfo::alpn) and attach
+/// [peer_certs](TlsInfo::peer_certs) to request extensions.
+///
+/// Every request from a tls connection carries it in extensions as `Arc<TlsInfo>`. Requests
+/// from plaintext connections do not have it. It's also passed to the on_handshake hook of tls
+/// acceptors after a successful handshake.
+#[derive(Clone, Debug, Default)]
+pub struct TlsInfo {
+    pub(crate) alpn: Option<Vec<u8>>,
+    pub(crate) server_name: Option<String>,
+    pub(crate) version: Option<String>,
+    pub(crate) cipher: Option<String>,
+    pub(crate) peer_certs: Option<PeerCertificates>,
+}
+
+impl TlsInfo {
+    /// Application protocol selected by ALPN. `h2` leads to http/2 and everything else to
+    /// http/1.
+    pub fn alpn(&self) -> Option<&[u8]> {
+        self.alpn.as_deref()
+    }
+
+    /// Application protocol selected by ALPN in string form. `None` when not valid UTF-8.
+    pub fn alpn_str(&self) -> Option<&str> {
+        self.alpn().and_then(|proto| std::str::from_utf8(proto).ok())
+    }
+
+    /// Host name from server name indication.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Tls protocol version. e.g. `TLSv1.3`.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Name of cipher suite.
+    pub fn cipher(&self) -> Option<&str> {
+        self.cipher.as_deref()
+    }
+
+    /// Certificate chain presented by client.
+    pub fn peer_certs(&self) -> Option<&PeerCertificates> {
+        self.peer_certs.as_ref()
+    }
+
+    pub fn set_alpn<P: Into<Vec<u8>>>(&mut self, proto: P) {
+        self.alpn = Some(proto.into());
+    }
+
+    pub fn set_server_name<N: Into<String>>(&mut self, name: N) {
+        self.server_name = Some(name.into());
+    }
+
+    pub fn set_version<V: Into<String>>(&mut self, version: V) {
+        self.version = Some(version.into());
+    }
+
+    pub fn set_cipher<C: Into<String>>(&mut self, cipher: C) {
+        self.cipher = Some(cipher.into());
+    }
+
+    pub fn set_peer_certs(&mut self, certs: PeerCertificates) {
+        self.peer_certs = Some(certs);
+    }
+}
+
+/// Trait for accepting tls connections. Implement it to use a tls implementation not bundled
+/// with this crate.
+///
+/// Built in openssl, rustls and native-tls acceptor services implement this trait.
+pub trait TlsAccept<St> {
+    /// Stream type after successful tls handshake.
+    type Stream: AsyncReadWrite;
+
+    type Error;
+
+    type Future<'f>: Future<Output = Result<(Self::Stream, TlsInfo), Self::Error>>;
+
+    fn accept(&self, io: St) -> Self::Future<'_>;
+}
+
+/// Adapter for using a [TlsAccept] type as tls acceptor of [HttpServiceBuilder](crate::HttpServiceBuilder).
+#[derive(Clone)]
+pub struct CustomTlsAcceptorService<T> {
+    acceptor: T,
+}
+
+impl<T> CustomTlsAcceptorService<T> {
+    pub fn new(acceptor: T) -> Self {
+        Self { acceptor }
+    }
+}
+
+impl<T> ServiceFactory<ServerStream> for CustomTlsAcceptorService<T>
+where
+    T: TlsAccept<ServerStream> + Clone + 'static,
+    T::Stream: 'static,
+    T::Error: Debug + Send + Sync + 'static,
+{
+    type Response = super::TlsStream;
+    type Error = HttpServiceError;
+    type Config = ();
+    type Service = Self;
+    type InitError = ();
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        let this = self.clone();
+        async move { Ok(this) }
+    }
+}
+
+impl<T> Service<ServerStream> for CustomTlsAcceptorService<T>
+where
+    T: TlsAccept<ServerStream> + 'static,
+    T::Stream: 'static,
+    T::Error: Debug + Send + Sync + 'static,
+{
+    type Response = super::TlsStream;
+    type Error = HttpServiceError;
+
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, io: ServerStream) -> Self::Future<'_> {
+        async move {
+            match self.acceptor.accept(io).await {
+                Ok((stream, info)) => Ok(super::TlsStream::Custom(CustomTlsStream {
+                    stream: Box::new(stream),
+                    info: Arc::new(info),
+                })),
+                Err(e) => Err(HttpServiceError::HandshakeFailed(None, TlsError::Custom(Box::new(e)))),
+            }
+        }
+    }
+}
+
+/// Object safe subset of [AsyncReadWrite].
+trait DynStream: AsyncRead + AsyncWrite + Unpin {
+    fn ready_dyn(&mut self, interest: Interest) -> Pin<Box<dyn Future<Output = io::Result<Ready>> + '_>>;
+
+    fn poll_read_ready_dyn(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    fn poll_write_ready_dyn(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    fn try_read_buf_dyn(&mut self, buf: &mut dyn BufMut) -> io::Result<usize>;
+
+    fn try_write_dyn(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    fn try_write_vectored_dyn(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize>;
+}
+
+impl<S: AsyncReadWrite> DynStream for S {
+    fn ready_dyn(&mut self, interest: Interest) -> Pin<Box<dyn Future<Output = io::Result<Ready>> + '_>> {
+        Box::pin(self.ready(interest))
+    }
+
+    fn poll_read_ready_dyn(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_read_ready(cx)
+    }
+
+    fn poll_write_ready_dyn(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_write_ready(cx)
+    }
+
+    fn try_read_buf_dyn(&mut self, mut buf: &mut dyn BufMut) -> io::Result<usize> {
+        self.try_read_buf(&mut buf)
+    }
+
+    fn try_write_dyn(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.try_write(buf)
+    }
+
+    fn try_write_vectored_dyn(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.try_write_vectored(bufs)
+    }
+}
+
+/// Stream from a [TlsAccept] type with its stream type erased.
+pub struct CustomTlsStream {
+    stream: Box<dyn DynStream>,
+    info: Arc<TlsInfo>,
+}
+
+impl CustomTlsStream {
+    pub fn info(&self) -> &TlsInfo {
+        &self.info
+    }
+}
+
+impl AsProtocol for CustomTlsStream {
+    fn as_protocol(&self) -> Protocol {
+        self.info
+            .alpn()
+            .map(|proto| {
+                if proto == b"h2" {
+                    Protocol::Http2
+                } else {
+                    Protocol::Http1Tls
+                }
+            })
+            .unwrap_or(Protocol::Http1Tls)
+    }
+}
+
+impl OnConnect for CustomTlsStream {
+    fn on_connect(&self, data: &mut ConnectionData) {
+        if let Some(certs) = self.info.peer_certs() {
+            data.set_peer_certificates(certs.clone());
+        }
+        data.set_tls_info(self.info.clone());
+    }
+}
+
+impl AsyncRead for CustomTlsStream {
+    #[inline]
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CustomTlsStream {
+    #[inline]
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.get_mut().stream).poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.get_mut().stream).poll_shutdown(cx)
+    }
+
+    #[inline]
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+impl AsyncReadWrite for CustomTlsStream {
+    type ReadyFuture<'f> = impl Future<Output = io::Result<Ready>>;
+
+    #[inline]
+    fn ready(&mut self, interest: Interest) -> Self::ReadyFuture<'_> {
+        self.stream.ready_dyn(interest)
+    }
+
+    #[inline]
+    fn try_read_buf<B: BufMut>(&mut self, buf: &mut B) -> io::Result<usize> {
+        self.stream.try_read_buf_dyn(buf)
+    }
+
+    #[inline]
+    fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.try_write_dyn(buf)
+    }
+
+    #[inline]
+    fn try_write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.stream.try_write_vectored_dyn(bufs)
+    }
+
+    #[inline]
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream.poll_read_ready_dyn(cx)
+    }
+
+    #[inline]
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream.poll_write_ready_dyn(cx)
+    }
+}
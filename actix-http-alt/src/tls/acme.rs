@@ -0,0 +1,82 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+
+use tokio_rustls::rustls::{sign::CertifiedKey, NoClientAuth, ResolvesServerCert, ServerConfig};
+
+use super::client_hello::ClientHello;
+
+/// ALPN protocol of acme TLS-ALPN-01 challenge.
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// Resolver of certificates for acme TLS-ALPN-01 challenges.
+pub trait ChallengeResolver: Send + Sync {
+    /// Return the self-signed challenge certificate for given server name.
+    ///
+    /// Return `None` when there is no pending challenge for the name and the connection
+    /// would be closed.
+    fn resolve(&self, server_name: &str) -> Option<CertifiedKey>;
+}
+
+/// A handle to swap challenge resolver of a running rustls acceptor.
+#[derive(Clone, Default)]
+pub struct AcmeHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    // checked for every connection so the lock is only taken for challenge connections.
+    enabled: AtomicBool,
+    resolver: RwLock<Option<Arc<dyn ChallengeResolver>>>,
+}
+
+impl AcmeHandle {
+    /// Set or replace challenge resolver. Takes effect on the next accepted connection.
+    pub fn set<R>(&self, resolver: R)
+    where
+        R: ChallengeResolver + 'static,
+    {
+        *self.inner.resolver.write().unwrap() = Some(Arc::new(resolver));
+        self.inner.enabled.store(true, Ordering::Release);
+    }
+
+    /// Remove challenge resolver. acme-tls/1 connections are handled like others afterwards.
+    pub fn clear(&self) {
+        self.inner.enabled.store(false, Ordering::Release);
+        *self.inner.resolver.write().unwrap() = None;
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn get(&self) -> Option<Arc<dyn ChallengeResolver>> {
+        self.inner.resolver.read().unwrap().clone()
+    }
+}
+
+/// Check if ClientHello starts an acme-tls/1 challenge.
+pub(crate) fn is_challenge(hello: &ClientHello) -> bool {
+    hello.alpn().any(|proto| proto == ACME_TLS_ALPN)
+}
+
+/// Server config for challenge handshake with given challenge certificate.
+///
+/// Built from scratch so client certificate verification and other settings of the server config
+/// do not apply to the acme server.
+pub(crate) fn challenge_config(key: CertifiedKey) -> Arc<ServerConfig> {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.cert_resolver = Arc::new(Challenge(key));
+    config.alpn_protocols = vec![ACME_TLS_ALPN.to_vec()];
+    Arc::new(config)
+}
+
+struct Challenge(CertifiedKey);
+
+impl ResolvesServerCert for Challenge {
+    fn resolve(&self, _: tokio_rustls::rustls::ClientHello) -> Option<CertifiedKey> {
+        Some(self.0.clone())
+    }
+}
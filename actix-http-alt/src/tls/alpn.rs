@@ -0,0 +1,64 @@
+use log::warn;
+
+/// ALPN protocols supported by enabled features in the order of preference.
+pub(crate) fn default_protocols() -> Vec<Vec<u8>> {
+    vec![
+        #[cfg(feature = "http2")]
+        b"h2".to_vec(),
+        #[cfg(feature = "http1")]
+        b"http/1.1".to_vec(),
+    ]
+}
+
+/// Warn about ALPN protocols that are advertised but not supported by enabled features.
+pub(crate) fn check_protocols<'a>(protos: impl IntoIterator<Item = &'a [u8]>) {
+    for proto in protos {
+        let supported = match proto {
+            b"h2" => cfg!(feature = "http2"),
+            b"http/1.1" | b"http/1.0" => cfg!(feature = "http1"),
+            _ => true,
+        };
+
+        if !supported {
+            warn!(
+                "ALPN protocol {:?} is advertised but its feature is not enabled. Connections negotiating it would fail.",
+                String::from_utf8_lossy(proto)
+            );
+        }
+    }
+}
+
+/// Select the first server protocol that is offered in client's wire format protocol list.
+#[cfg(feature = "openssl")]
+pub(crate) fn select<'a>(server: &[Vec<u8>], client: &'a [u8]) -> Option<&'a [u8]> {
+    server.iter().find_map(|proto| {
+        let mut client = client;
+        while let Some((&len, rest)) = client.split_first() {
+            let len = len as usize;
+            if rest.len() < len {
+                return None;
+            }
+            let (offered, rest) = rest.split_at(len);
+            if offered == proto.as_slice() {
+                return Some(offered);
+            }
+            client = rest;
+        }
+        None
+    })
+}
+
+#[cfg(all(test, feature = "openssl"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn select_server_preference() {
+        let server = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        assert_eq!(select(&server, b"\x08http/1.1\x02h2"), Some(&b"h2"[..]));
+        assert_eq!(select(&server, b"\x08http/1.1"), Some(&b"http/1.1"[..]));
+        assert_eq!(select(&server, b"\x06spdy/1"), None);
+        assert_eq!(select(&server, b"\x08http"), None);
+    }
+}
@@ -0,0 +1,322 @@
+use std::io;
+
+use actix_server_alt::net::AsyncReadWrite;
+use bytes::BytesMut;
+use tokio::io::AsyncReadExt;
+
+/// tls record header size.
+const RECORD_HEADER: usize = 5;
+
+/// Max size of tls record payload.
+const MAX_RECORD: usize = 16384;
+
+/// Max size of ClientHello message joined from one or more tls records.
+const MAX_CLIENT_HELLO: usize = 16384;
+
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 0x01;
+
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const EXTENSION_ALPN: u16 = 0x0010;
+
+/// Information from the ClientHello message a client sent to start tls handshake.
+#[derive(Debug, Default)]
+pub struct ClientHello {
+    server_name: Option<String>,
+    alpn: Vec<Vec<u8>>,
+    cipher_suites: Vec<u16>,
+}
+
+impl ClientHello {
+    /// Host name from server name indication extension.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Application protocols offered by client in the order of preference.
+    pub fn alpn(&self) -> impl Iterator<Item = &[u8]> {
+        self.alpn.iter().map(|proto| proto.as_slice())
+    }
+
+    /// Cipher suites offered by client in the order of preference.
+    pub fn cipher_suites(&self) -> &[u16] {
+        &self.cipher_suites
+    }
+
+    /// Read from io until a complete ClientHello is received.
+    ///
+    /// All bytes read are kept in given buffer so they can be replayed to tls acceptor.
+    pub(crate) async fn read<St>(io: &mut St, buf: &mut BytesMut) -> io::Result<Self>
+    where
+        St: AsyncReadWrite,
+    {
+        // buffer is bounded as parse rejects ClientHello larger than MAX_CLIENT_HELLO and empty
+        // records.
+        loop {
+            if let Some(hello) = Self::parse(buf)? {
+                return Ok(hello);
+            }
+
+            buf.reserve(4096);
+            if io.read_buf(buf).await? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Parse ClientHello from the leading tls records of buffer.
+    ///
+    /// Large ClientHello can be fragmented into multiple records and is joined before parsing.
+    /// Return `Ok(None)` when more bytes are needed.
+    pub(crate) fn parse(buf: &[u8]) -> io::Result<Option<Self>> {
+        let mut msg = Vec::new();
+        let mut rest = buf;
+
+        // join record payloads until the whole handshake message is received.
+        let len = loop {
+            if rest.len() < RECORD_HEADER {
+                return Ok(None);
+            }
+
+            if rest[0] != CONTENT_TYPE_HANDSHAKE {
+                return Err(invalid());
+            }
+
+            let len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+
+            // empty handshake record is not allowed.
+            if len == 0 || len > MAX_RECORD {
+                return Err(invalid());
+            }
+
+            if rest.len() < RECORD_HEADER + len {
+                return Ok(None);
+            }
+
+            msg.extend_from_slice(&rest[RECORD_HEADER..RECORD_HEADER + len]);
+            rest = &rest[RECORD_HEADER + len..];
+
+            if msg.len() >= 4 {
+                let mut reader = Reader(&msg);
+
+                if reader.u8()? != HANDSHAKE_TYPE_CLIENT_HELLO {
+                    return Err(invalid());
+                }
+
+                let len = reader.u24()?;
+
+                if len > MAX_CLIENT_HELLO {
+                    return Err(invalid());
+                }
+
+                if msg.len() >= 4 + len {
+                    break len;
+                }
+            }
+        };
+
+        let mut reader = Reader(&msg[4..4 + len]);
+
+        // legacy version and random.
+        reader.take(2 + 32)?;
+        // legacy session id.
+        let len = reader.u8()? as usize;
+        reader.take(len)?;
+
+        let len = reader.u16()? as usize;
+        let cipher_suites = reader
+            .take(len)?
+            .chunks_exact(2)
+            .map(|suite| u16::from_be_bytes([suite[0], suite[1]]))
+            .collect();
+
+        // legacy compression methods.
+        let len = reader.u8()? as usize;
+        reader.take(len)?;
+
+        let mut hello = ClientHello {
+            cipher_suites,
+            ..Default::default()
+        };
+
+        // extensions are optional.
+        if reader.0.is_empty() {
+            return Ok(Some(hello));
+        }
+
+        let len = reader.u16()? as usize;
+        let mut extensions = Reader(reader.take(len)?);
+
+        while !extensions.0.is_empty() {
+            let ty = extensions.u16()?;
+            let len = extensions.u16()? as usize;
+            let mut ext = Reader(extensions.take(len)?);
+
+            match ty {
+                EXTENSION_SERVER_NAME => {
+                    let len = ext.u16()? as usize;
+                    let mut names = Reader(ext.take(len)?);
+                    while !names.0.is_empty() {
+                        let ty = names.u8()?;
+                        let len = names.u16()? as usize;
+                        let name = names.take(len)?;
+                        // host_name type.
+                        if ty == 0 {
+                            let name = std::str::from_utf8(name).map_err(|_| invalid())?;
+                            hello.server_name = Some(name.to_owned());
+                        }
+                    }
+                }
+                EXTENSION_ALPN => {
+                    let len = ext.u16()? as usize;
+                    let mut protos = Reader(ext.take(len)?);
+                    while !protos.0.is_empty() {
+                        let len = protos.u8()? as usize;
+                        hello.alpn.push(protos.take(len)?.to_vec());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(hello))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid());
+        }
+        let (l, r) = self.0.split_at(len);
+        self.0 = r;
+        Ok(l)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> io::Result<usize> {
+        self.take(3)
+            .map(|b| (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid tls ClientHello")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn client_hello(server_name: &str, alpn: &[&[u8]]) -> Vec<u8> {
+        let mut ext = Vec::new();
+
+        let name = server_name.as_bytes();
+        ext.extend_from_slice(&EXTENSION_SERVER_NAME.to_be_bytes());
+        ext.extend_from_slice(&(name.len() as u16 + 5).to_be_bytes());
+        ext.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+        ext.push(0);
+        ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        ext.extend_from_slice(name);
+
+        let protos = alpn.iter().fold(Vec::new(), |mut protos, proto| {
+            protos.push(proto.len() as u8);
+            protos.extend_from_slice(proto);
+            protos
+        });
+        ext.extend_from_slice(&EXTENSION_ALPN.to_be_bytes());
+        ext.extend_from_slice(&(protos.len() as u16 + 2).to_be_bytes());
+        ext.extend_from_slice(&(protos.len() as u16).to_be_bytes());
+        ext.extend_from_slice(&protos);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.push(0);
+        body.extend_from_slice(&[0x00, 0x04, 0x13, 0x01, 0x13, 0x02]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(ext.len() as u16).to_be_bytes());
+        body.extend_from_slice(&ext);
+
+        let mut handshake = vec![HANDSHAKE_TYPE_CLIENT_HELLO];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+
+        let mut record = vec![CONTENT_TYPE_HANDSHAKE, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[test]
+    fn parse() {
+        let buf = client_hello("example.com", &[b"h2", b"http/1.1"]);
+
+        let hello = ClientHello::parse(&buf).unwrap().unwrap();
+        assert_eq!(hello.server_name(), Some("example.com"));
+        assert_eq!(hello.alpn().collect::<Vec<_>>(), vec![&b"h2"[..], &b"http/1.1"[..]]);
+        assert_eq!(hello.cipher_suites(), &[0x1301, 0x1302]);
+    }
+
+    #[test]
+    fn parse_partial() {
+        let buf = client_hello("example.com", &[b"h2"]);
+
+        for len in 0..buf.len() {
+            assert!(ClientHello::parse(&buf[..len]).unwrap().is_none());
+        }
+    }
+
+    // split payload of a single record ClientHello into two records at given index.
+    fn fragment(record: &[u8], at: usize) -> Vec<u8> {
+        let (head, tail) = record[RECORD_HEADER..].split_at(at);
+
+        [head, tail].iter().fold(Vec::new(), |mut buf, payload| {
+            buf.extend_from_slice(&[CONTENT_TYPE_HANDSHAKE, 0x03, 0x01]);
+            buf.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            buf.extend_from_slice(payload);
+            buf
+        })
+    }
+
+    #[test]
+    fn parse_fragmented() {
+        let record = client_hello("example.com", &[b"h2", b"http/1.1"]);
+
+        // split inside handshake header and inside ClientHello body.
+        for &at in [2, record.len() / 2].iter() {
+            let buf = fragment(&record, at);
+
+            for len in 0..buf.len() {
+                assert!(ClientHello::parse(&buf[..len]).unwrap().is_none());
+            }
+
+            let hello = ClientHello::parse(&buf).unwrap().unwrap();
+            assert_eq!(hello.server_name(), Some("example.com"));
+            assert_eq!(hello.alpn().collect::<Vec<_>>(), vec![&b"h2"[..], &b"http/1.1"[..]]);
+        }
+    }
+
+    #[test]
+    fn parse_too_large() {
+        // ClientHello of 20KiB announced in handshake header.
+        let mut buf = vec![CONTENT_TYPE_HANDSHAKE, 0x03, 0x01, 0x00, 0x04];
+        buf.extend_from_slice(&[HANDSHAKE_TYPE_CLIENT_HELLO, 0x00, 0x50, 0x00]);
+        assert!(ClientHello::parse(&buf).is_err());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let mut buf = client_hello("example.com", &[b"h2"]);
+        buf[0] = b'G';
+        assert!(ClientHello::parse(&buf).is_err());
+    }
+}
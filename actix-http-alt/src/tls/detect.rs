@@ -0,0 +1,296 @@
+use std::{
+    future::Future,
+    task::{Context, Poll},
+};
+
+use actix_server_alt::net::Stream as ServerStream;
+use actix_service_alt::{Service, ServiceFactory};
+use bytes::BytesMut;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::error::HttpServiceError;
+
+use super::{TlsAcceptorService, TlsStream};
+
+/// Max size of plaintext request head read before sending a canned response.
+const MAX_HEAD: usize = 8192;
+
+const MAX_HEADERS: usize = 32;
+
+/// Behavior for plaintext http connections accepted on a tls listener.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Plaintext {
+    /// Serve the connection as plaintext Http/1.
+    Serve,
+    /// Respond with `400 Bad Request` and close the connection.
+    Reject,
+    /// Respond with `301 Moved Permanently` to the https url of the same host and path
+    /// and close the connection.
+    Redirect,
+}
+
+/// Tls acceptor that detects plaintext http connections on the same listener.
+///
+/// The first byte of every tcp stream is peeked without consuming it. An ascii method byte
+/// is handled according to [Plaintext] and anything else (0x16 for a tls handshake record)
+/// is passed to the inner acceptor. Both paths read the stream from its start.
+#[derive(Clone)]
+pub struct DetectTlsAcceptorService {
+    tls: TlsAcceptorService,
+    plaintext: Plaintext,
+}
+
+impl DetectTlsAcceptorService {
+    pub fn new(tls: TlsAcceptorService, plaintext: Plaintext) -> Self {
+        Self { tls, plaintext }
+    }
+}
+
+impl ServiceFactory<ServerStream> for DetectTlsAcceptorService {
+    type Response = TlsStream;
+    type Error = HttpServiceError;
+    type Config = ();
+    type Service = Self;
+    type InitError = ();
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let tls = self.tls.new_service(cfg);
+        let plaintext = self.plaintext;
+        async move {
+            let tls = tls.await?;
+            Ok(Self::new(tls, plaintext))
+        }
+    }
+}
+
+impl Service<ServerStream> for DetectTlsAcceptorService {
+    type Response = TlsStream;
+    type Error = HttpServiceError;
+
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.tls.poll_ready(cx)
+    }
+
+    fn call(&self, stream: ServerStream) -> Self::Future<'_> {
+        async move {
+            if is_tls(&stream).await? {
+                return self.tls.call(stream).await;
+            }
+
+            match self.plaintext {
+                Plaintext::Serve => Ok(TlsStream::NoOp(stream)),
+                plaintext => {
+                    respond(stream, plaintext).await?;
+                    // connection is already answered and closed.
+                    Err(HttpServiceError::Ignored)
+                }
+            }
+        }
+    }
+}
+
+async fn is_tls(stream: &ServerStream) -> Result<bool, HttpServiceError> {
+    match *stream {
+        ServerStream::Tcp(ref tcp) => {
+            let mut byte = [0u8; 1];
+            match tcp.peek(&mut byte).await {
+                Ok(n) if n > 0 => Ok(!byte[0].is_ascii_uppercase()),
+                // connection closed or failed before sending anything.
+                _ => Err(HttpServiceError::Ignored),
+            }
+        }
+        // peek is only available on tcp stream. let tls acceptor handle the others.
+        #[allow(unreachable_patterns)]
+        _ => Ok(true),
+    }
+}
+
+async fn respond(mut stream: ServerStream, plaintext: Plaintext) -> Result<(), HttpServiceError> {
+    let mut buf = BytesMut::with_capacity(1024);
+
+    let res = loop {
+        if let Some(res) = response(plaintext, &buf) {
+            break res;
+        }
+
+        if buf.len() >= MAX_HEAD {
+            break response(Plaintext::Reject, b"").unwrap();
+        }
+
+        match stream.read_buf(&mut buf).await {
+            Ok(0) | Err(_) => return Err(HttpServiceError::Ignored),
+            Ok(_) => {}
+        }
+    };
+
+    stream.write_all(&res).await.map_err(|_| HttpServiceError::Ignored)?;
+    let _ = stream.shutdown().await;
+
+    Ok(())
+}
+
+/// Generate canned response from given request head.
+///
+/// Return `None` when more bytes are needed.
+fn response(plaintext: Plaintext, head: &[u8]) -> Option<Vec<u8>> {
+    const BAD_REQUEST: &[u8] = b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+
+    if plaintext != Plaintext::Redirect {
+        return Some(BAD_REQUEST.to_vec());
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut req = httparse::Request::new(&mut headers);
+
+    match req.parse(head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => return None,
+        Err(_) => return Some(BAD_REQUEST.to_vec()),
+    }
+
+    let host = req
+        .headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case("host"))
+        .map(|header| header.value)
+        .filter(|host| !host.is_empty());
+
+    match host {
+        Some(host) => {
+            let path = req.path.filter(|path| path.starts_with('/')).unwrap_or("/");
+
+            let mut res = b"HTTP/1.1 301 Moved Permanently\r\nlocation: https://".to_vec();
+            res.extend_from_slice(host);
+            res.extend_from_slice(path.as_bytes());
+            res.extend_from_slice(b"\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+
+            Some(res)
+        }
+        None => Some(BAD_REQUEST.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::testing::tcp_pair;
+
+    use super::*;
+
+    // run server stream through detect acceptor and return the stream passed to inner acceptor.
+    async fn detect(plaintext: Plaintext, io: ServerStream) -> Option<ServerStream> {
+        let service = DetectTlsAcceptorService::new(TlsAcceptorService::new(), plaintext);
+
+        match service.call(io).await {
+            Ok(TlsStream::NoOp(stream)) => Some(stream),
+            Err(HttpServiceError::Ignored) => None,
+            _ => panic!("unexpected tls stream"),
+        }
+    }
+
+    #[tokio::test]
+    async fn detect_plaintext() {
+        let head = b"GET /foo HTTP/1.1\r\nHost: example.com\r\n\r\n";
+
+        let (mut client, io) = tcp_pair().await.unwrap();
+        client.write_all(head).await.unwrap();
+
+        // request head is not consumed by detection.
+        let mut stream = detect(Plaintext::Serve, io).await.unwrap();
+        let mut buf = vec![0; head.len()];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf[..], &head[..]);
+
+        let (mut client, io) = tcp_pair().await.unwrap();
+        client.write_all(head).await.unwrap();
+        assert!(detect(Plaintext::Redirect, io).await.is_none());
+
+        // connection is answered and closed.
+        let mut res = Vec::new();
+        client.read_to_end(&mut res).await.unwrap();
+        let res = String::from_utf8(res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(res.contains("\r\nlocation: https://example.com/foo\r\n"));
+    }
+
+    #[tokio::test]
+    async fn detect_tls() {
+        let record = [0x16, 0x03, 0x01, 0x00, 0x05];
+
+        let (mut client, io) = tcp_pair().await.unwrap();
+        client.write_all(&record).await.unwrap();
+
+        // tls record is passed to inner acceptor from its first byte.
+        let mut stream = detect(Plaintext::Redirect, io).await.unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, record);
+    }
+
+    #[cfg(feature = "rustls")]
+    #[tokio::test]
+    async fn detect_client_hello() {
+        use std::sync::Arc;
+
+        use tokio_rustls::{rustls::ClientConfig, webpki::DNSNameRef, TlsConnector};
+
+        use super::super::ClientHello;
+
+        let (client, io) = tcp_pair().await.unwrap();
+
+        let connector = TlsConnector::from(Arc::new(ClientConfig::new()));
+        let name = DNSNameRef::try_from_ascii_str("example.com").unwrap();
+        let connect = connector.connect(name, client);
+
+        let service = DetectTlsAcceptorService::new(TlsAcceptorService::new(), Plaintext::Reject);
+        let accept = async {
+            match service.call(io).await {
+                Ok(TlsStream::NoOp(mut stream)) => ClientHello::read(&mut stream, &mut BytesMut::new()).await,
+                _ => panic!("unexpected tls stream"),
+            }
+        };
+
+        // handshake of client can not finish without server.
+        tokio::select! {
+            _ = connect => panic!("unexpected tls handshake"),
+            hello = accept => assert_eq!(hello.unwrap().server_name(), Some("example.com")),
+        }
+    }
+
+    #[test]
+    fn redirect() {
+        let head = b"GET /foo?bar=1 HTTP/1.1\r\nHost: example.com:8443\r\n\r\n";
+
+        let res = response(Plaintext::Redirect, head).unwrap();
+        let res = std::str::from_utf8(&res).unwrap();
+
+        assert!(res.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(res.contains("\r\nlocation: https://example.com:8443/foo?bar=1\r\n"));
+    }
+
+    #[test]
+    fn redirect_partial() {
+        let head = b"GET / HTTP/1.1\r\nHost: example.com\r\n";
+
+        for len in 0..head.len() {
+            assert!(response(Plaintext::Redirect, &head[..len]).is_none());
+        }
+    }
+
+    #[test]
+    fn redirect_without_host() {
+        let head = b"GET / HTTP/1.1\r\n\r\n";
+
+        let res = response(Plaintext::Redirect, head).unwrap();
+        assert!(res.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn reject() {
+        let res = response(Plaintext::Reject, b"").unwrap();
+        assert!(res.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    cell::Cell,
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    task::Poll,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+
+use crate::util::poll_fn::poll_fn;
+
+/// Collection of tls handshake errors from different tls acceptors.
+pub enum TlsError {
+    #[cfg(feature = "openssl")]
+    Openssl(super::openssl::OpensslError),
+    #[cfg(feature = "rustls")]
+    Rustls(super::rustls::RustlsError),
+    #[cfg(feature = "native-tls")]
+    NativeTls(super::native_tls::NativeTlsError),
+    /// Error from a [TlsAccept](super::TlsAccept) implementation.
+    Custom(Box<dyn Debug + Send + Sync>),
+}
+
+impl TlsError {
+    /// Categorized reason of the handshake failure.
+    pub fn reason(&self) -> HandshakeReason {
+        match *self {
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref e) => e.reason(),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(ref e) => e.reason(),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref e) => super::native_tls::reason(e),
+            Self::Custom(_) => HandshakeReason::Other,
+        }
+    }
+}
+
+impl Debug for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref e) => write!(f, "{:?}", e),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(ref e) => write!(f, "{:?}", e),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref e) => write!(f, "{:?}", e),
+            Self::Custom(ref e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref e) => write!(f, "{}", e),
+            Self::Custom(ref e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl Error for TlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref e) => Some(e),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(ref e) => Some(e),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref e) => Some(e),
+            Self::Custom(_) => None,
+        }
+    }
+}
+
+/// Categorized reason of a failed tls handshake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeReason {
+    /// Handshake did not finish in time.
+    Timeout,
+    /// Handshake is dropped before finish. e.g. server shutdown.
+    Cancelled,
+    /// No protocol version both sides support.
+    ProtocolVersion,
+    /// No cipher suite both sides support.
+    NoSharedCipher,
+    /// Client certificate is missing or failed verification.
+    ClientCertificate,
+    /// Connection rejected before handshake.
+    Rejected,
+    /// Io error from underlying connection.
+    Io,
+    Other,
+}
+
+impl HandshakeReason {
+    const COUNT: usize = 8;
+}
+
+impl Display for HandshakeReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let reason = match *self {
+            Self::Timeout => "timeout",
+            Self::Cancelled => "cancelled",
+            Self::ProtocolVersion => "protocol version mismatch",
+            Self::NoSharedCipher => "no shared cipher",
+            Self::ClientCertificate => "client certificate rejected",
+            Self::Rejected => "rejected",
+            Self::Io => "io error",
+            Self::Other => "other",
+        };
+
+        f.write_str(reason)
+    }
+}
+
+// unix time in seconds of last log for every reason.
+static LAST_LOG: [AtomicU64; HandshakeReason::COUNT] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+// count of suppressed logs since last log for every reason.
+static SUPPRESSED: [AtomicUsize; HandshakeReason::COUNT] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Log handshake failure at most once per second for every reason.
+pub(crate) fn log(peer: Option<SocketAddr>, reason: HandshakeReason, detail: &dyn Debug) {
+    let idx = reason as usize;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or(0);
+
+    let last = LAST_LOG[idx].load(Ordering::Relaxed);
+
+    if now <= last
+        || LAST_LOG[idx]
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        SUPPRESSED[idx].fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    let suppressed = SUPPRESSED[idx].swap(0, Ordering::Relaxed);
+
+    match peer {
+        Some(peer) => warn!(
+            "Tls handshake from {} failed on {}: {:?}. {} similar failure(s) suppressed.",
+            peer, reason, detail, suppressed
+        ),
+        None => warn!(
+            "Tls handshake failed on {}: {:?}. {} similar failure(s) suppressed.",
+            reason, detail, suppressed
+        ),
+    }
+}
+
+thread_local! {
+    // set while tls accept future is dropped by accept timer.
+    static TIMED_OUT: Cell<bool> = Cell::new(false);
+}
+
+/// Drive tls accept future until it finishes or timer fires. Return `None` on timeout.
+///
+/// Handshake dropped here on timeout is reported to on_handshake hook as
+/// [HandshakeReason::Timeout]. Any other drop of it is reported as [HandshakeReason::Cancelled].
+pub(crate) async fn accept_timeout<F, T>(accept: F, mut timer: Pin<&mut T>) -> Option<F::Output>
+where
+    F: Future,
+    T: Future,
+{
+    let accept = Some(accept);
+    tokio::pin!(accept);
+
+    poll_fn(|cx| {
+        if let Poll::Ready(res) = accept.as_mut().as_pin_mut().unwrap().poll(cx) {
+            return Poll::Ready(Some(res));
+        }
+
+        if timer.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        TIMED_OUT.with(|timed_out| timed_out.set(true));
+        accept.as_mut().set(None);
+        TIMED_OUT.with(|timed_out| timed_out.set(false));
+
+        Poll::Ready(None)
+    })
+    .await
+}
+
+/// Failure of a tls handshake.
+#[derive(Clone, Debug)]
+pub struct HandshakeError {
+    reason: HandshakeReason,
+}
+
+impl HandshakeError {
+    #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+    pub(crate) fn new(reason: HandshakeReason) -> Self {
+        Self { reason }
+    }
+
+    pub fn reason(&self) -> HandshakeReason {
+        self.reason
+    }
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Tls handshake failed on {}", self.reason)
+    }
+}
+
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+pub(crate) use self::hook::{HandshakeGuard, OnHandshake};
+
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+mod hook {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use super::super::accept::TlsInfo;
+    use super::{HandshakeError, HandshakeReason, TIMED_OUT};
+
+    pub(crate) type OnHandshake = Arc<dyn Fn(Result<&TlsInfo, HandshakeError>, Duration) + Send + Sync>;
+
+    /// Report the outcome of a handshake to hook.
+    ///
+    /// A guard dropped without [finish](HandshakeGuard::finish) means the handshake future is
+    /// cancelled. It's reported as timeout when dropped by [accept_timeout](super::accept_timeout).
+    pub(crate) struct HandshakeGuard<'a> {
+        hook: &'a OnHandshake,
+        start: Instant,
+        finished: bool,
+    }
+
+    impl<'a> HandshakeGuard<'a> {
+        pub(crate) fn new(hook: &'a OnHandshake) -> Self {
+            Self {
+                hook,
+                start: Instant::now(),
+                finished: false,
+            }
+        }
+
+        pub(crate) fn finish(mut self, res: Result<&TlsInfo, HandshakeError>) {
+            self.finished = true;
+            (self.hook)(res, self.start.elapsed());
+        }
+
+        /// Finish without reporting to hook.
+        pub(crate) fn skip(mut self) {
+            self.finished = true;
+        }
+    }
+
+    impl Drop for HandshakeGuard<'_> {
+        fn drop(&mut self) {
+            if !self.finished {
+                let reason = if TIMED_OUT.with(|timed_out| timed_out.get()) {
+                    HandshakeReason::Timeout
+                } else {
+                    HandshakeReason::Cancelled
+                };
+                (self.hook)(Err(HandshakeError::new(reason)), self.start.elapsed());
+            }
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "openssl", feature = "rustls", feature = "native-tls")))]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::super::accept::TlsInfo;
+    use super::*;
+
+    #[test]
+    fn guard_report_once() {
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+
+        let hook: OnHandshake = {
+            let reasons = reasons.clone();
+            Arc::new(move |res, _| reasons.lock().unwrap().push(res.err().map(|e| e.reason())))
+        };
+
+        HandshakeGuard::new(&hook).finish(Ok(&TlsInfo::default()));
+        HandshakeGuard::new(&hook).finish(Err(HandshakeError::new(HandshakeReason::NoSharedCipher)));
+        HandshakeGuard::new(&hook).skip();
+        drop(HandshakeGuard::new(&hook));
+
+        assert_eq!(
+            *reasons.lock().unwrap(),
+            vec![
+                None,
+                Some(HandshakeReason::NoSharedCipher),
+                Some(HandshakeReason::Cancelled)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn accept_timeout_report() {
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+
+        let hook: OnHandshake = {
+            let reasons = reasons.clone();
+            Arc::new(move |res, _| reasons.lock().unwrap().push(res.err().map(|e| e.reason())))
+        };
+
+        let accept = async {
+            let _guard = HandshakeGuard::new(&hook);
+            std::future::pending::<()>().await
+        };
+
+        let timer = async {};
+        tokio::pin!(timer);
+
+        assert!(accept_timeout(accept, timer).await.is_none());
+        assert_eq!(*reasons.lock().unwrap(), vec![Some(HandshakeReason::Timeout)]);
+
+        // handshake finished before timer.
+        let timer = async {};
+        tokio::pin!(timer);
+        assert_eq!(accept_timeout(async { 996 }, timer).await, Some(996));
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use log::{error, warn};
+
+/// Destination of tls session secrets for debugging encrypted traffic with tools like
+/// Wireshark. Secrets are written in NSS key log format.
+///
+/// Anyone with access to the written file can decrypt the traffic. Never enable it in
+/// production unless it's absolutely necessary.
+#[derive(Clone, Debug)]
+pub enum KeyLog {
+    /// File path from `SSLKEYLOGFILE` environment variable. Key logging stays off when
+    /// the variable is not set.
+    Env,
+    /// Explicit file path.
+    Path(PathBuf),
+}
+
+impl KeyLog {
+    /// Path of key log file. Return `None` when key logging is not enabled.
+    pub(crate) fn path(&self) -> Option<PathBuf> {
+        match *self {
+            Self::Env => env::var_os("SSLKEYLOGFILE").map(PathBuf::from),
+            Self::Path(ref path) => Some(path.clone()),
+        }
+    }
+}
+
+/// Append only writer of key log file.
+pub(crate) struct KeyLogWriter {
+    file: Mutex<File>,
+}
+
+impl KeyLogWriter {
+    /// Open key log file.
+    ///
+    /// Return `None` when key logging is not enabled or the file can not be opened.
+    pub(crate) fn open(keylog: &KeyLog) -> Option<Self> {
+        let path = keylog.path()?;
+
+        match OpenOptions::new().append(true).create(true).open(&path) {
+            Ok(file) => {
+                warn!(
+                    "TLS KEY LOGGING IS ENABLED. Session secrets are written to {}. \
+                    Anyone with access to this file can decrypt the traffic of this server.",
+                    path.display()
+                );
+
+                Some(Self { file: Mutex::new(file) })
+            }
+            Err(e) => {
+                error!("Failed to open tls key log file {}: {:?}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Write a line of key log. New line is appended.
+    pub(crate) fn write_line(&self, line: &str) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Err(e) = writeln!(file, "{}", line) {
+                error!("Failed to write tls key log: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl tokio_rustls::rustls::KeyLog for KeyLogWriter {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        use std::fmt::Write as _;
+
+        let mut line = String::with_capacity(label.len() + 2 + (client_random.len() + secret.len()) * 2);
+
+        line.push_str(label);
+        line.push(' ');
+        client_random.iter().for_each(|b| write!(line, "{:02x}", b).unwrap());
+        line.push(' ');
+        secret.iter().for_each(|b| write!(line, "{:02x}", b).unwrap());
+
+        self.write_line(&line);
+    }
+}
@@ -4,12 +4,36 @@
 //! For plain Tcp and Unix sockets connection a dummy Tls acceptor and tls stream type
 //! is used.
 
+mod accept;
+#[cfg(feature = "rustls")]
+mod acme;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+mod alpn;
+#[cfg(feature = "rustls")]
+mod client_hello;
+mod detect;
+pub(crate) mod handshake;
+#[cfg(any(feature = "openssl", feature = "rustls"))]
+mod keylog;
+#[cfg(feature = "rustls")]
+mod rewind;
+
 #[cfg(feature = "native-tls")]
-pub(crate) mod native_tls;
+pub mod native_tls;
 #[cfg(feature = "openssl")]
-pub(crate) mod openssl;
+pub mod openssl;
 #[cfg(feature = "rustls")]
-pub(crate) mod rustls;
+pub mod rustls;
+
+pub use self::accept::{CustomTlsAcceptorService, CustomTlsStream, TlsAccept, TlsInfo};
+#[cfg(feature = "rustls")]
+pub use self::acme::{AcmeHandle, ChallengeResolver, ACME_TLS_ALPN};
+#[cfg(feature = "rustls")]
+pub use self::client_hello::ClientHello;
+pub use self::detect::{DetectTlsAcceptorService, Plaintext};
+pub use self::handshake::{HandshakeError, HandshakeReason, TlsError};
+#[cfg(any(feature = "openssl", feature = "rustls"))]
+pub use self::keylog::KeyLog;
 
 use std::{
     future::Future,
@@ -21,8 +45,11 @@ use std::{
 use actix_server_alt::net::{AsyncReadWrite, Stream as ServerStream};
 use actix_service_alt::{Service, ServiceFactory};
 use bytes::BufMut;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use log::warn;
 use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
 
+use super::connection::{ConnectionData, OnConnect};
 use super::error::HttpServiceError;
 use super::protocol::{AsProtocol, Protocol};
 
@@ -76,6 +103,68 @@ impl TlsAcceptorService {
     pub fn new() -> Self {
         Self::NoOp(NoOpTlsAcceptorService)
     }
+
+    /// Override ALPN protocols of tls acceptor. Protocols are in the order of preference.
+    ///
+    /// Existing protocols are kept with a warning when there is no tls acceptor or ALPN protocols
+    /// of it can not be overridden.
+    #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+    pub fn alpn(self, protos: &[&str]) -> Self {
+        match self {
+            #[cfg(feature = "openssl")]
+            Self::OpenSsl(tls) => Self::OpenSsl(tls.alpn(protos)),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(tls) => Self::Rustls(tls.alpn(protos)),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(tls) => Self::NativeTls(tls.alpn(protos)),
+            Self::NoOp(tls) => {
+                warn!("ALPN protocols can not be overridden without tls acceptor.");
+                Self::NoOp(tls)
+            }
+        }
+    }
+
+    /// Observe the outcome and duration of every tls handshake.
+    #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+    pub fn on_handshake<F>(self, f: F) -> Self
+    where
+        F: Fn(Result<&TlsInfo, HandshakeError>, std::time::Duration) + Send + Sync + 'static,
+    {
+        match self {
+            #[cfg(feature = "openssl")]
+            Self::OpenSsl(tls) => Self::OpenSsl(tls.on_handshake(f)),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(tls) => Self::Rustls(tls.on_handshake(f)),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(tls) => Self::NativeTls(tls.on_handshake(f)),
+            // no handshake happens on plain connections.
+            this => this,
+        }
+    }
+
+    /// Write tls session secrets to given key log destination.
+    ///
+    /// Key logging stays off with a warning when there is no tls acceptor or it does not support
+    /// key logging.
+    #[cfg(any(feature = "openssl", feature = "rustls"))]
+    pub fn keylog(self, keylog: KeyLog) -> Self {
+        match self {
+            #[cfg(feature = "openssl")]
+            Self::OpenSsl(tls) => Self::OpenSsl(tls.keylog(keylog)),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(tls) => Self::Rustls(tls.keylog(keylog)),
+            // key logging is not supported by other acceptors.
+            this => {
+                if let Some(path) = keylog.path() {
+                    warn!(
+                        "Key logging is not supported by tls acceptor. Session secrets are not written to {}.",
+                        path.display()
+                    );
+                }
+                this
+            }
+        }
+    }
 }
 
 impl Default for TlsAcceptorService {
@@ -160,6 +249,7 @@ pub enum TlsStream {
     Rustls(self::rustls::TlsStream<ServerStream>),
     #[cfg(feature = "native-tls")]
     NativeTls(self::native_tls::TlsStream<ServerStream>),
+    Custom(CustomTlsStream),
 }
 
 impl AsProtocol for TlsStream {
@@ -173,6 +263,23 @@ impl AsProtocol for TlsStream {
             Self::Rustls(ref tls) => tls.as_protocol(),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(ref tls) => tls.as_protocol(),
+            Self::Custom(ref tls) => tls.as_protocol(),
+        }
+    }
+}
+
+impl OnConnect for TlsStream {
+    #[inline]
+    fn on_connect(&self, data: &mut ConnectionData) {
+        match *self {
+            Self::NoOp(ref tls) => tls.on_connect(data),
+            #[cfg(feature = "openssl")]
+            Self::OpenSsl(ref tls) => tls.on_connect(data),
+            #[cfg(feature = "rustls")]
+            Self::Rustls(ref tls) => tls.on_connect(data),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref tls) => tls.on_connect(data),
+            Self::Custom(ref tls) => tls.on_connect(data),
         }
     }
 }
@@ -188,6 +295,7 @@ impl AsyncRead for TlsStream {
             Self::Rustls(tls) => Pin::new(tls).poll_read(cx, buf),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(tls) => Pin::new(tls).poll_read(cx, buf),
+            Self::Custom(tls) => Pin::new(tls).poll_read(cx, buf),
         }
     }
 }
@@ -204,6 +312,7 @@ impl AsyncWrite for TlsStream {
 
             #[cfg(feature = "native-tls")]
             Self::NativeTls(tls) => Pin::new(tls).poll_write(cx, buf),
+            Self::Custom(tls) => Pin::new(tls).poll_write(cx, buf),
         }
     }
 
@@ -217,6 +326,7 @@ impl AsyncWrite for TlsStream {
             Self::Rustls(tls) => Pin::new(tls).poll_flush(cx),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(tls) => Pin::new(tls).poll_flush(cx),
+            Self::Custom(tls) => Pin::new(tls).poll_flush(cx),
         }
     }
 
@@ -230,6 +340,7 @@ impl AsyncWrite for TlsStream {
             Self::Rustls(tls) => Pin::new(tls).poll_shutdown(cx),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(tls) => Pin::new(tls).poll_shutdown(cx),
+            Self::Custom(tls) => Pin::new(tls).poll_shutdown(cx),
         }
     }
 
@@ -247,6 +358,7 @@ impl AsyncWrite for TlsStream {
             Self::Rustls(tls) => Pin::new(tls).poll_write_vectored(cx, bufs),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(tls) => Pin::new(tls).poll_write_vectored(cx, bufs),
+            Self::Custom(tls) => Pin::new(tls).poll_write_vectored(cx, bufs),
         }
     }
 
@@ -260,6 +372,7 @@ impl AsyncWrite for TlsStream {
             Self::Rustls(ref tls) => tls.is_write_vectored(),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(ref tls) => tls.is_write_vectored(),
+            Self::Custom(ref tls) => tls.is_write_vectored(),
         }
     }
 }
@@ -278,6 +391,7 @@ impl AsyncReadWrite for TlsStream {
                 Self::Rustls(ref mut tls) => tls.ready(interest).await,
                 #[cfg(feature = "native-tls")]
                 Self::NativeTls(ref mut tls) => tls.ready(interest).await,
+                Self::Custom(ref mut tls) => tls.ready(interest).await,
             }
         }
     }
@@ -292,6 +406,7 @@ impl AsyncReadWrite for TlsStream {
             Self::Rustls(ref mut tls) => tls.try_read_buf(buf),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(ref mut tls) => tls.try_read_buf(buf),
+            Self::Custom(ref mut tls) => tls.try_read_buf(buf),
         }
     }
 
@@ -305,6 +420,7 @@ impl AsyncReadWrite for TlsStream {
             Self::Rustls(ref mut tls) => tls.try_write(buf),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(ref mut tls) => tls.try_write(buf),
+            Self::Custom(ref mut tls) => tls.try_write(buf),
         }
     }
 
@@ -318,6 +434,7 @@ impl AsyncReadWrite for TlsStream {
             Self::Rustls(ref mut tls) => tls.try_write_vectored(bufs),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(ref mut tls) => tls.try_write_vectored(bufs),
+            Self::Custom(ref mut tls) => tls.try_write_vectored(bufs),
         }
     }
 
@@ -331,6 +448,7 @@ impl AsyncReadWrite for TlsStream {
             Self::Rustls(ref mut tls) => tls.poll_read_ready(cx),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(ref mut tls) => tls.poll_read_ready(cx),
+            Self::Custom(ref mut tls) => tls.poll_read_ready(cx),
         }
     }
 
@@ -344,6 +462,7 @@ impl AsyncReadWrite for TlsStream {
             Self::Rustls(ref mut tls) => tls.poll_write_ready(cx),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(ref mut tls) => tls.poll_write_ready(cx),
+            Self::Custom(ref mut tls) => tls.poll_write_ready(cx),
         }
     }
 }
@@ -3,20 +3,30 @@ use std::{
     io,
     ops::{Deref, DerefMut},
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
+    time::Duration,
 };
 
 use actix_server_alt::net::AsyncReadWrite;
 use actix_service_alt::{Service, ServiceFactory};
 use bytes::BufMut;
 use futures_task::noop_waker;
+use log::warn;
 use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
 use tokio_util::io::poll_read_buf;
 
+use crate::connection::{ConnectionData, OnConnect};
 use crate::error::HttpServiceError;
 use crate::protocol::{AsProtocol, Protocol};
 
-pub(crate) use tokio_native_tls::native_tls::{Error as NativeTlsError, TlsAcceptor};
+use super::accept::{TlsAccept, TlsInfo};
+use super::handshake::{HandshakeError, HandshakeGuard, HandshakeReason, OnHandshake};
+
+pub use tokio_native_tls::native_tls::{Error as NativeTlsError, TlsAcceptor, TlsAcceptorBuilder};
+
+// backend of native-tls negotiates ALPN on server side. see build script.
+const ALPN_SUPPORTED: bool = cfg!(native_tls_alpn);
 
 /// A wrapper type for [TlsStream](tokio_rustls::TlsStream).
 ///
@@ -45,6 +55,28 @@ where
     }
 }
 
+impl<S> OnConnect for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn on_connect(&self, data: &mut ConnectionData) {
+        data.set_tls_info(self.tls_info());
+    }
+}
+
+impl<S> TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // native-tls does not expose client certificate chain, negotiated version and cipher.
+    fn tls_info(&self) -> TlsInfo {
+        TlsInfo {
+            alpn: self.get_ref().negotiated_alpn().ok().and_then(|proto| proto),
+            ..Default::default()
+        }
+    }
+}
+
 impl<S> Deref for TlsStream<S> {
     type Target = tokio_native_tls::TlsStream<S>;
 
@@ -59,18 +91,96 @@ impl<S> DerefMut for TlsStream<S> {
     }
 }
 
-/// Rustls Acceptor. Used to accept a unsecure Stream and upgrade it to a TlsStream.
+/// NativeTls Acceptor. Used to accept a unsecure Stream and upgrade it to a TlsStream.
 #[derive(Clone)]
 pub struct TlsAcceptorService {
     acceptor: tokio_native_tls::TlsAcceptor,
+    // shared by clones. every rebuild sets ALPN protocols of its own.
+    builder: Option<Arc<Mutex<TlsAcceptorBuilder>>>,
+    on_handshake: Option<OnHandshake>,
 }
 
 impl TlsAcceptorService {
+    /// Construct from a prebuilt acceptor.
+    ///
+    /// ALPN protocols of a prebuilt acceptor can not be changed afterwards.
     pub fn new(acceptor: TlsAcceptor) -> Self {
         Self {
             acceptor: tokio_native_tls::TlsAcceptor::from(acceptor),
+            builder: None,
+            on_handshake: None,
         }
     }
+
+    /// Construct from an acceptor builder.
+    ///
+    /// ALPN protocols default to the ones supported by enabled features. On platforms where
+    /// native-tls can not negotiate ALPN connections fall back to Http/1.
+    pub fn from_builder(builder: TlsAcceptorBuilder) -> Result<Self, NativeTlsError> {
+        let mut builder = builder;
+        let acceptor = build(&mut builder, &[])?;
+
+        Ok(Self {
+            acceptor: tokio_native_tls::TlsAcceptor::from(acceptor),
+            builder: Some(Arc::new(Mutex::new(builder))),
+            on_handshake: None,
+        })
+    }
+
+    /// Override ALPN protocols. Protocols are in the order of preference.
+    ///
+    /// Only take effect when constructed with [from_builder](Self::from_builder). Services cloned
+    /// before keep their protocols. Empty protocols would fall back to the ones supported by
+    /// enabled features.
+    ///
+    /// Existing protocols are kept with a warning when the acceptor can not be rebuilt.
+    pub fn alpn(mut self, protos: &[&str]) -> Self {
+        match self.builder {
+            Some(ref builder) => match build(&mut builder.lock().unwrap(), protos) {
+                Ok(acceptor) => self.acceptor = tokio_native_tls::TlsAcceptor::from(acceptor),
+                Err(e) => warn!("Failed to rebuild native-tls TlsAcceptor with ALPN protocols: {:?}", e),
+            },
+            None => warn!(
+                "ALPN protocols of a prebuilt native-tls TlsAcceptor can not be overridden. Use TlsAcceptorService::from_builder"
+            ),
+        }
+
+        self
+    }
+
+    /// Observe the outcome and duration of every tls handshake.
+    pub fn on_handshake<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Result<&TlsInfo, HandshakeError>, Duration) + Send + Sync + 'static,
+    {
+        self.on_handshake = Some(Arc::new(f));
+        self
+    }
+}
+
+fn build(builder: &mut TlsAcceptorBuilder, protos: &[&str]) -> Result<TlsAcceptor, NativeTlsError> {
+    let protos = if protos.is_empty() {
+        super::alpn::default_protocols()
+            .iter()
+            .map(|proto| String::from_utf8_lossy(proto).into_owned())
+            .collect()
+    } else {
+        protos.iter().map(|proto| proto.to_string()).collect::<Vec<_>>()
+    };
+
+    if !ALPN_SUPPORTED && protos.iter().any(|proto| proto == "h2") {
+        warn!("native-tls can not negotiate ALPN on this platform. Http/2 would not be used and connections fall back to Http/1.");
+    }
+
+    super::alpn::check_protocols(protos.iter().map(|proto| proto.as_bytes()));
+
+    builder.accept_alpn(&protos).build()
+}
+
+impl From<TlsAcceptor> for TlsAcceptorService {
+    fn from(acceptor: TlsAcceptor) -> Self {
+        Self::new(acceptor)
+    }
 }
 
 impl<St: AsyncReadWrite> ServiceFactory<St> for TlsAcceptorService {
@@ -98,11 +208,34 @@ impl<St: AsyncReadWrite> Service<St> for TlsAcceptorService {
         Poll::Ready(Ok(()))
     }
 
-    #[inline]
     fn call(&self, io: St) -> Self::Future<'_> {
         async move {
-            let stream = self.acceptor.accept(io).await?;
-            Ok(TlsStream { stream })
+            let guard = self.on_handshake.as_ref().map(HandshakeGuard::new);
+
+            let res = self.acceptor.accept(io).await.map(|stream| TlsStream { stream });
+
+            if let Some(guard) = guard {
+                match res {
+                    Ok(ref stream) => guard.finish(Ok(&stream.tls_info())),
+                    Err(ref e) => guard.finish(Err(HandshakeError::new(reason(e)))),
+                }
+            }
+
+            res
+        }
+    }
+}
+
+impl<St: AsyncReadWrite> TlsAccept<St> for TlsAcceptorService {
+    type Stream = TlsStream<St>;
+    type Error = NativeTlsError;
+    type Future<'f> = impl Future<Output = Result<(Self::Stream, TlsInfo), Self::Error>>;
+
+    fn accept(&self, io: St) -> Self::Future<'_> {
+        async move {
+            let stream = Service::call(self, io).await?;
+            let info = stream.tls_info();
+            Ok((stream, info))
         }
     }
 }
@@ -194,8 +327,75 @@ impl<S: AsyncReadWrite> AsyncReadWrite for TlsStream<S> {
     }
 }
 
+/// Categorize native-tls error by the error of platform backend found in its sources.
+///
+/// Errors of openssl backend are only recognized when `openssl` feature is enabled. Errors of
+/// security-framework backend are not exposed by native-tls.
+pub(crate) fn reason(e: &NativeTlsError) -> HandshakeReason {
+    let mut source = std::error::Error::source(e);
+    while let Some(e) = source {
+        #[cfg(feature = "openssl")]
+        if let Some(e) = e.downcast_ref::<openssl_crate::ssl::Error>() {
+            return super::openssl::ssl_reason(e);
+        }
+        #[cfg(feature = "openssl")]
+        if let Some(e) = e.downcast_ref::<openssl_crate::error::ErrorStack>() {
+            return super::openssl::stack_reason(e);
+        }
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return io_reason(e);
+        }
+        source = e.source();
+    }
+
+    HandshakeReason::Other
+}
+
+// schannel backend reports handshake failure as io error with SSPI status code.
+const SEC_E_UNSUPPORTED_FUNCTION: u32 = 0x8009_0302;
+const SEC_E_UNTRUSTED_ROOT: u32 = 0x8009_0325;
+const SEC_E_CERT_UNKNOWN: u32 = 0x8009_0327;
+const SEC_E_CERT_EXPIRED: u32 = 0x8009_0328;
+const SEC_E_ALGORITHM_MISMATCH: u32 = 0x8009_0331;
+
+fn io_reason(e: &io::Error) -> HandshakeReason {
+    match e.raw_os_error().map(|code| code as u32) {
+        Some(SEC_E_ALGORITHM_MISMATCH) => HandshakeReason::NoSharedCipher,
+        Some(SEC_E_UNSUPPORTED_FUNCTION) => HandshakeReason::ProtocolVersion,
+        Some(SEC_E_UNTRUSTED_ROOT) | Some(SEC_E_CERT_UNKNOWN) | Some(SEC_E_CERT_EXPIRED) => {
+            HandshakeReason::ClientCertificate
+        }
+        _ => match e.kind() {
+            io::ErrorKind::Other | io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => HandshakeReason::Other,
+            _ => HandshakeReason::Io,
+        },
+    }
+}
+
 impl From<NativeTlsError> for HttpServiceError {
     fn from(e: NativeTlsError) -> Self {
-        Self::NativeTls(e)
+        Self::HandshakeFailed(None, super::TlsError::NativeTls(e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn io_error_reason() {
+        let reason = |e: io::Error| io_reason(&e);
+
+        assert_eq!(reason(io::ErrorKind::ConnectionReset.into()), HandshakeReason::Io);
+        assert_eq!(reason(io::ErrorKind::UnexpectedEof.into()), HandshakeReason::Io);
+        assert_eq!(reason(io::Error::new(io::ErrorKind::Other, "")), HandshakeReason::Other);
+        assert_eq!(
+            reason(io::Error::from_raw_os_error(SEC_E_ALGORITHM_MISMATCH as i32)),
+            HandshakeReason::NoSharedCipher
+        );
+        assert_eq!(
+            reason(io::Error::from_raw_os_error(SEC_E_CERT_UNKNOWN as i32)),
+            HandshakeReason::ClientCertificate
+        );
     }
 }
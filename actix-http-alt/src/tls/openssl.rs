@@ -1,31 +1,43 @@
 use std::{
-    fmt::{self, Debug, Formatter},
+    fmt::{self, Debug, Display, Formatter},
     future::Future,
     io,
     ops::{Deref, DerefMut},
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use actix_server_alt::net::AsyncReadWrite;
 use actix_service_alt::{Service, ServiceFactory};
 use bytes::BufMut;
 use futures_task::noop_waker;
+use http::Extensions;
+use log::warn;
 use openssl_crate::error::{Error, ErrorStack};
-use openssl_crate::ssl::{Error as TlsError, Ssl};
+use openssl_crate::ex_data::Index;
+use openssl_crate::ssl::{AlpnError, Error as TlsError, NameType, Ssl, SslAcceptorBuilder, SslRef};
+use openssl_crate::x509::X509VerifyResult;
 use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
 use tokio_util::io::poll_read_buf;
 
+use crate::connection::{ConnectionData, ConnectionExtensions, OnConnect, PeerCertificates};
 use crate::error::HttpServiceError;
 use crate::protocol::{AsProtocol, Protocol};
 
-pub(crate) use openssl_crate::ssl::SslAcceptor as TlsAcceptor;
+use super::accept::{TlsAccept, TlsInfo};
+use super::handshake::{HandshakeError, HandshakeGuard, HandshakeReason, OnHandshake};
+use super::keylog::{KeyLog, KeyLogWriter};
+
+pub use openssl_crate::ssl::SslAcceptor as TlsAcceptor;
 
 /// A wrapper type for [SslStream](tokio_openssl::SslStream).
 ///
 /// This is to impl new trait for it.
 pub struct TlsStream<S> {
     stream: tokio_openssl::SslStream<S>,
+    extensions: Option<ConnectionExtensions>,
 }
 
 impl<S> AsProtocol for TlsStream<S> {
@@ -43,6 +55,52 @@ impl<S> AsProtocol for TlsStream<S> {
     }
 }
 
+impl<S> OnConnect for TlsStream<S> {
+    fn on_connect(&self, data: &mut ConnectionData) {
+        let info = self.tls_info();
+        if let Some(certs) = info.peer_certs() {
+            data.set_peer_certificates(certs.clone());
+        }
+        data.set_tls_info(info);
+
+        if let Some(ref extensions) = self.extensions {
+            data.set_extensions(extensions.clone());
+        }
+    }
+}
+
+impl<S> TlsStream<S> {
+    /// Result of client certificate verification.
+    ///
+    /// With a verify callback tolerating failures the handshake succeeds regardless and the
+    /// result can be checked here.
+    pub fn verify_result(&self) -> X509VerifyResult {
+        self.ssl().verify_result()
+    }
+
+    fn tls_info(&self) -> TlsInfo {
+        let ssl = self.ssl();
+
+        // peer_cert_chain does not include client's own certificate on server side.
+        let certs = ssl.peer_certificate().map(|leaf| {
+            let chain = std::iter::once(&*leaf)
+                .chain(ssl.peer_cert_chain().into_iter().flatten())
+                .filter_map(|cert| cert.to_der().ok())
+                .collect();
+
+            PeerCertificates::new(chain)
+        });
+
+        TlsInfo {
+            version: Some(ssl.version_str().to_owned()),
+            cipher: ssl.current_cipher().map(|cipher| cipher.name().to_owned()),
+            alpn: ssl.selected_alpn_protocol().map(|proto| proto.to_vec()),
+            server_name: ssl.servername(NameType::HOST_NAME).map(|name| name.to_owned()),
+            peer_certs: certs,
+        }
+    }
+}
+
 impl<S> Deref for TlsStream<S> {
     type Target = tokio_openssl::SslStream<S>;
 
@@ -57,15 +115,153 @@ impl<S> DerefMut for TlsStream<S> {
     }
 }
 
+type AlpnProtocols = Arc<[Vec<u8>]>;
+
+#[derive(Clone)]
+struct Alpn {
+    idx: Index<Ssl, AlpnProtocols>,
+    protos: AlpnProtocols,
+}
+
+#[derive(Clone)]
+struct KeyLogger {
+    idx: Index<Ssl, Arc<KeyLogWriter>>,
+    writer: Option<Arc<KeyLogWriter>>,
+}
+
+type OnAccept = Arc<dyn Fn(&SslRef) -> Extensions + Send + Sync>;
+
 /// Openssl Acceptor. Used to accept a unsecure Stream and upgrade it to a TlsStream.
+///
+/// When client presented a certificate the [X509VerifyResult] of it is available from
+/// [ConnectionExtensions] of requests so tolerated verification failure can be handled by
+/// application.
 #[derive(Clone)]
 pub struct TlsAcceptorService {
     acceptor: TlsAcceptor,
+    alpn: Option<Alpn>,
+    keylog: Option<KeyLogger>,
+    on_handshake: Option<OnHandshake>,
+    on_accept: Option<OnAccept>,
 }
 
 impl TlsAcceptorService {
+    /// Construct from a prebuilt acceptor.
+    ///
+    /// ALPN protocols and key logging of a prebuilt acceptor can not be changed afterwards.
     pub fn new(acceptor: TlsAcceptor) -> Self {
-        Self { acceptor }
+        Self {
+            acceptor,
+            alpn: None,
+            keylog: None,
+            on_handshake: None,
+            on_accept: None,
+        }
+    }
+
+    /// Construct from an acceptor builder and let TlsAcceptorService select ALPN protocol.
+    ///
+    /// ALPN protocols default to the ones supported by enabled features.
+    pub fn from_builder(mut builder: SslAcceptorBuilder) -> Result<Self, ErrorStack> {
+        let idx = Ssl::new_ex_index::<AlpnProtocols>()?;
+
+        builder.set_alpn_select_callback(move |ssl, client| {
+            ssl.ex_data(idx)
+                .and_then(|server| super::alpn::select(server, client))
+                .ok_or(AlpnError::NOACK)
+        });
+
+        let keylog_idx = Ssl::new_ex_index::<Arc<KeyLogWriter>>()?;
+
+        // key log writer is only attached to ssl when key logging is enabled.
+        builder.set_keylog_callback(move |ssl, line| {
+            if let Some(writer) = ssl.ex_data(keylog_idx) {
+                writer.write_line(line);
+            }
+        });
+
+        Ok(Self {
+            acceptor: builder.build(),
+            alpn: Some(Alpn {
+                idx,
+                protos: super::alpn::default_protocols().into(),
+            }),
+            keylog: Some(KeyLogger {
+                idx: keylog_idx,
+                writer: None,
+            }),
+            on_handshake: None,
+            on_accept: None,
+        })
+    }
+
+    /// Override ALPN protocols. Protocols are in the order of preference.
+    ///
+    /// Only take effect when constructed with [from_builder](Self::from_builder). Empty protocols
+    /// would fall back to the ones supported by enabled features.
+    pub fn alpn(mut self, protos: &[&str]) -> Self {
+        match self.alpn {
+            Some(ref mut alpn) => {
+                alpn.protos = if protos.is_empty() {
+                    super::alpn::default_protocols().into()
+                } else {
+                    protos.iter().map(|proto| proto.as_bytes().to_vec()).collect()
+                };
+            }
+            None => warn!(
+                "ALPN protocols of a prebuilt SslAcceptor can not be overridden. Use TlsAcceptorService::from_builder"
+            ),
+        }
+
+        self
+    }
+
+    /// Write tls session secrets to given key log destination.
+    ///
+    /// Only take effect when constructed with [from_builder](Self::from_builder).
+    pub fn keylog(mut self, keylog: KeyLog) -> Self {
+        match self.keylog {
+            Some(ref mut logger) => logger.writer = KeyLogWriter::open(&keylog).map(Arc::new),
+            None => {
+                if let Some(path) = keylog.path() {
+                    warn!(
+                        "Key logging of a prebuilt SslAcceptor can not be enabled. Session secrets are not written to {}. \
+                        Use TlsAcceptorService::from_builder",
+                        path.display()
+                    );
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Observe the outcome and duration of every tls handshake.
+    pub fn on_handshake<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Result<&TlsInfo, HandshakeError>, Duration) + Send + Sync + 'static,
+    {
+        self.on_handshake = Some(Arc::new(f));
+        self
+    }
+
+    /// Produce connection level extensions from ssl session after a successful handshake.
+    ///
+    /// Returned extensions are shared by all requests of the connection as
+    /// [ConnectionExtensions]. Data gathered in verify callback can be passed through ex data of
+    /// ssl session.
+    pub fn on_accept<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&SslRef) -> Extensions + Send + Sync + 'static,
+    {
+        self.on_accept = Some(Arc::new(f));
+        self
+    }
+}
+
+impl From<TlsAcceptor> for TlsAcceptorService {
+    fn from(acceptor: TlsAcceptor) -> Self {
+        Self::new(acceptor)
     }
 }
 
@@ -78,6 +274,10 @@ impl<St: AsyncReadWrite> ServiceFactory<St> for TlsAcceptorService {
     type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
 
     fn new_service(&self, _: Self::Config) -> Self::Future {
+        if let Some(ref alpn) = self.alpn {
+            super::alpn::check_protocols(alpn.protos.iter().map(|proto| proto.as_slice()));
+        }
+
         let this = self.clone();
         async move { Ok(this) }
     }
@@ -96,12 +296,68 @@ impl<St: AsyncReadWrite> Service<St> for TlsAcceptorService {
 
     fn call(&self, io: St) -> Self::Future<'_> {
         async move {
-            let ctx = self.acceptor.context();
-            let ssl = Ssl::new(ctx)?;
-            let mut stream = tokio_openssl::SslStream::new(ssl, io)?;
-            Pin::new(&mut stream).accept().await?;
-            Ok(TlsStream { stream })
+            let guard = self.on_handshake.as_ref().map(HandshakeGuard::new);
+
+            let res = self.handshake(io).await;
+
+            if let Some(guard) = guard {
+                match res {
+                    Ok(ref stream) => guard.finish(Ok(&stream.tls_info())),
+                    Err(ref e) => guard.finish(Err(HandshakeError::new(e.reason()))),
+                }
+            }
+
+            res
+        }
+    }
+}
+
+impl<St: AsyncReadWrite> TlsAccept<St> for TlsAcceptorService {
+    type Stream = TlsStream<St>;
+    type Error = OpensslError;
+    type Future<'f> = impl Future<Output = Result<(Self::Stream, TlsInfo), Self::Error>>;
+
+    fn accept(&self, io: St) -> Self::Future<'_> {
+        async move {
+            let stream = Service::call(self, io).await?;
+            let info = stream.tls_info();
+            Ok((stream, info))
+        }
+    }
+}
+
+impl TlsAcceptorService {
+    async fn handshake<St: AsyncReadWrite>(&self, io: St) -> Result<TlsStream<St>, OpensslError> {
+        let ctx = self.acceptor.context();
+        let mut ssl = Ssl::new(ctx)?;
+        if let Some(ref alpn) = self.alpn {
+            ssl.set_ex_data(alpn.idx, alpn.protos.clone());
         }
+        if let Some(KeyLogger {
+            idx,
+            writer: Some(ref writer),
+        }) = self.keylog
+        {
+            ssl.set_ex_data(idx, writer.clone());
+        }
+        let mut stream = tokio_openssl::SslStream::new(ssl, io)?;
+        if let Err(e) = Pin::new(&mut stream).accept().await {
+            let res = stream.ssl().verify_result();
+            return if res != X509VerifyResult::OK {
+                Err(OpensslError::Verify(res))
+            } else {
+                Err(e.into())
+            };
+        }
+
+        let ssl = stream.ssl();
+        let mut extensions = self.on_accept.as_ref().map(|f| f(ssl)).unwrap_or_default();
+        if ssl.peer_certificate().is_some() {
+            extensions.insert(ssl.verify_result());
+        }
+        let extensions = (!extensions.is_empty()).then(|| ConnectionExtensions::new(extensions));
+
+        Ok(TlsStream { stream, extensions })
     }
 }
 
@@ -110,6 +366,44 @@ pub enum OpensslError {
     Ssl(TlsError),
     Single(Error),
     Stack(ErrorStack),
+    /// Client certificate failed verification.
+    Verify(X509VerifyResult),
+}
+
+impl OpensslError {
+    pub(crate) fn reason(&self) -> HandshakeReason {
+        match *self {
+            Self::Verify(_) => HandshakeReason::ClientCertificate,
+            Self::Ssl(ref e) => ssl_reason(e),
+            Self::Stack(ref e) => stack_reason(e),
+            Self::Single(_) => HandshakeReason::Other,
+        }
+    }
+}
+
+pub(crate) fn ssl_reason(e: &TlsError) -> HandshakeReason {
+    if e.io_error().is_some() {
+        HandshakeReason::Io
+    } else {
+        e.ssl_error().map(stack_reason).unwrap_or(HandshakeReason::Other)
+    }
+}
+
+pub(crate) fn stack_reason(stack: &ErrorStack) -> HandshakeReason {
+    stack
+        .errors()
+        .iter()
+        .find_map(|e| match e.reason()? {
+            "no shared cipher" => Some(HandshakeReason::NoSharedCipher),
+            "unsupported protocol" | "wrong version number" | "version too low" => {
+                Some(HandshakeReason::ProtocolVersion)
+            }
+            "peer did not return a certificate" | "certificate verify failed" => {
+                Some(HandshakeReason::ClientCertificate)
+            }
+            _ => None,
+        })
+        .unwrap_or(HandshakeReason::Other)
 }
 
 impl Debug for OpensslError {
@@ -118,6 +412,29 @@ impl Debug for OpensslError {
             Self::Ssl(ref e) => write!(f, "{:?}", e),
             Self::Single(ref e) => write!(f, "{:?}", e),
             Self::Stack(ref e) => write!(f, "{:?}", e),
+            Self::Verify(ref e) => write!(f, "Client certificate verification failed: {}", e.error_string()),
+        }
+    }
+}
+
+impl Display for OpensslError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Ssl(ref e) => write!(f, "{}", e),
+            Self::Single(ref e) => write!(f, "{}", e),
+            Self::Stack(ref e) => write!(f, "{}", e),
+            Self::Verify(ref e) => write!(f, "Client certificate verification failed: {}", e.error_string()),
+        }
+    }
+}
+
+impl std::error::Error for OpensslError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Self::Ssl(ref e) => Some(e),
+            Self::Single(ref e) => Some(e),
+            Self::Stack(ref e) => Some(e),
+            Self::Verify(_) => None,
         }
     }
 }
@@ -229,6 +546,6 @@ impl<S: AsyncReadWrite> AsyncReadWrite for TlsStream<S> {
 
 impl From<OpensslError> for HttpServiceError {
     fn from(e: OpensslError) -> Self {
-        Self::Openssl(e)
+        Self::HandshakeFailed(None, super::TlsError::Openssl(e))
     }
 }
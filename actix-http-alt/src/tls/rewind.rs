@@ -0,0 +1,121 @@
+use std::{
+    cmp,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use actix_server_alt::net::AsyncReadWrite;
+use bytes::{Buf, BufMut, Bytes};
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
+
+/// A stream type replay the bytes already read from it before reading from the io again.
+pub struct Rewind<S> {
+    pre: Bytes,
+    io: S,
+}
+
+impl<S> Rewind<S> {
+    pub(crate) fn new(io: S, pre: Bytes) -> Self {
+        Self { pre, io }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.io
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewind<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.pre.is_empty() {
+            Pin::new(&mut this.io).poll_read(cx, buf)
+        } else {
+            let len = cmp::min(this.pre.len(), buf.remaining());
+            buf.put_slice(&this.pre[..len]);
+            this.pre.advance(len);
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewind<S> {
+    #[inline]
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+
+    #[inline]
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write_vectored(cx, bufs)
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+}
+
+impl<S: AsyncReadWrite> AsyncReadWrite for Rewind<S> {
+    type ReadyFuture<'f> = impl Future<Output = io::Result<Ready>>;
+
+    fn ready(&mut self, interest: Interest) -> Self::ReadyFuture<'_> {
+        async move {
+            if interest.is_readable() && !self.pre.is_empty() {
+                Ok(Ready::READABLE)
+            } else {
+                self.io.ready(interest).await
+            }
+        }
+    }
+
+    fn try_read_buf<B: BufMut>(&mut self, buf: &mut B) -> io::Result<usize> {
+        if self.pre.is_empty() {
+            self.io.try_read_buf(buf)
+        } else {
+            let len = cmp::min(self.pre.len(), buf.remaining_mut());
+            buf.put_slice(&self.pre[..len]);
+            self.pre.advance(len);
+            Ok(len)
+        }
+    }
+
+    #[inline]
+    fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.try_write(buf)
+    }
+
+    #[inline]
+    fn try_write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.io.try_write_vectored(bufs)
+    }
+
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.pre.is_empty() {
+            self.io.poll_read_ready(cx)
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[inline]
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.io.poll_write_ready(cx)
+    }
+}
@@ -1,34 +1,51 @@
 use std::{
-    fmt::{self, Debug, Formatter},
+    fmt::{self, Debug, Display, Formatter},
     future::Future,
     io,
     ops::{Deref, DerefMut},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
+    time::Duration,
 };
 
 use actix_server_alt::net::AsyncReadWrite;
 use actix_service_alt::{Service, ServiceFactory};
-use bytes::BufMut;
+use bytes::{BufMut, Bytes, BytesMut};
 use futures_task::noop_waker;
-use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, Interest, ReadBuf, Ready};
 use tokio_rustls::{
-    rustls::{ServerConfig, Session},
+    rustls::{
+        ProducesTickets, ProtocolVersion, ServerConfig, ServerSession, ServerSessionMemoryCache, Session, TLSError,
+        Ticketer,
+    },
     TlsAcceptor,
 };
 use tokio_util::io::poll_read_buf;
 
+use crate::connection::{ConnectionData, OnConnect, PeerCertificates, VirtualHost};
 use crate::error::HttpServiceError;
 use crate::protocol::{AsProtocol, Protocol};
+use crate::vhost::HostRouter;
 
-pub(crate) type RustlsConfig = Arc<ServerConfig>;
+use super::accept::{TlsAccept, TlsInfo};
+use super::acme::{self, AcmeHandle, ChallengeResolver};
+use super::client_hello::ClientHello;
+use super::handshake::{HandshakeError, HandshakeGuard, HandshakeReason, OnHandshake};
+use super::keylog::{KeyLog, KeyLogWriter};
+use super::rewind::Rewind;
+
+pub type RustlsConfig = Arc<ServerConfig>;
 
 /// A wrapper type for [TlsStream](tokio_rustls::TlsStream).
 ///
 /// This is to impl new trait for it.
 pub struct TlsStream<S> {
-    stream: tokio_rustls::server::TlsStream<S>,
+    stream: tokio_rustls::server::TlsStream<Rewind<S>>,
+    vhost: Option<VirtualHost>,
 }
 
 impl<S> AsProtocol for TlsStream<S> {
@@ -47,8 +64,51 @@ impl<S> AsProtocol for TlsStream<S> {
     }
 }
 
+impl<S> OnConnect for TlsStream<S> {
+    fn on_connect(&self, data: &mut ConnectionData) {
+        let info = self.tls_info();
+        if let Some(certs) = info.peer_certs() {
+            data.set_peer_certificates(certs.clone());
+        }
+        data.set_tls_info(info);
+
+        if let Some(ref vhost) = self.vhost {
+            data.set_virtual_host(vhost.clone());
+        }
+    }
+}
+
+impl<S> TlsStream<S> {
+    fn tls_info(&self) -> TlsInfo {
+        let session = self.get_ref().1;
+
+        let certs = session.get_peer_certificates().map(|certs| {
+            let chain = certs.into_iter().map(|cert| cert.0).collect();
+            PeerCertificates::new(chain)
+        });
+
+        TlsInfo {
+            version: session.get_protocol_version().map(version_str),
+            cipher: session
+                .get_negotiated_ciphersuite()
+                .map(|suite| format!("{:?}", suite.suite)),
+            alpn: session.get_alpn_protocol().map(|proto| proto.to_vec()),
+            server_name: ServerSession::get_sni_hostname(session).map(|name| name.to_owned()),
+            peer_certs: certs,
+        }
+    }
+}
+
+fn version_str(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_2 => "TLSv1.2".to_owned(),
+        ProtocolVersion::TLSv1_3 => "TLSv1.3".to_owned(),
+        version => format!("{:?}", version),
+    }
+}
+
 impl<S> Deref for TlsStream<S> {
-    type Target = tokio_rustls::server::TlsStream<S>;
+    type Target = tokio_rustls::server::TlsStream<Rewind<S>>;
 
     fn deref(&self) -> &Self::Target {
         &self.stream
@@ -61,18 +121,172 @@ impl<S> DerefMut for TlsStream<S> {
     }
 }
 
+/// Decision made on a ClientHello before tls handshake.
+pub enum Decision {
+    /// Continue handshake with given server config.
+    Accept(RustlsConfig),
+    /// Close the connection without handshake.
+    Reject,
+}
+
+type ClientHelloHook = Arc<dyn Fn(&ClientHello) -> Decision + Send + Sync>;
+
 /// Rustls Acceptor. Used to accept a unsecure Stream and upgrade it to a TlsStream.
 #[derive(Clone)]
 pub struct TlsAcceptorService {
-    acceptor: TlsAcceptor,
+    config: RustlsConfig,
+    client_hello: Option<ClientHelloHook>,
+    on_handshake: Option<OnHandshake>,
+    acme: AcmeHandle,
+    vhosts: Option<Arc<HostRouter>>,
+    rejected: Arc<AtomicUsize>,
 }
 
 impl TlsAcceptorService {
-    pub fn new(config: Arc<ServerConfig>) -> Self {
+    /// Construct from a rustls server config.
+    ///
+    /// When config has no ALPN protocols the ones supported by enabled features are used.
+    pub fn new(config: RustlsConfig) -> Self {
+        Self::from_config(with_default_alpn(config))
+    }
+
+    /// Construct from a fully built rustls server config. The config is used as is.
+    pub fn from_config(config: RustlsConfig) -> Self {
         Self {
-            acceptor: TlsAcceptor::from(config),
+            config,
+            client_hello: None,
+            on_handshake: None,
+            acme: AcmeHandle::default(),
+            vhosts: None,
+            rejected: Arc::new(AtomicUsize::new(0)),
         }
     }
+
+    /// Select server config by server name of ClientHello. Config of the default host is used
+    /// as the fallback of other hooks.
+    ///
+    /// [client_hello](Self::client_hello) hook is not called when virtual hosts are used.
+    pub(crate) fn virtual_hosts(config: RustlsConfig, mut router: HostRouter) -> Self {
+        router
+            .configs_mut()
+            .for_each(|config| *config = with_default_alpn(config.clone()));
+
+        let mut this = Self::new(config);
+        this.vhosts = Some(Arc::new(router));
+        this
+    }
+
+    fn map_config<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut ServerConfig),
+    {
+        let mut config = ServerConfig::clone(&self.config);
+        f(&mut config);
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Override ALPN protocols of server config. Protocols are in the order of preference.
+    ///
+    /// Empty protocols would fall back to the ones supported by enabled features.
+    pub fn alpn(self, protos: &[&str]) -> Self {
+        let protos = if protos.is_empty() {
+            super::alpn::default_protocols()
+        } else {
+            protos.iter().map(|proto| proto.as_bytes().to_vec()).collect()
+        };
+
+        self.map_config(|config| config.set_protocols(&protos))
+    }
+
+    /// Enable stateless session resumption with tls session tickets.
+    ///
+    /// Ticket keys are rotated every 6 hours. All workers share the same ticket keys as they
+    /// share the same server config. When replacing the server config afterwards (e.g. swapping
+    /// certificates) carry over its `ticketer` so issued tickets stay valid.
+    pub fn session_tickets(self) -> Self {
+        self.ticketer(Ticketer::new())
+    }
+
+    /// Use given ticket producer for session tickets.
+    ///
+    /// Useful for sharing ticket keys between multiple acceptors of the process.
+    pub fn ticketer(self, ticketer: Arc<dyn ProducesTickets>) -> Self {
+        self.map_config(|config| config.ticketer = ticketer)
+    }
+
+    /// Enable stateful session resumption with an in memory cache of given size.
+    pub fn session_cache(self, size: usize) -> Self {
+        self.map_config(|config| config.set_persistence(ServerSessionMemoryCache::new(size)))
+    }
+
+    /// Write tls session secrets to given key log destination.
+    ///
+    /// Server configs returned from [client_hello](Self::client_hello) hook are not affected.
+    pub fn keylog(self, keylog: KeyLog) -> Self {
+        match KeyLogWriter::open(&keylog) {
+            Some(writer) => self.map_config(|config| config.key_log = Arc::new(writer)),
+            None => self,
+        }
+    }
+
+    /// Inspect ClientHello of every connection before tls handshake and decide if the
+    /// handshake should go on and with which server config.
+    pub fn client_hello<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ClientHello) -> Decision + Send + Sync + 'static,
+    {
+        self.client_hello = Some(Arc::new(f));
+        self
+    }
+
+    /// Observe the outcome and duration of every tls handshake.
+    pub fn on_handshake<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Result<&TlsInfo, HandshakeError>, Duration) + Send + Sync + 'static,
+    {
+        self.on_handshake = Some(Arc::new(f));
+        self
+    }
+
+    /// Solve acme TLS-ALPN-01 challenges with given resolver.
+    ///
+    /// Connections offering `acme-tls/1` protocol finish tls handshake with the challenge
+    /// certificate and are closed right after. They never reach http layer.
+    pub fn acme_challenge<R>(self, resolver: R) -> Self
+    where
+        R: ChallengeResolver + 'static,
+    {
+        self.acme.set(resolver);
+        self
+    }
+
+    /// Handle for swapping challenge resolver at runtime.
+    pub fn acme_handle(&self) -> AcmeHandle {
+        self.acme.clone()
+    }
+
+    /// Count of connections rejected by [client_hello](Self::client_hello) hook or unknown
+    /// virtual host.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+fn with_default_alpn(config: RustlsConfig) -> RustlsConfig {
+    if config.alpn_protocols.is_empty() {
+        let mut config = ServerConfig::clone(&config);
+        config.set_protocols(&super::alpn::default_protocols());
+        Arc::new(config)
+    } else {
+        config
+    }
+}
+
+impl From<RustlsConfig> for TlsAcceptorService {
+    fn from(config: RustlsConfig) -> Self {
+        Self::new(config)
+    }
 }
 
 impl<St: AsyncReadWrite> ServiceFactory<St> for TlsAcceptorService {
@@ -84,6 +298,8 @@ impl<St: AsyncReadWrite> ServiceFactory<St> for TlsAcceptorService {
     type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
 
     fn new_service(&self, _: Self::Config) -> Self::Future {
+        super::alpn::check_protocols(self.config.alpn_protocols.iter().map(|proto| proto.as_slice()));
+
         let this = self.clone();
         async move { Ok(this) }
     }
@@ -100,15 +316,99 @@ impl<St: AsyncReadWrite> Service<St> for TlsAcceptorService {
         Poll::Ready(Ok(()))
     }
 
-    #[inline]
     fn call(&self, io: St) -> Self::Future<'_> {
         async move {
-            let stream = self.acceptor.accept(io).await?;
-            Ok(TlsStream { stream })
+            let guard = self.on_handshake.as_ref().map(HandshakeGuard::new);
+
+            let res = self.handshake(io).await;
+
+            if let Some(guard) = guard {
+                match res {
+                    Ok(ref stream) => guard.finish(Ok(&stream.tls_info())),
+                    // acme challenge is not a handshake of served connection.
+                    Err(RustlsError::AcmeChallenge) => guard.skip(),
+                    Err(ref e) => guard.finish(Err(HandshakeError::new(e.reason()))),
+                }
+            }
+
+            res
         }
     }
 }
 
+impl<St: AsyncReadWrite> TlsAccept<St> for TlsAcceptorService {
+    type Stream = TlsStream<St>;
+    type Error = RustlsError;
+    type Future<'f> = impl Future<Output = Result<(Self::Stream, TlsInfo), Self::Error>>;
+
+    fn accept(&self, io: St) -> Self::Future<'_> {
+        async move {
+            let stream = Service::call(self, io).await?;
+            let info = stream.tls_info();
+            Ok((stream, info))
+        }
+    }
+}
+
+impl TlsAcceptorService {
+    async fn handshake<St: AsyncReadWrite>(&self, mut io: St) -> Result<TlsStream<St>, RustlsError> {
+        let acme = self.acme.is_enabled();
+
+        if self.client_hello.is_none() && self.vhosts.is_none() && !acme {
+            let stream = TlsAcceptor::from(self.config.clone())
+                .accept(Rewind::new(io, Bytes::new()))
+                .await?;
+            return Ok(TlsStream { stream, vhost: None });
+        }
+
+        let mut buf = BytesMut::new();
+        let hello = ClientHello::read(&mut io, &mut buf).await?;
+        let io = Rewind::new(io, buf.freeze());
+
+        let resolver = if acme && acme::is_challenge(&hello) {
+            self.acme.get()
+        } else {
+            None
+        };
+
+        match resolver {
+            Some(resolver) => {
+                let key = hello
+                    .server_name()
+                    .and_then(|name| resolver.resolve(name))
+                    .ok_or(RustlsError::Rejected)?;
+
+                let config = acme::challenge_config(key);
+                let mut stream = TlsAcceptor::from(config).accept(io).await?;
+                let _ = stream.shutdown().await;
+
+                Err(RustlsError::AcmeChallenge)
+            }
+            None => {
+                let (config, vhost) = match (&self.vhosts, &self.client_hello) {
+                    (Some(hosts), _) => match hosts.route(hello.server_name()) {
+                        Some((vhost, config)) => (config, Some(vhost)),
+                        None => return Err(self.reject()),
+                    },
+                    (None, Some(hook)) => match hook(&hello) {
+                        Decision::Accept(config) => (config, None),
+                        Decision::Reject => return Err(self.reject()),
+                    },
+                    (None, None) => (self.config.clone(), None),
+                };
+
+                let stream = TlsAcceptor::from(config).accept(io).await?;
+                Ok(TlsStream { stream, vhost })
+            }
+        }
+    }
+
+    fn reject(&self) -> RustlsError {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        RustlsError::Rejected
+    }
+}
+
 impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
     #[inline]
     fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
@@ -197,22 +497,77 @@ impl<S: AsyncReadWrite> AsyncReadWrite for TlsStream<S> {
 }
 
 /// Collection of 'rustls' error types.
-pub struct RustlsError(io::Error);
+pub enum RustlsError {
+    Io(io::Error),
+    /// Connection rejected by ClientHello hook.
+    Rejected,
+    /// Connection finished acme challenge handshake and closed.
+    AcmeChallenge,
+}
 
 impl Debug for RustlsError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.0)
+        match *self {
+            Self::Io(ref e) => write!(f, "{:?}", e),
+            Self::Rejected => write!(f, "Connection rejected on ClientHello"),
+            Self::AcmeChallenge => write!(f, "Connection closed after acme challenge"),
+        }
+    }
+}
+
+impl Display for RustlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Io(ref e) => write!(f, "{}", e),
+            ref e => write!(f, "{:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for RustlsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Self::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl RustlsError {
+    pub(crate) fn reason(&self) -> HandshakeReason {
+        match *self {
+            Self::Rejected => HandshakeReason::Rejected,
+            // not reported to on_handshake hook.
+            Self::AcmeChallenge => HandshakeReason::Other,
+            Self::Io(ref e) => match e.get_ref().and_then(|e| e.downcast_ref::<TLSError>()) {
+                Some(TLSError::NoCertificatesPresented)
+                | Some(TLSError::WebPKIError(_))
+                | Some(TLSError::InvalidSCT(_)) => HandshakeReason::ClientCertificate,
+                Some(TLSError::PeerIncompatibleError(ref why)) if why.contains("TLS") => {
+                    HandshakeReason::ProtocolVersion
+                }
+                Some(TLSError::PeerIncompatibleError(ref why)) if why.contains("ciphersuites") => {
+                    HandshakeReason::NoSharedCipher
+                }
+                Some(_) => HandshakeReason::Other,
+                None => HandshakeReason::Io,
+            },
+        }
     }
 }
 
 impl From<io::Error> for RustlsError {
     fn from(e: io::Error) -> Self {
-        Self(e)
+        Self::Io(e)
     }
 }
 
 impl From<RustlsError> for HttpServiceError {
     fn from(e: RustlsError) -> Self {
-        Self::Rustls(e)
+        match e {
+            // acme challenge is an expected close and not a failure.
+            RustlsError::AcmeChallenge => Self::Ignored,
+            e => Self::HandshakeFailed(None, super::TlsError::Rustls(e)),
+        }
     }
 }
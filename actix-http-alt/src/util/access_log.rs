@@ -0,0 +1,412 @@
+use std::{
+    fmt::{self, Write},
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use bytes::Bytes;
+use futures_core::Stream;
+use http::{header, Method, Request, Response, StatusCode, Uri, Version};
+use httpdate::HttpDate;
+use pin_project::{pin_project, pinned_drop};
+
+use crate::body::ResponseBody;
+use crate::connection::ConnectionAddrs;
+use crate::response::ResponseError;
+
+use super::date::cached_system_time;
+use super::request_id::RequestId;
+
+/// Format of access log lines.
+///
+/// [RequestId](super::RequestId) of request is appended when present.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// Common Log Format with quoted user agent and duration in milli seconds appended.
+    ///
+    /// `127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] "GET /path HTTP/1.1" 200 5 "curl/7.64.1" 0.312`
+    Common,
+    /// Space separated key=value pairs.
+    ///
+    /// `peer=127.0.0.1:50000 method=GET path="/path" version=HTTP/1.1 status=200 size=5
+    /// duration_ms=0.312 user_agent="curl/7.64.1"`
+    KeyValue,
+}
+
+/// A factory that log one line for every request handled by the service it wraps.
+///
+/// Compose it around the service factory passed to [HttpServiceBuilder](crate::HttpServiceBuilder).
+/// Lines are emitted with [log::info] when response body is finished or dropped. Duration is
+/// measured from the service call, right after request head is decoded. Response size is the
+/// number of body bytes yielded and is only final for a body streamed to its end.
+///
+/// Service errors are converted to responses by the wrapper so their status can be logged.
+pub struct AccessLogFactory<F> {
+    factory: F,
+    format: AccessLogFormat,
+}
+
+impl<F> AccessLogFactory<F> {
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            format: AccessLogFormat::Common,
+        }
+    }
+
+    /// Change format of log lines. Default to [AccessLogFormat::Common].
+    pub fn format(mut self, format: AccessLogFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl<F, ReqB, B> ServiceFactory<Request<ReqB>> for AccessLogFactory<F>
+where
+    F: ServiceFactory<Request<ReqB>, Response = Response<ResponseBody<B>>>,
+    F::Service: 'static,
+    F::Error: ResponseError<F::Response>,
+{
+    type Response = Response<ResponseBody<AccessLogBody<B>>>;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = AccessLogService<F::Service>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let format = self.format;
+
+        async move {
+            let service = service.await?;
+
+            Ok(AccessLogService { service, format })
+        }
+    }
+}
+
+pub struct AccessLogService<S> {
+    service: S,
+    format: AccessLogFormat,
+}
+
+impl<S, ReqB, B> Service<Request<ReqB>> for AccessLogService<S>
+where
+    S: Service<Request<ReqB>, Response = Response<ResponseBody<B>>> + 'static,
+    S::Error: ResponseError<S::Response>,
+{
+    type Response = Response<ResponseBody<AccessLogBody<B>>>;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: Request<ReqB>) -> Self::Future<'_> {
+        async move {
+            let mut entry = Entry::new(self.format, &req);
+
+            let res = self
+                .service
+                .call(req)
+                .await
+                .unwrap_or_else(|ref mut e| ResponseError::response_error(e));
+
+            entry.status = res.status();
+
+            let (parts, body) = res.into_parts();
+
+            let body = match body {
+                ResponseBody::None => {
+                    entry.log();
+                    ResponseBody::None
+                }
+                // bytes body is written along with response head.
+                ResponseBody::Bytes { bytes } => {
+                    entry.size = bytes.len();
+                    entry.log();
+                    ResponseBody::Bytes { bytes }
+                }
+                ResponseBody::Stream { stream } => ResponseBody::Stream {
+                    stream: AccessLogBody {
+                        stream,
+                        entry: Some(entry),
+                    },
+                },
+            };
+
+            Ok(Response::from_parts(parts, body))
+        }
+    }
+}
+
+/// Stream body that count bytes yielded and log the request when it's finished or dropped.
+#[pin_project(PinnedDrop)]
+pub struct AccessLogBody<B> {
+    #[pin]
+    stream: B,
+    entry: Option<Entry>,
+}
+
+impl<B, E> Stream for AccessLogBody<B>
+where
+    B: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let item = futures_core::ready!(this.stream.poll_next(cx));
+
+        match item {
+            Some(Ok(ref bytes)) => {
+                if let Some(ref mut entry) = *this.entry {
+                    entry.size += bytes.len();
+                }
+            }
+            _ => {
+                if let Some(entry) = this.entry.take() {
+                    entry.log();
+                }
+            }
+        }
+
+        Poll::Ready(item)
+    }
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for AccessLogBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        // body is not streamed to its end.
+        if let Some(entry) = self.project().entry.take() {
+            entry.log();
+        }
+    }
+}
+
+struct Entry {
+    format: AccessLogFormat,
+    start: Instant,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    peer: Option<SocketAddr>,
+    user_agent: Option<String>,
+    request_id: Option<RequestId>,
+    status: StatusCode,
+    size: usize,
+}
+
+impl Entry {
+    fn new<B>(format: AccessLogFormat, req: &Request<B>) -> Self {
+        let peer = req
+            .extensions()
+            .get::<ConnectionAddrs>()
+            .and_then(ConnectionAddrs::peer);
+
+        // header value is not guaranteed to be utf-8.
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned());
+
+        Self {
+            format,
+            start: Instant::now(),
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            peer,
+            user_agent,
+            request_id: req.extensions().get::<RequestId>().cloned(),
+            status: StatusCode::OK,
+            size: 0,
+        }
+    }
+
+    fn log(self) {
+        let mut line = String::new();
+        // cached date of http service saves a clock read per request.
+        let now = cached_system_time().unwrap_or_else(SystemTime::now);
+        let _ = self.write(&mut line, self.start.elapsed(), now);
+        log::info!("{}", line);
+    }
+
+    fn write<W: Write>(&self, w: &mut W, duration: Duration, now: SystemTime) -> fmt::Result {
+        let duration = duration.as_secs_f64() * 1000.0;
+
+        match self.format {
+            AccessLogFormat::Common => {
+                match self.peer {
+                    Some(peer) => write!(w, "{}", peer.ip())?,
+                    None => w.write_char('-')?,
+                }
+
+                // convert "Sun, 06 Nov 1994 08:49:37 GMT" to "06/Nov/1994:08:49:37 +0000"
+                let date = HttpDate::from(now).to_string();
+                write!(
+                    w,
+                    " - - [{}/{}/{}:{} +0000]",
+                    &date[5..7],
+                    &date[8..11],
+                    &date[12..16],
+                    &date[17..25]
+                )?;
+
+                write!(
+                    w,
+                    " \"{} {} {:?}\" {} {}",
+                    self.method,
+                    self.path(),
+                    self.version,
+                    self.status.as_u16(),
+                    self.size
+                )?;
+
+                match self.user_agent {
+                    Some(ref ua) => write!(w, " {:?}", ua)?,
+                    None => w.write_str(" \"-\"")?,
+                }
+
+                write!(w, " {:.3}", duration)?;
+
+                match self.request_id {
+                    Some(ref id) => write!(w, " {}", id),
+                    None => Ok(()),
+                }
+            }
+            AccessLogFormat::KeyValue => {
+                match self.peer {
+                    Some(peer) => write!(w, "peer={}", peer)?,
+                    None => w.write_str("peer=-")?,
+                }
+
+                write!(
+                    w,
+                    " method={} path={:?} version={:?} status={} size={} duration_ms={:.3}",
+                    self.method,
+                    self.path(),
+                    self.version,
+                    self.status.as_u16(),
+                    self.size,
+                    duration
+                )?;
+
+                match self.user_agent {
+                    Some(ref ua) => write!(w, " user_agent={:?}", ua)?,
+                    None => w.write_str(" user_agent=-")?,
+                }
+
+                match self.request_id {
+                    Some(ref id) => write!(w, " request_id={}", id),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn path(&self) -> &str {
+        self.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{convert::Infallible, time::UNIX_EPOCH};
+
+    use actix_service_alt::fn_service;
+
+    fn entry(format: AccessLogFormat) -> Entry {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri("/path?q=1")
+            .header(header::USER_AGENT, &b"agent \"\xff\""[..])
+            .body(())
+            .unwrap();
+
+        req.extensions_mut().insert(ConnectionAddrs::Inet {
+            peer: "127.0.0.1:50000".parse().unwrap(),
+            local: "127.0.0.1:8080".parse().unwrap(),
+        });
+
+        let mut entry = Entry::new(format, &req);
+        entry.status = StatusCode::NOT_FOUND;
+        entry.size = 5;
+        entry
+    }
+
+    fn line(entry: &Entry) -> String {
+        let mut line = String::new();
+        let now = UNIX_EPOCH + Duration::from_secs(784111777);
+        entry.write(&mut line, Duration::from_micros(1500), now).unwrap();
+        line
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(
+            line(&entry(AccessLogFormat::Common)),
+            "127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] \"POST /path?q=1 HTTP/1.1\" 404 5 \"agent \\\"\u{fffd}\\\"\" 1.500"
+        );
+
+        assert_eq!(
+            line(&entry(AccessLogFormat::KeyValue)),
+            "peer=127.0.0.1:50000 method=POST path=\"/path?q=1\" version=HTTP/1.1 status=404 size=5 \
+             duration_ms=1.500 user_agent=\"agent \\\"\u{fffd}\\\"\""
+        );
+
+        let mut entry = entry(AccessLogFormat::KeyValue);
+        entry.request_id = RequestId::from_header(&http::HeaderValue::from_static("id-1"));
+        assert!(line(&entry).ends_with(" request_id=id-1"));
+
+        entry.format = AccessLogFormat::Common;
+        assert!(line(&entry).ends_with(" 1.500 id-1"));
+    }
+
+    #[tokio::test]
+    async fn stream_body_size() {
+        let factory = AccessLogFactory::new(fn_service(|_: Request<()>| async {
+            let body = Once(Some(Bytes::from_static(b"hello")));
+            Ok::<_, Infallible>(Response::new(ResponseBody::stream(body)))
+        }));
+
+        let service = factory.new_service(()).await.ok().unwrap();
+        let res = service.call(Request::new(())).await.unwrap();
+
+        let mut body = match res.into_body() {
+            ResponseBody::Stream { stream } => stream,
+            _ => panic!("body must be streamed"),
+        };
+
+        let mut cx = Context::from_waker(futures_task::noop_waker_ref());
+
+        let bytes = Pin::new(&mut body).poll_next(&mut cx);
+        assert!(matches!(bytes, Poll::Ready(Some(Ok(_)))));
+        assert_eq!(body.entry.as_ref().unwrap().size, 5);
+
+        // entry is taken and logged at the end of body.
+        assert!(matches!(Pin::new(&mut body).poll_next(&mut cx), Poll::Ready(None)));
+        assert!(body.entry.is_none());
+    }
+
+    struct Once(Option<Bytes>);
+
+    impl Stream for Once {
+        type Item = Result<Bytes, crate::error::BodyError>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.0.take().map(Ok))
+        }
+    }
+}
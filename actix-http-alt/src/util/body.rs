@@ -0,0 +1,187 @@
+//! Response body types.
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures_core::{ready, Stream};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use crate::body::ResponseBody;
+use crate::error::BodyError;
+
+/// Construct a response body fed by [BodySender] from another task.
+///
+/// At most `buffer` chunks are queued. [BodySender::send] waits when the queue is full so a slow
+/// connection slows down the producer.
+///
+/// # Panics
+/// When `buffer` is zero.
+///
+/// # Examples:
+/// ```rust
+/// use actix_http_alt::{http::Response, util::body::channel};
+/// use bytes::Bytes;
+///
+/// # async fn handler() -> Response<actix_http_alt::ResponseBody<actix_http_alt::util::body::ChannelBody>> {
+/// let (tx, body) = channel(8);
+///
+/// tokio::spawn(async move {
+///     for i in 0..3 {
+///         if tx.send(Bytes::from(format!("data: {}\n\n", i))).await.is_err() {
+///             // response body is dropped.
+///             return;
+///         }
+///     }
+///     tx.close();
+/// });
+///
+/// Response::new(body)
+/// # }
+/// ```
+pub fn channel(buffer: usize) -> (BodySender, ResponseBody<ChannelBody>) {
+    let (tx, rx) = mpsc::channel(buffer);
+    let closed = Arc::new(AtomicBool::new(false));
+
+    let sender = BodySender {
+        tx,
+        closed: closed.clone(),
+    };
+    let body = ChannelBody {
+        rx,
+        closed,
+        done: false,
+    };
+
+    (sender, ResponseBody::stream(body))
+}
+
+/// Sending half of [channel].
+///
+/// Body ends when every sender is dropped. It ends cleanly when any of them is closed with
+/// [BodySender::close]. Otherwise it ends with an error and the response is cut short. (Http/1
+/// connection is closed and Http/2 and Http/3 stream is reset.)
+#[derive(Clone)]
+pub struct BodySender {
+    tx: Sender<Bytes>,
+    closed: Arc<AtomicBool>,
+}
+
+impl BodySender {
+    /// Send a chunk of body. Wait when buffer of channel is full.
+    ///
+    /// Chunk is given back when response body is dropped.
+    pub async fn send(&self, bytes: Bytes) -> Result<(), Bytes> {
+        self.tx.send(bytes).await.map_err(|e| e.0)
+    }
+
+    /// Return true when response body is dropped.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// End body once chunks already sent are consumed.
+    pub fn close(self) {
+        self.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Receiving half of [channel].
+pub struct ChannelBody {
+    rx: Receiver<Bytes>,
+    closed: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl Stream for ChannelBody {
+    type Item = Result<Bytes, BodyError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        match ready!(this.rx.poll_recv(cx)) {
+            Some(bytes) => Poll::Ready(Some(Ok(bytes))),
+            None => {
+                this.done = true;
+                if this.closed.load(Ordering::Acquire) {
+                    Poll::Ready(None)
+                } else {
+                    let e = io::Error::new(io::ErrorKind::UnexpectedEof, "BodySender dropped without close");
+                    Poll::Ready(Some(Err(e.into())))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::future::Future;
+
+    use futures_task::noop_waker_ref;
+    use tokio::pin;
+
+    fn poll_next<S: Stream + Unpin>(stream: &mut S) -> Poll<Option<S::Item>> {
+        Pin::new(stream).poll_next(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    #[tokio::test]
+    async fn close() {
+        let (tx, mut body) = channel(1);
+
+        tx.send(Bytes::from_static(b"foo")).await.unwrap();
+
+        // buffer is full.
+        {
+            let send = tx.send(Bytes::from_static(b"bar"));
+            pin!(send);
+            let poll = send.as_mut().poll(&mut Context::from_waker(noop_waker_ref()));
+            assert!(poll.is_pending());
+        }
+
+        let tx2 = tx.clone();
+        tx.close();
+
+        match poll_next(&mut body) {
+            Poll::Ready(Some(Ok(bytes))) => assert_eq!(bytes, "foo"),
+            _ => panic!("chunk is not received"),
+        }
+
+        // body is not ended when there is live sender.
+        assert!(poll_next(&mut body).is_pending());
+
+        drop(tx2);
+        assert!(matches!(poll_next(&mut body), Poll::Ready(None)));
+        assert!(matches!(poll_next(&mut body), Poll::Ready(None)));
+    }
+
+    #[tokio::test]
+    async fn drop_sender() {
+        let (tx, mut body) = channel(4);
+
+        tx.send(Bytes::from_static(b"foo")).await.unwrap();
+        drop(tx);
+
+        assert!(matches!(poll_next(&mut body), Poll::Ready(Some(Ok(_)))));
+        assert!(matches!(poll_next(&mut body), Poll::Ready(Some(Err(BodyError::Io(_))))));
+        assert!(matches!(poll_next(&mut body), Poll::Ready(None)));
+
+        let (tx, body) = channel(4);
+        drop(body);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(Bytes::from_static(b"foo")).await.unwrap_err(), "foo");
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    io,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use futures_core::ready;
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Cooperative work budget of a connection task.
+///
+/// Refilled every time the task is polled through [Budget::run]. Work done by the task consumes
+/// the budget and the task yields to other tasks on the same thread when it's exhausted.
+#[derive(Clone)]
+pub(crate) struct Budget {
+    limit: usize,
+    remain: Rc<Cell<usize>>,
+}
+
+impl Budget {
+    /// Construct budget with given units per poll. Zero is treated as one.
+    pub(crate) fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            limit,
+            remain: Rc::new(Cell::new(limit)),
+        }
+    }
+
+    /// Consume one unit of budget. Return false when budget is exhausted.
+    pub(crate) fn consume(&self) -> bool {
+        match self.remain.get() {
+            0 => false,
+            n => {
+                self.remain.set(n - 1);
+                true
+            }
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.remain.get() == 0
+    }
+
+    /// Poll given future with budget refilled on every poll.
+    pub(crate) fn run<F: Future>(&self, fut: F) -> Budgeted<F> {
+        Budgeted {
+            fut,
+            budget: self.clone(),
+        }
+    }
+}
+
+#[pin_project]
+pub(crate) struct Budgeted<F> {
+    #[pin]
+    fut: F,
+    budget: Budget,
+}
+
+impl<F: Future> Future for Budgeted<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        this.budget.remain.set(this.budget.limit);
+        this.fut.poll(cx)
+    }
+}
+
+/// Io wrapper consuming one unit of budget for every successful read. Read returns pending and
+/// wakes the task when budget is exhausted.
+#[pin_project]
+pub(crate) struct BudgetIo<Io> {
+    #[pin]
+    io: Io,
+    budget: Budget,
+}
+
+impl<Io> BudgetIo<Io> {
+    pub(crate) fn new(io: Io, budget: &Budget) -> Self {
+        Self {
+            io,
+            budget: budget.clone(),
+        }
+    }
+}
+
+impl<Io: AsyncRead> AsyncRead for BudgetIo<Io> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+
+        if this.budget.is_exhausted() {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        ready!(this.io.poll_read(cx, buf))?;
+        this.budget.consume();
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<Io: AsyncWrite> AsyncWrite for BudgetIo<Io> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.project().io.poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().io.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_shutdown(cx)
+    }
+}
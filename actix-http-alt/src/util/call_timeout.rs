@@ -0,0 +1,173 @@
+use std::{
+    future::Future,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use http::{Request, Response};
+
+use crate::body::ResponseBody;
+use crate::response::{self, ErrorContext};
+
+/// Request extension for overriding timeout of [CallTimeoutService] for a single request.
+///
+/// Must be inserted before request reaches [CallTimeoutService].
+#[derive(Clone, Copy, Debug)]
+pub struct CallTimeout(pub Duration);
+
+/// Factory of [CallTimeoutService].
+///
+/// Only the future returned by inner service call is timed. Streaming of response body happens
+/// after and is not affected.
+pub struct CallTimeoutFactory<F> {
+    factory: F,
+    timeout: Duration,
+    retry_after: Duration,
+}
+
+impl<F> CallTimeoutFactory<F> {
+    pub fn new(factory: F, timeout: Duration) -> Self {
+        Self {
+            factory,
+            timeout,
+            retry_after: Duration::from_secs(1),
+        }
+    }
+
+    /// Change duration of `retry-after` header on timed out response. Default to 1 second.
+    pub fn retry_after(mut self, dur: Duration) -> Self {
+        self.retry_after = dur;
+        self
+    }
+}
+
+impl<F, ReqB, ResB> ServiceFactory<Request<ReqB>> for CallTimeoutFactory<F>
+where
+    F: ServiceFactory<Request<ReqB>, Response = Response<ResponseBody<ResB>>>,
+    F::Service: 'static,
+{
+    type Response = F::Response;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = CallTimeoutService<F::Service>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let timeout = self.timeout;
+        let retry_after = self.retry_after;
+
+        async move {
+            let service = service.await?;
+
+            Ok(CallTimeoutService {
+                service,
+                timeout,
+                retry_after,
+            })
+        }
+    }
+}
+
+pub struct CallTimeoutService<S> {
+    service: S,
+    timeout: Duration,
+    retry_after: Duration,
+}
+
+impl<S, ReqB, ResB> Service<Request<ReqB>> for CallTimeoutService<S>
+where
+    S: Service<Request<ReqB>, Response = Response<ResponseBody<ResB>>> + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: Request<ReqB>) -> Self::Future<'_> {
+        async move {
+            let timeout = req
+                .extensions()
+                .get::<CallTimeout>()
+                .map(|timeout| timeout.0)
+                .unwrap_or(self.timeout);
+            let version = req.version();
+
+            match tokio::time::timeout(timeout, self.service.call(req)).await {
+                Ok(res) => res,
+                Err(_) => Ok(response::unavailable(
+                    None,
+                    self.retry_after,
+                    ErrorContext::new(version),
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use http::{header::RETRY_AFTER, StatusCode};
+
+    use crate::util::delay;
+
+    #[tokio::test]
+    async fn timeout_override() {
+        let factory = CallTimeoutFactory::new(delay(Duration::from_millis(20)), Duration::from_millis(1))
+            .retry_after(Duration::from_millis(1500));
+        let service = ServiceFactory::<Request<()>>::new_service(&factory, ()).await.unwrap();
+
+        let res = service.call(Request::new(())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[RETRY_AFTER], "2");
+
+        let mut req = Request::new(());
+        req.extensions_mut().insert(CallTimeout(Duration::from_secs(1)));
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[cfg(feature = "http1")]
+    #[tokio::test]
+    async fn keep_alive() {
+        use tokio::task::LocalSet;
+
+        use crate::builder::HttpServiceBuilder;
+        use crate::util::testing::{duplex, serve, H1Client, TestStream};
+
+        LocalSet::new()
+            .run_until(async {
+                let factory = CallTimeoutFactory::new(delay(Duration::from_millis(20)), Duration::from_millis(1));
+                let builder = HttpServiceBuilder::h1(factory);
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+                let ((first, second), _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+                    client.send("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+                    let first = client.response().await.unwrap();
+                    client
+                        .send("GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let second = client.response().await.unwrap();
+                    (first, second)
+                })
+                .await;
+
+                assert_eq!(first.status(), StatusCode::SERVICE_UNAVAILABLE);
+                assert_eq!(first.headers()[RETRY_AFTER], "1");
+                assert!(first.headers().get("connection").is_none());
+                assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+            })
+            .await
+    }
+}
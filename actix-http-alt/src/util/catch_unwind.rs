@@ -0,0 +1,78 @@
+use std::{
+    any::Any,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use log::error;
+use pin_project::pin_project;
+
+use super::log_context::LogContext;
+
+/// Future catching panic from polling inner future. Pass through when disabled.
+#[pin_project]
+pub(crate) struct CatchUnwind<Fut> {
+    #[pin]
+    fut: Fut,
+    enabled: bool,
+}
+
+impl<Fut> CatchUnwind<Fut> {
+    pub(crate) fn new(fut: Fut, enabled: bool) -> Self {
+        Self { fut, enabled }
+    }
+}
+
+impl<Fut: Future> Future for CatchUnwind<Fut> {
+    type Output = Result<Fut::Output, Panic>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if !*this.enabled {
+            return this.fut.poll(cx).map(Ok);
+        }
+
+        // inner future is never polled again after a panic so its broken state is not observable.
+        let fut = this.fut;
+        match panic::catch_unwind(AssertUnwindSafe(|| fut.poll(cx))) {
+            Ok(Poll::Ready(res)) => Poll::Ready(Ok(res)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(Panic(payload))),
+        }
+    }
+}
+
+/// Payload of a caught panic.
+pub(crate) struct Panic(Box<dyn Any + Send>);
+
+impl Panic {
+    pub(crate) fn log(&self, ctx: &LogContext) {
+        let msg = match self.0.downcast_ref::<&str>() {
+            Some(msg) => msg,
+            None => self
+                .0
+                .downcast_ref::<String>()
+                .map(|msg| msg.as_str())
+                .unwrap_or("Box<dyn Any>"),
+        };
+
+        error!("{}: Service panicked: {}", ctx, msg);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn catch() {
+        let res = CatchUnwind::new(async { panic!("boom") }, true).await;
+        assert_eq!(*res.err().unwrap().0.downcast_ref::<&str>().unwrap(), "boom");
+
+        let res = CatchUnwind::new(async { 996 }, true).await;
+        assert_eq!(res.ok(), Some(996));
+    }
+}
@@ -2,33 +2,197 @@ use std::{
     cell::Cell,
     fmt::{self, Write},
     rc::Rc,
-    time::Duration,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use http::header::{HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH};
 use httpdate::HttpDate;
-use std::time::SystemTime;
 use tokio::{
+    select,
+    sync::Notify,
     task::JoinHandle,
-    time::{interval, Instant},
+    time::{interval, Instant, MissedTickBehavior},
 };
 
-pub(crate) const DATE_VALUE_LENGTH: usize = 29;
+use super::poll_fn::poll_fn;
+use super::timer::TimerWheel;
 
-pub(crate) type Date = Cell<DateTimeInner>;
+/// Source of time for cached `date` header and timer deadlines of http services.
+pub trait Clock: Send + Sync + 'static {
+    /// Monotonic time keep alive and request timeout deadlines are counted from.
+    fn now(&self) -> Instant;
+
+    /// Wall clock time sent as `date` header.
+    fn system_time(&self) -> SystemTime;
+
+    /// Notified when clock is moved by hand so cached date is refreshed without waiting for next
+    /// update interval. By default clock only moves with time.
+    fn notify(&self) -> Option<&Notify> {
+        None
+    }
+}
+
+/// [Clock] reading system time. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[inline]
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// [Clock] only moving forward when [ManualClock::advance] is called. Clones share the same time.
+///
+/// Deadlines are computed from the manual time while timers still fire on tokio's clock. Use
+/// [tokio::time::pause] together to fast forward timers.
+#[derive(Clone)]
+pub struct ManualClock(Arc<ManualClockInner>);
+
+struct ManualClockInner {
+    instant: Instant,
+    system_time: SystemTime,
+    // nanos passed since construction.
+    offset: AtomicU64,
+    notify: Notify,
+}
+
+impl ManualClock {
+    /// Construct a clock stopped at current time.
+    pub fn new() -> Self {
+        Self::with_system_time(SystemTime::now())
+    }
+
+    /// Construct a clock stopped at given wall clock time.
+    pub fn with_system_time(system_time: SystemTime) -> Self {
+        Self(Arc::new(ManualClockInner {
+            instant: Instant::now(),
+            system_time,
+            offset: AtomicU64::new(0),
+            notify: Notify::new(),
+        }))
+    }
+
+    /// Move clock forward by given duration.
+    pub fn advance(&self, dur: Duration) {
+        self.0.offset.fetch_add(dur.as_nanos() as u64, Ordering::Relaxed);
+        self.0.notify.notify_waiters();
+    }
+
+    fn offset(&self) -> Duration {
+        Duration::from_nanos(self.0.offset.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManualClock")
+            .field("system_time", &self.system_time())
+            .finish()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.0.instant + self.offset()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.0.system_time + self.offset()
+    }
+
+    fn notify(&self) -> Option<&Notify> {
+        Some(&self.0.notify)
+    }
+}
+
+/// Length of formatted http date.
+pub const DATE_VALUE_LENGTH: usize = 29;
+
+/// Default interval of refreshing cached date.
+pub(crate) const DEFAULT_REFRESH: Duration = Duration::from_millis(500);
+
+// age of cached date refreshed on access.
+const STALE: Duration = Duration::from_secs(1);
+
+thread_local! {
+    // unix seconds of date last refreshed on this thread. zero when there is none.
+    static UNIX_SECS: Cell<u64> = Cell::new(0);
+    // number of live DateTimeTask on this thread.
+    static TASKS: Cell<usize> = Cell::new(0);
+}
+
+/// Wall clock time of cached date last refreshed on current thread.
+///
+/// Lags behind system time by up to the refresh interval of the service. `None` when no http service
+/// is alive on current thread.
+pub(crate) fn cached_system_time() -> Option<SystemTime> {
+    match UNIX_SECS.with(Cell::get) {
+        0 => None,
+        secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+    }
+}
+
+/// Cached date shared by connections of a service.
+pub(crate) struct Date {
+    current: Cell<DateTimeInner>,
+    // clock date is read from when it's stale on access.
+    on_demand: Option<Arc<dyn Clock>>,
+}
+
+impl Date {
+    #[inline]
+    pub(crate) fn get(&self) -> DateTimeInner {
+        let date = self.current.get();
+        match self.on_demand {
+            Some(ref clock) if clock.now().saturating_duration_since(date.now) >= STALE => {
+                let date = DateTimeInner::new(&**clock);
+                self.set(date);
+                date
+            }
+            _ => date,
+        }
+    }
+
+    fn set(&self, date: DateTimeInner) {
+        self.current.set(date);
+        UNIX_SECS.with(|secs| secs.set(date.unix_secs));
+    }
+}
 
 #[derive(Copy, Clone)]
 pub(crate) struct DateTimeInner {
     date: [u8; DATE_VALUE_LENGTH],
     now: Instant,
+    unix_secs: u64,
 }
 
 impl DateTimeInner {
-    fn new() -> Self {
+    fn new(clock: &dyn Clock) -> Self {
+        let system_time = clock.system_time();
         let mut date = Self {
             date: [0; DATE_VALUE_LENGTH],
-            now: Instant::now(),
+            now: clock.now(),
+            unix_secs: system_time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
         };
-        let _ = write!(&mut date, "{}", HttpDate::from(SystemTime::now()));
+        format_http_date(system_time, &mut date.date);
         date
     }
 
@@ -45,16 +209,76 @@ impl DateTimeInner {
     }
 }
 
-impl fmt::Write for DateTimeInner {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.date[..].copy_from_slice(s.as_bytes());
-        Ok(())
+/// Format given time as IMF-fixdate into buffer. e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(time: SystemTime, buf: &mut [u8; DATE_VALUE_LENGTH]) {
+    struct DateBuf<'a>(&'a mut [u8; DATE_VALUE_LENGTH]);
+
+    impl fmt::Write for DateBuf<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0[..].copy_from_slice(s.as_bytes());
+            Ok(())
+        }
     }
+
+    let _ = write!(DateBuf(buf), "{}", HttpDate::from(time));
 }
 
-/// Struct with Date update periodically at 500 milli seconds interval.
+/// Parse value of `Last-Modified`, `If-Modified-Since` or `If-Unmodified-Since` header.
+///
+/// IMF-fixdate and the obsolete RFC 850 and asctime formats are accepted.
+pub fn parse_http_date(value: &[u8]) -> Option<SystemTime> {
+    let value = std::str::from_utf8(value).ok()?;
+    value.parse::<HttpDate>().ok().map(SystemTime::from)
+}
+
+/// Check `If-None-Match` and `If-Modified-Since` headers of a GET or HEAD request against current
+/// state of resource. Return true when it should be answered with `304 Not Modified`.
+///
+/// `If-Modified-Since` is ignored when `If-None-Match` is present. Entity tags are compared
+/// weakly and `last_modified` is compared at one second precision.
+pub fn not_modified(headers: &HeaderMap, last_modified: Option<SystemTime>, etag: Option<&str>) -> bool {
+    let mut if_none_match = headers.get_all(IF_NONE_MATCH).iter().peekable();
+
+    if if_none_match.peek().is_some() {
+        let etag = match etag {
+            Some(etag) => weak(etag.as_bytes()),
+            None => return false,
+        };
+
+        return if_none_match
+            .flat_map(|value| value.as_bytes().split(|b| *b == b','))
+            .map(trim)
+            .any(|tag| tag == b"*" || weak(tag) == etag);
+    }
+
+    match (headers.get(IF_MODIFIED_SINCE), last_modified) {
+        (Some(since), Some(last_modified)) => match parse_http_date(since.as_bytes()) {
+            Some(since) => HttpDate::from(last_modified) <= HttpDate::from(since),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+// tag without surrounding whitespace.
+fn trim(tag: &[u8]) -> &[u8] {
+    let start = tag.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(tag.len());
+    let end = tag
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    &tag[start..end]
+}
+
+// opaque tag without weak indicator.
+fn weak(tag: &[u8]) -> &[u8] {
+    tag.strip_prefix(b"W/").unwrap_or(tag)
+}
+
+/// Struct with [Date] and [TimerWheel] updated periodically from a [Clock].
 pub(crate) struct DateTimeTask {
-    current: Rc<Cell<DateTimeInner>>,
+    current: Rc<Date>,
+    wheel: Rc<TimerWheel>,
     handle: JoinHandle<()>,
 }
 
@@ -62,31 +286,237 @@ impl Drop for DateTimeTask {
     fn drop(&mut self) {
         // stop the timer update async task on drop.
         self.handle.abort();
+
+        // cached date is shared by services of current thread. clear it with the last one.
+        let tasks = TASKS.with(|tasks| {
+            let n = tasks.get() - 1;
+            tasks.set(n);
+            n
+        });
+        if tasks == 0 {
+            UNIX_SECS.with(|secs| secs.set(0));
+        }
     }
 }
 
 impl DateTimeTask {
-    pub(crate) fn new() -> Self {
+    /// Refresh at given interval. `None` refreshes date on access and only runs the task while
+    /// there is timer registered.
+    pub(crate) fn new(refresh: Option<Duration>) -> Self {
+        Self::with_clock(Arc::new(SystemClock), refresh)
+    }
+
+    pub(crate) fn with_clock(clock: Arc<dyn Clock>, refresh: Option<Duration>) -> Self {
         // shared date and timer for Date and update async task.
-        let current = Rc::new(Cell::new(DateTimeInner::new()));
-        let current_clone = Rc::clone(&current);
-        // spawn an async task sleep for 1 sec and update date in a loop.
+        let date = DateTimeInner::new(&*clock);
+        let current = Rc::new(Date {
+            current: Cell::new(date),
+            on_demand: refresh.is_none().then(|| clock.clone()),
+        });
+        current.set(date);
+        let tick = refresh.unwrap_or(DEFAULT_REFRESH);
+        let wheel = TimerWheel::new(clock.clone(), tick);
+        // task does not keep date alive and exits on next update after DateTimeTask is gone.
+        let current_weak = Rc::downgrade(&current);
+        let wheel_weak = Rc::downgrade(&wheel);
+        // spawn an async task sleep for tick and update date in a loop.
         // handle is used to stop the task on Date drop.
         let handle = tokio::task::spawn_local(async move {
-            let mut interval = interval(Duration::from_millis(500));
+            let mut interval = interval(tick);
+            // no burst of ticks after parked.
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
             loop {
-                let _ = interval.tick().await;
-                let date = DateTimeInner::new();
-                current_clone.set(date);
+                if refresh.is_none() {
+                    // park while no connection holds a timer.
+                    let busy = poll_fn(|cx| match wheel_weak.upgrade() {
+                        Some(wheel) => wheel.poll_busy(cx).map(|_| true),
+                        None => Poll::Ready(false),
+                    })
+                    .await;
+
+                    if !busy {
+                        break;
+                    }
+                }
+
+                match clock.notify() {
+                    Some(notify) => select! {
+                        _ = interval.tick() => {},
+                        _ = notify.notified() => {}
+                    },
+                    None => {
+                        let _ = interval.tick().await;
+                    }
+                }
+                match (current_weak.upgrade(), wheel_weak.upgrade()) {
+                    (Some(current), Some(wheel)) => {
+                        let date = DateTimeInner::new(&*clock);
+                        current.set(date);
+                        wheel.advance(date.now());
+                    }
+                    _ => break,
+                }
             }
         });
 
-        Self { current, handle }
+        TASKS.with(|tasks| tasks.set(tasks.get() + 1));
+
+        Self { current, wheel, handle }
     }
 
     #[inline(always)]
     pub(crate) fn get(&self) -> &Date {
         &*self.current
     }
+
+    /// Timer wheel advanced on every update of date.
+    #[inline(always)]
+    pub(crate) fn wheel(&self) -> &Rc<TimerWheel> {
+        &self.wheel
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::task::{yield_now, LocalSet};
+
+    #[tokio::test]
+    async fn manual_clock() {
+        LocalSet::new()
+            .run_until(async {
+                let clock = ManualClock::with_system_time(UNIX_EPOCH);
+                let task = DateTimeTask::with_clock(Arc::new(clock.clone()), Some(DEFAULT_REFRESH));
+
+                let start = task.get().get();
+                assert_eq!(start.date(), b"Thu, 01 Jan 1970 00:00:00 GMT");
+
+                // let the refresh task start waiting.
+                yield_now().await;
+
+                clock.advance(Duration::from_secs(86_400));
+                yield_now().await;
+
+                let date = task.get().get();
+                assert_eq!(date.date(), b"Fri, 02 Jan 1970 00:00:00 GMT");
+                assert_eq!(date.now() - start.now(), Duration::from_secs(86_400));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn on_demand() {
+        LocalSet::new()
+            .run_until(async {
+                let clock = ManualClock::with_system_time(UNIX_EPOCH);
+                let task = DateTimeTask::with_clock(Arc::new(clock.clone()), None);
+                yield_now().await;
+
+                // task is parked without timer. date is refreshed on access once it's stale.
+                clock.advance(Duration::from_millis(900));
+                yield_now().await;
+                assert_eq!(task.get().get().date(), b"Thu, 01 Jan 1970 00:00:00 GMT");
+
+                clock.advance(Duration::from_millis(100));
+                assert_eq!(task.get().get().date(), b"Thu, 01 Jan 1970 00:00:01 GMT");
+                assert_eq!(cached_system_time(), Some(UNIX_EPOCH + Duration::from_secs(1)));
+
+                // task is woken up by timer.
+                let timer = task.wheel().timer(clock.now() + Duration::from_secs(1));
+                yield_now().await;
+
+                clock.advance(Duration::from_secs(2));
+                yield_now().await;
+                assert!(timer
+                    .poll_expired(&mut std::task::Context::from_waker(futures_task::noop_waker_ref()))
+                    .is_ready());
+
+                drop(task);
+                assert_eq!(cached_system_time(), None);
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn shared_cache() {
+        LocalSet::new()
+            .run_until(async {
+                let time = UNIX_EPOCH + Duration::from_secs(86_400);
+                let clock = Arc::new(ManualClock::with_system_time(time));
+                let task = DateTimeTask::with_clock(clock.clone(), Some(DEFAULT_REFRESH));
+
+                // replacing task of a service keeps cached date of the thread.
+                let task2 = DateTimeTask::with_clock(clock, Some(DEFAULT_REFRESH));
+                drop(task);
+                assert_eq!(cached_system_time(), Some(time));
+
+                drop(task2);
+                assert_eq!(cached_system_time(), None);
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn drop_task() {
+        LocalSet::new()
+            .run_until(async {
+                // every task holds the clock until it's finished.
+                let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+                for _ in 0..64 {
+                    let task = DateTimeTask::with_clock(clock.clone(), Some(DEFAULT_REFRESH));
+                    yield_now().await;
+                    drop(task);
+                }
+
+                yield_now().await;
+                assert_eq!(Arc::strong_count(&clock), 1);
+            })
+            .await
+    }
+
+    #[test]
+    fn http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+
+        let mut buf = [0; DATE_VALUE_LENGTH];
+        format_http_date(time, &mut buf);
+        assert_eq!(&buf, b"Sun, 06 Nov 1994 08:49:37 GMT");
+
+        assert_eq!(parse_http_date(&buf), Some(time));
+        assert_eq!(parse_http_date(b"Sunday, 06-Nov-94 08:49:37 GMT"), Some(time));
+        assert_eq!(parse_http_date(b"Sun Nov  6 08:49:37 1994"), Some(time));
+        assert_eq!(parse_http_date(b"Sun, 06 Nov 1994"), None);
+        assert_eq!(parse_http_date(b"\xff"), None);
+    }
+
+    #[test]
+    fn conditional() {
+        use http::header::HeaderValue;
+
+        let time = UNIX_EPOCH + Duration::from_millis(784_111_777_500);
+
+        let mut headers = HeaderMap::new();
+        assert!(!not_modified(&headers, Some(time), Some("\"a\"")));
+
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        assert!(not_modified(&headers, Some(time), None));
+        assert!(!not_modified(&headers, Some(time + Duration::from_secs(1)), None));
+        assert!(!not_modified(&headers, None, None));
+
+        // etag takes precedence over modified time.
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"b\", W/\"a\""));
+        assert!(not_modified(&headers, None, Some("\"a\"")));
+        assert!(not_modified(&headers, None, Some("W/\"b\"")));
+        assert!(!not_modified(&headers, Some(time), Some("\"c\"")));
+        assert!(!not_modified(&headers, Some(time), None));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(not_modified(&headers, None, Some("\"c\"")));
+    }
 }
@@ -0,0 +1,244 @@
+use std::{
+    convert::Infallible,
+    future::{ready, Future, Ready},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use bytes::Bytes;
+use http::{header::CONTENT_TYPE, HeaderMap, Request, Response, StatusCode};
+
+use crate::body::ResponseBody;
+
+/// Construct a [StaticResponse] responding every request with given status, headers and body.
+pub fn static_response(status: StatusCode, headers: HeaderMap, body: Bytes) -> StaticResponse {
+    StaticResponse { status, headers, body }
+}
+
+/// Construct an [Echo] streaming request body back as response body.
+pub fn echo() -> Echo {
+    Echo
+}
+
+/// Construct a [Delay] responding with empty 200 response after given duration.
+pub fn delay(dur: Duration) -> Delay {
+    Delay { dur }
+}
+
+/// Factory and service of [static_response]. Response is cloned from the same status, headers and
+/// body for every request.
+#[derive(Clone)]
+pub struct StaticResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl<ReqB> ServiceFactory<Request<ReqB>> for StaticResponse {
+    type Response = Response<ResponseBody>;
+    type Error = Infallible;
+    type Config = ();
+    type Service = Self;
+    type InitError = ();
+    type Future = Ready<Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        ready(Ok(self.clone()))
+    }
+}
+
+impl<ReqB> Service<Request<ReqB>> for StaticResponse {
+    type Response = Response<ResponseBody>;
+    type Error = Infallible;
+    type Future<'f> = Ready<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, _: Request<ReqB>) -> Self::Future<'_> {
+        let mut res = Response::new(self.body.clone().into());
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers.clone();
+        ready(Ok(res))
+    }
+}
+
+/// Factory and service of [echo]. Content-Type header of request is copied to response.
+#[derive(Clone, Copy, Default)]
+pub struct Echo;
+
+impl<ReqB> ServiceFactory<Request<ReqB>> for Echo {
+    type Response = Response<ResponseBody<ReqB>>;
+    type Error = Infallible;
+    type Config = ();
+    type Service = Self;
+    type InitError = ();
+    type Future = Ready<Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        ready(Ok(*self))
+    }
+}
+
+impl<ReqB> Service<Request<ReqB>> for Echo {
+    type Response = Response<ResponseBody<ReqB>>;
+    type Error = Infallible;
+    type Future<'f> = Ready<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, req: Request<ReqB>) -> Self::Future<'_> {
+        let (parts, body) = req.into_parts();
+
+        let mut res = Response::new(ResponseBody::Stream { stream: body });
+        if let Some(value) = parts.headers.get(CONTENT_TYPE) {
+            res.headers_mut().insert(CONTENT_TYPE, value.clone());
+        }
+
+        ready(Ok(res))
+    }
+}
+
+/// Factory and service of [delay]. Useful for exercising timeouts.
+#[derive(Clone, Copy)]
+pub struct Delay {
+    dur: Duration,
+}
+
+impl<ReqB> ServiceFactory<Request<ReqB>> for Delay {
+    type Response = Response<ResponseBody>;
+    type Error = Infallible;
+    type Config = ();
+    type Service = Self;
+    type InitError = ();
+    type Future = Ready<Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        ready(Ok(*self))
+    }
+}
+
+impl<ReqB> Service<Request<ReqB>> for Delay {
+    type Response = Response<ResponseBody>;
+    type Error = Infallible;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, _: Request<ReqB>) -> Self::Future<'_> {
+        async move {
+            tokio::time::sleep(self.dur).await;
+            Ok(Response::new(ResponseBody::None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use http::HeaderValue;
+    use tokio::{task::LocalSet, time::Instant};
+
+    use crate::builder::HttpServiceBuilder;
+    use crate::util::testing::{duplex, serve, H1Client, TestStream};
+
+    #[tokio::test]
+    async fn handlers() {
+        LocalSet::new()
+            .run_until(async {
+                let mut headers = HeaderMap::new();
+                headers.insert("x-static", HeaderValue::from_static("1"));
+                let builder = HttpServiceBuilder::h1(static_response(StatusCode::ACCEPTED, headers, "hello".into()));
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+                let (res, _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+                    client
+                        .send("GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                        .await
+                        .unwrap();
+                    client.response().await.unwrap()
+                })
+                .await;
+                assert_eq!(res.status(), StatusCode::ACCEPTED);
+                assert_eq!(res.headers()["x-static"], "1");
+                assert_eq!(res.body(), "hello");
+
+                let builder = HttpServiceBuilder::h1(echo());
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+                let (res, _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+                    client
+                        .send(
+                            "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: text/plain\r\n\
+                             Content-Length: 5\r\n\r\nhello",
+                        )
+                        .await
+                        .unwrap();
+                    client.response().await.unwrap()
+                })
+                .await;
+                assert_eq!(res.headers()["content-type"], "text/plain");
+                assert_eq!(res.body(), "hello");
+
+                let builder = HttpServiceBuilder::h1(delay(Duration::from_millis(20)));
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+                let start = Instant::now();
+                let (res, _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+                    client
+                        .send("GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                        .await
+                        .unwrap();
+                    client.response().await.unwrap()
+                })
+                .await;
+                assert_eq!(res.status(), StatusCode::OK);
+                assert!(start.elapsed() >= Duration::from_millis(20));
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn builder_bounds() {
+        LocalSet::new()
+            .run_until(async {
+                use actix_server_alt::net::Stream as ServerStream;
+
+                let headers = HeaderMap::new();
+
+                let builder = HttpServiceBuilder::new(static_response(StatusCode::OK, headers.clone(), Bytes::new()));
+                assert!(ServiceFactory::<ServerStream>::new_service(&builder, ()).await.is_ok());
+                let builder = HttpServiceBuilder::new(echo());
+                assert!(ServiceFactory::<ServerStream>::new_service(&builder, ()).await.is_ok());
+                let builder = HttpServiceBuilder::new(delay(Duration::from_secs(1)));
+                assert!(ServiceFactory::<ServerStream>::new_service(&builder, ()).await.is_ok());
+
+                #[cfg(feature = "http2")]
+                {
+                    let builder = HttpServiceBuilder::h2(static_response(StatusCode::OK, headers, Bytes::new()));
+                    assert!(ServiceFactory::<TestStream>::new_service(&builder, ()).await.is_ok());
+                    let builder = HttpServiceBuilder::h2(echo());
+                    assert!(ServiceFactory::<TestStream>::new_service(&builder, ()).await.is_ok());
+                    let builder = HttpServiceBuilder::h2(delay(Duration::from_secs(1)));
+                    assert!(ServiceFactory::<TestStream>::new_service(&builder, ()).await.is_ok());
+                }
+            })
+            .await
+    }
+}
@@ -0,0 +1,89 @@
+//! Helpers for constructing header values.
+
+use std::cell::RefCell;
+
+use http::header::{HeaderValue, InvalidHeaderValue};
+
+// cache is direct mapped. a value evicts the one sharing its slot.
+const SLOTS: usize = 64;
+// longer values are rarely repeated.
+const MAX_LEN: usize = 128;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: Option<HeaderValue> = None;
+
+thread_local! {
+    static CACHE: RefCell<[Option<HeaderValue>; SLOTS]> = RefCell::new([EMPTY; SLOTS]);
+}
+
+/// Get a [HeaderValue] equal to given value from cache of current thread.
+///
+/// Every worker thread has its own bounded cache. Clone of a cached value shares its buffer so a
+/// value repeated on most responses(`content-type`, `server`, `cache-control` etc) is allocated
+/// once. Values longer than 128 bytes are not cached.
+///
+/// # Examples:
+/// ```rust
+/// use actix_http_alt::util::header::cached_value;
+///
+/// let value = cached_value("text/plain; charset=utf-8").unwrap();
+/// assert_eq!(value, "text/plain; charset=utf-8");
+///
+/// assert!(cached_value("invalid\r\n").is_err());
+/// ```
+pub fn cached_value(value: &str) -> Result<HeaderValue, InvalidHeaderValue> {
+    if value.len() > MAX_LEN {
+        return HeaderValue::from_str(value);
+    }
+
+    let idx = slot(value.as_bytes());
+
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match cache[idx] {
+            Some(ref cached) if cached.as_bytes() == value.as_bytes() => Ok(cached.clone()),
+            ref mut slot => {
+                let value = HeaderValue::from_str(value)?;
+                *slot = Some(value.clone());
+                Ok(value)
+            }
+        }
+    })
+}
+
+// FNV-1a.
+fn slot(bytes: &[u8]) -> usize {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash % SLOTS as u64) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache() {
+        let a = cached_value("no-cache").unwrap();
+        let b = cached_value(&String::from("no-cache")).unwrap();
+        assert_eq!(a, b);
+        // shared buffer.
+        assert_eq!(a.as_bytes().as_ptr(), b.as_bytes().as_ptr());
+
+        let long = "a".repeat(MAX_LEN + 1);
+        let a = cached_value(&long).unwrap();
+        let b = cached_value(&long).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a.as_bytes().as_ptr(), b.as_bytes().as_ptr());
+
+        assert!(cached_value("\n").is_err());
+
+        // cache stays bounded.
+        for i in 0..SLOTS * 4 {
+            let value = i.to_string();
+            assert_eq!(cached_value(&value).unwrap(), value.as_str());
+        }
+        CACHE.with(|cache| assert!(cache.borrow().iter().filter(|v| v.is_some()).count() <= SLOTS));
+    }
+}
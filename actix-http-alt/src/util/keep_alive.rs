@@ -1,28 +1,30 @@
 use std::{
     future::Future,
     pin::Pin,
+    rc::Rc,
     task::{Context, Poll},
 };
 
 use futures_core::ready;
-use pin_project::pin_project;
-use tokio::time::{sleep_until, Instant, Sleep};
+use tokio::time::Instant;
+
+use super::timer::{Timer, TimerWheel};
 
 /// A keep alive timer lazily reset the deadline.
 /// after each successful poll.
-#[pin_project]
+///
+/// Timer is an entry of [TimerWheel] shared by connections and fires up to one tick late.
 pub(crate) struct KeepAlive {
-    #[pin]
-    timer: Sleep,
+    timer: Timer,
     deadline: Instant,
 }
 
 impl KeepAlive {
     // time is passed from outside of keep alive to reduce overhead
     // of timer syscall.
-    pub(crate) fn new(deadline: Instant) -> Self {
+    pub(crate) fn new(wheel: &Rc<TimerWheel>, deadline: Instant) -> Self {
         Self {
-            timer: sleep_until(deadline),
+            timer: wheel.timer(deadline),
             deadline,
         }
     }
@@ -30,8 +32,13 @@ impl KeepAlive {
     #[cfg(any(feature = "http1", feature = "http2"))]
     #[inline(always)]
     pub(crate) fn update(self: Pin<&mut Self>, deadline: Instant) {
-        let this = self.project();
-        *this.deadline = deadline;
+        self.get_mut().deadline = deadline;
+    }
+
+    /// Wheel timer is registered in. Other timers of connection share it.
+    #[inline(always)]
+    pub(crate) fn wheel(&self) -> &Rc<TimerWheel> {
+        self.timer.wheel()
     }
 
     pub(crate) fn is_expired(&self) -> bool {
@@ -39,8 +46,8 @@ impl KeepAlive {
     }
 
     pub(crate) fn reset(self: Pin<&mut Self>) {
-        let this = self.project();
-        this.timer.reset(*this.deadline)
+        let this = self.get_mut();
+        this.timer.reset(this.deadline)
     }
 }
 
@@ -48,8 +55,7 @@ impl Future for KeepAlive {
     type Output = ();
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = self.as_mut().project();
-        ready!(this.timer.poll(cx));
+        ready!(self.timer.poll_expired(cx));
 
         if self.as_mut().is_expired() {
             Poll::Ready(())
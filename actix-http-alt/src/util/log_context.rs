@@ -0,0 +1,88 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    net::SocketAddr,
+};
+
+use http::{Method, Request, Uri, Version};
+
+use crate::connection::ConnectionId;
+
+/// Connection and request information prefixed to logs emitted by dispatchers.
+///
+/// Displayed as `conn=#1 peer=127.0.0.1:50000 req="GET /path HTTP/1.1"`. Query string of uri
+/// is omitted.
+#[derive(Clone)]
+pub(crate) struct LogContext {
+    id: ConnectionId,
+    peer: Option<SocketAddr>,
+    req: Option<(Method, Uri, Version)>,
+}
+
+impl LogContext {
+    pub(crate) fn new(id: ConnectionId, peer: Option<SocketAddr>) -> Self {
+        Self { id, peer, req: None }
+    }
+
+    /// Context of given request on the same connection.
+    #[cfg(any(feature = "http2", feature = "http3"))]
+    pub(crate) fn with_request<B>(&self, req: &Request<B>) -> Self {
+        let mut ctx = self.clone();
+        ctx.set_request(req);
+        ctx
+    }
+
+    pub(crate) fn set_request<B>(&mut self, req: &Request<B>) {
+        self.req = Some((req.method().clone(), req.uri().clone(), req.version()));
+    }
+
+    pub(crate) fn clear_request(&mut self) {
+        self.req = None;
+    }
+
+    pub(crate) fn request(&self) -> Option<(&Method, &Uri, Version)> {
+        self.req.as_ref().map(|(method, uri, version)| (method, uri, *version))
+    }
+}
+
+impl Display for LogContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "conn={}", self.id)?;
+
+        if let Some(peer) = self.peer {
+            write!(f, " peer={}", peer)?;
+        }
+
+        if let Some((ref method, ref uri, version)) = self.req {
+            write!(f, " req=\"{} {} {:?}\"", method, uri.path(), version)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::connection::ConnectionData;
+
+    #[test]
+    fn display() {
+        let data = ConnectionData::new();
+        let id = data.id();
+
+        let mut ctx = LogContext::new(id, Some(([127, 0, 0, 1], 8080).into()));
+        assert_eq!(ctx.to_string(), format!("conn={} peer=127.0.0.1:8080", id));
+
+        let req = Request::get("/foo?secret=1").body(()).unwrap();
+        ctx.set_request(&req);
+        assert_eq!(
+            ctx.to_string(),
+            format!("conn={} peer=127.0.0.1:8080 req=\"GET /foo HTTP/1.1\"", id)
+        );
+
+        assert_eq!(ctx.request().unwrap().0, Method::GET);
+        ctx.clear_request();
+        assert!(ctx.request().is_none());
+    }
+}
@@ -1,10 +1,55 @@
+pub mod body;
+#[cfg(feature = "http2")]
+pub(crate) mod budget;
 #[cfg(feature = "http1")]
 pub(crate) mod buf_list;
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub(crate) mod catch_unwind;
 pub(crate) mod date;
+pub mod header;
 pub(crate) mod keep_alive;
-#[cfg(any(feature = "http1", feature = "http2"))]
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub(crate) mod log_context;
 pub(crate) mod poll_fn;
+pub mod service;
+#[cfg(feature = "http2")]
+pub(crate) mod stats_io;
+pub mod testing;
+pub(crate) mod timer;
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub(crate) mod trace;
+#[cfg(feature = "io-uring")]
+pub(crate) mod uring_io;
 
+mod access_log;
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+mod call_timeout;
 mod error_logger;
+mod handler;
+#[cfg(feature = "http1")]
+mod proxy;
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+mod rate_limit;
+mod request_id;
+#[cfg(feature = "tower")]
+mod tower;
+#[cfg(feature = "websocket")]
+mod websocket;
 
+pub use self::access_log::{AccessLogBody, AccessLogFactory, AccessLogFormat, AccessLogService};
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub use self::call_timeout::{CallTimeout, CallTimeoutFactory, CallTimeoutService};
+pub use self::date::{
+    format_http_date, not_modified, parse_http_date, Clock, ManualClock, SystemClock, DATE_VALUE_LENGTH,
+};
 pub use self::error_logger::ErrorLoggerFactory;
+pub use self::handler::{delay, echo, static_response, Delay, Echo, StaticResponse};
+#[cfg(feature = "http1")]
+pub use self::proxy::{ProxyBody, ProxyError, ProxyFactory, ProxyService, UpgradePolicy};
+#[cfg(any(feature = "http1", feature = "http2", feature = "http3"))]
+pub use self::rate_limit::{peer_addr, peer_ip, RateLimitFactory, RateLimitService};
+pub use self::request_id::{RequestId, RequestIdFactory, RequestIdService};
+#[cfg(feature = "tower")]
+pub use self::tower::{TowerCompat, TowerFactory, TowerService};
+#[cfg(feature = "websocket")]
+pub use self::websocket::{websocket, ws, ws_with_codec, WebSocket, WsSession};
@@ -0,0 +1,595 @@
+use std::{
+    cell::RefCell,
+    error,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use actix_server_alt::net::TcpStream;
+use actix_service_alt::{Service, ServiceFactory};
+use bytes::Bytes;
+use futures_core::{ready, Stream};
+use http::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, HOST, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION,
+        TE, TRAILER, TRANSFER_ENCODING, UPGRADE,
+    },
+    Method, Request, Response, StatusCode, Version,
+};
+use tokio::time::timeout;
+
+use crate::body::{RequestBody, ResponseBody};
+use crate::connection::ConnectionAddrs;
+use crate::error::BodyError;
+use crate::h1::{ClientBody, ClientConnection, ClientError};
+use crate::protocol::RequestProtocol;
+use crate::response::ResponseError;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// Headers only meaningful for a single connection. Removed along with headers listed in
+/// connection header before forwarding.
+const HOP_BY_HOP: [HeaderName; 9] = [
+    CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    PROXY_AUTHENTICATE,
+    PROXY_AUTHORIZATION,
+    TE,
+    TRAILER,
+    TRANSFER_ENCODING,
+    UPGRADE,
+];
+
+/// How upgrade and CONNECT requests are forwarded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpgradePolicy {
+    /// Forward them to upstream and tunnel the connection when upstream responds with 101 or 2xx
+    /// to CONNECT.
+    Tunnel,
+    /// Respond with 501 without contacting upstream.
+    Reject,
+}
+
+/// A factory of service forwarding requests to an upstream Http/1 server.
+///
+/// Hop-by-hop headers are removed and `x-forwarded-for`, `x-forwarded-proto` headers are set from
+/// [ConnectionAddrs] and [RequestProtocol] of request. Bodies are streamed in both directions.
+/// Upstream connections are kept alive and reused when possible.
+///
+/// Only Http/1.1 requests can be tunneled. Upgrade and CONNECT requests of other versions are
+/// always rejected.
+pub struct ProxyFactory {
+    upstream: SocketAddr,
+    upgrade: UpgradePolicy,
+    connect_timeout: Duration,
+    response_timeout: Duration,
+    max_idle: usize,
+}
+
+impl ProxyFactory {
+    pub fn new(upstream: SocketAddr) -> Self {
+        Self {
+            upstream,
+            upgrade: UpgradePolicy::Reject,
+            connect_timeout: Duration::from_secs(5),
+            response_timeout: Duration::from_secs(30),
+            max_idle: 32,
+        }
+    }
+
+    /// Change how upgrade and CONNECT requests are handled. Default to [UpgradePolicy::Reject].
+    pub fn upgrade(mut self, upgrade: UpgradePolicy) -> Self {
+        self.upgrade = upgrade;
+        self
+    }
+
+    /// Timeout of connecting to upstream. Default to 5 seconds.
+    pub fn connect_timeout(mut self, dur: Duration) -> Self {
+        self.connect_timeout = dur;
+        self
+    }
+
+    /// Timeout of waiting for response head from upstream after request is sent.
+    /// Default to 30 seconds.
+    pub fn response_timeout(mut self, dur: Duration) -> Self {
+        self.response_timeout = dur;
+        self
+    }
+
+    /// Max number of idle upstream connections kept by each service. Default to 32.
+    pub fn max_idle(mut self, max: usize) -> Self {
+        self.max_idle = max;
+        self
+    }
+}
+
+impl ServiceFactory<Request<RequestBody>> for ProxyFactory {
+    type Response = Response<ResponseBody<ProxyBody>>;
+    type Error = ProxyError;
+    type Config = ();
+    type Service = ProxyService;
+    type InitError = ();
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        let service = ProxyService {
+            upstream: self.upstream,
+            upgrade: self.upgrade,
+            connect_timeout: self.connect_timeout,
+            response_timeout: self.response_timeout,
+            pool: Rc::new(Pool {
+                idle: RefCell::new(Vec::new()),
+                max_idle: self.max_idle,
+            }),
+        };
+
+        async { Ok(service) }
+    }
+}
+
+pub struct ProxyService {
+    upstream: SocketAddr,
+    upgrade: UpgradePolicy,
+    connect_timeout: Duration,
+    response_timeout: Duration,
+    pool: Rc<Pool>,
+}
+
+impl Service<Request<RequestBody>> for ProxyService {
+    type Response = Response<ResponseBody<ProxyBody>>;
+    type Error = ProxyError;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, req: Request<RequestBody>) -> Self::Future<'_> {
+        async move {
+            let is_upgrade = req.method() == Method::CONNECT || req.headers().contains_key(UPGRADE);
+
+            if is_upgrade && (self.upgrade == UpgradePolicy::Reject || req.version() != Version::HTTP_11) {
+                return Err(ProxyError::UpgradeRejected);
+            }
+
+            let has_body = has_body(&req);
+            let method = req.method().clone();
+
+            let mut req = forward_request(req, is_upgrade, has_body);
+
+            loop {
+                let (conn, reused) = match self.pool.get() {
+                    Some(conn) => (conn, true),
+                    None => (self.connect().await?, false),
+                };
+
+                // idle connection could be closed by upstream. request without body can be sent again.
+                let retry = if reused && !has_body {
+                    Some(copy_request(&req))
+                } else {
+                    None
+                };
+
+                match timeout(self.response_timeout, conn.send(req)).await {
+                    Ok(Ok(res)) => return Ok(self.forward_response(res, &method, is_upgrade)),
+                    Ok(Err(ClientError::Closed)) if retry.is_some() => req = retry.unwrap(),
+                    Ok(Err(e)) => return Err(ProxyError::Upstream(e)),
+                    Err(_) => return Err(ProxyError::ResponseTimeout),
+                }
+            }
+        }
+    }
+}
+
+impl ProxyService {
+    async fn connect(&self) -> Result<ClientConnection<TcpStream>, ProxyError> {
+        match timeout(self.connect_timeout, TcpStream::connect(self.upstream)).await {
+            Ok(Ok(stream)) => {
+                let _ = stream.set_nodelay(true);
+                Ok(ClientConnection::new(stream))
+            }
+            Ok(Err(e)) => Err(ProxyError::Connect(e)),
+            Err(_) => Err(ProxyError::ConnectTimeout),
+        }
+    }
+
+    fn forward_response(
+        &self,
+        res: Response<ClientBody<TcpStream, RequestBody>>,
+        method: &Method,
+        is_upgrade: bool,
+    ) -> Response<ResponseBody<ProxyBody>> {
+        let (mut parts, body) = res.into_parts();
+
+        let is_tunnel = is_upgrade
+            && (parts.status == StatusCode::SWITCHING_PROTOCOLS
+                || (method == Method::CONNECT && parts.status.is_success()));
+
+        remove_hop_by_hop(
+            &mut parts.headers,
+            is_tunnel && parts.status == StatusCode::SWITCHING_PROTOCOLS,
+        );
+        parts.version = Version::HTTP_11;
+
+        let body = if !is_tunnel && body.is_end_stream() {
+            // content-length is kept for response to HEAD request.
+            if let Some(conn) = body.into_connection() {
+                self.pool.put(conn);
+            }
+            ResponseBody::None
+        } else {
+            // body is re-encoded by dispatcher.
+            parts.headers.remove(CONTENT_LENGTH);
+            ResponseBody::stream(ProxyBody {
+                body: Some(body),
+                pool: self.pool.clone(),
+            })
+        };
+
+        Response::from_parts(parts, body)
+    }
+}
+
+/// Body of response streamed from upstream. Upstream connection is returned to pool when it's
+/// finished.
+pub struct ProxyBody {
+    body: Option<ClientBody<TcpStream, RequestBody>>,
+    pool: Rc<Pool>,
+}
+
+impl Stream for ProxyBody {
+    type Item = Result<Bytes, BodyError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let body = match this.body {
+            Some(ref mut body) => body,
+            None => return Poll::Ready(None),
+        };
+
+        match ready!(Pin::new(body).poll_next(cx)) {
+            Some(Ok(bytes)) => Poll::Ready(Some(Ok(bytes))),
+            Some(Err(e)) => {
+                this.body = None;
+                Poll::Ready(Some(Err(e.into())))
+            }
+            None => {
+                if let Some(conn) = this.body.take().and_then(ClientBody::into_connection) {
+                    this.pool.put(conn);
+                }
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+struct Pool {
+    idle: RefCell<Vec<ClientConnection<TcpStream>>>,
+    max_idle: usize,
+}
+
+impl Pool {
+    fn get(&self) -> Option<ClientConnection<TcpStream>> {
+        self.idle.borrow_mut().pop()
+    }
+
+    fn put(&self, conn: ClientConnection<TcpStream>) {
+        let mut idle = self.idle.borrow_mut();
+        if idle.len() < self.max_idle {
+            idle.push(conn);
+        }
+    }
+}
+
+// Http/1 request without content-length or transfer-encoding header has no body.
+fn has_body(req: &Request<RequestBody>) -> bool {
+    if req.headers().contains_key(CONTENT_LENGTH) || req.headers().contains_key(TRANSFER_ENCODING) {
+        return true;
+    }
+
+    match *req.body() {
+        RequestBody::H1(_) | RequestBody::None => false,
+        #[cfg(feature = "http2")]
+        RequestBody::H2(ref body) => !body.is_end_stream(),
+        #[cfg(feature = "http3")]
+        RequestBody::H3(_) => true,
+    }
+}
+
+fn forward_request(req: Request<RequestBody>, is_upgrade: bool, has_body: bool) -> Request<RequestBody> {
+    let (mut parts, body) = req.into_parts();
+
+    remove_hop_by_hop(&mut parts.headers, is_upgrade && parts.method != Method::CONNECT);
+
+    // Http/2 and Http/3 requests carry host in uri.
+    if !parts.headers.contains_key(HOST) {
+        if let Some(value) = parts
+            .uri
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        {
+            parts.headers.insert(HOST, value);
+        }
+    }
+
+    if let Some(peer) = parts.extensions.get::<ConnectionAddrs>().and_then(|addrs| addrs.peer()) {
+        let mut value = parts
+            .headers
+            .get_all(&X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if !value.is_empty() {
+            value.push_str(", ");
+        }
+        value.push_str(&peer.ip().to_string());
+
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            parts.headers.insert(X_FORWARDED_FOR, value);
+        }
+    }
+
+    if let Some(protocol) = parts.extensions.get::<RequestProtocol>() {
+        let proto = if protocol.is_tls() { "https" } else { "http" };
+        parts.headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(proto));
+    }
+
+    if has_body && !parts.headers.contains_key(CONTENT_LENGTH) {
+        parts
+            .headers
+            .insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+    }
+
+    parts.version = Version::HTTP_11;
+
+    Request::from_parts(parts, body)
+}
+
+// request is copied without body and extensions.
+fn copy_request(req: &Request<RequestBody>) -> Request<RequestBody> {
+    let mut copy = Request::new(RequestBody::None);
+    *copy.method_mut() = req.method().clone();
+    *copy.uri_mut() = req.uri().clone();
+    *copy.version_mut() = req.version();
+    *copy.headers_mut() = req.headers().clone();
+    copy
+}
+
+/// Remove hop-by-hop headers. Upgrade header is kept along with `connection: upgrade` when
+/// `upgrade` is true.
+fn remove_hop_by_hop(headers: &mut HeaderMap, upgrade: bool) {
+    let listed = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+
+    for name in listed.iter().chain(HOP_BY_HOP.iter()) {
+        if !(upgrade && name == UPGRADE) {
+            headers.remove(name);
+        }
+    }
+
+    if upgrade {
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    }
+}
+
+/// Error of [ProxyService]. Converted to response with status code of variant.
+#[derive(Debug)]
+pub enum ProxyError {
+    /// Upgrade or CONNECT request is rejected. 501.
+    UpgradeRejected,
+    /// Connecting to upstream failed. 502.
+    Connect(io::Error),
+    /// Connecting to upstream did not finish within connect timeout. 504.
+    ConnectTimeout,
+    /// Response head did not arrive within response timeout. 504.
+    ResponseTimeout,
+    /// Upstream connection failed or responded with malformed response. 502.
+    Upstream(ClientError),
+}
+
+impl Display for ProxyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // detail of upstream failure is only available from source so it does not leak to client.
+        let msg = match *self {
+            Self::UpgradeRejected => "Upgrade is not supported",
+            Self::Connect(_) => "Upstream connection failed",
+            Self::ConnectTimeout => "Upstream connection timed out",
+            Self::ResponseTimeout => "Upstream response timed out",
+            Self::Upstream(_) => "Upstream response failed",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl error::Error for ProxyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::Connect(ref e) => Some(e),
+            Self::Upstream(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<B> ResponseError<Response<ResponseBody<B>>> for ProxyError {
+    fn status_code(&self) -> StatusCode {
+        match *self {
+            Self::UpgradeRejected => StatusCode::NOT_IMPLEMENTED,
+            Self::Connect(_) | Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::ConnectTimeout | Self::ResponseTimeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    fn response_error(&mut self) -> Response<ResponseBody<B>> {
+        ResponseError::<Response<ResponseBody<B>>>::response(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use actix_server_alt::net::TcpListener;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::util::poll_fn::poll_fn;
+
+    // accept one connection and answer each request read from it with given response.
+    // return the requests received.
+    async fn upstream(responses: Vec<&'static str>) -> (SocketAddr, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut reqs = Vec::new();
+
+            for res in responses {
+                let mut buf = Vec::new();
+                loop {
+                    let mut chunk = [0; 1024];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+
+                    let req = String::from_utf8_lossy(&buf);
+                    let complete = match req.find("\r\n\r\n") {
+                        Some(_) if req.contains("transfer-encoding: chunked") => req.ends_with("0\r\n\r\n"),
+                        Some(_) => true,
+                        None => false,
+                    };
+
+                    if n == 0 || complete {
+                        break;
+                    }
+                }
+
+                reqs.push(String::from_utf8(buf).unwrap());
+                stream.write_all(res.as_bytes()).await.unwrap();
+            }
+
+            reqs
+        });
+
+        (addr, handle)
+    }
+
+    async fn collect(body: ResponseBody<ProxyBody>) -> Vec<u8> {
+        let mut body = Box::pin(body);
+        let mut buf = Vec::new();
+        while let Some(bytes) = poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+            buf.extend_from_slice(&bytes.unwrap());
+        }
+        buf
+    }
+
+    #[tokio::test]
+    async fn forward() {
+        let (addr, handle) = upstream(vec![
+            "HTTP/1.1 200 OK\r\nkeep-alive: timeout=5\r\ncontent-length: 5\r\n\r\nhello",
+            "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n5\r\nworld\r\n0\r\n\r\n",
+        ])
+        .await;
+
+        let service = ProxyFactory::new(addr).new_service(()).await.ok().unwrap();
+
+        let mut req = Request::builder()
+            .uri("/path?q=1")
+            .header(HOST, "example.com")
+            .header(CONNECTION, "x-custom")
+            .header("x-custom", "1")
+            .header(X_FORWARDED_FOR, "10.0.0.1")
+            .body(RequestBody::None)
+            .unwrap();
+        req.extensions_mut().insert(ConnectionAddrs::Inet {
+            peer: "192.168.0.1:50000".parse().unwrap(),
+            local: "127.0.0.1:8080".parse().unwrap(),
+        });
+        req.extensions_mut().insert(RequestProtocol::Http11Tls);
+
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key("keep-alive"));
+        assert_eq!(collect(res.into_body()).await, b"hello");
+
+        // upstream connection is reused.
+        assert_eq!(service.pool.idle.borrow().len(), 1);
+
+        let req = Request::builder().uri("/").body(RequestBody::None).unwrap();
+        let res = service.call(req).await.unwrap();
+        assert!(!res.headers().contains_key(TRANSFER_ENCODING));
+        assert_eq!(collect(res.into_body()).await, b"world");
+
+        let reqs = handle.await.unwrap();
+        assert_eq!(
+            reqs[0],
+            "GET /path?q=1 HTTP/1.1\r\nhost: example.com\r\nx-forwarded-for: 10.0.0.1, 192.168.0.1\r\nx-forwarded-proto: https\r\n\r\n"
+        );
+        assert_eq!(reqs[1], "GET / HTTP/1.1\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn upstream_error() {
+        let (addr, _handle) = upstream(vec!["HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhel"]).await;
+        let service = ProxyFactory::new(addr).new_service(()).await.ok().unwrap();
+
+        let req = Request::builder().uri("/").body(RequestBody::None).unwrap();
+        let res = service.call(req).await.unwrap();
+        let mut body = Box::pin(res.into_body());
+        assert_eq!(poll_fn(|cx| body.as_mut().poll_next(cx)).await.unwrap().unwrap(), "hel");
+        // upstream closed connection before body is finished.
+        assert!(poll_fn(|cx| body.as_mut().poll_next(cx)).await.unwrap().is_err());
+
+        // connection is queued in backlog and upstream never responds.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = ProxyFactory::new(listener.local_addr().unwrap())
+            .response_timeout(Duration::from_millis(50))
+            .new_service(())
+            .await
+            .ok()
+            .unwrap();
+
+        let req = Request::builder().uri("/").body(RequestBody::None).unwrap();
+        let mut err = service.call(req).await.err().unwrap();
+        assert!(matches!(err, ProxyError::ResponseTimeout));
+        let res: Response<ResponseBody> = err.response_error();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        // nothing listens on the port after listener is dropped.
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let service = ProxyFactory::new(addr).new_service(()).await.ok().unwrap();
+
+        let req = Request::builder().uri("/").body(RequestBody::None).unwrap();
+        let mut err = service.call(req).await.err().unwrap();
+        assert!(matches!(err, ProxyError::Connect(_)));
+        let res: Response<ResponseBody> = err.response_error();
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+
+        let req = Request::builder()
+            .uri("/")
+            .header(UPGRADE, "websocket")
+            .body(RequestBody::None)
+            .unwrap();
+        let mut err = service.call(req).await.err().unwrap();
+        assert!(matches!(err, ProxyError::UpgradeRejected));
+        let res: Response<ResponseBody> = err.response_error();
+        assert_eq!(res.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use http::{Request, Response};
+use tokio::time::Instant;
+
+use crate::body::ResponseBody;
+use crate::connection::ConnectionAddrs;
+use crate::response::{self, ErrorContext};
+
+// number of keys tracked before idle buckets are pruned.
+const PRUNE_AT: usize = 1024;
+
+/// Key of client ip address from [ConnectionAddrs] extension. For [RateLimitFactory::new].
+pub fn peer_ip<B>(req: &Request<B>) -> Option<IpAddr> {
+    req.extensions().get::<ConnectionAddrs>()?.peer().map(|addr| addr.ip())
+}
+
+/// Key of client socket address from [ConnectionAddrs] extension. It limits every connection on
+/// its own. For [RateLimitFactory::new].
+pub fn peer_addr<B>(req: &Request<B>) -> Option<SocketAddr> {
+    req.extensions().get::<ConnectionAddrs>()?.peer()
+}
+
+/// Factory of [RateLimitService].
+///
+/// Every key has a token bucket holding up to `burst` tokens and refilled with `rate` tokens per
+/// `per` duration. A request takes one token of its key. When there is none it's answered with
+/// `429 Too Many Requests` and a `retry-after` header of the time next token is available.
+/// Rejected request is dropped without reading its body. Requests without a key are not limited.
+///
+/// Buckets are kept by every service on its own so workers limit their requests independently.
+///
+/// # Examples:
+/// ```rust
+/// # use std::{convert::Infallible, time::Duration};
+/// use actix_http_alt::{
+///     http::{Request, Response},
+///     util::{peer_ip, RateLimitFactory},
+///     HttpServiceBuilder, RequestBody, ResponseBody,
+/// };
+/// use actix_service_alt::fn_service;
+///
+/// # async fn handler(_: Request<RequestBody>) -> Result<Response<ResponseBody>, Infallible> {
+/// #     Ok(Response::new(ResponseBody::None))
+/// # }
+/// // 10 requests per second for every client ip with burst of 20.
+/// let factory = RateLimitFactory::new(fn_service(handler), peer_ip::<RequestBody>, 10, Duration::from_secs(1))
+///     .burst(20);
+///
+/// // limit by header value.
+/// let factory = RateLimitFactory::new(
+///     factory,
+///     |req: &Request<RequestBody>| req.headers().get("x-api-key").cloned(),
+///     100,
+///     Duration::from_secs(60),
+/// );
+///
+/// let builder = HttpServiceBuilder::new(factory);
+/// ```
+pub struct RateLimitFactory<F, K> {
+    factory: F,
+    key: K,
+    rate: Rate,
+}
+
+#[derive(Clone, Copy)]
+struct Rate {
+    // time one token takes to refill.
+    interval: Duration,
+    // time a full bucket takes to refill.
+    tolerance: Duration,
+}
+
+impl Rate {
+    fn new(interval: Duration, burst: u32) -> Self {
+        Self {
+            interval,
+            tolerance: interval * burst.saturating_sub(1),
+        }
+    }
+}
+
+impl<F, K> RateLimitFactory<F, K> {
+    /// Allow `rate` requests every `per` duration for every key returned by `key`. Burst defaults
+    /// to `rate`.
+    ///
+    /// # Panics
+    /// When `rate` is zero.
+    pub fn new(factory: F, key: K, rate: u32, per: Duration) -> Self {
+        assert!(rate > 0, "rate limit can not be zero");
+        Self {
+            factory,
+            key,
+            rate: Rate::new(per / rate, rate),
+        }
+    }
+
+    /// Change number of requests a key can make at once after it stays idle.
+    ///
+    /// # Panics
+    /// When `burst` is zero.
+    pub fn burst(mut self, burst: u32) -> Self {
+        assert!(burst > 0, "rate limit burst can not be zero");
+        self.rate = Rate::new(self.rate.interval, burst);
+        self
+    }
+}
+
+impl<F, K, Key, ReqB, ResB> ServiceFactory<Request<ReqB>> for RateLimitFactory<F, K>
+where
+    F: ServiceFactory<Request<ReqB>, Response = Response<ResponseBody<ResB>>>,
+    F::Service: 'static,
+    K: Fn(&Request<ReqB>) -> Option<Key> + Clone + 'static,
+    Key: Hash + Eq + 'static,
+{
+    type Response = F::Response;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = RateLimitService<F::Service, K, Key>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let key = self.key.clone();
+        let rate = self.rate;
+
+        async move {
+            let service = service.await?;
+
+            Ok(RateLimitService {
+                service,
+                key,
+                limiter: RefCell::new(Limiter::new(rate)),
+            })
+        }
+    }
+}
+
+pub struct RateLimitService<S, K, Key> {
+    service: S,
+    key: K,
+    limiter: RefCell<Limiter<Key>>,
+}
+
+impl<S, K, Key, ReqB, ResB> Service<Request<ReqB>> for RateLimitService<S, K, Key>
+where
+    S: Service<Request<ReqB>, Response = Response<ResponseBody<ResB>>> + 'static,
+    K: Fn(&Request<ReqB>) -> Option<Key> + 'static,
+    Key: Hash + Eq + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: Request<ReqB>) -> Self::Future<'_> {
+        let admit = match (self.key)(&req) {
+            Some(key) => self.limiter.borrow_mut().acquire(key, Instant::now()),
+            None => Ok(()),
+        };
+
+        async move {
+            match admit {
+                Ok(()) => self.service.call(req).await,
+                Err(retry_after) => {
+                    let ctx = ErrorContext::new(req.version()).request(req.method(), req.uri());
+                    Ok(response::too_many_requests(retry_after, ctx))
+                }
+            }
+        }
+    }
+}
+
+/// Token buckets stored as theoretical arrival time of next request. (GCRA)
+struct Limiter<Key> {
+    rate: Rate,
+    buckets: HashMap<Key, Instant>,
+    prune_at: usize,
+}
+
+impl<Key: Hash + Eq> Limiter<Key> {
+    fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            buckets: HashMap::new(),
+            prune_at: PRUNE_AT,
+        }
+    }
+
+    /// Take a token of key. Return time until next token on failure.
+    fn acquire(&mut self, key: Key, now: Instant) -> Result<(), Duration> {
+        let Rate { interval, tolerance } = self.rate;
+
+        if self.buckets.len() >= self.prune_at {
+            // bucket fully refilled is the same as a missing one.
+            self.buckets.retain(|_, tat| *tat > now);
+            self.prune_at = (self.buckets.len() * 2).max(PRUNE_AT);
+        }
+
+        let tat = self.buckets.entry(key).or_insert(now);
+        let allow_at = tat.checked_sub(tolerance).unwrap_or(now);
+
+        if now < allow_at {
+            return Err(allow_at - now);
+        }
+
+        *tat = (*tat).max(now) + interval;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use http::{header::RETRY_AFTER, StatusCode};
+
+    use crate::util::echo;
+
+    #[test]
+    fn steady_rate() {
+        // 100 requests per second with burst of 10.
+        let mut limiter = Limiter::new(Rate::new(Duration::from_millis(10), 10));
+        let start = Instant::now();
+
+        // one client sends 1000 requests per second for 10 seconds.
+        let admitted = (0..10_000)
+            .filter(|i| limiter.acquire(0, start + Duration::from_millis(*i)).is_ok())
+            .count();
+        assert_eq!(admitted, 10 + 1000 - 1);
+
+        // next token is 10 milli seconds away after burst is used up.
+        let now = start + Duration::from_secs(10);
+        assert!(limiter.acquire(0, now).is_ok());
+        assert_eq!(limiter.acquire(0, now), Err(Duration::from_millis(10)));
+
+        // keys do not share bucket.
+        assert!(limiter.acquire(1, now).is_ok());
+    }
+
+    #[test]
+    fn prune() {
+        let mut limiter = Limiter::new(Rate::new(Duration::from_millis(10), 1));
+        let start = Instant::now();
+
+        for key in 0..PRUNE_AT {
+            assert!(limiter.acquire(key, start).is_ok());
+        }
+
+        // refilled buckets are dropped once key count reaches limit.
+        assert!(limiter.acquire(PRUNE_AT, start + Duration::from_millis(10)).is_ok());
+        assert_eq!(limiter.buckets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reject() {
+        let factory = RateLimitFactory::new(echo(), |_: &Request<()>| Some(()), 1, Duration::from_secs(90));
+        let service = ServiceFactory::<Request<()>>::new_service(&factory, ()).await.unwrap();
+
+        let res = service.call(Request::new(())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = service.call(Request::new(())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[RETRY_AFTER], "90");
+
+        // request without key is not limited.
+        let factory = RateLimitFactory::new(echo(), peer_ip, 1, Duration::from_secs(90));
+        let service = ServiceFactory::<Request<()>>::new_service(&factory, ()).await.unwrap();
+        for _ in 0..2 {
+            let res = service.call(Request::new(())).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[cfg(feature = "http1")]
+    #[tokio::test]
+    async fn keep_alive() {
+        use tokio::task::LocalSet;
+
+        use crate::builder::HttpServiceBuilder;
+        use crate::util::testing::{duplex, serve, H1Client, TestStream};
+
+        LocalSet::new()
+            .run_until(async {
+                let factory = RateLimitFactory::new(echo(), |_: &Request<_>| Some(()), 1, Duration::from_secs(60));
+                let builder = HttpServiceBuilder::h1(factory);
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+                let (res, _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+                    let mut res = Vec::new();
+                    for _ in 0..3 {
+                        client.send("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+                        res.push(client.response().await.unwrap());
+                    }
+                    res
+                })
+                .await;
+
+                assert_eq!(res[0].status(), StatusCode::OK);
+                // rejected requests are answered on the same connection.
+                for res in &res[1..] {
+                    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+                    assert_eq!(res.headers()[RETRY_AFTER], "60");
+                    assert!(res.headers().get("connection").is_none());
+                }
+            })
+            .await
+    }
+}
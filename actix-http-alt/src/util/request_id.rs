@@ -0,0 +1,247 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use http::{header::HeaderName, HeaderValue, Request, Response};
+
+/// Max length of request id accepted from client.
+const MAX_LEN: usize = 128;
+
+/// Id of a request. Inserted into request extensions by [RequestIdService].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    /// Generate a new id. 26 characters of Crockford base32 encoded 48 bits of milli seconds
+    /// since unix epoch followed by 80 random bits. Ids are sortable by the time generated.
+    pub fn generate() -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let value = (u128::from(millis) << 80) | (u128::from(random()) << 16) | u128::from(random() as u16);
+
+        let mut buf = [0u8; 26];
+        for (i, b) in buf.iter_mut().enumerate() {
+            let shift = (25 - i) * 5;
+            *b = CROCKFORD[((value >> shift) & 0x1f) as usize];
+        }
+
+        Self(HeaderValue::from_bytes(&buf).unwrap())
+    }
+
+    /// Accept id from header value. `None` when value is empty, longer than 128 bytes or contains
+    /// bytes other than ascii alphanumeric and `-_.:`.
+    pub fn from_header(value: &HeaderValue) -> Option<Self> {
+        let bytes = value.as_bytes();
+        let valid = !bytes.is_empty()
+            && bytes.len() <= MAX_LEN
+            && bytes.iter().all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(b));
+
+        if valid {
+            Some(Self(value.clone()))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // value is either generated or validated as ascii.
+        self.0.to_str().unwrap()
+    }
+
+    pub fn as_header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+// xorshift64* seeded per thread by std's random hasher keys.
+fn random() -> u64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new({
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0));
+            hasher.finish() | 1
+        });
+    }
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    })
+}
+
+/// A factory that assign a [RequestId] to every request handled by the service it wraps.
+///
+/// Id from request header is kept when it's valid. See [RequestId::from_header]. Otherwise a new
+/// one is generated. [AccessLogFactory](super::AccessLogFactory) and tracing spans include the id
+/// when they are wrapped by this factory.
+pub struct RequestIdFactory<F> {
+    factory: F,
+    header: HeaderName,
+    echo: bool,
+}
+
+impl<F> RequestIdFactory<F> {
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            header: HeaderName::from_static("x-request-id"),
+            echo: false,
+        }
+    }
+
+    /// Change name of the header id is read from and echoed with. Default to `x-request-id`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set id header on response returned by service. Default to false.
+    ///
+    /// Response converted from service error does not have the header.
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+}
+
+impl<F, ReqB, ResB> ServiceFactory<Request<ReqB>> for RequestIdFactory<F>
+where
+    F: ServiceFactory<Request<ReqB>, Response = Response<ResB>>,
+    F::Service: 'static,
+{
+    type Response = F::Response;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = RequestIdService<F::Service>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let header = self.header.clone();
+        let echo = self.echo;
+
+        async move {
+            let service = service.await?;
+
+            Ok(RequestIdService { service, header, echo })
+        }
+    }
+}
+
+pub struct RequestIdService<S> {
+    service: S,
+    header: HeaderName,
+    echo: bool,
+}
+
+impl<S, ReqB, ResB> Service<Request<ReqB>> for RequestIdService<S>
+where
+    S: Service<Request<ReqB>, Response = Response<ResB>> + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: Request<ReqB>) -> Self::Future<'_> {
+        async move {
+            let id = req
+                .headers()
+                .get(&self.header)
+                .and_then(RequestId::from_header)
+                .unwrap_or_else(RequestId::generate);
+
+            #[cfg(feature = "tracing")]
+            super::trace::record_request_id(&id);
+            req.extensions_mut().insert(id.clone());
+
+            let mut res = self.service.call(req).await?;
+
+            if self.echo {
+                res.headers_mut().insert(self.header.clone(), id.0);
+            }
+
+            Ok(res)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use actix_service_alt::fn_service;
+
+    #[test]
+    fn generate() {
+        let a = RequestId::generate();
+        let b = RequestId::generate();
+
+        assert_eq!(a.as_str().len(), 26);
+        assert!(a.as_str().bytes().all(|b| CROCKFORD.contains(&b)));
+        assert_ne!(a, b);
+        // same milli second prefix or later.
+        assert!(a.as_str()[..10] <= b.as_str()[..10]);
+    }
+
+    #[test]
+    fn from_header() {
+        let valid = HeaderValue::from_static("abc-123_x.y:z");
+        assert_eq!(RequestId::from_header(&valid).unwrap().as_str(), "abc-123_x.y:z");
+
+        for invalid in ["", "a b", "a/b", &"a".repeat(129)].iter() {
+            assert!(RequestId::from_header(&HeaderValue::from_str(invalid).unwrap()).is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn service() {
+        let factory = RequestIdFactory::new(fn_service(|req: Request<()>| async move {
+            let id = req.extensions().get::<RequestId>().unwrap().clone();
+            Ok::<_, Infallible>(Response::new(id))
+        }))
+        .header(HeaderName::from_static("x-id"))
+        .echo(true);
+
+        let service = factory.new_service(()).await.ok().unwrap();
+
+        let req = Request::builder().header("x-id", "client-id").body(()).unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.body().as_str(), "client-id");
+        assert_eq!(res.headers().get("x-id").unwrap(), "client-id");
+
+        let req = Request::builder().header("x-id", "bad id").body(()).unwrap();
+        let res = service.call(req).await.unwrap();
+        assert_eq!(res.body().as_str().len(), 26);
+        assert_eq!(res.headers().get("x-id").unwrap(), res.body().as_header_value());
+    }
+}
@@ -0,0 +1,393 @@
+//! Combinators for [ServiceFactory] and the services it creates.
+//!
+//! # Examples:
+//! ```rust
+//! # use std::convert::Infallible;
+//! use actix_http_alt::{
+//!     http::{header, HeaderValue, Request, Response, Uri},
+//!     util::service::ServiceFactoryMapExt,
+//!     HttpServiceBuilder, RequestBody, ResponseBody,
+//! };
+//! use actix_service_alt::fn_service;
+//!
+//! # async fn handler(_: Request<RequestBody>) -> Result<Response<ResponseBody>, Infallible> {
+//! #     Ok(Response::new(ResponseBody::None))
+//! # }
+//! let factory = fn_service(handler)
+//!     // rewrite legacy path before it reaches handler.
+//!     .map_request(|mut req: Request<RequestBody>| {
+//!         if req.uri().path() == "/old" {
+//!             *req.uri_mut() = Uri::from_static("/new");
+//!         }
+//!         req
+//!     })
+//!     // inject server header to every response.
+//!     .map_response(|mut res: Response<ResponseBody>| {
+//!         res.headers_mut().insert(header::SERVER, HeaderValue::from_static("actix-http-alt"));
+//!         res
+//!     });
+//!
+//! let builder = HttpServiceBuilder::new(factory);
+//! ```
+
+use std::{
+    future::Future,
+    task::{Context, Poll},
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+
+/// Extension methods for mapping request, response and error of a [ServiceFactory].
+pub trait ServiceFactoryMapExt<Req>: ServiceFactory<Req> {
+    /// Map request with `f` before it's passed to service.
+    fn map_request<M>(self, f: M) -> MapRequest<Self, M>
+    where
+        Self: Sized,
+    {
+        MapRequest { factory: self, f }
+    }
+
+    /// Map response of service with `f`.
+    fn map_response<M, Res>(self, f: M) -> MapResponse<Self, M>
+    where
+        M: Fn(Self::Response) -> Res + Clone,
+        Self: Sized,
+    {
+        MapResponse { factory: self, f }
+    }
+
+    /// Map error of service with `f`. Error from [Service::poll_ready] is mapped too.
+    fn map_err<M, Err>(self, f: M) -> MapErr<Self, M>
+    where
+        M: Fn(Self::Error) -> Err + Clone,
+        Self: Sized,
+    {
+        MapErr { factory: self, f }
+    }
+
+    /// Pass successful response of service to async function `f`. Error is returned as is.
+    fn and_then<M, Fut, Res>(self, f: M) -> AndThen<Self, M>
+    where
+        M: Fn(Self::Response) -> Fut + Clone,
+        Fut: Future<Output = Result<Res, Self::Error>>,
+        Self: Sized,
+    {
+        AndThen { factory: self, f }
+    }
+}
+
+impl<F, Req> ServiceFactoryMapExt<Req> for F where F: ServiceFactory<Req> {}
+
+macro_rules! combinator {
+    ($(#[$meta: meta])* $factory: ident, $service: ident) => {
+        $(#[$meta])*
+        pub struct $factory<F, M> {
+            factory: F,
+            f: M,
+        }
+
+        #[doc = concat!("Service created by [", stringify!($factory), "].")]
+        pub struct $service<S, M> {
+            service: S,
+            f: M,
+        }
+    };
+}
+
+combinator!(
+    /// Factory returned by [ServiceFactoryMapExt::map_request].
+    MapRequest,
+    MapRequestService
+);
+
+combinator!(
+    /// Factory returned by [ServiceFactoryMapExt::map_response].
+    MapResponse,
+    MapResponseService
+);
+
+combinator!(
+    /// Factory returned by [ServiceFactoryMapExt::map_err].
+    MapErr,
+    MapErrService
+);
+
+combinator!(
+    /// Factory returned by [ServiceFactoryMapExt::and_then].
+    AndThen,
+    AndThenService
+);
+
+impl<F, M, Req, Req2> ServiceFactory<Req> for MapRequest<F, M>
+where
+    F: ServiceFactory<Req2>,
+    M: Fn(Req) -> Req2 + Clone,
+{
+    type Response = F::Response;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = MapRequestService<F::Service, M>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let f = self.f.clone();
+
+        async move {
+            let service = service.await?;
+            Ok(MapRequestService { service, f })
+        }
+    }
+}
+
+impl<S, M, Req, Req2> Service<Req> for MapRequestService<S, M>
+where
+    S: Service<Req2>,
+    M: Fn(Req) -> Req2,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future<'f> = S::Future<'f>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&self, req: Req) -> Self::Future<'_> {
+        self.service.call((self.f)(req))
+    }
+}
+
+impl<F, M, Req, Res> ServiceFactory<Req> for MapResponse<F, M>
+where
+    F: ServiceFactory<Req>,
+    F::Service: 'static,
+    M: Fn(F::Response) -> Res + Clone + 'static,
+{
+    type Response = Res;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = MapResponseService<F::Service, M>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let f = self.f.clone();
+
+        async move {
+            let service = service.await?;
+            Ok(MapResponseService { service, f })
+        }
+    }
+}
+
+impl<S, M, Req, Res> Service<Req> for MapResponseService<S, M>
+where
+    S: Service<Req> + 'static,
+    M: Fn(S::Response) -> Res + 'static,
+{
+    type Response = Res;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&self, req: Req) -> Self::Future<'_> {
+        async move { self.service.call(req).await.map(&self.f) }
+    }
+}
+
+impl<F, M, Req, Err> ServiceFactory<Req> for MapErr<F, M>
+where
+    F: ServiceFactory<Req>,
+    F::Service: 'static,
+    M: Fn(F::Error) -> Err + Clone + 'static,
+{
+    type Response = F::Response;
+    type Error = Err;
+    type Config = F::Config;
+    type Service = MapErrService<F::Service, M>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let f = self.f.clone();
+
+        async move {
+            let service = service.await?;
+            Ok(MapErrService { service, f })
+        }
+    }
+}
+
+impl<S, M, Req, Err> Service<Req> for MapErrService<S, M>
+where
+    S: Service<Req> + 'static,
+    M: Fn(S::Error) -> Err + 'static,
+{
+    type Response = S::Response;
+    type Error = Err;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx).map_err(&self.f)
+    }
+
+    #[inline]
+    fn call(&self, req: Req) -> Self::Future<'_> {
+        async move { self.service.call(req).await.map_err(&self.f) }
+    }
+}
+
+impl<F, M, Req, Fut, Res> ServiceFactory<Req> for AndThen<F, M>
+where
+    F: ServiceFactory<Req>,
+    F::Service: 'static,
+    M: Fn(F::Response) -> Fut + Clone + 'static,
+    Fut: Future<Output = Result<Res, F::Error>>,
+{
+    type Response = Res;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = AndThenService<F::Service, M>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        let service = self.factory.new_service(cfg);
+        let f = self.f.clone();
+
+        async move {
+            let service = service.await?;
+            Ok(AndThenService { service, f })
+        }
+    }
+}
+
+impl<S, M, Req, Fut, Res> Service<Req> for AndThenService<S, M>
+where
+    S: Service<Req> + 'static,
+    M: Fn(S::Response) -> Fut + 'static,
+    Fut: Future<Output = Result<Res, S::Error>>,
+{
+    type Response = Res;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&self, req: Req) -> Self::Future<'_> {
+        async move {
+            let res = self.service.call(req).await?;
+            (self.f)(res).await
+        }
+    }
+}
+
+#[cfg(all(test, feature = "http1"))]
+mod test {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use actix_service_alt::fn_service;
+    use bytes::Bytes;
+    use http::{header, HeaderValue, Request, Response, StatusCode, Uri};
+    use tokio::task::LocalSet;
+
+    use crate::body::ResponseBody;
+    use crate::builder::HttpServiceBuilder;
+    use crate::util::testing::{duplex, serve, H1Client, TestStream};
+
+    async fn handler<B>(req: Request<B>) -> Result<Response<ResponseBody>, Infallible> {
+        Ok(Response::new(Bytes::from(req.uri().path().to_owned()).into()))
+    }
+
+    fn rewrite<B>(mut req: Request<B>) -> Request<B> {
+        if req.uri().path() == "/old" {
+            *req.uri_mut() = Uri::from_static("/new");
+        }
+        req
+    }
+
+    fn server(mut res: Response<ResponseBody>) -> Response<ResponseBody> {
+        res.headers_mut()
+            .insert(header::SERVER, HeaderValue::from_static("actix-http-alt"));
+        res
+    }
+
+    async fn teapot(mut res: Response<ResponseBody>) -> Result<Response<ResponseBody>, Infallible> {
+        *res.status_mut() = StatusCode::IM_A_TEAPOT;
+        Ok(res)
+    }
+
+    #[tokio::test]
+    async fn h1() {
+        LocalSet::new()
+            .run_until(async {
+                let factory = fn_service(handler)
+                    .map_request(rewrite)
+                    .map_response(server)
+                    .and_then(teapot)
+                    .map_err(|e| e);
+                let builder = HttpServiceBuilder::h1(factory);
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+
+                let (res, _) = serve(&service, io, async move {
+                    let mut client = H1Client::new(client);
+                    client
+                        .send("GET /old HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                        .await
+                        .unwrap();
+                    client.response().await.unwrap()
+                })
+                .await;
+
+                assert_eq!(res.status(), StatusCode::IM_A_TEAPOT);
+                assert_eq!(res.headers()[header::SERVER], "actix-http-alt");
+                assert_eq!(res.body(), "/new");
+            })
+            .await
+    }
+
+    #[cfg(feature = "http2")]
+    #[tokio::test]
+    async fn h2() {
+        LocalSet::new()
+            .run_until(async {
+                let factory = fn_service(handler).map_request(rewrite).map_response(server);
+                let builder = HttpServiceBuilder::h2(factory);
+                let service = ServiceFactory::<TestStream>::new_service(&builder, ()).await.unwrap();
+
+                let (client, io) = duplex();
+
+                let (res, _) = serve(&service, io, async move {
+                    let client = crate::util::testing::H2Client::handshake(client).await.unwrap();
+                    let req = Request::get("http://localhost/old").body(Bytes::new()).unwrap();
+                    client.send(req).await.unwrap()
+                })
+                .await;
+
+                assert_eq!(res.status(), StatusCode::OK);
+                assert_eq!(res.headers()[header::SERVER], "actix-http-alt");
+                assert_eq!(res.body(), "/new");
+            })
+            .await
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::ready;
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::stats::StatsRecorder;
+
+/// Io wrapper counting bytes read from and written to inner io.
+#[pin_project]
+pub(crate) struct StatsIo<Io> {
+    #[pin]
+    io: Io,
+    stats: StatsRecorder,
+}
+
+impl<Io> StatsIo<Io> {
+    pub(crate) fn new(io: Io, stats: &StatsRecorder) -> Self {
+        Self {
+            io,
+            stats: stats.clone(),
+        }
+    }
+}
+
+impl<Io: AsyncRead> AsyncRead for StatsIo<Io> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let len = buf.filled().len();
+        ready!(this.io.poll_read(cx, buf))?;
+        this.stats.read(buf.filled().len() - len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<Io: AsyncWrite> AsyncWrite for StatsIo<Io> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let n = ready!(this.io.poll_write(cx, buf))?;
+        this.stats.written(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let n = ready!(this.io.poll_write_vectored(cx, bufs))?;
+        this.stats.written(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,602 @@
+//! In memory io and clients for testing services without binding sockets.
+//!
+//! Services of this crate and the Http/2 client are expected to run inside a
+//! [LocalSet](tokio::task::LocalSet).
+//!
+//! # Example:
+//! ```rust,no_run
+//! # async fn test() {
+//! use std::convert::Infallible;
+//!
+//! use actix_http_alt::{
+//!     http::{Request, Response},
+//!     util::testing::{duplex, serve, H1Client, TestStream},
+//!     h1, HttpServiceBuilder, ResponseBody,
+//! };
+//! use actix_service_alt::{fn_service, ServiceFactory};
+//!
+//! let service = ServiceFactory::<TestStream>::new_service(
+//!     &HttpServiceBuilder::h1(fn_service(|_: Request<h1::RequestBody>| async {
+//!         Ok::<Response<ResponseBody>, Infallible>(Response::new(ResponseBody::None))
+//!     })),
+//!     (),
+//! )
+//! .await
+//! .unwrap();
+//!
+//! let (client, io) = duplex();
+//!
+//! let (res, _) = serve(&service, io, async move {
+//!     let mut client = H1Client::new(client);
+//!     client.send(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+//!     client.response().await.unwrap()
+//! })
+//! .await;
+//!
+//! assert!(res.status().is_success());
+//! # }
+//! ```
+
+use std::{
+    cell::RefCell,
+    cmp,
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use actix_server_alt::net::{AsyncReadWrite, Stream as ServerStream, TcpListener, TcpStream};
+use actix_service_alt::Service;
+use bytes::{BufMut, Bytes};
+use futures_core::ready;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready},
+    time::{sleep_until, Instant, Sleep},
+};
+
+use crate::connection::{ConnectionData, OnConnect};
+
+use super::poll_fn::poll_fn;
+
+/// Configuration of stream pair created by [DuplexConfig::pair].
+#[derive(Copy, Clone, Debug)]
+pub struct DuplexConfig {
+    capacity: usize,
+    chunk: usize,
+    latency: Duration,
+}
+
+impl Default for DuplexConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DuplexConfig {
+    pub const fn new() -> Self {
+        Self {
+            capacity: 64 * 1024,
+            chunk: usize::MAX,
+            latency: Duration::from_secs(0),
+        }
+    }
+
+    /// Bytes can be buffered in one direction before write is pending. Default to 64KiB.
+    pub fn capacity(mut self, bytes: usize) -> Self {
+        assert_ne!(bytes, 0, "capacity must be larger than 0");
+        self.capacity = bytes;
+        self
+    }
+
+    /// Max bytes returned by one read. Default to no limit.
+    ///
+    /// Use a small value to exercise parsing of partial messages.
+    pub fn chunk(mut self, bytes: usize) -> Self {
+        assert_ne!(bytes, 0, "chunk must be larger than 0");
+        self.chunk = bytes;
+        self
+    }
+
+    /// Delay between bytes being written and them becoming readable. Default to zero.
+    pub fn latency(mut self, dur: Duration) -> Self {
+        self.latency = dur;
+        self
+    }
+
+    /// Construct a pair of connected streams. Bytes written to one are read from the other.
+    pub fn pair(self) -> (TestStream, TestStream) {
+        let a = Rc::new(RefCell::new(Pipe::new(self.capacity)));
+        let b = Rc::new(RefCell::new(Pipe::new(self.capacity)));
+
+        (TestStream::new(a.clone(), b.clone(), self), TestStream::new(b, a, self))
+    }
+}
+
+/// Construct a pair of connected streams with default [DuplexConfig].
+pub fn duplex() -> (TestStream, TestStream) {
+    DuplexConfig::new().pair()
+}
+
+// bytes written in one direction.
+struct Pipe {
+    // bytes and the time they become readable.
+    segments: VecDeque<(Instant, Bytes)>,
+    len: usize,
+    capacity: usize,
+    // writer is shutdown or dropped.
+    write_closed: bool,
+    // reader is dropped.
+    read_closed: bool,
+    // write after reader is dropped has happened.
+    reset: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Self {
+        Self {
+            segments: VecDeque::new(),
+            len: 0,
+            capacity,
+            write_closed: false,
+            read_closed: false,
+            reset: false,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn close_write(&mut self) {
+        self.write_closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close_read(&mut self) {
+        self.read_closed = true;
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// One end of an in memory connection constructed by [duplex] or [DuplexConfig::pair].
+///
+/// Reading returns 0 after the other end is shutdown or dropped. Like a tcp socket whose peer is
+/// closed, the first write after the other end is dropped is discarded and following ones fail
+/// with [io::ErrorKind::BrokenPipe].
+pub struct TestStream {
+    read: Rc<RefCell<Pipe>>,
+    write: Rc<RefCell<Pipe>>,
+    chunk: usize,
+    latency: Duration,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl TestStream {
+    fn new(read: Rc<RefCell<Pipe>>, write: Rc<RefCell<Pipe>>, config: DuplexConfig) -> Self {
+        Self {
+            read,
+            write,
+            chunk: config.chunk,
+            latency: config.latency,
+            delay: None,
+        }
+    }
+
+    fn read_chunk(&mut self, max: usize) -> io::Result<Bytes> {
+        let mut pipe = self.read.borrow_mut();
+        let pipe = &mut *pipe;
+
+        let bytes = match pipe.segments.front_mut() {
+            Some((at, bytes)) if *at <= Instant::now() => {
+                let len = cmp::min(cmp::min(bytes.len(), self.chunk), max);
+                bytes.split_to(len)
+            }
+            Some(_) => return Err(io::ErrorKind::WouldBlock.into()),
+            None if pipe.write_closed => return Ok(Bytes::new()),
+            None => return Err(io::ErrorKind::WouldBlock.into()),
+        };
+
+        if pipe.segments.front().unwrap().1.is_empty() {
+            pipe.segments.pop_front();
+        }
+
+        pipe.len -= bytes.len();
+        if let Some(waker) = pipe.write_waker.take() {
+            waker.wake();
+        }
+
+        Ok(bytes)
+    }
+
+    fn write_slices(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let mut pipe = self.write.borrow_mut();
+
+        if pipe.write_closed || pipe.reset {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        if pipe.read_closed {
+            pipe.reset = true;
+            return Ok(bufs.iter().map(|buf| buf.len()).sum());
+        }
+
+        let mut bytes = Vec::new();
+        for buf in bufs {
+            let len = cmp::min(buf.len(), pipe.capacity - pipe.len - bytes.len());
+            bytes.extend_from_slice(&buf[..len]);
+        }
+
+        if bytes.is_empty() {
+            return if bufs.iter().all(|buf| buf.is_empty()) {
+                Ok(0)
+            } else {
+                Err(io::ErrorKind::WouldBlock.into())
+            };
+        }
+
+        let len = bytes.len();
+        pipe.len += len;
+        pipe.segments.push_back((Instant::now() + self.latency, bytes.into()));
+        if let Some(waker) = pipe.read_waker.take() {
+            waker.wake();
+        }
+
+        Ok(len)
+    }
+
+    fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let mut pipe = self.read.borrow_mut();
+
+            let at = match pipe.segments.front() {
+                Some((at, _)) if *at <= Instant::now() => return Poll::Ready(Ok(())),
+                Some((at, _)) => *at,
+                None if pipe.write_closed => return Poll::Ready(Ok(())),
+                None => {
+                    pipe.read_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            };
+
+            drop(pipe);
+
+            let delay = self.delay.get_or_insert_with(|| Box::pin(sleep_until(at)));
+            delay.as_mut().reset(at);
+            ready!(delay.as_mut().poll(cx));
+        }
+    }
+
+    fn poll_writable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut pipe = self.write.borrow_mut();
+
+        if pipe.read_closed || pipe.write_closed || pipe.len < pipe.capacity {
+            Poll::Ready(Ok(()))
+        } else {
+            pipe.write_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for TestStream {
+    fn drop(&mut self) {
+        self.read.borrow_mut().close_read();
+        self.write.borrow_mut().close_write();
+    }
+}
+
+impl AsyncRead for TestStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            ready!(this.poll_readable(cx))?;
+
+            match this.read_chunk(buf.remaining()) {
+                Ok(bytes) => {
+                    buf.put_slice(&bytes);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for TestStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[io::IoSlice::new(buf)])
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().write.borrow_mut().close_write();
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            ready!(this.poll_writable(cx))?;
+
+            match this.write_slices(bufs) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+}
+
+impl AsyncReadWrite for TestStream {
+    type ReadyFuture<'f> = impl Future<Output = io::Result<Ready>>;
+
+    fn ready(&mut self, interest: Interest) -> Self::ReadyFuture<'_> {
+        poll_fn(move |cx| {
+            let mut ready = Ready::EMPTY;
+
+            if interest.is_readable() && self.poll_readable(cx)?.is_ready() {
+                ready |= Ready::READABLE;
+            }
+
+            if interest.is_writable() && self.poll_writable(cx)?.is_ready() {
+                ready |= Ready::WRITABLE;
+            }
+
+            if ready.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(Ok(ready))
+            }
+        })
+    }
+
+    fn try_read_buf<B: BufMut>(&mut self, buf: &mut B) -> io::Result<usize> {
+        let bytes = self.read_chunk(buf.remaining_mut())?;
+        buf.put_slice(&bytes);
+        Ok(bytes.len())
+    }
+
+    #[inline]
+    fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_slices(&[io::IoSlice::new(buf)])
+    }
+
+    #[inline]
+    fn try_write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.write_slices(bufs)
+    }
+
+    #[inline]
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_readable(cx)
+    }
+
+    #[inline]
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_writable(cx)
+    }
+}
+
+impl OnConnect for TestStream {
+    // in memory connection has no address.
+    #[inline]
+    fn on_connect(&self, _: &mut ConnectionData) {}
+}
+
+/// Serve io with service while running client future. Return when both of them finish.
+///
+/// Io owned by client future is dropped when it finishes which closes the connection.
+pub async fn serve<S, St, F>(service: &S, io: St, client: F) -> (F::Output, Result<(), S::Error>)
+where
+    S: Service<St, Response = ()>,
+    F: Future,
+{
+    tokio::join!(client, service.call(io))
+}
+
+/// Loopback tcp connection with client side and server side.
+///
+/// [HttpService](crate::HttpService) detects protocol from server stream and can not serve
+/// [TestStream].
+pub async fn tcp_pair() -> io::Result<(TcpStream, ServerStream)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let client = TcpStream::connect(listener.local_addr()?).await?;
+    let (io, _) = listener.accept().await?;
+    Ok((client, ServerStream::Tcp(io)))
+}
+
+#[cfg(feature = "http1")]
+pub use self::h1::H1Client;
+
+#[cfg(feature = "http1")]
+mod h1 {
+    use bytes::BytesMut;
+    use http::{Method, Response};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::h1::{read_response, ClientError};
+
+    use super::*;
+
+    /// Http/1 client writes raw bytes and reads parsed responses.
+    ///
+    /// Responses are read in order. Pipelined requests can be written at once before reading
+    /// their responses.
+    pub struct H1Client<Io> {
+        io: Io,
+        read_buf: BytesMut,
+    }
+
+    impl<Io> H1Client<Io>
+    where
+        Io: AsyncRead + AsyncWrite + Unpin,
+    {
+        pub fn new(io: Io) -> Self {
+            Self {
+                io,
+                read_buf: BytesMut::new(),
+            }
+        }
+
+        /// Write raw bytes of one or more requests.
+        pub async fn send(&mut self, raw: impl AsRef<[u8]>) -> io::Result<()> {
+            self.io.write_all(raw.as_ref()).await?;
+            self.io.flush().await
+        }
+
+        /// Read next response with its whole body.
+        pub async fn response(&mut self) -> Result<Response<Bytes>, ClientError> {
+            self.response_to(&Method::GET).await
+        }
+
+        /// Read next response to request of given method. HEAD response is read without body.
+        pub async fn response_to(&mut self, method: &Method) -> Result<Response<Bytes>, ClientError> {
+            read_response(&mut self.io, &mut self.read_buf, method).await
+        }
+
+        /// Read all remaining bytes until connection is closed.
+        pub async fn read_to_end(&mut self) -> io::Result<Bytes> {
+            let mut buf = self.read_buf.split();
+            while self.io.read_buf(&mut buf).await? != 0 {}
+            Ok(buf.freeze())
+        }
+    }
+}
+
+#[cfg(feature = "http2")]
+pub use self::h2::H2Client;
+
+#[cfg(feature = "http2")]
+mod h2 {
+    use ::h2::{
+        client::{self, SendRequest},
+        Error,
+    };
+    use bytes::BytesMut;
+    use http::{Request, Response};
+    use tokio::task::JoinHandle;
+
+    use super::*;
+
+    /// Http/2 client collects whole body of responses.
+    ///
+    /// Connection is driven by a task spawned on current [LocalSet](tokio::task::LocalSet).
+    pub struct H2Client {
+        send: SendRequest<Bytes>,
+        conn: JoinHandle<Result<(), Error>>,
+    }
+
+    impl H2Client {
+        pub async fn handshake<Io>(io: Io) -> Result<Self, Error>
+        where
+            Io: AsyncRead + AsyncWrite + Unpin + 'static,
+        {
+            let (send, conn) = client::handshake(io).await?;
+            let conn = tokio::task::spawn_local(conn);
+            Ok(Self { send, conn })
+        }
+
+        /// Send request and read response with its whole body. Requests can be sent concurrently.
+        pub async fn send(&self, req: Request<Bytes>) -> Result<Response<Bytes>, Error> {
+            let (parts, body) = req.into_parts();
+            let req = Request::from_parts(parts, ());
+
+            let mut send = self.send.clone().ready().await?;
+            let (res, mut stream) = send.send_request(req, body.is_empty())?;
+            if !body.is_empty() {
+                stream.send_data(body, true)?;
+            }
+
+            let (parts, mut body) = res.await?.into_parts();
+
+            let mut buf = BytesMut::new();
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk?;
+                let _ = body.flow_control().release_capacity(chunk.len());
+                buf.extend_from_slice(&chunk);
+            }
+
+            Ok(Response::from_parts(parts, buf.freeze()))
+        }
+
+        /// Wait for connection to be closed by server.
+        pub async fn closed(self) -> Result<(), Error> {
+            drop(self.send);
+            self.conn.await.expect("Http/2 client connection panicked")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn chunk() {
+        let (mut a, mut b) = DuplexConfig::new().chunk(2).pair();
+
+        a.write_all(b"hello").await.unwrap();
+        drop(a);
+
+        let mut buf = [0; 8];
+        assert_eq!(b.read(&mut buf).await.unwrap(), 2);
+        assert_eq!(b.read(&mut buf).await.unwrap(), 2);
+        assert_eq!(b.read(&mut buf).await.unwrap(), 1);
+        assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn capacity() {
+        let (mut a, mut b) = DuplexConfig::new().capacity(4).pair();
+
+        assert_eq!(a.try_write(b"hello").unwrap(), 4);
+        assert_eq!(a.try_write(b"o").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        let mut buf = Vec::new();
+        assert_eq!(b.try_read_buf(&mut buf).unwrap(), 4);
+        assert_eq!(a.try_write(b"o").unwrap(), 1);
+
+        drop(b);
+        assert_eq!(a.write(b"!").await.unwrap(), 1);
+        assert_eq!(a.write(b"!").await.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn latency() {
+        let (mut a, mut b) = DuplexConfig::new().latency(Duration::from_millis(20)).pair();
+
+        let start = Instant::now();
+        a.write_all(b"hello").await.unwrap();
+        assert_eq!(b.ready(Interest::READABLE).await.unwrap(), Ready::READABLE);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let mut buf = Vec::new();
+        assert_eq!(b.try_read_buf(&mut buf).unwrap(), 5);
+        assert_eq!(buf, b"hello");
+    }
+}
@@ -0,0 +1,392 @@
+//! Coarse timer wheel shared by connections of one service.
+//!
+//! Wheel is advanced by the date refresh task of [DateTimeTask](super::date::DateTimeTask) so a
+//! deadline fires up to one tick late. Keep alive, request, service readiness and drain deadlines
+//! of connections are entries of the wheel and moving a deadline relinks its entry in O(1)
+//! without allocation.
+
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::date::Clock;
+
+// deadline further than SLOTS ticks stays in its slot for more than one round.
+const SLOTS: usize = 64;
+const NIL: u32 = u32::MAX;
+
+pub(crate) struct TimerWheel(RefCell<Wheel>);
+
+struct Wheel {
+    clock: Arc<dyn Clock>,
+    origin: Instant,
+    // interval between ticks.
+    tick_dur: Duration,
+    // time of last advance.
+    now: Instant,
+    // last tick processed.
+    tick: u64,
+    // head entry of every slot.
+    slots: [u32; SLOTS],
+    entries: Vec<Entry>,
+    // head of free entries linked by Entry::next.
+    free: u32,
+    // number of live timers.
+    live: usize,
+    // task advancing wheel waiting for a timer.
+    driver: Option<Waker>,
+}
+
+struct Entry {
+    deadline: Instant,
+    // slot entry is linked in. NIL when not linked.
+    slot: u32,
+    prev: u32,
+    next: u32,
+    expired: bool,
+    waker: Option<Waker>,
+}
+
+impl TimerWheel {
+    pub(crate) fn new(clock: Arc<dyn Clock>, tick: Duration) -> Rc<Self> {
+        let now = clock.now();
+        Rc::new(Self(RefCell::new(Wheel {
+            clock,
+            origin: now,
+            tick_dur: tick,
+            now,
+            tick: 0,
+            slots: [NIL; SLOTS],
+            entries: Vec::new(),
+            free: NIL,
+            live: 0,
+            driver: None,
+        })))
+    }
+
+    /// Register a new timer with given deadline.
+    pub(crate) fn timer(self: &Rc<Self>, deadline: Instant) -> Timer {
+        let key = self.0.borrow_mut().insert(deadline);
+        Timer {
+            wheel: Rc::clone(self),
+            key,
+        }
+    }
+
+    /// Register a new timer with deadline after given duration from now.
+    pub(crate) fn sleep(self: &Rc<Self>, dur: Duration) -> Timer {
+        let deadline = self.now() + dur;
+        self.timer(deadline)
+    }
+
+    /// Current time of the clock wheel is advanced with.
+    pub(crate) fn now(&self) -> Instant {
+        self.0.borrow().clock.now()
+    }
+
+    /// Expire timers with deadline passed at given time and wake their tasks.
+    pub(crate) fn advance(&self, now: Instant) {
+        self.0.borrow_mut().advance(now);
+    }
+
+    /// Resolve when there is any live timer.
+    pub(crate) fn poll_busy(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut wheel = self.0.borrow_mut();
+        if wheel.live > 0 {
+            Poll::Ready(())
+        } else {
+            wheel.driver = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Wheel {
+    // tick a deadline is due at. rounded up.
+    fn tick_of(&self, at: Instant) -> u64 {
+        let nanos = at.saturating_duration_since(self.origin).as_nanos();
+        let tick = self.tick_dur.as_nanos();
+        ((nanos + tick - 1) / tick) as u64
+    }
+
+    fn insert(&mut self, deadline: Instant) -> u32 {
+        let key = match self.free {
+            NIL => {
+                self.entries.push(Entry {
+                    deadline,
+                    slot: NIL,
+                    prev: NIL,
+                    next: NIL,
+                    expired: false,
+                    waker: None,
+                });
+                (self.entries.len() - 1) as u32
+            }
+            key => {
+                self.free = self.entries[key as usize].next;
+                key
+            }
+        };
+
+        self.link(key, deadline);
+
+        self.live += 1;
+        if let Some(driver) = self.driver.take() {
+            driver.wake();
+        }
+
+        key
+    }
+
+    fn remove(&mut self, key: u32) {
+        self.live -= 1;
+        self.unlink(key);
+        let entry = &mut self.entries[key as usize];
+        entry.waker = None;
+        entry.next = self.free;
+        self.free = key;
+    }
+
+    fn link(&mut self, key: u32, deadline: Instant) {
+        // deadline already passed expires right away.
+        if deadline <= self.now {
+            let entry = &mut self.entries[key as usize];
+            entry.deadline = deadline;
+            entry.expired = true;
+            return;
+        }
+
+        let tick = self.tick_of(deadline).max(self.tick + 1);
+        let slot = (tick % SLOTS as u64) as u32;
+        let head = self.slots[slot as usize];
+
+        let entry = &mut self.entries[key as usize];
+        entry.deadline = deadline;
+        entry.expired = false;
+        entry.slot = slot;
+        entry.prev = NIL;
+        entry.next = head;
+
+        if head != NIL {
+            self.entries[head as usize].prev = key;
+        }
+        self.slots[slot as usize] = key;
+    }
+
+    fn unlink(&mut self, key: u32) {
+        let entry = &mut self.entries[key as usize];
+        if entry.slot == NIL {
+            return;
+        }
+
+        let (slot, prev, next) = (entry.slot, entry.prev, entry.next);
+        entry.slot = NIL;
+
+        match prev {
+            NIL => self.slots[slot as usize] = next,
+            prev => self.entries[prev as usize].next = next,
+        }
+        if next != NIL {
+            self.entries[next as usize].prev = prev;
+        }
+    }
+
+    fn advance(&mut self, now: Instant) {
+        let target = now.saturating_duration_since(self.origin).as_nanos() / self.tick_dur.as_nanos();
+        let target = target as u64;
+
+        // one round visits every slot.
+        let end = target.min(self.tick + SLOTS as u64);
+        for tick in self.tick + 1..=end {
+            let mut key = self.slots[(tick % SLOTS as u64) as usize];
+            while key != NIL {
+                let entry = &self.entries[key as usize];
+                let next = entry.next;
+                if entry.deadline <= now {
+                    self.unlink(key);
+                    let entry = &mut self.entries[key as usize];
+                    entry.expired = true;
+                    // waking only schedules the task. it's not polled while wheel is borrowed.
+                    if let Some(waker) = entry.waker.take() {
+                        waker.wake();
+                    }
+                }
+                key = next;
+            }
+        }
+
+        self.tick = self.tick.max(target);
+        self.now = self.now.max(now);
+    }
+}
+
+/// Handle of one entry in [TimerWheel]. Entry is released when handle is dropped.
+pub(crate) struct Timer {
+    wheel: Rc<TimerWheel>,
+    key: u32,
+}
+
+impl Timer {
+    /// Wheel timer is registered in.
+    pub(crate) fn wheel(&self) -> &Rc<TimerWheel> {
+        &self.wheel
+    }
+
+    pub(crate) fn deadline(&self) -> Instant {
+        self.wheel.0.borrow().entries[self.key as usize].deadline
+    }
+
+    /// Move deadline of timer. Expired timer is registered again unless deadline is passed.
+    pub(crate) fn reset(&self, deadline: Instant) {
+        let mut wheel = self.wheel.0.borrow_mut();
+        wheel.unlink(self.key);
+        wheel.link(self.key, deadline);
+    }
+
+    /// Resolve once deadline is passed and stay ready until timer is reset.
+    pub(crate) fn poll_expired(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut wheel = self.wheel.0.borrow_mut();
+        let entry = &mut wheel.entries[self.key as usize];
+
+        if entry.expired {
+            return Poll::Ready(());
+        }
+
+        match entry.waker {
+            Some(ref waker) if waker.will_wake(cx.waker()) => {}
+            _ => entry.waker = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_expired(cx)
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.wheel.0.borrow_mut().remove(self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::mem::size_of;
+
+    use futures_task::noop_waker_ref;
+
+    use crate::util::date::ManualClock;
+
+    fn poll(timer: &Timer) -> Poll<()> {
+        timer.poll_expired(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    fn manual_wheel() -> (Rc<TimerWheel>, Instant) {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        (TimerWheel::new(Arc::new(clock), Duration::from_millis(500)), start)
+    }
+
+    #[test]
+    fn wheel() {
+        let (wheel, start) = manual_wheel();
+
+        let timer = wheel.timer(start + Duration::from_millis(800));
+        let timer2 = wheel.timer(start + Duration::from_secs(1));
+
+        wheel.advance(start + Duration::from_millis(600));
+        assert!(poll(&timer).is_pending());
+
+        // deadline is rounded up to tick.
+        wheel.advance(start + Duration::from_millis(1000));
+        assert!(poll(&timer).is_ready());
+        assert!(poll(&timer2).is_ready());
+
+        // more than one round of wheel.
+        timer.reset(start + Duration::from_secs(60));
+        wheel.advance(start + Duration::from_secs(40));
+        assert!(poll(&timer).is_pending());
+        wheel.advance(start + Duration::from_secs(59));
+        assert!(poll(&timer).is_pending());
+        wheel.advance(start + Duration::from_secs(60));
+        assert!(poll(&timer).is_ready());
+
+        // passed deadline fires right away.
+        timer.reset(start + Duration::from_millis(59_900));
+        assert!(poll(&timer).is_ready());
+        timer.reset(start + Duration::from_millis(60_100));
+        assert!(poll(&timer).is_pending());
+        wheel.advance(start + Duration::from_millis(60_500));
+        assert!(poll(&timer).is_ready());
+    }
+
+    #[test]
+    fn no_alloc() {
+        let (wheel, start) = manual_wheel();
+
+        // a connection timer is a pointer and a slot index plus one wheel entry reused by its
+        // deadlines.
+        assert_eq!(size_of::<Timer>(), size_of::<usize>() * 2);
+        assert!(size_of::<Entry>() <= 64);
+
+        let timers = (0..16)
+            .map(|i| wheel.timer(start + Duration::from_secs(i)))
+            .collect::<Vec<_>>();
+        let cap = wheel.0.borrow().entries.capacity();
+
+        // rescheduling and replacing timers reuse entries.
+        for i in 0..1024 {
+            timers[i % 16].reset(start + Duration::from_millis(i as u64 * 100));
+        }
+        drop(timers);
+        let timers = (0..16).map(|_| wheel.timer(start)).collect::<Vec<_>>();
+
+        assert_eq!(wheel.0.borrow().entries.capacity(), cap);
+        assert_eq!(wheel.0.borrow().entries.len(), timers.len());
+    }
+
+    extern crate test;
+
+    // cost of moving a deadline of live connection. run with `cargo bench timer`.
+    #[bench]
+    fn bench_reset(b: &mut test::Bencher) {
+        let (wheel, start) = manual_wheel();
+        let timers = (0..1024)
+            .map(|i| wheel.timer(start + Duration::from_secs(i)))
+            .collect::<Vec<_>>();
+
+        let mut i = 0;
+        b.iter(|| {
+            i += 1;
+            timers[i % timers.len()].reset(start + Duration::from_millis(i as u64));
+        });
+    }
+
+    // cost of connection accepted and closed with a timer.
+    #[bench]
+    fn bench_register(b: &mut test::Bencher) {
+        let (wheel, start) = manual_wheel();
+        let _timers = (0..1024)
+            .map(|i| wheel.timer(start + Duration::from_secs(i)))
+            .collect::<Vec<_>>();
+
+        b.iter(|| drop(wheel.timer(start + Duration::from_secs(30))));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    future::Future,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use tower_service::Service as TowerServiceTrait;
+
+use super::poll_fn::poll_fn;
+
+/// A factory of [TowerService] constructed from a [tower] `MakeService` with `()` as target.
+///
+/// A cloneable [tower] service can be turned into `MakeService` with [tower::make::Shared].
+/// The `MakeService` is cloned every time a new service is constructed. Its error is logged and
+/// service construction fails with `()` as error.
+///
+/// [tower]: https://docs.rs/tower
+/// [tower::make::Shared]: https://docs.rs/tower/0.4/tower/make/struct.Shared.html
+pub struct TowerFactory<M> {
+    make: M,
+}
+
+impl<M> TowerFactory<M> {
+    pub fn new(make: M) -> Self {
+        Self { make }
+    }
+}
+
+impl<M, S, Req> ServiceFactory<Req> for TowerFactory<M>
+where
+    M: TowerServiceTrait<(), Response = S> + Clone,
+    M::Error: fmt::Debug,
+    S: TowerServiceTrait<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Config = ();
+    type Service = TowerService<S>;
+    type InitError = ();
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        let mut make = self.make.clone();
+
+        async move {
+            poll_fn(|cx| make.poll_ready(cx)).await.map_err(|e| {
+                log::error!("Tower MakeService is not ready: {:?}", e);
+            })?;
+
+            make.call(()).await.map(TowerService::new).map_err(|e| {
+                log::error!("Tower MakeService failed to make service: {:?}", e);
+            })
+        }
+    }
+}
+
+/// Adapter for using a [tower](https://docs.rs/tower) service as [Service].
+///
+/// Readiness of tower service is always checked right before it's called. Readiness reserved by
+/// [Service::poll_ready] (e.g. permit of a concurrency limit) is consumed by the next call.
+pub struct TowerService<S> {
+    service: RefCell<S>,
+}
+
+impl<S> TowerService<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service: RefCell::new(service),
+        }
+    }
+}
+
+impl<S, Req> Service<Req> for TowerService<S>
+where
+    S: TowerServiceTrait<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&self, req: Req) -> Self::Future<'_> {
+        async move {
+            // borrow is released between polls as other calls could happen concurrently.
+            poll_fn(|cx| self.service.borrow_mut().poll_ready(cx)).await?;
+            let fut = self.service.borrow_mut().call(req);
+            fut.await
+        }
+    }
+}
+
+/// Adapter for using a [Service] as [tower](https://docs.rs/tower) service.
+///
+/// Service is shared by futures it returns so they do not borrow the adapter.
+pub struct TowerCompat<S> {
+    service: Rc<S>,
+}
+
+impl<S> TowerCompat<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Rc::new(service),
+        }
+    }
+}
+
+impl<S> Clone for TowerCompat<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+        }
+    }
+}
+
+impl<S, Req> TowerServiceTrait<Req> for TowerCompat<S>
+where
+    S: Service<Req> + 'static,
+    Req: 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let service = self.service.clone();
+        async move { service.call(req).await }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{
+        cell::Cell,
+        convert::Infallible,
+        future::{ready, Ready},
+    };
+
+    use actix_service_alt::fn_service;
+
+    // tower service only ready every other poll. panics when called without being ready.
+    #[derive(Clone, Default)]
+    struct Alternate {
+        ready: Rc<Cell<bool>>,
+        polled: Rc<Cell<usize>>,
+    }
+
+    impl TowerServiceTrait<u8> for Alternate {
+        type Response = u8;
+        type Error = Infallible;
+        type Future = Ready<Result<u8, Infallible>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.polled.set(self.polled.get() + 1);
+            if self.ready.get() || self.polled.get() & 1 == 0 {
+                self.ready.set(true);
+                Poll::Ready(Ok(()))
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+
+        fn call(&mut self, req: u8) -> Self::Future {
+            assert!(self.ready.replace(false), "called before ready");
+            ready(Ok(req + 1))
+        }
+    }
+
+    #[derive(Clone)]
+    struct Make(Alternate);
+
+    impl TowerServiceTrait<()> for Make {
+        type Response = Alternate;
+        type Error = Infallible;
+        type Future = Ready<Result<Alternate, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            ready(Ok(self.0.clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn tower_service() {
+        let tower = Alternate::default();
+        let factory = TowerFactory::new(Make(tower.clone()));
+        let service = factory.new_service(()).await.unwrap();
+
+        assert_eq!(service.call(1).await.unwrap(), 2);
+        assert_eq!(service.call(2).await.unwrap(), 3);
+
+        // readiness reserved by poll_ready is consumed by call.
+        poll_fn(|cx| Service::poll_ready(&service, cx)).await.unwrap();
+        let polled = tower.polled.get();
+        assert_eq!(service.call(3).await.unwrap(), 4);
+        assert_eq!(tower.polled.get(), polled + 1);
+    }
+
+    #[tokio::test]
+    async fn tower_compat() {
+        let mut service = TowerCompat::new(fn_service(|req: u8| async move { Ok::<_, Infallible>(req * 2) }));
+
+        poll_fn(|cx| TowerServiceTrait::poll_ready(&mut service, cx))
+            .await
+            .unwrap();
+        let fut = TowerServiceTrait::call(&mut service, 2);
+        // future does not borrow the adapter.
+        let mut clone = service.clone();
+        assert_eq!(TowerServiceTrait::call(&mut clone, 3).await.unwrap(), 6);
+        assert_eq!(fut.await.unwrap(), 4);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Request, StatusCode};
+use pin_project::pin_project;
+
+use crate::connection::ConnectionId;
+use crate::protocol::Protocol;
+
+#[cfg(feature = "tracing")]
+use tracing_crate as tracing;
+
+/// [tracing] span of a connection or a request. All methods are no op when `tracing` feature is
+/// disabled.
+///
+/// Connection span has fields `conn`, `peer` and `protocol`. Request span is a child of it with
+/// fields `method`, `path`, `version` and the `status`, `error` recorded when response is sent.
+/// `request_id` is recorded by [RequestIdService](super::RequestIdService).
+#[derive(Clone)]
+pub(crate) struct TraceSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+#[cfg(feature = "tracing")]
+impl TraceSpan {
+    pub(crate) fn connection(id: ConnectionId, peer: Option<SocketAddr>, protocol: Protocol) -> Self {
+        let span = tracing::info_span!(
+            "connection",
+            conn = %id,
+            peer = tracing::field::Empty,
+            protocol = ?protocol
+        );
+
+        if let Some(peer) = peer {
+            span.record("peer", tracing::field::display(peer));
+        }
+
+        Self { span }
+    }
+
+    pub(crate) fn request<B>(&self, req: &Request<B>) -> Self {
+        let span = tracing::info_span!(
+            parent: &self.span,
+            "request",
+            method = %req.method(),
+            path = req.uri().path(),
+            version = ?req.version(),
+            request_id = tracing::field::Empty,
+            status = tracing::field::Empty,
+            error = tracing::field::Empty
+        );
+
+        Self { span }
+    }
+
+    pub(crate) fn record_status(&self, status: StatusCode) {
+        self.span.record("status", status.as_u16());
+    }
+
+    pub(crate) fn record_error(&self, err: &dyn Display) {
+        self.span.record("error", tracing::field::display(err));
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl TraceSpan {
+    #[inline(always)]
+    pub(crate) fn connection(_: ConnectionId, _: Option<SocketAddr>, _: Protocol) -> Self {
+        Self {}
+    }
+
+    #[inline(always)]
+    pub(crate) fn request<B>(&self, _: &Request<B>) -> Self {
+        Self {}
+    }
+
+    #[inline(always)]
+    pub(crate) fn record_status(&self, _: StatusCode) {}
+
+    #[inline(always)]
+    pub(crate) fn record_error(&self, _: &dyn Display) {}
+}
+
+/// Record id of request on current request span.
+#[cfg(feature = "tracing")]
+pub(crate) fn record_request_id(id: &super::RequestId) {
+    tracing::Span::current().record("request_id", tracing::field::display(id));
+}
+
+impl TraceSpan {
+    /// Enter span every time given future is polled.
+    pub(crate) fn instrument<F: Future>(&self, fut: F) -> Instrumented<F> {
+        Instrumented {
+            fut,
+            span: self.clone(),
+        }
+    }
+}
+
+#[pin_project]
+pub(crate) struct Instrumented<F> {
+    #[pin]
+    fut: F,
+    span: TraceSpan,
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        #[cfg(feature = "tracing")]
+        let _enter = this.span.span.enter();
+        this.fut.poll(cx)
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use super::*;
+
+    use std::{
+        fmt::Debug,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    use crate::connection::ConnectionData;
+
+    // collect span names and field values in the order they are created or recorded.
+    #[derive(Default)]
+    struct Collector {
+        next_id: AtomicU64,
+        fields: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Visit for &Collector {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.fields
+                .lock()
+                .unwrap()
+                .push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Collector {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.fields.lock().unwrap().push(span.metadata().name().to_string());
+            span.record(&mut &*self);
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut &*self);
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn request_span() {
+        let collector = Collector::default();
+        let fields = collector.fields.clone();
+        let id = ConnectionData::new().id();
+
+        tracing::subscriber::with_default(collector, || {
+            let peer = "127.0.0.1:50000".parse().ok();
+            let conn = TraceSpan::connection(id, peer, Protocol::Http1);
+
+            let req = Request::builder().uri("/path?q=1").body(()).unwrap();
+            let span = conn.request(&req);
+            span.record_status(StatusCode::NOT_FOUND);
+            span.record_error(&"timeout");
+        });
+
+        let fields = fields.lock().unwrap();
+        assert_eq!(fields[0], "connection");
+        assert_eq!(fields[1], format!("conn={}", id));
+        assert_eq!(
+            fields[2..],
+            [
+                "protocol=Http1",
+                "peer=127.0.0.1:50000",
+                "request",
+                "method=GET",
+                "path=\"/path\"",
+                "version=HTTP/1.1",
+                "status=404",
+                "error=timeout"
+            ]
+        );
+    }
+}
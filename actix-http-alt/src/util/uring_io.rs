@@ -0,0 +1,283 @@
+use std::{
+    future::Future,
+    io, mem,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_server_alt::net::TcpStream;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_core::ready;
+use futures_task::noop_waker_ref;
+
+use crate::connection::{ConnectionAddrs, ConnectionData, OnConnect};
+use crate::h1::BufIo;
+
+const READ_BUF_SIZE: usize = 16 * 1024;
+
+type Op<T, B> = Pin<Box<dyn Future<Output = (io::Result<T>, B)>>>;
+
+enum ReadState {
+    // Empty buffer reused for next read.
+    Idle(BytesMut),
+    InFlight(Op<usize, BytesMut>),
+    // Finished read not taken by dispatcher yet.
+    Done(BytesMut),
+    Eof,
+    Error(io::Error),
+}
+
+enum WriteState {
+    Idle,
+    InFlight(Op<(), Bytes>),
+    // Bytes of finished write not counted by dispatcher yet.
+    Done(usize),
+    Error(io::Error),
+}
+
+/// Io-uring backed tcp stream for Http/1 dispatcher.
+///
+/// Io-uring operations own their buffers until completion so at most one read and one write is
+/// kept in flight:
+///
+/// - A read fills a buffer sized after dispatcher's spare read capacity. It's swapped with
+///   dispatcher's read buffer when that is empty and the empty one is reused for next read. It's
+///   only copied when dispatcher still holds unparsed bytes.
+/// - A write takes all bytes of dispatcher's write buffer without copying. Written bytes are
+///   reported after the write is finished.
+///
+/// Must be used on io-uring runtime. See [is_io_uring](actix_server_alt::net::is_io_uring).
+pub(crate) struct UringIo {
+    io: Rc<tokio_uring::net::TcpStream>,
+    addrs: Option<ConnectionAddrs>,
+    read: ReadState,
+    read_size: usize,
+    write: WriteState,
+}
+
+impl UringIo {
+    pub(crate) fn new(tcp: TcpStream) -> io::Result<Self> {
+        let addrs = match (tcp.peer_addr(), tcp.local_addr()) {
+            (Ok(peer), Ok(local)) => Some(ConnectionAddrs::Inet { peer, local }),
+            _ => None,
+        };
+
+        // deregister stream from tokio's poll.
+        let tcp = tcp.into_std()?;
+
+        Ok(Self {
+            io: Rc::new(tokio_uring::net::TcpStream::from_std(tcp)),
+            addrs,
+            read: ReadState::Idle(BytesMut::new()),
+            read_size: READ_BUF_SIZE,
+            write: WriteState::Idle,
+        })
+    }
+
+    fn start_read(&mut self) {
+        if let ReadState::Idle(ref mut buf) = self.read {
+            let mut buf = mem::take(buf);
+            // empty buffer is reclaimed by reserve.
+            buf.reserve(self.read_size);
+
+            let io = self.io.clone();
+            let mut op: Op<usize, BytesMut> = Box::pin(async move { io.read(buf).await });
+            // operation is pushed to submission queue on first poll.
+            let _ = op.as_mut().poll(&mut Context::from_waker(noop_waker_ref()));
+
+            self.read = ReadState::InFlight(op);
+        }
+    }
+}
+
+impl BufIo for UringIo {
+    fn try_read_buf(&mut self, dst: &mut BytesMut) -> io::Result<usize> {
+        match mem::replace(&mut self.read, ReadState::Eof) {
+            ReadState::Done(mut buf) => {
+                let n = buf.len();
+                if dst.is_empty() {
+                    mem::swap(dst, &mut buf);
+                } else {
+                    dst.extend_from_slice(&buf);
+                }
+                buf.clear();
+                self.read = ReadState::Idle(buf);
+                Ok(n)
+            }
+            ReadState::Eof => Ok(0),
+            ReadState::Error(e) => Err(e),
+            state => {
+                self.read = state;
+                let spare = dst.capacity() - dst.len();
+                if spare > 0 {
+                    self.read_size = spare;
+                }
+                self.start_read();
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        }
+    }
+
+    fn try_write_buf<B: Buf>(&mut self, buf: &mut B) -> io::Result<usize> {
+        // collect finished operation without registering waker.
+        let _ = self.poll_write_ready(&mut Context::from_waker(noop_waker_ref()))?;
+
+        match mem::replace(&mut self.write, WriteState::Idle) {
+            WriteState::Done(n) => Ok(n),
+            WriteState::Error(e) => {
+                let err = io::Error::new(e.kind(), e.to_string());
+                self.write = WriteState::Error(e);
+                Err(err)
+            }
+            WriteState::InFlight(op) => {
+                self.write = WriteState::InFlight(op);
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+            WriteState::Idle if !buf.has_remaining() => Ok(0),
+            WriteState::Idle => {
+                // zero copy for dispatcher's flat write buffer.
+                let bytes = buf.copy_to_bytes(buf.remaining());
+
+                let io = self.io.clone();
+                let mut op: Op<(), Bytes> = Box::pin(async move { io.write_all(bytes).await });
+                let _ = op.as_mut().poll(&mut Context::from_waker(noop_waker_ref()));
+
+                self.write = WriteState::InFlight(op);
+
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        }
+    }
+
+    fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.start_read();
+
+        if let ReadState::InFlight(ref mut op) = self.read {
+            let (res, buf) = ready!(op.as_mut().poll(cx));
+            self.read = match res {
+                Ok(0) => ReadState::Eof,
+                Ok(_) => ReadState::Done(buf),
+                Err(e) => ReadState::Error(e),
+            };
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let WriteState::InFlight(ref mut op) = self.write {
+            let (res, bytes) = ready!(op.as_mut().poll(cx));
+            self.write = match res {
+                Ok(_) => WriteState::Done(bytes.len()),
+                Err(e) => WriteState::Error(e),
+            };
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_ready(cx))?;
+        match self.write {
+            WriteState::Error(ref e) => Poll::Ready(Err(io::Error::new(e.kind(), e.to_string()))),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    // dispatcher keeps a flat write buffer which is taken by write without copying.
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        false
+    }
+}
+
+impl OnConnect for UringIo {
+    #[inline]
+    fn on_connect(&self, data: &mut ConnectionData) {
+        if let Some(addrs) = self.addrs {
+            data.set_addrs(addrs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    use crate::util::poll_fn::poll_fn;
+
+    #[test]
+    fn read_write() {
+        // io-uring is not supported by kernel.
+        if tokio_uring::Runtime::new(&tokio_uring::builder()).is_err() {
+            return;
+        }
+
+        tokio_uring::start(async {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let client = tokio_uring::net::TcpStream::connect(addr).await.unwrap();
+            let (io, _) = listener.accept().unwrap();
+            io.set_nonblocking(true).unwrap();
+
+            let mut io = UringIo::new(TcpStream::from_std(io).unwrap()).unwrap();
+
+            let (res, _) = client.write_all(&b"hello"[..]).await;
+            res.unwrap();
+
+            let mut buf = BytesMut::with_capacity(64);
+            while buf.len() < 5 {
+                poll_fn(|cx| io.poll_read_ready(cx)).await.unwrap();
+                match io.try_read_buf(&mut buf) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    res => assert!(res.unwrap() > 0),
+                }
+            }
+            assert_eq!(&buf[..], b"hello");
+
+            // write is taken without being reported until it's finished.
+            let mut buf = BytesMut::from(&b"world"[..]);
+            assert_eq!(
+                io.try_write_buf(&mut buf).unwrap_err().kind(),
+                io::ErrorKind::WouldBlock
+            );
+            assert!(buf.is_empty());
+
+            let mut written = 0;
+            loop {
+                poll_fn(|cx| io.poll_write_ready(cx)).await.unwrap();
+                match io.try_write_buf(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => written += n,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => panic!("{}", e),
+                }
+            }
+            assert_eq!(written, 5);
+            poll_fn(|cx| io.poll_flush(cx)).await.unwrap();
+
+            let mut buf = Vec::new();
+            while buf.len() < 5 {
+                let (res, b) = client.read(vec![0; 16]).await;
+                buf.extend_from_slice(&b[..res.unwrap()]);
+            }
+            assert_eq!(&buf[..], b"world");
+
+            drop(client);
+            let mut buf = BytesMut::new();
+            loop {
+                poll_fn(|cx| io.poll_read_ready(cx)).await.unwrap();
+                match io.try_read_buf(&mut buf) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    res => {
+                        assert_eq!(res.unwrap(), 0);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
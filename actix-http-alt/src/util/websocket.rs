@@ -0,0 +1,251 @@
+use std::{
+    convert::Infallible,
+    fmt,
+    future::{ready, Future, Ready},
+    task::{Context, Poll},
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use futures_core::Stream;
+use http::{response::Builder, Request, Response};
+use http_ws::{build_response, verify_request, Codec, DecodeStream, EncodeStream, HandshakeError, Message};
+use tokio::sync::mpsc::Sender;
+
+use crate::body::ResponseBody;
+
+use super::poll_fn::poll_fn;
+
+/// Websocket session made from a request by [ws].
+pub type WsSession<B> = (Response<ResponseBody<EncodeStream>>, DecodeStream<B>, Sender<Message>);
+
+/// Do websocket handshake with given request and construct a session with [Codec::new].
+///
+/// On success the `101 Switching Protocols` response streaming [EncodeStream] should be returned
+/// to client while [DecodeStream] and the sender of [EncodeStream] are driven by application.
+pub fn ws<B, T, E>(req: Request<B>) -> Result<WsSession<B>, HandshakeError>
+where
+    B: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    ws_with_codec(req, Codec::new())
+}
+
+/// [ws] with given codec shared by decode and encode stream.
+pub fn ws_with_codec<B, T, E>(req: Request<B>, codec: Codec) -> Result<WsSession<B>, HandshakeError>
+where
+    B: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    let builder = build_response(verify_request(&req)?);
+
+    let decode = DecodeStream::with_codec(req.into_body(), codec);
+    let (tx, encode) = decode.encode_stream();
+
+    let res = builder.body(ResponseBody::stream(encode)).unwrap();
+
+    Ok((res, decode, tx))
+}
+
+/// Construct a [WebSocket] serving websocket connections with given handler.
+///
+/// Handler is called with every message decoded from peer and a sender for replying to it.
+/// Messages of one connection are handled one by one in order.
+pub fn websocket<F, Fut>(handler: F) -> WebSocket<F>
+where
+    F: Fn(Message, Sender<Message>) -> Fut + Clone + 'static,
+    Fut: Future<Output = ()>,
+{
+    WebSocket {
+        handler,
+        codec: Codec::new(),
+    }
+}
+
+/// Factory and service of [websocket].
+///
+/// Every request goes through websocket handshake. On success a `101 Switching Protocols`
+/// response streaming [EncodeStream] is returned and request body is decoded in a task spawned
+/// with [tokio::task::spawn_local]. Failed handshake is responded with the status of
+/// [HandshakeError](http_ws::HandshakeError).
+#[derive(Clone)]
+pub struct WebSocket<F> {
+    handler: F,
+    codec: Codec,
+}
+
+impl<F> WebSocket<F> {
+    /// Change codec used by connections. Default to [Codec::new].
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+}
+
+impl<F, Fut, ReqB, T, E> ServiceFactory<Request<ReqB>> for WebSocket<F>
+where
+    F: Fn(Message, Sender<Message>) -> Fut + Clone + 'static,
+    Fut: Future<Output = ()>,
+    ReqB: Stream<Item = Result<T, E>> + 'static,
+    T: AsRef<[u8]>,
+    E: fmt::Display,
+{
+    type Response = Response<ResponseBody<EncodeStream>>;
+    type Error = Infallible;
+    type Config = ();
+    type Service = Self;
+    type InitError = ();
+    type Future = Ready<Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        ready(Ok(self.clone()))
+    }
+}
+
+impl<F, Fut, ReqB, T, E> Service<Request<ReqB>> for WebSocket<F>
+where
+    F: Fn(Message, Sender<Message>) -> Fut + Clone + 'static,
+    Fut: Future<Output = ()>,
+    ReqB: Stream<Item = Result<T, E>> + 'static,
+    T: AsRef<[u8]>,
+    E: fmt::Display,
+{
+    type Response = Response<ResponseBody<EncodeStream>>;
+    type Error = Infallible;
+    type Future<'f> = Ready<Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, req: Request<ReqB>) -> Self::Future<'_> {
+        let (res, decode, tx) = match ws_with_codec(req, self.codec.clone()) {
+            Ok(session) => session,
+            Err(e) => {
+                let res = Builder::from(e).body(ResponseBody::None).unwrap();
+                return ready(Ok(res));
+            }
+        };
+
+        let mut decode = Box::pin(decode);
+        let handler = self.handler.clone();
+
+        tokio::task::spawn_local(async move {
+            while let Some(res) = poll_fn(|cx| decode.as_mut().poll_next(cx)).await {
+                match res {
+                    Ok(msg) => handler(msg, tx.clone()).await,
+                    Err(e) => {
+                        log::debug!("WebSocket connection error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        ready(Ok(res))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{collections::VecDeque, io, pin::Pin};
+
+    use bytes::{Bytes, BytesMut};
+    use http::{header, StatusCode};
+    use http_ws::{ByteString, ClientHandshake};
+    use tokio::task::LocalSet;
+
+    // request body yielding given frames and stay pending afterwards.
+    struct Input(VecDeque<Bytes>);
+
+    impl Stream for Input {
+        type Item = Result<Bytes, io::Error>;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            match self.get_mut().0.pop_front() {
+                Some(frame) => Poll::Ready(Some(Ok(frame))),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn echo() {
+        LocalSet::new()
+            .run_until(async {
+                let factory = websocket(|msg, tx: Sender<Message>| async move {
+                    if let Message::Text(text) = msg {
+                        tx.send(Message::Text(text)).await.unwrap();
+                    }
+                });
+                let service = ServiceFactory::<Request<Input>>::new_service(&factory, ())
+                    .await
+                    .unwrap();
+
+                let client = Codec::client();
+                let mut frame = BytesMut::new();
+                client
+                    .encode(Message::Text(ByteString::from_static("996")), &mut frame)
+                    .unwrap();
+
+                let handshake = ClientHandshake::new();
+                let req = handshake
+                    .request("ws://localhost/")
+                    .body(Input(vec![frame.freeze()].into()))
+                    .unwrap();
+
+                let res = service.call(req).await.unwrap();
+                assert!(handshake.verify(res.status(), res.headers()).is_ok());
+
+                let mut body = Box::pin(res.into_body());
+                let mut buf = BytesMut::from(&body.as_mut().next().await.unwrap().unwrap()[..]);
+                assert_eq!(
+                    client.decode(&mut buf).unwrap().unwrap(),
+                    Message::Text(ByteString::from_static("996"))
+                );
+
+                let req = Request::get("/")
+                    .header(header::UPGRADE, "websocket")
+                    .body(Input(VecDeque::new()))
+                    .unwrap();
+                let res = service.call(req).await.unwrap();
+                assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn session() {
+        let client = Codec::client();
+        let mut frame = BytesMut::new();
+        client
+            .encode(Message::Ping(Bytes::from_static(b"996")), &mut frame)
+            .unwrap();
+
+        let handshake = ClientHandshake::new();
+        let req = handshake
+            .request("ws://localhost/")
+            .body(Input(vec![frame.freeze()].into()))
+            .unwrap();
+
+        let (res, mut decode, _tx) = ws_with_codec(req, Codec::new().auto_pong(true)).unwrap();
+        assert!(handshake.verify(res.status(), res.headers()).is_ok());
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Ping(Bytes::from_static(b"996"))
+        );
+
+        // ping is answered by encode stream sharing codec with decode stream.
+        let mut body = Box::pin(res.into_body());
+        let mut buf = BytesMut::from(&body.as_mut().next().await.unwrap().unwrap()[..]);
+        assert_eq!(
+            client.decode(&mut buf).unwrap().unwrap(),
+            Message::Pong(Bytes::from_static(b"996"))
+        );
+
+        let req = Request::post("/").body(Input(VecDeque::new())).unwrap();
+        assert!(matches!(ws(req), Err(HandshakeError::GetMethodRequired)));
+    }
+}
@@ -0,0 +1,271 @@
+//! Name based virtual hosting on one tls listener.
+//!
+//! Server name indication from ClientHello selects both the rustls server config and the http
+//! service of a connection. The selection is made once per connection and recorded as
+//! [VirtualHost] in the extensions of every request from that connection.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use actix_service_alt::{Service, ServiceFactory};
+use http::Request;
+
+use super::connection::VirtualHost;
+use super::tls::rustls::RustlsConfig;
+use super::util::poll_fn::poll_fn;
+
+/// A collection of virtual hosts. Each host has its own rustls server config and service factory.
+///
+/// Hosts are matched in the order they are added. A pattern is either an exact host name or
+/// a wildcard in the form of `*.example.com` which matches exactly one leading label.
+pub struct VirtualHosts<F> {
+    hosts: Vec<(HostPattern, RustlsConfig, F)>,
+    default: (RustlsConfig, F),
+    reject_unknown: bool,
+}
+
+impl<F> VirtualHosts<F> {
+    /// Construct with a default host. It serves connections with unknown or no server name.
+    pub fn new(config: RustlsConfig, factory: F) -> Self {
+        Self {
+            hosts: Vec::new(),
+            default: (config, factory),
+            reject_unknown: false,
+        }
+    }
+
+    /// Add a host with given pattern.
+    pub fn host(mut self, pattern: &str, config: RustlsConfig, factory: F) -> Self {
+        self.hosts.push((HostPattern::new(pattern), config, factory));
+        self
+    }
+
+    /// Reject tls handshake with unknown or no server name instead of falling back to the
+    /// default host.
+    ///
+    /// Default service still serves connections not going through tls acceptor. (e.g. plaintext
+    /// connections served by [Plaintext::Serve](crate::tls::Plaintext::Serve))
+    pub fn reject_unknown(mut self) -> Self {
+        self.reject_unknown = true;
+        self
+    }
+
+    pub(crate) fn default_config(&self) -> RustlsConfig {
+        self.default.0.clone()
+    }
+
+    pub(crate) fn router(&self) -> HostRouter {
+        HostRouter {
+            hosts: self
+                .hosts
+                .iter()
+                .map(|(pattern, config, _)| (pattern.clone(), config.clone()))
+                .collect(),
+            default: (!self.reject_unknown).then(|| self.default.0.clone()),
+        }
+    }
+}
+
+/// Tls half of [VirtualHosts]. Used by rustls acceptor to select server config.
+pub(crate) struct HostRouter {
+    hosts: Vec<(HostPattern, RustlsConfig)>,
+    default: Option<RustlsConfig>,
+}
+
+impl HostRouter {
+    /// Find virtual host and its server config for given server name.
+    ///
+    /// Return `None` when connection should be rejected.
+    pub(crate) fn route(&self, server_name: Option<&str>) -> Option<(VirtualHost, RustlsConfig)> {
+        let found = server_name.and_then(|name| {
+            self.hosts
+                .iter()
+                .enumerate()
+                .find(|(_, (pattern, _))| pattern.matches(name))
+        });
+
+        match found {
+            Some((idx, (pattern, config))) => Some((VirtualHost::new(Some(pattern.0.clone()), idx), config.clone())),
+            None => {
+                let config = self.default.as_ref()?;
+                Some((VirtualHost::new(None, self.hosts.len()), config.clone()))
+            }
+        }
+    }
+
+    pub(crate) fn configs_mut(&mut self) -> impl Iterator<Item = &mut RustlsConfig> {
+        self.hosts
+            .iter_mut()
+            .map(|(_, config)| config)
+            .chain(self.default.as_mut())
+    }
+}
+
+#[derive(Clone)]
+struct HostPattern(Arc<str>);
+
+impl HostPattern {
+    fn new(pattern: &str) -> Self {
+        Self(pattern.to_ascii_lowercase().into())
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self.0.strip_prefix('*') {
+            Some(suffix) => {
+                let mid = match name.len().checked_sub(suffix.len()) {
+                    Some(mid) if mid > 0 && name.is_char_boundary(mid) => mid,
+                    _ => return false,
+                };
+
+                let (label, rest) = name.split_at(mid);
+                !label.contains('.') && rest.eq_ignore_ascii_case(suffix)
+            }
+            None => name.eq_ignore_ascii_case(&self.0),
+        }
+    }
+}
+
+impl<F, ReqB> ServiceFactory<Request<ReqB>> for VirtualHosts<F>
+where
+    F: ServiceFactory<Request<ReqB>>,
+    F::Service: 'static,
+    F::Config: Clone,
+    ReqB: 'static,
+{
+    type Response = F::Response;
+    type Error = F::Error;
+    type Config = F::Config;
+    type Service = VirtualHostsService<F::Service>;
+    type InitError = F::InitError;
+    type Future = impl Future<Output = Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, cfg: Self::Config) -> Self::Future {
+        // default host is always the last one so its index equals to the count of hosts.
+        let futs = self
+            .hosts
+            .iter()
+            .map(|(_, _, factory)| factory)
+            .chain(Some(&self.default.1))
+            .map(|factory| factory.new_service(cfg.clone()))
+            .collect::<Vec<_>>();
+
+        async move {
+            let mut services = Vec::with_capacity(futs.len());
+            for fut in futs {
+                services.push(fut.await?);
+            }
+
+            Ok(VirtualHostsService { services })
+        }
+    }
+}
+
+/// Service dispatching requests to the service of their [VirtualHost].
+///
+/// The service is always ready. Readiness of a host service is polled when a request is routed
+/// to it so a saturated host does not hold back requests of other hosts.
+pub struct VirtualHostsService<S> {
+    services: Vec<S>,
+}
+
+impl<S, ReqB> Service<Request<ReqB>> for VirtualHostsService<S>
+where
+    S: Service<Request<ReqB>> + 'static,
+    ReqB: 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future<'f> = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, req: Request<ReqB>) -> Self::Future<'_> {
+        let idx = req
+            .extensions()
+            .get::<VirtualHost>()
+            .map(|vhost| vhost.index())
+            .unwrap_or(self.services.len() - 1);
+
+        async move {
+            let service = &self.services[idx];
+            poll_fn(|cx| service.poll_ready(cx)).await?;
+            service.call(req).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{convert::Infallible, future::Ready};
+
+    struct Host {
+        ready: bool,
+    }
+
+    impl Service<Request<()>> for Host {
+        type Response = bool;
+        type Error = Infallible;
+        type Future<'f> = Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            if self.ready {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        }
+
+        fn call(&self, _: Request<()>) -> Self::Future<'_> {
+            std::future::ready(Ok(self.ready))
+        }
+    }
+
+    #[test]
+    fn ready_per_host() {
+        let service = VirtualHostsService {
+            services: vec![Host { ready: false }, Host { ready: true }],
+        };
+
+        let mut cx = Context::from_waker(futures_task::noop_waker_ref());
+        assert!(Service::<Request<()>>::poll_ready(&service, &mut cx).is_ready());
+
+        let request = |idx| {
+            let mut req = Request::new(());
+            req.extensions_mut().insert(VirtualHost::new(None, idx));
+            req
+        };
+
+        // saturated host only holds back requests routed to it.
+        let fut = service.call(request(0));
+        tokio::pin!(fut);
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+
+        let fut = service.call(request(1));
+        tokio::pin!(fut);
+        assert!(matches!(fut.as_mut().poll(&mut cx), Poll::Ready(Ok(true))));
+    }
+
+    #[test]
+    fn pattern() {
+        let exact = HostPattern::new("Example.com");
+        assert!(exact.matches("example.com"));
+        assert!(exact.matches("EXAMPLE.COM"));
+        assert!(!exact.matches("www.example.com"));
+
+        let wildcard = HostPattern::new("*.example.com");
+        assert!(wildcard.matches("www.example.com"));
+        assert!(wildcard.matches("API.Example.com"));
+        assert!(!wildcard.matches("example.com"));
+        assert!(!wildcard.matches(".example.com"));
+        assert!(!wildcard.matches("a.b.example.com"));
+        assert!(!wildcard.matches("wwwexample.com"));
+    }
+}
@@ -13,6 +13,10 @@ pub struct Builder {
     pub(crate) enable_signal: bool,
     pub(crate) shutdown_timeout: Duration,
     tcp_backlog: u32,
+    #[cfg(feature = "io-uring")]
+    pub(crate) io_uring: bool,
+    #[cfg(feature = "http3")]
+    h3_keylog: bool,
 }
 
 impl Default for Builder {
@@ -34,6 +38,10 @@ impl Builder {
             enable_signal: true,
             shutdown_timeout: Duration::from_secs(30),
             tcp_backlog: 2048,
+            #[cfg(feature = "io-uring")]
+            io_uring: false,
+            #[cfg(feature = "http3")]
+            h3_keylog: false,
         }
     }
 
@@ -102,6 +110,19 @@ impl Builder {
         self
     }
 
+    /// Run worker threads on io-uring runtime.
+    ///
+    /// Worker threads fall back to epoll based runtime when io-uring is not supported by the
+    /// kernel. See [is_io_uring](crate::net::is_io_uring). Off by default.
+    ///
+    /// Io-uring runtime builds its blocking task thread pool with tokio's default size and does
+    /// not honor [worker_max_blocking_threads](Self::worker_max_blocking_threads).
+    #[cfg(feature = "io-uring")]
+    pub fn io_uring(mut self) -> Self {
+        self.io_uring = true;
+        self
+    }
+
     /// Disable signal listening.
     ///
     /// `tokio::signal` is used for listening and it only functional in tokio runtime 1.x.
@@ -232,6 +253,15 @@ impl Builder {
 
 #[cfg(feature = "http3")]
 impl Builder {
+    /// Write tls session secrets of Udp listeners to file from `SSLKEYLOGFILE` environment
+    /// variable for debugging encrypted traffic.
+    ///
+    /// Only apply to listeners bound after this call. Off by default.
+    pub fn h3_keylog(mut self) -> Self {
+        self.h3_keylog = true;
+        self
+    }
+
     /// Bind to both Tcp and Udp of the same address to enable http/1/2/3 handling
     /// with single service.
     pub fn bind_all<N, A, F>(
@@ -266,7 +296,10 @@ impl Builder {
             .or_insert_with(Vec::new)
             .push(Box::new(Some(listener)));
 
-        let builder = crate::net::UdpListenerBuilder::new(addr, config);
+        let mut builder = crate::net::UdpListenerBuilder::new(addr, config);
+        if self.h3_keylog {
+            builder = builder.keylog();
+        }
 
         self.listeners
             .entry(name.as_ref().to_string())
@@ -297,7 +330,10 @@ impl Builder {
             .next()
             .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "Can not parse SocketAddr"))?;
 
-        let builder = crate::net::UdpListenerBuilder::new(addr, config);
+        let mut builder = crate::net::UdpListenerBuilder::new(addr, config);
+        if self.h3_keylog {
+            builder = builder.keylog();
+        }
 
         self.listeners
             .entry(name.as_ref().to_string())
@@ -8,10 +8,10 @@ use std::{
 
 use async_channel::{Receiver, Recv};
 use futures_core::ready;
-use log::info;
+use log::{info, warn};
 use quinn::{
     crypto::{rustls::TlsSession, Session},
-    generic::{Connecting, Endpoint, Incoming, ServerConfig},
+    generic::{Connecting, Endpoint, Incoming, ServerConfig, ServerConfigBuilder},
     EndpointError,
 };
 
@@ -25,6 +25,7 @@ pub type H3ServerConfig = ServerConfig<TlsSession>;
 #[derive(Debug)]
 pub struct UdpListener<S: Session = TlsSession> {
     endpoint: Endpoint<S>,
+    local_addr: SocketAddr,
     /// `async-channel` is used to receive Connecting from [`Incoming`](quinn::generic::Incoming).
     incoming: Receiver<Connecting<S>>,
 }
@@ -34,20 +35,31 @@ impl<S: Session> UdpListener<S> {
     pub fn accept(&self) -> Accept<'_, S> {
         Accept {
             recv: self.incoming.recv(),
+            local_addr: self.local_addr,
         }
     }
+
+    /// Get local `SocketAddr` the endpoint is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
 }
 
 pub struct Accept<'a, S: Session> {
     recv: Recv<'a, Connecting<S>>,
+    local_addr: SocketAddr,
 }
 
 impl<S: Session> Future for Accept<'_, S> {
     type Output = io::Result<UdpStream<S>>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match ready!(Pin::new(&mut self.get_mut().recv).poll(cx)) {
-            Ok(connecting) => Poll::Ready(Ok(UdpStream { connecting })),
+        let this = self.get_mut();
+        match ready!(Pin::new(&mut this.recv).poll(cx)) {
+            Ok(connecting) => Poll::Ready(Ok(UdpStream {
+                connecting,
+                local_addr: this.local_addr,
+            })),
             Err(_) => Poll::Ready(Err(io::Error::new(
                 io::ErrorKind::BrokenPipe,
                 "quinn endpoint is closed",
@@ -91,6 +103,8 @@ where
             EndpointError::Socket(e) => e,
         })?;
 
+        let local_addr = endpoint.local_addr()?;
+
         // Use async channel to dispatch Connecting<Session> to worker threads.
         // Incoming can only be held by single task and sharing it between
         // threads would cause hanging.
@@ -104,7 +118,31 @@ where
             }
         });
 
-        Ok(UdpListener { endpoint, incoming: rx })
+        Ok(UdpListener {
+            endpoint,
+            local_addr,
+            incoming: rx,
+        })
+    }
+}
+
+impl UdpListenerBuilder<TlsSession> {
+    /// Write tls session secrets to file from `SSLKEYLOGFILE` environment variable.
+    ///
+    /// Key logging stays off when the variable is not set.
+    pub fn keylog(mut self) -> Self {
+        if let Some(path) = std::env::var_os("SSLKEYLOGFILE") {
+            warn!(
+                "TLS KEY LOGGING IS ENABLED for Udp listener on {}. Session secrets are written to {:?}.",
+                self.addr, path
+            );
+
+            let mut builder = ServerConfigBuilder::new(self.config);
+            builder.enable_keylog();
+            self.config = builder.build();
+        }
+
+        self
     }
 }
 
@@ -137,6 +175,7 @@ impl<S: Session> Future for Next<'_, S> {
 /// Naming is to keep consistent with `TcpStream` / `UnixStream`.
 pub struct UdpStream<S: Session = TlsSession> {
     connecting: Connecting<S>,
+    local_addr: SocketAddr,
 }
 
 impl<S: Session> UdpStream<S> {
@@ -159,6 +198,14 @@ impl<S: Session> UdpStream<S> {
     pub fn peer_addr(&self) -> SocketAddr {
         self.connecting.remote_address()
     }
+
+    /// Get local `SocketAddr` of the endpoint accepted self.
+    ///
+    /// Address is the one endpoint bound to and would be unspecified ip when listening on
+    /// wildcard address.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
 }
 
 impl FromStream for UdpStream {
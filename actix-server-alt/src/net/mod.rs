@@ -1,11 +1,17 @@
 #[cfg(feature = "http3")]
 mod h3;
 mod io;
+#[cfg(feature = "io-uring")]
+mod uring;
 
 #[cfg(feature = "http3")]
 pub use self::h3::{H3ServerConfig, UdpConnecting, UdpListener, UdpListenerBuilder, UdpStream};
 
 pub use self::io::AsyncReadWrite;
+#[cfg(feature = "io-uring")]
+pub use self::uring::is_io_uring;
+#[cfg(feature = "io-uring")]
+pub(crate) use self::uring::set_io_uring;
 pub use tokio::net::{TcpListener, TcpSocket, TcpStream};
 
 #[cfg(unix)]
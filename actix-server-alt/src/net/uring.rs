@@ -0,0 +1,17 @@
+use std::cell::Cell;
+
+thread_local! {
+    static IO_URING: Cell<bool> = Cell::new(false);
+}
+
+/// Check if current worker thread runs on io-uring runtime.
+///
+/// Worker threads fall back to epoll based runtime when io-uring is not supported by the kernel.
+/// Io-uring operations can only be used when this returns true.
+pub fn is_io_uring() -> bool {
+    IO_URING.with(|uring| uring.get())
+}
+
+pub(crate) fn set_io_uring() {
+    IO_URING.with(|uring| uring.set(true));
+}
@@ -23,10 +23,10 @@ use tokio::{
         mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
-    task::LocalSet,
 };
 
 use crate::builder::Builder;
+use crate::worker::WorkerRuntime;
 
 pub struct Server {
     is_graceful_shutdown: Arc<AtomicBool>,
@@ -47,9 +47,14 @@ impl Server {
             listeners,
             factories,
             shutdown_timeout,
+            #[cfg(feature = "io-uring")]
+            io_uring,
             ..
         } = builder;
 
+        #[cfg(not(feature = "io-uring"))]
+        let io_uring = false;
+
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
 
         let server_handle = thread::spawn(move || {
@@ -114,14 +119,9 @@ impl Server {
                 let handle = thread::Builder::new()
                     .name(format!("actix-server-worker-{}", idx))
                     .spawn(move || {
-                        let rt = runtime::Builder::new_current_thread()
-                            .enable_all()
-                            .max_blocking_threads(worker_max_blocking_threads)
-                            .build()
-                            .unwrap();
-                        let local = LocalSet::new();
-
-                        let services = rt.block_on(local.run_until(async {
+                        let rt = WorkerRuntime::new(worker_max_blocking_threads, io_uring);
+
+                        let services = rt.block_on(async {
                             let mut services = Vec::new();
 
                             for (name, factory) in factories {
@@ -130,13 +130,13 @@ impl Server {
                             }
 
                             Ok::<_, ()>(services)
-                        }));
+                        });
 
                         match services {
                             Ok(services) => {
                                 tx.send(Ok(())).unwrap();
 
-                                rt.block_on(local.run_until(async {
+                                rt.block_on(async {
                                     crate::worker::run(
                                         listeners,
                                         services,
@@ -145,7 +145,7 @@ impl Server {
                                         is_graceful_shutdown,
                                     )
                                     .await;
-                                }))
+                                })
                             }
                             Err(_) => {
                                 tx.send(Err(io::Error::new(
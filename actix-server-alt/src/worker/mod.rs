@@ -1,7 +1,9 @@
 mod limit;
+mod runtime;
 mod service;
 mod shutdown;
 
+pub(crate) use self::runtime::WorkerRuntime;
 pub(crate) use self::service::{RcWorkerService, WorkerService};
 
 use std::{
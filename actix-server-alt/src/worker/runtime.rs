@@ -0,0 +1,57 @@
+use std::future::Future;
+
+use tokio::{runtime, task::LocalSet};
+
+// tokio's default max blocking threads.
+#[cfg(feature = "io-uring")]
+const DEFAULT_MAX_BLOCKING_THREADS: usize = 512;
+
+/// Single threaded runtime of worker thread.
+pub(crate) enum WorkerRuntime {
+    Tokio(runtime::Runtime, LocalSet),
+    #[cfg(feature = "io-uring")]
+    Uring(tokio_uring::Runtime),
+}
+
+impl WorkerRuntime {
+    /// Construct io-uring runtime when it's enabled and kernel supports it.
+    /// Otherwise fall back to tokio's epoll based runtime.
+    pub(crate) fn new(max_blocking_threads: usize, io_uring: bool) -> Self {
+        #[cfg(feature = "io-uring")]
+        if io_uring {
+            match tokio_uring::Runtime::new(&tokio_uring::builder()) {
+                Ok(rt) => {
+                    // tokio-uring builds its inner tokio runtime without exposing its builder.
+                    if max_blocking_threads != DEFAULT_MAX_BLOCKING_THREADS {
+                        log::warn!(
+                            "io-uring runtime does not support max blocking threads. {} is ignored",
+                            max_blocking_threads
+                        );
+                    }
+                    crate::net::set_io_uring();
+                    return Self::Uring(rt);
+                }
+                Err(e) => log::warn!("io-uring is not supported: {}. Fall back to epoll runtime", e),
+            }
+        }
+
+        #[cfg(not(feature = "io-uring"))]
+        let _ = io_uring;
+
+        let rt = runtime::Builder::new_current_thread()
+            .enable_all()
+            .max_blocking_threads(max_blocking_threads)
+            .build()
+            .unwrap();
+
+        Self::Tokio(rt, LocalSet::new())
+    }
+
+    pub(crate) fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        match *self {
+            Self::Tokio(ref rt, ref local) => rt.block_on(local.run_until(fut)),
+            #[cfg(feature = "io-uring")]
+            Self::Uring(ref rt) => rt.block_on(fut),
+        }
+    }
+}
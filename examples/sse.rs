@@ -0,0 +1,49 @@
+//! A Http server streams server sent events produced by a separate task.
+
+use std::time::Duration;
+
+use actix_http_alt::{
+    http::{header, Request, Response},
+    util::body::{channel, ChannelBody},
+    RequestBody, ResponseBody,
+};
+use actix_service_alt::fn_service;
+use actix_web_alt::HttpServer;
+use bytes::Bytes;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::io::Result<()> {
+    std::env::set_var("RUST_LOG", "actix=trace, info");
+    env_logger::init();
+
+    HttpServer::new(|| fn_service(handler))
+        .bind("127.0.0.1:8080")?
+        .run()
+        .await
+}
+
+async fn handler(_: Request<RequestBody>) -> Result<Response<ResponseBody<ChannelBody>>, Box<dyn std::error::Error>> {
+    // at most 4 events are queued when client reads slowly.
+    let (tx, body) = channel(4);
+
+    tokio::task::spawn_local(async move {
+        for i in 0..10 {
+            let event = format!("id: {}\ndata: tick {}\n\n", i, i);
+            // client is gone.
+            if tx.send(Bytes::from(event)).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        // end response cleanly. dropping tx without close would cut the response short.
+        tx.close();
+    });
+
+    let res = Response::builder()
+        .status(200)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(body)?;
+    Ok(res)
+}
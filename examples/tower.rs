@@ -0,0 +1,39 @@
+//! A Http/1 server with a tower concurrency limit in front of the handler.
+
+use std::convert::Infallible;
+
+use actix_http_alt::{
+    http::{Request, Response},
+    util::{ErrorLoggerFactory, TowerFactory},
+    HttpServiceBuilder, RequestBody, ResponseBody,
+};
+use bytes::Bytes;
+use tower::{limit::ConcurrencyLimit, make::Shared, service_fn};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::io::Result<()> {
+    std::env::set_var("RUST_LOG", "actix=trace, info");
+    env_logger::init();
+
+    actix_server_alt::Builder::new()
+        .bind("tower", "127.0.0.1:8080", || {
+            // every worker has its own limit of 16 requests handled at the same time.
+            // requests beyond it wait for readiness of service.
+            let service = ConcurrencyLimit::new(service_fn(handler), 16);
+
+            let builder = HttpServiceBuilder::new(TowerFactory::new(Shared::new(service)));
+
+            ErrorLoggerFactory::new(builder)
+        })?
+        .build()
+        .await
+}
+
+async fn handler(_: Request<RequestBody>) -> Result<Response<ResponseBody>, Infallible> {
+    let res = Response::builder()
+        .status(200)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(Bytes::from_static(b"Hello World!").into())
+        .unwrap();
+    Ok(res)
+}
@@ -0,0 +1,56 @@
+//! A Http/1 websocket chat server broadcasts text message to all connected peers.
+//!
+//! Every worker thread has its own chat room.
+
+use std::{cell::RefCell, rc::Rc};
+
+use actix_http_alt::util::websocket;
+use actix_web_alt::HttpServer;
+use http_ws::Message;
+use log::info;
+use tokio::sync::mpsc::Sender;
+
+type Room = Rc<RefCell<Vec<Sender<Message>>>>;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::io::Result<()> {
+    std::env::set_var("RUST_LOG", "actix=trace, info");
+    env_logger::init();
+
+    HttpServer::new(|| {
+        let room = Room::default();
+        websocket(move |msg, tx| handler(room.clone(), msg, tx))
+    })
+    .bind("127.0.0.1:8080")?
+    .run()
+    .await
+}
+
+async fn handler(room: Room, msg: Message, tx: Sender<Message>) {
+    match msg {
+        Message::Text(text) => {
+            let mut room = room.borrow_mut();
+
+            // a peer joins the room with its first message.
+            if !room.iter().any(|peer| peer.same_channel(&tx)) {
+                info!("Peer joined. {} peers in room", room.len() + 1);
+                room.push(tx);
+            }
+
+            // drop peers that are gone and skip the ones too slow to receive.
+            room.retain(|peer| !peer.is_closed());
+            for peer in room.iter() {
+                let _ = peer.try_send(Message::Text(text.clone()));
+            }
+        }
+        Message::Ping(bytes) => {
+            let _ = tx.send(Message::Pong(bytes)).await;
+        }
+        // close message is echoed by encode stream and it's the last message.
+        Message::Close(_) => {
+            room.borrow_mut().retain(|peer| !peer.same_channel(&tx));
+            info!("Peer left");
+        }
+        _ => {}
+    }
+}
@@ -2,12 +2,12 @@
 
 use actix_http_alt::{
     http::{Request, Response},
-    BodyError, RequestBody, ResponseBody,
+    util::ws,
+    RequestBody, ResponseBody,
 };
 use actix_service_alt::fn_service;
 use actix_web_alt::HttpServer;
-use futures_util::TryStreamExt;
-use http_ws::{ws, Message};
+use http_ws::{EncodeStream, Message};
 use log::info;
 
 #[tokio::main(flavor = "current_thread")]
@@ -24,36 +24,28 @@ async fn main() -> std::io::Result<()> {
         .await
 }
 
-async fn handler(req: Request<RequestBody>) -> Result<Response<ResponseBody>, Box<dyn std::error::Error>> {
-    let (mut decode, res, tx) = ws(req)?;
+async fn handler(
+    req: Request<RequestBody>,
+) -> Result<Response<ResponseBody<EncodeStream>>, Box<dyn std::error::Error>> {
+    let (res, mut decode, tx) = ws(req)?;
 
     tokio::task::spawn_local(async move {
         while let Some(Ok(msg)) = decode.next().await {
             match msg {
-                Message::Text(bytes) => {
-                    let str = String::from_utf8_lossy(bytes.as_ref());
-                    info!("Got text message {:?}", str);
-                    tx.send(Message::Text(format!("Echo: {}", str).into())).await.unwrap();
+                Message::Text(text) => {
+                    info!("Got text message {:?}", text);
+                    tx.send(Message::Text(format!("Echo: {}", text).into())).await.unwrap();
                 }
                 Message::Ping(bytes) => {
                     info!("Got ping message");
                     tx.send(Message::Pong(bytes)).await.unwrap();
                 }
-                Message::Close(reason) => {
-                    info!("Got close message");
-                    tx.send(Message::Close(reason)).await.unwrap();
-                    return;
-                }
+                // close message is echoed by encode stream and it's the last message.
+                Message::Close(_) => info!("Got close message"),
                 _ => {}
             }
         }
     });
 
-    let (parts, body) = res.into_parts();
-    let body = body.map_err(|e| BodyError::Std(e.into()));
-    let body = Box::pin(body) as _;
-    let body = ResponseBody::stream(body);
-    let res = Response::from_parts(parts, body);
-
     Ok(res)
 }
@@ -1,23 +1,34 @@
 //! Copy from [actix-http](https://github.com/actix/actix-web)
 
-use std::cell::Cell;
+#[cfg(feature = "stream")]
+use std::time::Duration;
+use std::{cell::Cell, convert::TryFrom};
 
 use bytes::{Bytes, BytesMut};
 use log::error;
 
 use super::error::ProtocolError;
-use super::frame::Parser;
+#[cfg(feature = "deflate")]
+use super::frame::RSV1;
+use super::frame::{FrameHeader, Limits, Parser};
 use super::proto::{CloseReason, OpCode};
+use super::stats::{Recorder, SessionStats};
+use super::string::ByteString;
+use super::utf8::Utf8;
 
 /// A WebSocket message.
 #[derive(Debug, PartialEq)]
 pub enum Message {
     /// Text message.
-    Text(Bytes),
+    Text(ByteString),
 
     /// Binary message.
     Binary(Bytes),
 
+    /// Binary message sent without compression when permessage-deflate is enabled. Useful for
+    /// payload already compressed. It's never yielded by decoding.
+    BinaryUncompressed(Bytes),
+
     /// Continuation.
     Continuation(Item),
 
@@ -35,6 +46,24 @@ pub enum Message {
     Nop,
 }
 
+impl From<ByteString> for Message {
+    fn from(text: ByteString) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self::Text(text.into())
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Self::Text(text.into())
+    }
+}
+
 /// A WebSocket continuation item.
 #[derive(Debug, PartialEq)]
 pub enum Item {
@@ -49,7 +78,23 @@ pub enum Item {
 pub struct Codec {
     flags: Cell<Flags>,
     capacity: usize,
-    max_size: usize,
+    pub(crate) limits: Limits,
+    validate_utf8: bool,
+    strict: bool,
+    // header of frame whose payload is not complete yet.
+    header: Cell<Option<(FrameHeader, usize)>>,
+    utf8: Cell<Utf8>,
+    pub(crate) stats: Recorder,
+    #[cfg(feature = "stream")]
+    pub(crate) aggregate: bool,
+    #[cfg(feature = "stream")]
+    shared: super::stream::Shared,
+    #[cfg(feature = "deflate")]
+    deflate: Option<super::deflate::Deflate>,
+    #[cfg(feature = "deflate")]
+    compress_threshold: usize,
+    #[cfg(feature = "deflate")]
+    no_context_takeover: bool,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -59,6 +104,9 @@ impl Flags {
     const SERVER: Flags = Flags(0b0000_0001);
     const CONTINUATION: Flags = Flags(0b0000_0010);
     const W_CONTINUATION: Flags = Flags(0b0000_0100);
+    const TEXT: Flags = Flags(0b0000_1000);
+    #[cfg(feature = "deflate")]
+    const COMPRESSED: Flags = Flags(0b0001_0000);
 
     #[inline(always)]
     fn remove(&mut self, other: Self) {
@@ -77,12 +125,42 @@ impl Flags {
 }
 
 impl Codec {
-    /// Create new WebSocket frames decoder.
+    /// Create new WebSocket frames decoder in server mode.
     pub const fn new() -> Codec {
+        Self::with_mode(Flags::SERVER)
+    }
+
+    /// Create new WebSocket frames decoder in client mode.
+    ///
+    /// Client mode codec masks outgoing frames with random mask and requires incoming frames to be
+    /// unmasked.
+    pub const fn client() -> Codec {
+        Self::with_mode(Flags(0))
+    }
+
+    const fn with_mode(flags: Flags) -> Codec {
         Codec {
-            max_size: 65_536,
             capacity: 128,
-            flags: Cell::new(Flags::SERVER),
+            flags: Cell::new(flags),
+            validate_utf8: true,
+            strict: true,
+            header: Cell::new(None),
+            utf8: Cell::new(Utf8::new()),
+            stats: Recorder::new(),
+            limits: Limits {
+                continuation: 1_048_576,
+                ..Limits::new(65_536)
+            },
+            #[cfg(feature = "stream")]
+            aggregate: true,
+            #[cfg(feature = "stream")]
+            shared: super::stream::Shared::new(),
+            #[cfg(feature = "deflate")]
+            deflate: None,
+            #[cfg(feature = "deflate")]
+            compress_threshold: 64,
+            #[cfg(feature = "deflate")]
+            no_context_takeover: false,
         }
     }
 
@@ -90,7 +168,107 @@ impl Codec {
     ///
     /// By default max size is set to 64kB.
     pub fn max_size(mut self, size: usize) -> Self {
-        self.max_size = size;
+        self.limits.frame = size;
+        self
+    }
+
+    /// Set if incoming frames are strictly validated. Frame with reserved bits set results in
+    /// [ProtocolError::ReservedBits] and payload length not encoded in minimal number of bytes
+    /// results in [ProtocolError::NonMinimalLength].
+    ///
+    /// Lenient mode ignores both for interop with broken peers. By default strict mode is enabled.
+    pub fn strict(mut self, value: bool) -> Self {
+        self.strict = value;
+        self
+    }
+
+    /// Set max size of text frame. Exceeding it results in [ProtocolError::TextTooLarge].
+    ///
+    /// By default only [Codec::max_size] applies.
+    pub fn max_text_size(mut self, size: usize) -> Self {
+        self.limits.text = size;
+        self
+    }
+
+    /// Set max size of binary frame. Exceeding it results in [ProtocolError::BinaryTooLarge].
+    ///
+    /// By default only [Codec::max_size] applies.
+    pub fn max_binary_size(mut self, size: usize) -> Self {
+        self.limits.binary = size;
+        self
+    }
+
+    /// Set max size of continuation frame and message aggregated from continuation frames by
+    /// [DecodeStream](crate::DecodeStream). Exceeding it results in
+    /// [ProtocolError::ContinuationTooLarge].
+    ///
+    /// By default max message size is set to 1MB.
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.limits.continuation = size;
+        self
+    }
+
+    /// Set max size of ping, pong and close frame. Exceeding it results in
+    /// [ProtocolError::ControlTooLarge].
+    ///
+    /// By default max control size is set to 125 bytes.
+    ///
+    /// # Panics
+    /// When given size is larger than 125 bytes which is the limit of WebSocket protocol.
+    pub fn max_control_size(mut self, size: usize) -> Self {
+        assert!(size <= 125, "control frame can not be larger than 125 bytes");
+        self.limits.control = size;
+        self
+    }
+
+    /// Set if text message split into continuation frames is validated as UTF-8. Invalid text
+    /// results in [ProtocolError::InvalidUtf8].
+    ///
+    /// Unfragmented text message is always validated as it's yielded as [ByteString].
+    ///
+    /// By default validation is enabled.
+    pub fn validate_utf8(mut self, value: bool) -> Self {
+        self.validate_utf8 = value;
+        self
+    }
+
+    /// Set if permessage-deflate extension is enabled. It should only be enabled when the extension
+    /// is negotiated in handshake.
+    ///
+    /// Unfragmented text and binary message is sent compressed with RSV1 bit set unless it's
+    /// smaller than [Codec::compress_threshold] or sent as [Message::BinaryUncompressed].
+    /// Fragmented message is always sent uncompressed. Received message with RSV1 bit set is
+    /// decompressed and its decompressed size is checked against the limits of codec.
+    ///
+    /// By default permessage-deflate is disabled.
+    #[cfg(feature = "deflate")]
+    pub fn permessage_deflate(mut self, value: bool) -> Self {
+        self.deflate = if value {
+            Some(super::deflate::Deflate::new())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Set size of message payload below which it's sent uncompressed. Small payload gains little
+    /// from compression and can end up larger.
+    ///
+    /// By default threshold is set to 64 bytes. Only effective with [Codec::permessage_deflate].
+    #[cfg(feature = "deflate")]
+    pub fn compress_threshold(mut self, size: usize) -> Self {
+        self.compress_threshold = size;
+        self
+    }
+
+    /// Set if compression context is reset after every sent message. It should be enabled when
+    /// `server_no_context_takeover` is negotiated for server codec or `client_no_context_takeover`
+    /// for client codec.
+    ///
+    /// By default context is kept. Only effective with [Codec::permessage_deflate].
+    #[cfg(feature = "deflate")]
+    pub fn no_context_takeover(mut self, value: bool) -> Self {
+        self.no_context_takeover = value;
         self
     }
 
@@ -106,6 +284,143 @@ impl Codec {
         self.capacity
     }
 
+    /// Set if [DecodeStream](crate::DecodeStream) buffers text and binary message fragmented into
+    /// continuation frames and yield it as one complete message.
+    ///
+    /// By default aggregation is enabled.
+    #[cfg(feature = "stream")]
+    pub fn aggregate(mut self, value: bool) -> Self {
+        self.aggregate = value;
+        self
+    }
+
+    /// Set interval of idle [EncodeStream](crate::EncodeStream) before it sends a ping message.
+    ///
+    /// By default no ping is sent.
+    #[cfg(feature = "stream")]
+    pub fn ping_interval(mut self, dur: Duration) -> Self {
+        self.shared.ping_interval = Some(dur);
+        self
+    }
+
+    /// Set timeout of waiting for pong or any other frame after a ping is sent. On timeout
+    /// [DecodeStream](crate::DecodeStream) yields [DecodeError::Timeout](crate::DecodeError::Timeout)
+    /// and [EncodeStream](crate::EncodeStream) sends a close message and ends.
+    ///
+    /// By default there is no timeout. Only effective with [Codec::ping_interval].
+    #[cfg(feature = "stream")]
+    pub fn pong_timeout(mut self, dur: Duration) -> Self {
+        self.shared.pong_timeout = Some(dur);
+        self
+    }
+
+    /// Enable keep-alive with [Codec::ping_interval] and [Codec::pong_timeout] together.
+    ///
+    /// A ping is sent after `interval` of idle [EncodeStream](crate::EncodeStream). When no frame
+    /// is received from peer in `timeout` after that the connection is closed with
+    /// [CloseCode::Away](super::proto::CloseCode::Away).
+    ///
+    /// # Panics
+    /// When `interval` or `timeout` is zero.
+    #[cfg(feature = "stream")]
+    pub fn heartbeat(self, interval: Duration, timeout: Duration) -> Self {
+        assert!(
+            interval > Duration::ZERO && timeout > Duration::ZERO,
+            "heartbeat interval and timeout can not be zero"
+        );
+        self.ping_interval(interval).pong_timeout(timeout)
+    }
+
+    /// Answer ping message received by [DecodeStream](crate::DecodeStream) with pong message
+    /// sent by [EncodeStream](crate::EncodeStream).
+    ///
+    /// By default ping is not answered.
+    #[cfg(feature = "stream")]
+    pub fn auto_pong(mut self, value: bool) -> Self {
+        self.shared.auto_pong = value;
+        self
+    }
+
+    /// Set if ping message answered by [Codec::auto_pong] is still yielded from
+    /// [DecodeStream](crate::DecodeStream).
+    ///
+    /// By default ping is yielded.
+    #[cfg(feature = "stream")]
+    pub fn forward_ping(mut self, value: bool) -> Self {
+        self.shared.forward_ping = value;
+        self
+    }
+
+    /// Set max payload size of frame sent by [EncodeStream](crate::EncodeStream). Larger text and
+    /// binary message is split into continuation frames.
+    ///
+    /// By default fragment size is set to 64kB.
+    ///
+    /// # Panics
+    /// When given size is 0.
+    #[cfg(feature = "stream")]
+    pub fn fragment_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "fragment size can not be 0");
+        self.shared.fragment_size = size;
+        self
+    }
+
+    /// Set size limit of encoded bytes [EncodeStream](crate::EncodeStream) buffers before yielding
+    /// them. Messages are left in channel once limit is reached so a slow consumer of encode stream
+    /// is back pressure to sender instead of growing buffer.
+    ///
+    /// By default write buffer size is set to 64kB.
+    #[cfg(feature = "stream")]
+    pub fn write_buffer_size(mut self, size: usize) -> Self {
+        self.shared.write_buffer_size = size;
+        self
+    }
+
+    /// Set timeout of waiting for peer close message after local side sent close message through
+    /// [EncodeStream](crate::EncodeStream). On timeout [DecodeStream](crate::DecodeStream) yields
+    /// [DecodeError::Timeout](crate::DecodeError::Timeout) and [EncodeStream](crate::EncodeStream)
+    /// ends.
+    ///
+    /// By default close timeout is set to 5 seconds.
+    #[cfg(feature = "stream")]
+    pub fn close_timeout(mut self, dur: Duration) -> Self {
+        self.shared.close_timeout = dur;
+        self
+    }
+
+    /// Set the mapping from [ProtocolError] to close code of close message sent to peer when
+    /// [DecodeStream](crate::DecodeStream) fails to decode.
+    ///
+    /// By default [ProtocolError::close_code] is used.
+    #[cfg(feature = "stream")]
+    pub fn error_close_code(mut self, f: fn(&ProtocolError) -> super::proto::CloseCode) -> Self {
+        self.shared.close_code = f;
+        self
+    }
+
+    /// Set callback called with [SessionStats] once the session using this codec is closed.
+    ///
+    /// Session is closed when [DecodeStream](crate::DecodeStream) and
+    /// [EncodeStream](crate::EncodeStream) sharing the codec are both dropped.
+    #[cfg(feature = "stream")]
+    pub fn on_session_close<F>(mut self, on_close: F) -> Self
+    where
+        F: Fn(SessionStats) + Send + Sync + 'static,
+    {
+        self.stats.on_close = Some(std::sync::Arc::new(on_close));
+        self
+    }
+
+    /// Snapshot of frames and bytes encoded and decoded by codec so far.
+    pub fn stats(&self) -> SessionStats {
+        self.stats.snapshot()
+    }
+
+    #[cfg(feature = "stream")]
+    pub(crate) fn shared(&self) -> &super::stream::Shared {
+        &self.shared
+    }
+
     /// Set decoder to client mode.
     ///
     /// By default decoder works in server mode.
@@ -128,28 +443,61 @@ impl Codec {
 
 impl Codec {
     pub fn encode(&self, item: Message, dst: &mut BytesMut) -> Result<(), ProtocolError> {
+        let opcode = match item {
+            Message::Text(_) | Message::Continuation(Item::FirstText(_)) => OpCode::Text,
+            Message::Binary(_) | Message::BinaryUncompressed(_) | Message::Continuation(Item::FirstBinary(_)) => {
+                OpCode::Binary
+            }
+            Message::Continuation(_) => OpCode::Continue,
+            Message::Ping(_) => OpCode::Ping,
+            Message::Pong(_) => OpCode::Pong,
+            Message::Close(ref reason) => {
+                self.stats.close(reason.as_ref().map(|reason| reason.code));
+                OpCode::Close
+            }
+            Message::Nop => return Ok(()),
+        };
+
+        let len = dst.len();
+        self.write(item, dst)?;
+        self.stats.sent(opcode, dst.len() - len);
+
+        Ok(())
+    }
+
+    fn write(&self, item: Message, dst: &mut BytesMut) -> Result<(), ProtocolError> {
         match item {
             Message::Text(txt) => {
                 let mask = self.with_flags(|flags| !flags.contains(Flags::SERVER));
-                Parser::write_message(dst, txt, OpCode::Text, true, mask);
+                self.write_data(dst, txt.as_bytes(), OpCode::Text, mask);
                 Ok(())
             }
             Message::Binary(bin) => {
+                let mask = self.with_flags(|flags| !flags.contains(Flags::SERVER));
+                self.write_data(dst, &bin, OpCode::Binary, mask);
+                Ok(())
+            }
+            Message::BinaryUncompressed(bin) => {
                 let mask = self.with_flags(|flags| !flags.contains(Flags::SERVER));
                 Parser::write_message(dst, bin, OpCode::Binary, true, mask);
                 Ok(())
             }
             Message::Ping(txt) => {
+                check_control(&txt)?;
                 let mask = self.with_flags(|flags| !flags.contains(Flags::SERVER));
                 Parser::write_message(dst, txt, OpCode::Ping, true, mask);
                 Ok(())
             }
             Message::Pong(txt) => {
+                check_control(&txt)?;
                 let mask = self.with_flags(|flags| !flags.contains(Flags::SERVER));
                 Parser::write_message(dst, txt, OpCode::Pong, true, mask);
                 Ok(())
             }
             Message::Close(reason) => {
+                if let Some(ref reason) = reason {
+                    check_close(reason)?;
+                }
                 let mask = self.with_flags(|flags| !flags.contains(Flags::SERVER));
                 Parser::write_close(dst, reason, mask);
                 Ok(())
@@ -203,15 +551,112 @@ impl Codec {
         }
     }
 
+    // write unfragmented text or binary message. payload is compressed when permessage-deflate
+    // is enabled and it's not smaller than threshold.
+    fn write_data(&self, dst: &mut BytesMut, payload: &[u8], op: OpCode, mask: bool) {
+        #[cfg(feature = "deflate")]
+        if let Some(ref deflate) = self.deflate {
+            if payload.len() >= self.compress_threshold {
+                let compressed = deflate.compress(payload, self.no_context_takeover);
+                self.stats.sent_compressed(compressed.len(), payload.len());
+                return Parser::write_compressed(dst, compressed, op, mask);
+            }
+        }
+
+        Parser::write_message(dst, payload, op, true, mask)
+    }
+
+    // parse frame header. RSV1 is allowed when permessage-deflate is enabled.
+    fn parse_header(&self, src: &[u8], server: bool) -> Result<Option<(FrameHeader, usize)>, ProtocolError> {
+        #[cfg(feature = "deflate")]
+        if self.deflate.is_some() {
+            return Parser::parse_header_with_rsv(src, server, self.strict, RSV1, &self.limits);
+        }
+
+        Parser::parse_header(src, server, self.strict, &self.limits)
+    }
+
+    // parse frame with header kept from previous call so a frame arriving in small pieces is
+    // not parsed and validated again on every call.
+    fn parse(
+        &self,
+        src: &mut BytesMut,
+        server: bool,
+    ) -> Result<Option<(bool, OpCode, Option<BytesMut>)>, ProtocolError> {
+        let (header, size) = match self.header.take() {
+            Some(header) => header,
+            None => match self.parse_header(src, server)? {
+                Some(header) => header,
+                None => return Ok(None),
+            },
+        };
+
+        let frame = Parser::parse_payload(src, &header, size);
+        match frame {
+            Some(_) => self.stats.received(header.opcode, size + header.len as usize),
+            None => self.header.set(Some((header, size))),
+        }
+
+        #[cfg(feature = "deflate")]
+        let frame = match (frame, self.deflate.as_ref()) {
+            (Some((fin, opcode, payload)), Some(deflate)) => {
+                let payload = self.inflate(deflate, fin, header.rsv & RSV1 != 0, opcode, payload)?;
+                Some((fin, opcode, payload))
+            }
+            (frame, _) => frame,
+        };
+
+        Ok(frame)
+    }
+
+    // decompress payload of frame when it's part of compressed message.
+    #[cfg(feature = "deflate")]
+    fn inflate(
+        &self,
+        deflate: &super::deflate::Deflate,
+        fin: bool,
+        rsv1: bool,
+        opcode: OpCode,
+        payload: Option<BytesMut>,
+    ) -> Result<Option<BytesMut>, ProtocolError> {
+        // only the first frame of compressed message has RSV1 set.
+        let compressed = match opcode {
+            OpCode::Text | OpCode::Binary => rsv1,
+            OpCode::Continue => self.with_flags(|flags| flags.contains(Flags::COMPRESSED)),
+            _ => return Ok(payload),
+        };
+
+        self.with_flags(|flags| {
+            if compressed && !fin {
+                flags.insert(Flags::COMPRESSED);
+            } else {
+                flags.remove(Flags::COMPRESSED);
+            }
+        });
+
+        if !compressed {
+            return Ok(payload);
+        }
+
+        // decompressed payload is limited as if it's received uncompressed.
+        let compressed = payload.as_deref().unwrap_or_default();
+        let payload = deflate.decompress(compressed, fin, |len| self.limits.check(opcode, len))?;
+        self.stats.received_compressed(compressed.len(), payload.len());
+        Ok(if payload.is_empty() { None } else { Some(payload) })
+    }
+
     pub fn decode(&self, src: &mut BytesMut) -> Result<Option<Message>, ProtocolError> {
         let server = self.with_flags(|flags| flags.contains(Flags::SERVER));
-        match Parser::parse(src, server, self.max_size) {
+        match self.parse(src, server) {
             Ok(Some((finished, opcode, payload))) => {
                 // continuation is not supported
                 if !finished {
                     return match opcode {
                         OpCode::Continue => self.with_flags(|flags| {
                             if flags.contains(Flags::CONTINUATION) {
+                                if flags.contains(Flags::TEXT) {
+                                    self.utf8(&payload, false)?;
+                                }
                                 Ok(Some(Message::Continuation(Item::Continue(
                                     payload.map(|pl| pl.freeze()).unwrap_or_else(Bytes::new),
                                 ))))
@@ -232,6 +677,11 @@ impl Codec {
                         OpCode::Text => self.with_flags(|flags| {
                             if !flags.contains(Flags::CONTINUATION) {
                                 flags.insert(Flags::CONTINUATION);
+                                if self.validate_utf8 {
+                                    flags.insert(Flags::TEXT);
+                                    self.utf8.set(Utf8::new());
+                                    self.utf8(&payload, false)?;
+                                }
                                 Ok(Some(Message::Continuation(Item::FirstText(
                                     payload.map(|pl| pl.freeze()).unwrap_or_else(Bytes::new),
                                 ))))
@@ -250,6 +700,10 @@ impl Codec {
                     OpCode::Continue => self.with_flags(|flags| {
                         if flags.contains(Flags::CONTINUATION) {
                             flags.remove(Flags::CONTINUATION);
+                            if flags.contains(Flags::TEXT) {
+                                flags.remove(Flags::TEXT);
+                                self.utf8(&payload, true)?;
+                            }
                             Ok(Some(Message::Continuation(Item::Last(
                                 payload.map(|pl| pl.freeze()).unwrap_or_else(Bytes::new),
                             ))))
@@ -260,9 +714,11 @@ impl Codec {
                     OpCode::Bad => Err(ProtocolError::BadOpCode),
                     OpCode::Close => {
                         if let Some(ref pl) = payload {
-                            let close_reason = Parser::parse_close_payload(pl);
+                            let close_reason = Parser::parse_close_payload(pl)?;
+                            self.stats.close(close_reason.as_ref().map(|reason| reason.code));
                             Ok(Some(Message::Close(close_reason)))
                         } else {
+                            self.stats.close(None);
                             Ok(Some(Message::Close(None)))
                         }
                     }
@@ -275,15 +731,45 @@ impl Codec {
                     OpCode::Binary => Ok(Some(Message::Binary(
                         payload.map(|pl| pl.freeze()).unwrap_or_else(Bytes::new),
                     ))),
-                    OpCode::Text => Ok(Some(Message::Text(
-                        payload.map(|pl| pl.freeze()).unwrap_or_else(Bytes::new),
-                    ))),
+                    OpCode::Text => {
+                        let text = payload.map(|pl| pl.freeze()).unwrap_or_else(Bytes::new);
+                        let text = ByteString::try_from(text).map_err(|_| ProtocolError::InvalidUtf8)?;
+                        Ok(Some(Message::Text(text)))
+                    }
                 }
             }
             Ok(None) => Ok(None),
             Err(e) => Err(e),
         }
     }
+
+    fn utf8(&self, payload: &Option<BytesMut>, last: bool) -> Result<(), ProtocolError> {
+        let mut utf8 = self.utf8.get();
+        let res = utf8.validate(payload.as_deref().unwrap_or_default(), last);
+        self.utf8.set(utf8);
+        res
+    }
+}
+
+// control frame payload can not be longer than 125 bytes.
+fn check_control(payload: &[u8]) -> Result<(), ProtocolError> {
+    if payload.len() > 125 {
+        Err(ProtocolError::ControlTooLarge(payload.len()))
+    } else {
+        Ok(())
+    }
+}
+
+// close code must be allowed on the wire and description takes the rest of control frame payload.
+fn check_close(reason: &CloseReason) -> Result<(), ProtocolError> {
+    if !reason.code.is_allowed() {
+        return Err(ProtocolError::InvalidCloseCode(reason.code.into()));
+    }
+
+    match reason.description {
+        Some(ref description) if description.len() > 123 => Err(ProtocolError::ControlTooLarge(2 + description.len())),
+        _ => Ok(()),
+    }
 }
 
 #[cfg(test)]
@@ -313,4 +799,287 @@ mod test {
         assert!(flags.contains(Flags::W_CONTINUATION));
         assert!(!flags.contains(Flags::SERVER));
     }
+
+    #[test]
+    fn limits() {
+        let client = Codec::new().client_mode();
+        let codec = Codec::new()
+            .max_size(16 * 1024 * 1024)
+            .max_text_size(4)
+            .max_control_size(2);
+
+        let mut buf = BytesMut::new();
+        client
+            .encode(Message::Binary(vec![1u8; 70_000].into()), &mut buf)
+            .unwrap();
+        assert!(matches!(codec.decode(&mut buf), Ok(Some(Message::Binary(_)))));
+
+        client.encode(Message::Text("99996".into()), &mut buf).unwrap();
+        assert!(matches!(codec.decode(&mut buf), Err(ProtocolError::TextTooLarge(5))));
+
+        let mut buf = BytesMut::new();
+        client.encode(Message::Ping("996".into()), &mut buf).unwrap();
+        assert!(matches!(codec.decode(&mut buf), Err(ProtocolError::ControlTooLarge(3))));
+
+        let mut buf = BytesMut::new();
+        client.encode(Message::Pong(vec![0; 125].into()), &mut buf).unwrap();
+        assert!(matches!(
+            client.encode(Message::Ping(vec![0; 126].into()), &mut buf),
+            Err(ProtocolError::ControlTooLarge(126))
+        ));
+    }
+
+    #[test]
+    fn client() {
+        let client = Codec::client();
+        let server = Codec::new();
+
+        let mut buf = BytesMut::new();
+        client.encode(Message::Text("996".into()), &mut buf).unwrap();
+        // mask bit is set.
+        assert_eq!(buf[1] & 0x80, 0x80);
+        assert_eq!(server.decode(&mut buf).unwrap(), Some(Message::Text("996".into())));
+
+        server.encode(Message::Binary("996".into()), &mut buf).unwrap();
+        assert_eq!(buf[1] & 0x80, 0);
+        assert_eq!(client.decode(&mut buf).unwrap(), Some(Message::Binary("996".into())));
+
+        client.encode(Message::Ping("996".into()), &mut buf).unwrap();
+        assert!(matches!(client.decode(&mut buf), Err(ProtocolError::MaskedFrame)));
+    }
+
+    #[test]
+    fn utf8() {
+        let client = Codec::client();
+        let text = "€".as_bytes();
+
+        let mut buf = BytesMut::new();
+        client
+            .encode(
+                Message::Continuation(Item::FirstText(text[..1].to_vec().into())),
+                &mut buf,
+            )
+            .unwrap();
+        client
+            .encode(Message::Continuation(Item::Last(text[1..].to_vec().into())), &mut buf)
+            .unwrap();
+        Parser::write_message(&mut buf, &text[..2], OpCode::Text, true, true);
+
+        let codec = Codec::new();
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(matches!(codec.decode(&mut buf), Err(ProtocolError::InvalidUtf8)));
+
+        client
+            .encode(
+                Message::Continuation(Item::FirstText(text[..1].to_vec().into())),
+                &mut buf,
+            )
+            .unwrap();
+        client
+            .encode(Message::Continuation(Item::Last(Bytes::new())), &mut buf)
+            .unwrap();
+
+        let codec = Codec::new();
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(matches!(codec.decode(&mut buf), Err(ProtocolError::InvalidUtf8)));
+
+        client
+            .encode(
+                Message::Continuation(Item::FirstText(text[..1].to_vec().into())),
+                &mut buf,
+            )
+            .unwrap();
+        client
+            .encode(Message::Continuation(Item::Last(Bytes::new())), &mut buf)
+            .unwrap();
+        Parser::write_message(&mut buf, &text[..2], OpCode::Text, true, true);
+
+        let codec = Codec::new().validate_utf8(false);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Ok(Some(Message::Continuation(Item::Last(_))))
+        ));
+        assert!(matches!(codec.decode(&mut buf), Err(ProtocolError::InvalidUtf8)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn control_limit() {
+        let _ = Codec::new().max_control_size(126);
+    }
+
+    #[test]
+    fn close() {
+        use crate::proto::CloseCode;
+
+        let client = Codec::client();
+        let server = Codec::new();
+
+        let mut buf = BytesMut::new();
+        client.encode(Message::Close(None), &mut buf).unwrap();
+        assert_eq!(server.decode(&mut buf).unwrap(), Some(Message::Close(None)));
+
+        let reason = CloseReason::from((CloseCode::Other(4000), "a".repeat(123)));
+        client.encode(Message::Close(Some(reason.clone())), &mut buf).unwrap();
+        assert_eq!(server.decode(&mut buf).unwrap(), Some(Message::Close(Some(reason))));
+
+        let reason = CloseReason::from((CloseCode::Normal, "a".repeat(124)));
+        assert!(matches!(
+            client.encode(Message::Close(Some(reason)), &mut buf),
+            Err(ProtocolError::ControlTooLarge(126))
+        ));
+        assert!(matches!(
+            client.encode(Message::Close(Some(CloseCode::Abnormal.into())), &mut buf),
+            Err(ProtocolError::InvalidCloseCode(1006))
+        ));
+
+        // reserved code, truncated code and invalid description are rejected on receive.
+        Parser::write_close(&mut buf, Some(CloseCode::Other(1005).into()), true);
+        assert!(matches!(
+            server.decode(&mut buf),
+            Err(ProtocolError::InvalidCloseCode(1005))
+        ));
+
+        Parser::write_message(&mut buf, [3], OpCode::Close, true, true);
+        assert!(matches!(server.decode(&mut buf), Err(ProtocolError::InvalidLength(1))));
+
+        Parser::write_message(&mut buf, [3, 232, 0xFF], OpCode::Close, true, true);
+        assert!(matches!(server.decode(&mut buf), Err(ProtocolError::InvalidUtf8)));
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let payload = (0..=255u8).cycle().take(1024 * 1024).collect::<Vec<_>>();
+        let mut frame = BytesMut::new();
+        Codec::client()
+            .encode(Message::Binary(payload.clone().into()), &mut frame)
+            .unwrap();
+
+        let codec = Codec::new().max_size(2 * 1024 * 1024);
+        let mut buf = BytesMut::new();
+        let (last, rest) = frame.split_last().unwrap();
+
+        for (i, byte) in rest.iter().enumerate() {
+            buf.extend_from_slice(&[*byte]);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+            // header is parsed once and kept until payload is complete.
+            assert_eq!(codec.header.get().is_some(), i >= 13);
+        }
+
+        buf.extend_from_slice(&[*last]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Message::Binary(payload.into())));
+        assert!(codec.header.get().is_none());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn zero_copy() {
+        let client = Codec::client();
+        let server = Codec::new();
+
+        let payload = vec![7u8; 64 * 1024];
+        let mut buf = BytesMut::new();
+        client
+            .encode(Message::Binary(payload.clone().into()), &mut buf)
+            .unwrap();
+        client
+            .encode(Message::Text(String::from_utf8(payload).unwrap().into()), &mut buf)
+            .unwrap();
+
+        // payload is unmasked in place and split out of read buffer. header is 2 + 8 + 4 bytes.
+        let start = buf.as_ptr() as usize;
+        let frame_size = 14 + 64 * 1024;
+
+        match server.decode(&mut buf).unwrap().unwrap() {
+            Message::Binary(bytes) => assert_eq!(bytes.as_ptr() as usize, start + 14),
+            msg => panic!("unexpected {:?}", msg),
+        }
+        match server.decode(&mut buf).unwrap().unwrap() {
+            Message::Text(text) => assert_eq!(text.as_ptr() as usize, start + frame_size + 14),
+            msg => panic!("unexpected {:?}", msg),
+        }
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn permessage_deflate() {
+        let client = Codec::client().permessage_deflate(true).compress_threshold(16);
+        let server = Codec::new().permessage_deflate(true);
+
+        let text = "Hello, Hello, Hello, Hello, Hello";
+        let mut buf = BytesMut::new();
+        client.encode(Message::Text(text.into()), &mut buf).unwrap();
+        // RSV1 bit is set and payload is compressed. header is 2 + 4 bytes.
+        assert_eq!(buf[0] & 0x70, RSV1 << 4);
+        assert!(buf.len() < 6 + text.len());
+        let compressed = buf.len() as u64 - 6;
+        assert_eq!(server.decode(&mut buf).unwrap(), Some(Message::Text(text.into())));
+
+        // payload size on the wire and before compression is counted by both sides.
+        for traffic in &[client.stats().sent, server.stats().received] {
+            assert_eq!(traffic.compressed, compressed);
+            assert_eq!(traffic.uncompressed, text.len() as u64);
+            assert!(traffic.compression_ratio().unwrap() > 1.0);
+        }
+        assert_eq!(Codec::new().stats().sent.compression_ratio(), None);
+
+        // message below threshold or opted out is sent uncompressed.
+        let payload = Bytes::from(vec![1; 1024]);
+        for msg in [
+            Message::Binary("996".into()),
+            Message::BinaryUncompressed(payload.clone()),
+        ] {
+            let expected = match msg {
+                Message::Binary(ref bin) | Message::BinaryUncompressed(ref bin) => Message::Binary(bin.clone()),
+                _ => unreachable!(),
+            };
+            client.encode(msg, &mut buf).unwrap();
+            assert_eq!(buf[0] & 0x70, 0);
+            assert_eq!(server.decode(&mut buf).unwrap(), Some(expected));
+        }
+
+        // size limit applies to decompressed payload.
+        let client = Codec::client().permessage_deflate(true);
+        let server = Codec::new().permessage_deflate(true).max_binary_size(512);
+        client.encode(Message::Binary(payload.clone()), &mut buf).unwrap();
+        assert!(buf.len() < 512);
+        assert!(matches!(server.decode(&mut buf), Err(ProtocolError::BinaryTooLarge(_))));
+
+        // RSV1 is rejected when permessage-deflate is not enabled.
+        let client = Codec::client().permessage_deflate(true);
+        let mut buf = BytesMut::new();
+        client.encode(Message::Binary(payload), &mut buf).unwrap();
+        assert!(matches!(
+            Codec::new().decode(&mut buf),
+            Err(ProtocolError::ReservedBits(RSV1))
+        ));
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn permessage_deflate_fragmented() {
+        let text = "Hello, Hello, Hello, Hello, Hello".repeat(4);
+        let compressed = crate::deflate::Deflate::new().compress(text.as_bytes(), false);
+        let (first, last) = compressed.split_at(compressed.len() / 2);
+
+        // only the first frame of compressed message has RSV1 set.
+        let mut buf = BytesMut::new();
+        Parser::write_message(&mut buf, first, OpCode::Text, false, true);
+        buf[0] |= RSV1 << 4;
+        Parser::write_message(&mut buf, last, OpCode::Continue, true, true);
+
+        let server = Codec::new().permessage_deflate(true);
+        let mut decoded = Vec::new();
+        while let Some(msg) = server.decode(&mut buf).unwrap() {
+            match msg {
+                Message::Continuation(Item::FirstText(bytes)) | Message::Continuation(Item::Last(bytes)) => {
+                    decoded.extend_from_slice(&bytes)
+                }
+                msg => panic!("unexpected {:?}", msg),
+            }
+        }
+        assert_eq!(decoded, text.as_bytes());
+    }
 }
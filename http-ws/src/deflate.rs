@@ -0,0 +1,334 @@
+//! permessage-deflate extension. See [RFC 7692](https://datatracker.ietf.org/doc/html/rfc7692).
+
+use std::cell::RefCell;
+
+use bytes::BytesMut;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+
+use super::error::{HandshakeError, ProtocolError};
+
+// trailing bytes of sync flushed deflate block. stripped from sent payload and appended to
+// received one.
+const TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+// compressor and decompressor always use the largest LZ77 window.
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// Extension token of permessage-deflate.
+pub(crate) const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+
+/// Parameters of negotiated permessage-deflate extension.
+///
+/// Only 15 bits LZ77 window is supported. An offer asking server to use smaller window is declined
+/// by server and a response asking client to use smaller window fails client handshake.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeflateConfig {
+    /// Server resets its compression context after every message.
+    pub server_no_context_takeover: bool,
+    /// Client resets its compression context after every message.
+    pub client_no_context_takeover: bool,
+}
+
+impl DeflateConfig {
+    /// Accept permessage-deflate offer of client. Return `None` when offer is for other extension
+    /// or its parameters can not be honored.
+    pub(crate) fn from_offer(extension: &str) -> Option<Self> {
+        let mut config = Self::default();
+        let mut seen = Vec::new();
+
+        for (name, value) in params(extension, PERMESSAGE_DEFLATE)? {
+            // a parameter can only appear once.
+            if seen.contains(&name) {
+                return None;
+            }
+            seen.push(name);
+
+            match (name, value) {
+                ("server_no_context_takeover", None) => config.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => config.client_no_context_takeover = true,
+                // server can not compress with smaller window.
+                ("server_max_window_bits", Some(value)) if window_bits(value)? == MAX_WINDOW_BITS => {}
+                // decompression with the largest window accepts any window of client.
+                ("client_max_window_bits", None) => {}
+                ("client_max_window_bits", Some(value)) => {
+                    window_bits(value)?;
+                }
+                _ => return None,
+            }
+        }
+
+        Some(config)
+    }
+
+    /// Verify permessage-deflate response of server.
+    pub(crate) fn from_response(extension: &str) -> Result<Self, HandshakeError> {
+        let mut config = Self::default();
+
+        for (name, value) in params(extension, PERMESSAGE_DEFLATE).ok_or(HandshakeError::BadWebsocketExtension)? {
+            match (name, value) {
+                ("server_no_context_takeover", None) => config.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => config.client_no_context_takeover = true,
+                // decompression with the largest window accepts any window of server.
+                ("server_max_window_bits", Some(value)) if window_bits(value).is_some() => {}
+                // client can not compress with smaller window.
+                ("client_max_window_bits", Some(value)) if window_bits(value) == Some(MAX_WINDOW_BITS) => {}
+                _ => return Err(HandshakeError::BadWebsocketExtension),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// `SEC_WEBSOCKET_EXTENSIONS` header value of server response.
+    pub(crate) fn header_value(&self) -> String {
+        let mut value = String::from(PERMESSAGE_DEFLATE);
+        if self.server_no_context_takeover {
+            value.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            value.push_str("; client_no_context_takeover");
+        }
+        value
+    }
+}
+
+// parameters of extension with given name. parameter value can be quoted.
+fn params<'a>(extension: &'a str, name: &str) -> Option<impl Iterator<Item = (&'a str, Option<&'a str>)>> {
+    let mut parts = extension.split(';').map(str::trim);
+    if parts.next()? != name {
+        return None;
+    }
+
+    Some(parts.filter(|param| !param.is_empty()).map(|param| {
+        let mut kv = param.splitn(2, '=');
+        let key = kv.next().unwrap_or("").trim();
+        let value = kv.next().map(|value| value.trim().trim_matches('"'));
+        (key, value)
+    }))
+}
+
+// LZ77 window size between 8 and 15 bits.
+fn window_bits(value: &str) -> Option<u8> {
+    value.parse().ok().filter(|bits| (8..=MAX_WINDOW_BITS).contains(bits))
+}
+
+/// Compression state of a [Codec](crate::Codec) with permessage-deflate negotiated.
+///
+/// Sliding window of decompression is kept between messages which works whether peer takes over
+/// its context or not.
+#[derive(Debug)]
+pub(crate) struct Deflate {
+    compress: RefCell<Compress>,
+    decompress: RefCell<Decompress>,
+}
+
+impl Deflate {
+    pub(crate) fn new() -> Self {
+        Self {
+            compress: RefCell::new(Compress::new(Compression::default(), false)),
+            decompress: RefCell::new(Decompress::new(false)),
+        }
+    }
+
+    /// Compress payload of a whole message. Compression context is reset afterwards when `reset`
+    /// is true.
+    pub(crate) fn compress(&self, payload: &[u8], reset: bool) -> Vec<u8> {
+        let mut compress = self.compress.borrow_mut();
+
+        let mut out = Vec::with_capacity(payload.len() + 64);
+        let mut input = payload;
+
+        loop {
+            let before = compress.total_in();
+            compress
+                .compress_vec(input, &mut out, FlushCompress::Sync)
+                .expect("deflate compression can not fail");
+            input = &input[(compress.total_in() - before) as usize..];
+
+            // sync flush is finished when input is consumed and output is not full.
+            if input.is_empty() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(out.len().max(64));
+        }
+
+        if out.ends_with(&TAIL) {
+            out.truncate(out.len() - TAIL.len());
+        }
+
+        if reset {
+            compress.reset();
+        }
+
+        out
+    }
+
+    /// Decompress payload of a frame. `last` is true for the final frame of message. `check` is
+    /// called with size of decompressed payload as it grows so decompression stops early once it
+    /// exceeds a limit.
+    pub(crate) fn decompress<F>(&self, payload: &[u8], last: bool, check: F) -> Result<BytesMut, ProtocolError>
+    where
+        F: Fn(usize) -> Result<(), ProtocolError>,
+    {
+        let mut decompress = self.decompress.borrow_mut();
+
+        let mut out = BytesMut::with_capacity((payload.len() * 2).max(64));
+        let tail: &[u8] = if last { &TAIL } else { &[] };
+
+        for input in &[payload, tail] {
+            let mut input = *input;
+            loop {
+                let (total_in, total_out, before_out) = (decompress.total_in(), decompress.total_out(), out.len());
+
+                // decompress into spare capacity and drop what is not written.
+                out.resize(out.capacity(), 0);
+                let res = decompress.decompress(input, &mut out[before_out..], FlushDecompress::Sync);
+                out.truncate(before_out + (decompress.total_out() - total_out) as usize);
+                res.map_err(|_| ProtocolError::InvalidDeflate)?;
+                input = &input[(decompress.total_in() - total_in) as usize..];
+
+                check(out.len())?;
+
+                // all output is written when input is consumed and output is not full.
+                if input.is_empty() && out.len() < out.capacity() {
+                    break;
+                }
+
+                // final deflate block is seen and the rest of input can not be consumed.
+                if decompress.total_in() == total_in && out.len() == before_out && out.len() < out.capacity() {
+                    return Err(ProtocolError::InvalidDeflate);
+                }
+
+                out.reserve(out.len().max(64));
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+// compression state is not cloned. cloned codec starts with fresh context.
+impl Clone for Deflate {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(
+            DeflateConfig::from_offer("permessage-deflate"),
+            Some(DeflateConfig::default())
+        );
+        assert_eq!(
+            DeflateConfig::from_offer(
+                "permessage-deflate; client_max_window_bits; server_no_context_takeover; server_max_window_bits=\"15\""
+            ),
+            Some(DeflateConfig {
+                server_no_context_takeover: true,
+                client_no_context_takeover: false,
+            })
+        );
+        assert_eq!(
+            DeflateConfig::from_offer("permessage-deflate; client_max_window_bits=10; client_no_context_takeover"),
+            Some(DeflateConfig {
+                server_no_context_takeover: false,
+                client_no_context_takeover: true,
+            })
+        );
+
+        // smaller server window, invalid, duplicate and unknown parameters are declined.
+        for offer in &[
+            "x-webkit-deflate-frame",
+            "permessage-deflate; server_max_window_bits=10",
+            "permessage-deflate; client_max_window_bits=16",
+            "permessage-deflate; server_no_context_takeover; server_no_context_takeover",
+            "permessage-deflate; server_no_context_takeover=1",
+            "permessage-deflate; foo",
+        ] {
+            assert_eq!(DeflateConfig::from_offer(offer), None, "{}", offer);
+        }
+
+        let config = DeflateConfig {
+            server_no_context_takeover: true,
+            client_no_context_takeover: true,
+        };
+        assert_eq!(DeflateConfig::from_response(&config.header_value()), Ok(config));
+        assert_eq!(
+            DeflateConfig::from_response("permessage-deflate; server_max_window_bits=9; client_max_window_bits=15"),
+            Ok(DeflateConfig::default())
+        );
+        assert_eq!(
+            DeflateConfig::from_response("permessage-deflate; client_max_window_bits=10"),
+            Err(HandshakeError::BadWebsocketExtension)
+        );
+        assert_eq!(
+            DeflateConfig::from_response("permessage-deflate; foo"),
+            Err(HandshakeError::BadWebsocketExtension)
+        );
+    }
+
+    #[test]
+    fn context_takeover() {
+        let payload = b"Hello, Hello, Hello".repeat(8);
+
+        for &no_context_takeover in &[false, true] {
+            let sender = Deflate::new();
+            let receiver = Deflate::new();
+
+            let first = sender.compress(&payload, no_context_takeover);
+            let second = sender.compress(&payload, no_context_takeover);
+            assert!(!first.ends_with(&TAIL));
+            assert!(first.len() < payload.len());
+            // a repeated message refers back to the previous one when context is kept.
+            assert_eq!(second.len() < first.len(), !no_context_takeover);
+
+            for compressed in &[first, second] {
+                let decompressed = receiver.decompress(compressed, true, |_| Ok(())).unwrap();
+                assert_eq!(&decompressed[..], &payload[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn fragmented() {
+        let payload = (0..=255u8).cycle().take(4096).collect::<Vec<_>>();
+        let compressed = Deflate::new().compress(&payload, false);
+
+        let receiver = Deflate::new();
+        let mut decompressed = Vec::new();
+        let chunks = compressed.chunks(7).collect::<Vec<_>>();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let last = i == chunks.len() - 1;
+            let out = receiver.decompress(chunk, last, |_| Ok(())).unwrap();
+            decompressed.extend_from_slice(&out);
+        }
+        assert_eq!(decompressed, payload);
+    }
+
+    #[test]
+    fn limit() {
+        let compressed = Deflate::new().compress(&[0; 4096], false);
+
+        let check = |len| {
+            if len > 1024 {
+                Err(ProtocolError::TextTooLarge(len))
+            } else {
+                Ok(())
+            }
+        };
+
+        assert!(matches!(
+            Deflate::new().decompress(&compressed, true, check),
+            Err(ProtocolError::TextTooLarge(_))
+        ));
+        assert!(matches!(
+            Deflate::new().decompress(&[0xff; 8], true, check),
+            Err(ProtocolError::InvalidDeflate)
+        ));
+    }
+}
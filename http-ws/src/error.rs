@@ -1,6 +1,6 @@
 use std::{error, fmt, io};
 
-use super::proto::OpCode;
+use super::proto::{CloseCode, OpCode};
 
 /// WebSocket protocol errors.
 #[derive(Debug)]
@@ -9,8 +9,22 @@ pub enum ProtocolError {
     MaskedFrame,
     InvalidOpcode(u8),
     InvalidLength(usize),
+    /// Reserved bits are set while no extension using them is negotiated.
+    ReservedBits(u8),
+    /// Payload length is not encoded in minimal number of bytes.
+    NonMinimalLength(u64),
+    /// Close code not allowed in a close frame. See [CloseCode::is_allowed].
+    InvalidCloseCode(u16),
     BadOpCode,
-    Overflow,
+    /// Payload length exceeds frame size limit.
+    Overflow(usize),
+    TextTooLarge(usize),
+    BinaryTooLarge(usize),
+    ContinuationTooLarge(usize),
+    ControlTooLarge(usize),
+    InvalidUtf8,
+    /// Payload of compressed message can not be decompressed.
+    InvalidDeflate,
     ContinuationNotStarted,
     ContinuationStarted,
     ContinuationFragment(OpCode),
@@ -22,10 +36,19 @@ impl fmt::Display for ProtocolError {
         match *self {
             Self::UnmaskedFrame => write!(f, "Received an unmasked frame from client."),
             Self::MaskedFrame => write!(f, "Received a masked frame from server."),
-            Self::InvalidOpcode(code) => write!(f, " Encountered invalid OpCode: {}", code),
+            Self::InvalidOpcode(code) => write!(f, "Encountered invalid OpCode: {:#04x}.", code),
             Self::InvalidLength(len) => write!(f, "Invalid control frame length: {}.", len),
+            Self::ReservedBits(bits) => write!(f, "Reserved bits are set: {:#05b}.", bits),
+            Self::NonMinimalLength(len) => write!(f, "Payload length {} is not minimally encoded.", len),
+            Self::InvalidCloseCode(code) => write!(f, "Invalid close code: {}.", code),
             Self::BadOpCode => write!(f, "Bad opcode."),
-            Self::Overflow => write!(f, "A payload reached size limit."),
+            Self::Overflow(size) => write!(f, "Frame payload of {} bytes exceeds limit.", size),
+            Self::TextTooLarge(size) => write!(f, "Text payload of {} bytes exceeds limit.", size),
+            Self::BinaryTooLarge(size) => write!(f, "Binary payload of {} bytes exceeds limit.", size),
+            Self::ContinuationTooLarge(size) => write!(f, "Continuation payload of {} bytes exceeds limit.", size),
+            Self::ControlTooLarge(size) => write!(f, "Control frame payload of {} bytes exceeds limit.", size),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 in text message."),
+            Self::InvalidDeflate => write!(f, "Invalid deflate data in compressed message."),
             Self::ContinuationNotStarted => write!(f, "Continuation is not started."),
             Self::ContinuationStarted => write!(f, "Received new continuation but it is already started."),
             Self::ContinuationFragment(ref code) => write!(f, "Unknown continuation fragment with OpCode: {}.", code),
@@ -34,7 +57,28 @@ impl fmt::Display for ProtocolError {
     }
 }
 
-impl error::Error for ProtocolError {}
+impl ProtocolError {
+    /// Close code of close message that should be sent to peer for the error.
+    pub fn close_code(&self) -> CloseCode {
+        match *self {
+            Self::InvalidUtf8 => CloseCode::Invalid,
+            Self::Overflow(_) | Self::TextTooLarge(_) | Self::BinaryTooLarge(_) | Self::ContinuationTooLarge(_) => {
+                CloseCode::Size
+            }
+            Self::Io(_) => CloseCode::Error,
+            _ => CloseCode::Protocol,
+        }
+    }
+}
+
+impl error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Self::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<OpCode> for ProtocolError {
     fn from(e: OpCode) -> Self {
@@ -48,6 +92,15 @@ impl From<io::Error> for ProtocolError {
     }
 }
 
+impl From<ProtocolError> for io::Error {
+    fn from(e: ProtocolError) -> Self {
+        match e {
+            ProtocolError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
 /// WebSocket handshake errors
 #[derive(PartialEq, Debug)]
 pub enum HandshakeError {
@@ -57,17 +110,27 @@ pub enum HandshakeError {
     NoVersionHeader,
     UnsupportedVersion,
     BadWebsocketKey,
+    SwitchingProtocolsRequired,
+    NoWebsocketAccept,
+    BadWebsocketAccept,
+    BadWebsocketProtocol,
+    BadWebsocketExtension,
 }
 
 impl fmt::Display for HandshakeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             Self::GetMethodRequired => write!(f, "Only get method is allowed."),
-            Self::NoWebsocketUpgrade => write!(f, "grade header if not set to WebSocket."),
+            Self::NoWebsocketUpgrade => write!(f, "Upgrade header is not set to WebSocket."),
             Self::NoConnectionUpgrade => write!(f, "Connection header is not set to upgrade."),
-            Self::NoVersionHeader => write!(f, " WebSocket version header is not set."),
+            Self::NoVersionHeader => write!(f, "WebSocket version header is not set."),
             Self::UnsupportedVersion => write!(f, "Unsupported WebSocket version."),
             Self::BadWebsocketKey => write!(f, "WebSocket key is not set or wrong."),
+            Self::SwitchingProtocolsRequired => write!(f, "Response status is not 101 Switching Protocols."),
+            Self::NoWebsocketAccept => write!(f, "WebSocket accept key is not set."),
+            Self::BadWebsocketAccept => write!(f, "WebSocket accept key does not match request key."),
+            Self::BadWebsocketProtocol => write!(f, "WebSocket protocol is not one of requested protocols."),
+            Self::BadWebsocketExtension => write!(f, "WebSocket extension is not one of requested extensions."),
         }
     }
 }
@@ -1,205 +1,457 @@
 //! Copy from [actix-http](https://github.com/actix/actix-web)
 
-use std::convert::TryFrom;
+use std::{convert::TryFrom, str};
 
-use bytes::{Buf, BufMut, BytesMut};
-use log::debug;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 use super::error::ProtocolError;
-use super::mask::apply_mask;
+use super::mask::{apply_mask, random_mask};
 use super::proto::{CloseCode, CloseReason, OpCode};
 
+/// RSV1 bit of [FrameHeader::rsv]. Set on the first frame of message compressed with
+/// permessage-deflate.
+pub const RSV1: u8 = 0b100;
+
 /// A struct representing a WebSocket frame.
 #[derive(Debug)]
 pub struct Parser;
 
-pub type MetaData = (usize, bool, OpCode, usize, Option<[u8; 4]>);
+/// Max payload size of incoming frame. `frame` applies to all frames and the rest to their
+/// respective frame types.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub frame: usize,
+    pub text: usize,
+    pub binary: usize,
+    pub continuation: usize,
+    pub control: usize,
+}
 
-impl Parser {
-    fn parse_metadata(src: &[u8], server: bool, max_size: usize) -> Result<Option<MetaData>, ProtocolError> {
-        let chunk_len = src.len();
+impl Limits {
+    pub const fn new(frame: usize) -> Self {
+        Self {
+            frame,
+            text: usize::MAX,
+            binary: usize::MAX,
+            continuation: usize::MAX,
+            control: 125,
+        }
+    }
 
-        let mut idx = 2;
-        if chunk_len < 2 {
+    pub(crate) fn check(&self, opcode: OpCode, length: usize) -> Result<(), ProtocolError> {
+        if length > self.frame {
+            return Err(ProtocolError::Overflow(length));
+        }
+
+        match opcode {
+            OpCode::Text if length > self.text => Err(ProtocolError::TextTooLarge(length)),
+            OpCode::Binary if length > self.binary => Err(ProtocolError::BinaryTooLarge(length)),
+            OpCode::Continue if length > self.continuation => Err(ProtocolError::ContinuationTooLarge(length)),
+            OpCode::Ping | OpCode::Pong | OpCode::Close if length > self.control => {
+                Err(ProtocolError::ControlTooLarge(length))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Header of a WebSocket frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameHeader {
+    pub fin: bool,
+    /// RSV1, RSV2 and RSV3 bits. RSV1 is [RSV1].
+    pub rsv: u8,
+    pub opcode: OpCode,
+    /// Payload length.
+    pub len: u64,
+    pub mask: Option<[u8; 4]>,
+}
+
+impl FrameHeader {
+    /// Parse header from the start of given bytes. Return the header and its size in bytes or
+    /// `None` when header is incomplete.
+    pub fn parse(src: &[u8]) -> Result<Option<(Self, usize)>, ProtocolError> {
+        if src.len() < 2 {
             return Ok(None);
         }
 
         let first = src[0];
         let second = src[1];
-        let finished = first & 0x80 != 0;
-
-        // check masking
-        let masked = second & 0x80 != 0;
-        if !masked && server {
-            return Err(ProtocolError::UnmaskedFrame);
-        } else if masked && !server {
-            return Err(ProtocolError::MaskedFrame);
-        }
 
-        // Op code
         let opcode = OpCode::from(first & 0x0F);
-
         if let OpCode::Bad = opcode {
             return Err(ProtocolError::InvalidOpcode(first & 0x0F));
         }
 
-        let len = second & 0x7F;
-        let length = if len == 126 {
-            if chunk_len < 4 {
-                return Ok(None);
-            }
-            let len = usize::from(u16::from_be_bytes(TryFrom::try_from(&src[idx..idx + 2]).unwrap()));
-            idx += 2;
-            len
-        } else if len == 127 {
-            if chunk_len < 10 {
-                return Ok(None);
+        let mut idx = 2;
+        let len = match second & 0x7F {
+            126 => {
+                if src.len() < 4 {
+                    return Ok(None);
+                }
+                idx += 2;
+                u64::from(u16::from_be_bytes(TryFrom::try_from(&src[2..4]).unwrap()))
             }
-            let len = u64::from_be_bytes(TryFrom::try_from(&src[idx..idx + 8]).unwrap());
-            if len > max_size as u64 {
-                return Err(ProtocolError::Overflow);
+            127 => {
+                if src.len() < 10 {
+                    return Ok(None);
+                }
+                idx += 8;
+                u64::from_be_bytes(TryFrom::try_from(&src[2..10]).unwrap())
             }
-            idx += 8;
-            len as usize
-        } else {
-            len as usize
+            len => u64::from(len),
         };
 
-        // check for max allowed size
-        if length > max_size {
-            return Err(ProtocolError::Overflow);
-        }
-
-        let mask = if server {
-            if chunk_len < idx + 4 {
+        let mask = if second & 0x80 != 0 {
+            if src.len() < idx + 4 {
                 return Ok(None);
             }
-
             let mask = TryFrom::try_from(&src[idx..idx + 4]).unwrap();
-
             idx += 4;
-
             Some(mask)
         } else {
             None
         };
 
-        Ok(Some((idx, finished, opcode, length, mask)))
+        let header = Self {
+            fin: first & 0x80 != 0,
+            rsv: (first >> 4) & 0x07,
+            opcode,
+            len,
+            mask,
+        };
+
+        Ok(Some((header, idx)))
     }
 
-    /// Parse the input stream into a frame.
-    pub fn parse(
-        src: &mut BytesMut,
+    /// Size of encoded header in bytes.
+    pub fn size(&self) -> usize {
+        let len = match self.len {
+            0..=125 => 2,
+            126..=65_535 => 4,
+            _ => 10,
+        };
+
+        len + if self.mask.is_some() { 4 } else { 0 }
+    }
+
+    /// Write encoded header to given buffer.
+    pub fn write(&self, dst: &mut BytesMut) {
+        let one = if self.fin { 0x80 } else { 0 } | self.rsv << 4 | u8::from(self.opcode);
+        let two = if self.mask.is_some() { 0x80 } else { 0 };
+
+        match self.len {
+            0..=125 => dst.put_slice(&[one, two | self.len as u8]),
+            126..=65_535 => {
+                dst.put_slice(&[one, two | 126]);
+                dst.put_u16(self.len as u16);
+            }
+            _ => {
+                dst.put_slice(&[one, two | 127]);
+                dst.put_u64(self.len);
+            }
+        }
+
+        if let Some(mask) = self.mask {
+            dst.put_slice(&mask);
+        }
+    }
+}
+
+/// Mask/unmask payload split into chunks.
+#[derive(Debug, Clone, Copy)]
+struct Masker {
+    mask: Option<[u8; 4]>,
+    offset: usize,
+}
+
+impl Masker {
+    fn new(mask: Option<[u8; 4]>) -> Self {
+        Self { mask, offset: 0 }
+    }
+
+    fn apply(&mut self, chunk: &mut [u8]) {
+        if let Some(mut mask) = self.mask {
+            // continue from where previous chunk ends.
+            mask.rotate_left(self.offset & 3);
+            apply_mask(chunk, mask);
+            self.offset += chunk.len();
+        }
+    }
+}
+
+/// Item decoded by [FrameDecoder].
+#[derive(Debug, PartialEq)]
+pub enum FrameItem {
+    Header(FrameHeader),
+    /// A chunk of unmasked payload. `last` is true when it's the end of frame payload.
+    Payload {
+        bytes: Bytes,
+        last: bool,
+    },
+}
+
+/// Incremental frame decoder yielding frame header and payload chunks as they arrive. Payload is
+/// never buffered as a whole so frame size is not limited.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    server: bool,
+    payload: Option<(u64, Masker)>,
+}
+
+impl FrameDecoder {
+    /// Construct decoder. Frames from client are expected masked when `server` is true and
+    /// unmasked otherwise.
+    pub fn new(server: bool) -> Self {
+        Self { server, payload: None }
+    }
+
+    pub fn decode(&mut self, src: &mut BytesMut) -> Result<Option<FrameItem>, ProtocolError> {
+        match self.payload {
+            Some((ref mut remaining, ref mut masker)) => {
+                if src.is_empty() {
+                    return Ok(None);
+                }
+
+                let n = usize::try_from(*remaining).map_or(src.len(), |remaining| remaining.min(src.len()));
+                let mut bytes = src.split_to(n);
+                masker.apply(&mut bytes);
+                *remaining -= n as u64;
+
+                let last = *remaining == 0;
+                if last {
+                    self.payload = None;
+                }
+
+                Ok(Some(FrameItem::Payload {
+                    bytes: bytes.freeze(),
+                    last,
+                }))
+            }
+            None => {
+                let (header, size) = match FrameHeader::parse(src)? {
+                    Some(res) => res,
+                    None => return Ok(None),
+                };
+
+                check_mask(&header, self.server)?;
+                src.advance(size);
+
+                if header.len > 0 {
+                    self.payload = Some((header.len, Masker::new(header.mask)));
+                }
+
+                Ok(Some(FrameItem::Header(header)))
+            }
+        }
+    }
+}
+
+/// Incremental frame encoder writing frame header and payload chunks.
+#[derive(Debug, Default)]
+pub struct FrameEncoder {
+    remaining: u64,
+    masker: Option<Masker>,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write frame header. Payload of `header.len` bytes is expected to follow.
+    ///
+    /// # Panics
+    /// When payload of previous frame is not finished.
+    pub fn header(&mut self, header: &FrameHeader, dst: &mut BytesMut) {
+        assert_eq!(self.remaining, 0, "payload of previous frame is not finished");
+        header.write(dst);
+        self.remaining = header.len;
+        self.masker = Some(Masker::new(header.mask));
+    }
+
+    /// Write a chunk of payload. It's masked when frame header has a mask.
+    ///
+    /// # Panics
+    /// When written payload exceeds length of frame header.
+    pub fn payload(&mut self, chunk: &[u8], dst: &mut BytesMut) {
+        assert!(
+            chunk.len() as u64 <= self.remaining,
+            "payload exceeds length of frame header"
+        );
+        self.remaining -= chunk.len() as u64;
+
+        let pos = dst.len();
+        dst.put_slice(chunk);
+        if let Some(ref mut masker) = self.masker {
+            masker.apply(&mut dst[pos..]);
+        }
+    }
+}
+
+fn check_mask(header: &FrameHeader, server: bool) -> Result<(), ProtocolError> {
+    match (header.mask.is_some(), server) {
+        (false, true) => Err(ProtocolError::UnmaskedFrame),
+        (true, false) => Err(ProtocolError::MaskedFrame),
+        _ => Ok(()),
+    }
+}
+
+// reject what is not needed to parse a frame but is not allowed by protocol. reserved bits other
+// than `rsv` used by negotiated extension on the first frame of data message must be unset. size is
+// the size of parsed header.
+fn check_strict(header: &FrameHeader, size: usize, rsv: u8) -> Result<(), ProtocolError> {
+    let rsv = match header.opcode {
+        OpCode::Text | OpCode::Binary => rsv,
+        _ => 0,
+    };
+
+    if header.rsv & !rsv != 0 {
+        return Err(ProtocolError::ReservedBits(header.rsv));
+    }
+
+    // payload length must be encoded in minimal number of bytes.
+    if size != header.size() {
+        return Err(ProtocolError::NonMinimalLength(header.len));
+    }
+
+    Ok(())
+}
+
+impl Parser {
+    /// Parse and validate frame header. Return the header and its size in bytes. Reserved bits and
+    /// length encoding are validated when `strict` is true.
+    pub fn parse_header(
+        src: &[u8],
         server: bool,
-        max_size: usize,
-    ) -> Result<Option<(bool, OpCode, Option<BytesMut>)>, ProtocolError> {
-        // try to parse ws frame metadata
-        let (idx, finished, opcode, length, mask) = match Parser::parse_metadata(src, server, max_size)? {
+        strict: bool,
+        limits: &Limits,
+    ) -> Result<Option<(FrameHeader, usize)>, ProtocolError> {
+        Self::parse_header_with_rsv(src, server, strict, 0, limits)
+    }
+
+    /// Parse and validate frame header like [Parser::parse_header]. Reserved bits in `rsv` are
+    /// allowed on text and binary frame for negotiated extension.
+    pub fn parse_header_with_rsv(
+        src: &[u8],
+        server: bool,
+        strict: bool,
+        rsv: u8,
+        limits: &Limits,
+    ) -> Result<Option<(FrameHeader, usize)>, ProtocolError> {
+        let (header, size) = match FrameHeader::parse(src)? {
             None => return Ok(None),
             Some(res) => res,
         };
 
+        check_mask(&header, server)?;
+        if strict {
+            check_strict(&header, size, rsv)?;
+        }
+
+        // check for max allowed size before payload is buffered.
+        let length = usize::try_from(header.len).unwrap_or(usize::MAX);
+        limits.check(header.opcode, length)?;
+
+        Ok(Some((header, size)))
+    }
+
+    /// Split frame with given header from the input stream when its payload is complete.
+    pub fn parse_payload(
+        src: &mut BytesMut,
+        header: &FrameHeader,
+        size: usize,
+    ) -> Option<(bool, OpCode, Option<BytesMut>)> {
+        let length = usize::try_from(header.len).unwrap_or(usize::MAX);
+
         // not enough data
-        if src.len() < idx + length {
-            return Ok(None);
+        if src.len() < size + length {
+            return None;
         }
 
         // remove prefix
-        src.advance(idx);
+        src.advance(size);
 
         // no need for body
         if length == 0 {
-            return Ok(Some((finished, opcode, None)));
+            return Some((header.fin, header.opcode, None));
         }
 
         let mut data = src.split_to(length);
+        Masker::new(header.mask).apply(&mut data);
 
-        // control frames must have length <= 125
-        match opcode {
-            OpCode::Ping | OpCode::Pong if length > 125 => {
-                return Err(ProtocolError::InvalidLength(length));
-            }
-            OpCode::Close if length > 125 => {
-                debug!("Received close frame with payload length exceeding 125. Morphing to protocol close frame.");
-                return Ok(Some((true, OpCode::Close, None)));
-            }
-            _ => {}
-        }
-
-        // unmask
-        if let Some(mask) = mask {
-            apply_mask(&mut data, mask);
-        }
-
-        Ok(Some((finished, opcode, Some(data))))
+        Some((header.fin, header.opcode, Some(data)))
     }
 
     /// Parse the payload of a close frame.
-    pub fn parse_close_payload(payload: &[u8]) -> Option<CloseReason> {
-        if payload.len() >= 2 {
-            let raw_code = u16::from_be_bytes(TryFrom::try_from(&payload[..2]).unwrap());
-            let code = CloseCode::from(raw_code);
-            let description = if payload.len() > 2 {
-                Some(String::from_utf8_lossy(&payload[2..]).into())
-            } else {
-                None
-            };
-            Some(CloseReason { code, description })
-        } else {
-            None
+    ///
+    /// Payload is either empty or a close code allowed on the wire followed by UTF-8 description.
+    pub fn parse_close_payload(payload: &[u8]) -> Result<Option<CloseReason>, ProtocolError> {
+        match payload.len() {
+            0 => Ok(None),
+            1 => Err(ProtocolError::InvalidLength(1)),
+            _ => {
+                let raw_code = u16::from_be_bytes(TryFrom::try_from(&payload[..2]).unwrap());
+                let code = CloseCode::from(raw_code);
+                if !code.is_allowed() {
+                    return Err(ProtocolError::InvalidCloseCode(raw_code));
+                }
+
+                let description = if payload.len() > 2 {
+                    let description = str::from_utf8(&payload[2..]).map_err(|_| ProtocolError::InvalidUtf8)?;
+                    Some(description.to_owned())
+                } else {
+                    None
+                };
+
+                Ok(Some(CloseReason { code, description }))
+            }
         }
     }
 
     /// Generate binary representation
     pub fn write_message<B: AsRef<[u8]>>(dst: &mut BytesMut, pl: B, op: OpCode, fin: bool, mask: bool) {
-        let payload = pl.as_ref();
-        let one: u8 = if fin { 0x80 | Into::<u8>::into(op) } else { op.into() };
-        let payload_len = payload.len();
-        let (two, p_len) = if mask {
-            (0x80, payload_len + 4)
-        } else {
-            (0, payload_len)
-        };
-
-        if payload_len < 126 {
-            dst.reserve(p_len + 2 + if mask { 4 } else { 0 });
-            dst.put_slice(&[one, two | payload_len as u8]);
-        } else if payload_len <= 65_535 {
-            dst.reserve(p_len + 4 + if mask { 4 } else { 0 });
-            dst.put_slice(&[one, two | 126]);
-            dst.put_u16(payload_len as u16);
-        } else {
-            dst.reserve(p_len + 10 + if mask { 4 } else { 0 });
-            dst.put_slice(&[one, two | 127]);
-            dst.put_u64(payload_len as u64);
-        };
+        Self::write_frame(dst, &[pl.as_ref()], op, fin, 0, mask)
+    }
 
-        if mask {
-            let mask = rand::random::<[u8; 4]>();
-            dst.put_slice(mask.as_ref());
-            dst.put_slice(payload.as_ref());
-            let pos = dst.len() - payload_len;
-            apply_mask(&mut dst[pos..], mask);
-        } else {
-            dst.put_slice(payload.as_ref());
-        }
+    /// Generate binary representation of unfragmented message compressed with permessage-deflate.
+    /// RSV1 bit is set.
+    #[cfg(feature = "deflate")]
+    pub fn write_compressed<B: AsRef<[u8]>>(dst: &mut BytesMut, pl: B, op: OpCode, mask: bool) {
+        Self::write_frame(dst, &[pl.as_ref()], op, true, RSV1, mask)
     }
 
     /// Create a new Close control frame.
     #[inline]
     pub fn write_close(dst: &mut BytesMut, reason: Option<CloseReason>, mask: bool) {
-        let payload = match reason {
-            None => Vec::new(),
+        match reason {
+            None => Self::write_frame(dst, &[], OpCode::Close, true, 0, mask),
             Some(reason) => {
-                let mut payload = Into::<u16>::into(reason.code).to_be_bytes().to_vec();
-                if let Some(description) = reason.description {
-                    payload.extend(description.as_bytes());
-                }
-                payload
+                let code = Into::<u16>::into(reason.code).to_be_bytes();
+                let description = reason.description.as_deref().unwrap_or("");
+                Self::write_frame(dst, &[&code, description.as_bytes()], OpCode::Close, true, 0, mask)
             }
+        }
+    }
+
+    // write frame with payload concatenated from given parts. the exact frame size is reserved
+    // before writing.
+    fn write_frame(dst: &mut BytesMut, parts: &[&[u8]], op: OpCode, fin: bool, rsv: u8, mask: bool) {
+        let header = FrameHeader {
+            fin,
+            rsv,
+            opcode: op,
+            len: parts.iter().map(|part| part.len() as u64).sum(),
+            mask: if mask { Some(random_mask()) } else { None },
         };
 
-        Parser::write_message(dst, payload, OpCode::Close, true, mask)
+        dst.reserve(header.size() + header.len as usize);
+
+        let mut encoder = FrameEncoder::new();
+        encoder.header(&header, dst);
+        for part in parts {
+            encoder.payload(part, dst);
+        }
     }
 }
 
@@ -208,6 +460,19 @@ mod tests {
     use super::*;
     use bytes::Bytes;
 
+    // parse a frame at once.
+    fn parse(
+        src: &mut BytesMut,
+        server: bool,
+        strict: bool,
+        limits: &Limits,
+    ) -> Result<Option<(bool, OpCode, Option<BytesMut>)>, ProtocolError> {
+        match Parser::parse_header(src, server, strict, limits)? {
+            Some((header, size)) => Ok(Parser::parse_payload(src, &header, size)),
+            None => Ok(None),
+        }
+    }
+
     struct F {
         finished: bool,
         opcode: OpCode,
@@ -232,12 +497,12 @@ mod tests {
     #[test]
     fn test_parse() {
         let mut buf = BytesMut::from(&[0b0000_0001u8, 0b0000_0001u8][..]);
-        assert!(is_none(&Parser::parse(&mut buf, false, 1024)));
+        assert!(is_none(&parse(&mut buf, false, true, &Limits::new(1024))));
 
         let mut buf = BytesMut::from(&[0b0000_0001u8, 0b0000_0001u8][..]);
         buf.extend(b"1");
 
-        let frame = extract(Parser::parse(&mut buf, false, 1024));
+        let frame = extract(parse(&mut buf, false, true, &Limits::new(1024)));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload.as_ref(), &b"1"[..]);
@@ -246,7 +511,7 @@ mod tests {
     #[test]
     fn test_parse_length0() {
         let mut buf = BytesMut::from(&[0b0000_0001u8, 0b0000_0000u8][..]);
-        let frame = extract(Parser::parse(&mut buf, false, 1024));
+        let frame = extract(parse(&mut buf, false, true, &Limits::new(1024)));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert!(frame.payload.is_empty());
@@ -255,13 +520,18 @@ mod tests {
     #[test]
     fn test_parse_length2() {
         let mut buf = BytesMut::from(&[0b0000_0001u8, 126u8][..]);
-        assert!(is_none(&Parser::parse(&mut buf, false, 1024)));
+        assert!(is_none(&parse(&mut buf, false, true, &Limits::new(1024))));
 
         let mut buf = BytesMut::from(&[0b0000_0001u8, 126u8][..]);
         buf.extend(&[0u8, 4u8][..]);
         buf.extend(b"1234");
 
-        let frame = extract(Parser::parse(&mut buf, false, 1024));
+        // 4 bytes payload length is not minimally encoded.
+        assert!(matches!(
+            parse(&mut buf.clone(), false, true, &Limits::new(1024)),
+            Err(ProtocolError::NonMinimalLength(4))
+        ));
+        let frame = extract(parse(&mut buf, false, false, &Limits::new(1024)));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload.as_ref(), &b"1234"[..]);
@@ -270,27 +540,64 @@ mod tests {
     #[test]
     fn test_parse_length4() {
         let mut buf = BytesMut::from(&[0b0000_0001u8, 127u8][..]);
-        assert!(is_none(&Parser::parse(&mut buf, false, 1024)));
+        assert!(is_none(&parse(&mut buf, false, true, &Limits::new(1024))));
 
         let mut buf = BytesMut::from(&[0b0000_0001u8, 127u8][..]);
         buf.extend(&[0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 4u8][..]);
         buf.extend(b"1234");
 
-        let frame = extract(Parser::parse(&mut buf, false, 1024));
+        // 4 bytes payload length is not minimally encoded.
+        assert!(matches!(
+            parse(&mut buf.clone(), false, true, &Limits::new(1024)),
+            Err(ProtocolError::NonMinimalLength(4))
+        ));
+        let frame = extract(parse(&mut buf, false, false, &Limits::new(1024)));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload.as_ref(), &b"1234"[..]);
     }
 
+    #[test]
+    fn test_parse_reserved_bits() {
+        let mut buf = BytesMut::from(&[0b1100_0001u8, 0b0000_0001u8][..]);
+        buf.extend(b"1");
+
+        assert!(matches!(
+            parse(&mut buf.clone(), false, true, &Limits::new(1024)),
+            Err(ProtocolError::ReservedBits(0b100))
+        ));
+        let frame = extract(parse(&mut buf.clone(), false, false, &Limits::new(1024)));
+        assert!(frame.finished);
+        assert_eq!(frame.payload.as_ref(), &b"1"[..]);
+
+        // RSV1 of negotiated extension is allowed on data frame only.
+        let (header, _) = Parser::parse_header_with_rsv(&buf, false, true, RSV1, &Limits::new(1024))
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.rsv, RSV1);
+
+        let buf = BytesMut::from(&[0b1100_1001u8, 0b0000_0000u8][..]);
+        assert!(matches!(
+            Parser::parse_header_with_rsv(&buf, false, true, RSV1, &Limits::new(1024)),
+            Err(ProtocolError::ReservedBits(0b100))
+        ));
+
+        let buf = BytesMut::from(&[0b1010_0001u8, 0b0000_0000u8][..]);
+        assert!(matches!(
+            Parser::parse_header_with_rsv(&buf, false, true, RSV1, &Limits::new(1024)),
+            Err(ProtocolError::ReservedBits(0b010))
+        ));
+    }
+
     #[test]
     fn test_parse_frame_mask() {
         let mut buf = BytesMut::from(&[0b0000_0001u8, 0b1000_0001u8][..]);
         buf.extend(b"0001");
         buf.extend(b"1");
 
-        assert!(Parser::parse(&mut buf, false, 1024).is_err());
+        assert!(parse(&mut buf, false, true, &Limits::new(1024)).is_err());
 
-        let frame = extract(Parser::parse(&mut buf, true, 1024));
+        let frame = extract(parse(&mut buf, true, true, &Limits::new(1024)));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload, Bytes::from(vec![1u8]));
@@ -301,9 +608,9 @@ mod tests {
         let mut buf = BytesMut::from(&[0b0000_0001u8, 0b0000_0001u8][..]);
         buf.extend(&[1u8]);
 
-        assert!(Parser::parse(&mut buf, true, 1024).is_err());
+        assert!(parse(&mut buf, true, true, &Limits::new(1024)).is_err());
 
-        let frame = extract(Parser::parse(&mut buf, false, 1024));
+        let frame = extract(parse(&mut buf, false, true, &Limits::new(1024)));
         assert!(!frame.finished);
         assert_eq!(frame.opcode, OpCode::Text);
         assert_eq!(frame.payload, Bytes::from(vec![1u8]));
@@ -314,14 +621,60 @@ mod tests {
         let mut buf = BytesMut::from(&[0b0000_0001u8, 0b0000_0010u8][..]);
         buf.extend(&[1u8, 1u8]);
 
-        assert!(Parser::parse(&mut buf, true, 1).is_err());
+        assert!(parse(&mut buf, true, true, &Limits::new(1)).is_err());
 
-        if let Err(ProtocolError::Overflow) = Parser::parse(&mut buf, false, 0) {
+        if let Err(ProtocolError::Overflow(2)) = parse(&mut buf, false, true, &Limits::new(0)) {
         } else {
             unreachable!("error");
         }
     }
 
+    #[test]
+    fn test_parse_frame_limits() {
+        let mut limits = Limits::new(1024);
+        limits.text = 2;
+        limits.binary = 4;
+        limits.continuation = 1;
+
+        let frame = |opcode: OpCode, len: usize| {
+            let mut buf = BytesMut::new();
+            Parser::write_message(&mut buf, vec![1u8; len], opcode, true, false);
+            buf
+        };
+
+        assert!(parse(&mut frame(OpCode::Text, 2), false, true, &limits).is_ok());
+        assert!(matches!(
+            parse(&mut frame(OpCode::Text, 3), false, true, &limits),
+            Err(ProtocolError::TextTooLarge(3))
+        ));
+        assert!(parse(&mut frame(OpCode::Binary, 4), false, true, &limits).is_ok());
+        assert!(matches!(
+            parse(&mut frame(OpCode::Binary, 5), false, true, &limits),
+            Err(ProtocolError::BinaryTooLarge(5))
+        ));
+        assert!(matches!(
+            parse(&mut frame(OpCode::Continue, 2), false, true, &limits),
+            Err(ProtocolError::ContinuationTooLarge(2))
+        ));
+        assert!(parse(&mut frame(OpCode::Close, 125), false, true, &limits).is_ok());
+        assert!(matches!(
+            parse(&mut frame(OpCode::Close, 126), false, true, &limits),
+            Err(ProtocolError::ControlTooLarge(126))
+        ));
+        assert!(matches!(
+            parse(&mut frame(OpCode::Ping, 1025), false, true, &limits),
+            Err(ProtocolError::Overflow(1025))
+        ));
+
+        // only header is needed to reject a frame.
+        let mut buf = frame(OpCode::Text, 70_000);
+        buf.truncate(10);
+        assert!(matches!(
+            parse(&mut buf, false, true, &limits),
+            Err(ProtocolError::Overflow(70_000))
+        ));
+    }
+
     #[test]
     fn test_ping_frame() {
         let mut buf = BytesMut::new();
@@ -359,4 +712,139 @@ mod tests {
         Parser::write_close(&mut buf, None, false);
         assert_eq!(&buf[..], &vec![0x88, 0x00][..]);
     }
+
+    #[test]
+    fn test_reserve_exact() {
+        let payload = vec![0; 1024 * 1024];
+
+        for &mask in &[false, true] {
+            let mut buf = BytesMut::new();
+            Parser::write_message(&mut buf, &payload, OpCode::Binary, true, mask);
+            assert_eq!(buf.len(), payload.len() + 10 + if mask { 4 } else { 0 });
+            assert_eq!(buf.capacity(), buf.len());
+        }
+
+        let mut buf = BytesMut::new();
+        Parser::write_close(&mut buf, Some((CloseCode::Normal, "data").into()), true);
+        assert_eq!(buf.capacity(), buf.len());
+    }
+
+    #[test]
+    fn test_frame_header() {
+        for &len in &[0, 125, 126, 65_535, 65_536, u64::MAX] {
+            for &mask in &[None, Some([1, 2, 3, 4])] {
+                let header = FrameHeader {
+                    fin: len % 2 == 0,
+                    rsv: 0,
+                    opcode: OpCode::Binary,
+                    len,
+                    mask,
+                };
+                let mut buf = BytesMut::new();
+                header.write(&mut buf);
+                assert_eq!(buf.len(), header.size());
+                assert_eq!(FrameHeader::parse(&buf).unwrap(), Some((header, buf.len())));
+                assert_eq!(FrameHeader::parse(&buf[..buf.len() - 1]).unwrap(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame_chunks() {
+        let payload = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+
+        // unmasked encoder output is the same as whole message.
+        let mut whole = BytesMut::new();
+        Parser::write_message(&mut whole, &payload, OpCode::Text, false, false);
+        let mut encoder = FrameEncoder::new();
+        let mut buf = BytesMut::new();
+        let header = FrameHeader {
+            fin: false,
+            rsv: 0,
+            opcode: OpCode::Text,
+            len: payload.len() as u64,
+            mask: None,
+        };
+        encoder.header(&header, &mut buf);
+        for chunk in payload.chunks(333) {
+            encoder.payload(chunk, &mut buf);
+        }
+        assert_eq!(buf, whole);
+
+        // masked payload is unmasked across chunk boundaries.
+        let header = FrameHeader {
+            mask: Some([7, 8, 9, 10]),
+            ..header
+        };
+        buf.clear();
+        encoder.header(&header, &mut buf);
+        for chunk in payload.chunks(3) {
+            encoder.payload(chunk, &mut buf);
+        }
+
+        let mut whole = buf.clone();
+        let (_, _, decoded) = parse(&mut whole, true, true, &Limits::new(1024)).unwrap().unwrap();
+        assert_eq!(decoded.unwrap(), payload);
+
+        let mut decoder = FrameDecoder::new(true);
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        let mut headers = 0;
+        for chunk in buf.chunks(5) {
+            src.extend_from_slice(chunk);
+            while let Some(item) = decoder.decode(&mut src).unwrap() {
+                match item {
+                    FrameItem::Header(h) => {
+                        assert_eq!(h, header);
+                        headers += 1;
+                    }
+                    FrameItem::Payload { bytes, last } => {
+                        decoded.extend_from_slice(&bytes);
+                        assert_eq!(last, decoded.len() == payload.len());
+                    }
+                }
+            }
+        }
+        assert_eq!(headers, 1);
+        assert_eq!(decoded, payload);
+
+        // client decoder rejects masked frame.
+        let mut buf = BytesMut::new();
+        header.write(&mut buf);
+        assert!(matches!(
+            FrameDecoder::new(false).decode(&mut buf),
+            Err(ProtocolError::MaskedFrame)
+        ));
+    }
+
+    #[test]
+    fn test_frame_decoder_byte_by_byte() {
+        let payload = (0..=255u8).cycle().take(1024).collect::<Vec<_>>();
+        let mut frame = BytesMut::new();
+        Parser::write_message(&mut frame, &payload, OpCode::Binary, true, true);
+
+        let mut decoder = FrameDecoder::new(true);
+        let mut buf = BytesMut::new();
+        let mut decoded = Vec::new();
+
+        for (i, byte) in frame.iter().enumerate() {
+            buf.extend_from_slice(&[*byte]);
+            match decoder.decode(&mut buf).unwrap() {
+                // header is yielded once its last byte arrives.
+                Some(FrameItem::Header(header)) => {
+                    assert_eq!(i, 7);
+                    assert_eq!(header.len, 1024);
+                }
+                // payload streams out as it arrives.
+                Some(FrameItem::Payload { bytes, last }) => {
+                    assert_eq!(bytes.len(), 1);
+                    decoded.extend_from_slice(&bytes);
+                    assert_eq!(last, i == frame.len() - 1);
+                }
+                None => assert!(i < 7),
+            }
+        }
+
+        assert_eq!(decoded, payload);
+    }
 }
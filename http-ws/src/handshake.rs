@@ -0,0 +1,750 @@
+use std::convert::TryFrom;
+
+use http::{
+    header::{self, HeaderValue},
+    request::{self, Request},
+    response::{Builder, Response},
+    HeaderMap, Method, StatusCode, Uri,
+};
+
+use super::codec::Codec;
+#[cfg(feature = "deflate")]
+use super::deflate::{DeflateConfig, PERMESSAGE_DEFLATE};
+use super::error::HandshakeError;
+use super::{mask, proto};
+
+/// Verify WebSocket handshake request and create handshake response.
+pub fn handshake(method: &Method, headers: &HeaderMap) -> Result<Builder, HandshakeError> {
+    let key = verify_handshake(method, headers)?;
+    let builder = handshake_response(key);
+    Ok(builder)
+}
+
+/// Verify WebSocket handshake request and return `SEC_WEBSOCKET_KEY` header value as `&[u8]`
+fn verify_handshake<'a>(method: &'a Method, headers: &'a HeaderMap) -> Result<&'a [u8], HandshakeError> {
+    // WebSocket accepts only GET
+    if method != Method::GET {
+        return Err(HandshakeError::GetMethodRequired);
+    }
+
+    verify_upgrade(headers)?;
+
+    // check supported version
+    let value = headers
+        .get(header::SEC_WEBSOCKET_VERSION)
+        .ok_or(HandshakeError::NoVersionHeader)?;
+
+    if value != "13" && value != "8" && value != "7" {
+        return Err(HandshakeError::UnsupportedVersion);
+    }
+
+    // check client handshake for validity
+    let value = headers
+        .get(header::SEC_WEBSOCKET_KEY)
+        .ok_or(HandshakeError::BadWebsocketKey)?;
+
+    Ok(value.as_bytes())
+}
+
+/// Create WebSocket handshake response.
+///
+/// This function returns handshake `http::response::Builder`, ready to send to peer.
+fn handshake_response(key: &[u8]) -> Builder {
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::UPGRADE, "websocket")
+        .header(header::CONNECTION, "upgrade")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept_key(key))
+}
+
+/// Derive `SEC_WEBSOCKET_ACCEPT` header value from `SEC_WEBSOCKET_KEY` header value.
+pub fn accept_key(key: &[u8]) -> HeaderValue {
+    let key = proto::hash_key(key);
+    // key is known to be header value safe ascii
+    HeaderValue::from_bytes(&key).unwrap()
+}
+
+/// Validated WebSocket handshake request.
+#[derive(Debug)]
+pub struct HandshakeParts {
+    key: HeaderValue,
+    protocols: Vec<String>,
+    protocol: Option<HeaderValue>,
+    extensions: Vec<String>,
+    #[cfg(feature = "deflate")]
+    deflate: Option<DeflateConfig>,
+}
+
+impl HandshakeParts {
+    /// `SEC_WEBSOCKET_ACCEPT` header value of handshake response.
+    pub fn accept(&self) -> HeaderValue {
+        accept_key(self.key.as_bytes())
+    }
+
+    /// Sub protocols requested by client in its preference order.
+    pub fn protocols(&self) -> impl Iterator<Item = &str> {
+        self.protocols.iter().map(String::as_str)
+    }
+
+    /// Select the first protocol from server preferred list that is requested by client.
+    ///
+    /// Selected protocol is added to response by [build_response]. Return `None` and response
+    /// does not contain any protocol when none of them is requested.
+    pub fn select_protocol(&mut self, preferred: &[&str]) -> Option<&str> {
+        self.protocol = preferred
+            .iter()
+            .find(|p| self.protocols.iter().any(|requested| requested == *p))
+            .and_then(|p| HeaderValue::from_str(p).ok());
+
+        self.protocol.as_ref().and_then(|p| p.to_str().ok())
+    }
+
+    /// Extensions offered by client with their parameters in its preference order.
+    pub fn extensions(&self) -> impl Iterator<Item = &str> {
+        self.extensions.iter().map(String::as_str)
+    }
+
+    /// Accept the first permessage-deflate offer of client that can be honored.
+    ///
+    /// Accepted extension is added to response by [build_response] and applied to codec from
+    /// [HandshakeParts::codec]. Return `None` when no offer can be accepted.
+    #[cfg(feature = "deflate")]
+    pub fn accept_deflate(&mut self) -> Option<DeflateConfig> {
+        self.deflate = self.extensions.iter().find_map(|ext| DeflateConfig::from_offer(ext));
+        self.deflate
+    }
+
+    /// Codec for decoding client messages and encoding server messages with accepted extensions.
+    pub fn codec(&self) -> Codec {
+        #[cfg(feature = "deflate")]
+        if let Some(config) = self.deflate {
+            return Codec::new()
+                .permessage_deflate(true)
+                .no_context_takeover(config.server_no_context_takeover);
+        }
+
+        Codec::new()
+    }
+}
+
+/// Verify WebSocket handshake request and collect what is needed for response.
+///
+/// # Examples:
+/// ```rust
+/// use http::{header, Request};
+/// use http_ws::{build_response, verify_request};
+///
+/// let req = Request::get("/")
+///     .header(header::UPGRADE, "websocket")
+///     .header(header::CONNECTION, "upgrade")
+///     .header(header::SEC_WEBSOCKET_VERSION, "13")
+///     .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+///     .header(header::SEC_WEBSOCKET_PROTOCOL, "chat, superchat")
+///     .body(())
+///     .unwrap();
+///
+/// let mut parts = verify_request(&req).unwrap();
+/// assert_eq!(parts.select_protocol(&["superchat"]), Some("superchat"));
+///
+/// let res = build_response(parts).body(()).unwrap();
+/// assert_eq!(res.headers()[header::SEC_WEBSOCKET_PROTOCOL], "superchat");
+/// ```
+pub fn verify_request<B>(req: &Request<B>) -> Result<HandshakeParts, HandshakeError> {
+    let key = verify_handshake(req.method(), req.headers())?;
+
+    let protocols = list(req.headers(), header::SEC_WEBSOCKET_PROTOCOL)
+        .map(String::from)
+        .collect();
+
+    let extensions = list(req.headers(), header::SEC_WEBSOCKET_EXTENSIONS)
+        .map(String::from)
+        .collect();
+
+    Ok(HandshakeParts {
+        // key is from a header value.
+        key: HeaderValue::from_bytes(key).unwrap(),
+        protocols,
+        protocol: None,
+        extensions,
+        #[cfg(feature = "deflate")]
+        deflate: None,
+    })
+}
+
+/// Create `101 Switching Protocols` response builder from verified handshake request.
+pub fn build_response(parts: HandshakeParts) -> Builder {
+    let mut builder = handshake_response(parts.key.as_bytes());
+
+    if let Some(protocol) = parts.protocol {
+        builder = builder.header(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+    }
+
+    #[cfg(feature = "deflate")]
+    if let Some(config) = parts.deflate {
+        builder = builder.header(header::SEC_WEBSOCKET_EXTENSIONS, config.header_value());
+    }
+
+    builder
+}
+
+/// Check for "Upgrade" and "Connection" header.
+fn verify_upgrade(headers: &HeaderMap) -> Result<(), HandshakeError> {
+    let contains = |name, value| {
+        headers
+            .get(name)
+            .and_then(|hdr| hdr.to_str().ok())
+            .filter(|s| s.to_ascii_lowercase().contains(value))
+            .is_some()
+    };
+
+    if !contains(header::UPGRADE, "websocket") {
+        return Err(HandshakeError::NoWebsocketUpgrade);
+    }
+
+    if !contains(header::CONNECTION, "upgrade") {
+        return Err(HandshakeError::NoConnectionUpgrade);
+    }
+
+    Ok(())
+}
+
+/// Comma separated values of all headers with given name.
+fn list(headers: &HeaderMap, name: header::HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Create client WebSocket handshake request for given uri.
+///
+/// Return request builder and generated `SEC_WEBSOCKET_KEY` header value that is needed for
+/// verifying server response with [verify_response].
+pub fn client_handshake<T>(uri: T) -> (request::Builder, HeaderValue)
+where
+    Uri: TryFrom<T>,
+    <Uri as TryFrom<T>>::Error: Into<http::Error>,
+{
+    let handshake = ClientHandshake::new();
+    let builder = handshake.request(uri);
+    (builder, handshake.key)
+}
+
+/// Generate random `SEC_WEBSOCKET_KEY` header value.
+fn client_key() -> HeaderValue {
+    let mut nonce = [0; 16];
+    nonce[..8].copy_from_slice(&mask::random_u64().to_ne_bytes());
+    nonce[8..].copy_from_slice(&mask::random_u64().to_ne_bytes());
+
+    let mut key = [0; 24];
+    let n = base64::encode_config_slice(nonce, base64::STANDARD, &mut key);
+    assert_eq!(n, 24);
+
+    // key is known to be header value safe ascii
+    HeaderValue::from_bytes(&key).unwrap()
+}
+
+/// Verify server handshake response of client handshake request sent with given
+/// `SEC_WEBSOCKET_KEY` header value.
+pub fn verify_response(key: &HeaderValue, status: StatusCode, headers: &HeaderMap) -> Result<(), HandshakeError> {
+    if status != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(HandshakeError::SwitchingProtocolsRequired);
+    }
+
+    verify_upgrade(headers)?;
+
+    let accept = headers
+        .get(header::SEC_WEBSOCKET_ACCEPT)
+        .ok_or(HandshakeError::NoWebsocketAccept)?;
+
+    if accept.as_bytes() != &proto::hash_key(key.as_bytes())[..] {
+        return Err(HandshakeError::BadWebsocketAccept);
+    }
+
+    Ok(())
+}
+
+/// Client WebSocket handshake with optional sub protocols and extensions.
+///
+/// # Examples:
+/// ```rust
+/// use http::{header, Response, StatusCode};
+/// use http_ws::ClientHandshake;
+///
+/// let handshake = ClientHandshake::new().protocols(&["chat"]);
+/// let req = handshake.request("ws://localhost/").body(()).unwrap();
+///
+/// // server response.
+/// let res = Response::builder()
+///     .status(StatusCode::SWITCHING_PROTOCOLS)
+///     .header(header::UPGRADE, "websocket")
+///     .header(header::CONNECTION, "upgrade")
+///     .header(header::SEC_WEBSOCKET_ACCEPT, http_ws::accept_key(handshake.key().as_bytes()))
+///     .header(header::SEC_WEBSOCKET_PROTOCOL, "chat")
+///     .body(())
+///     .unwrap();
+///
+/// let negotiated = handshake.verify(res.status(), res.headers()).unwrap();
+/// assert_eq!(negotiated.protocol(), Some("chat"));
+/// ```
+#[derive(Debug)]
+pub struct ClientHandshake {
+    key: HeaderValue,
+    protocols: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl Default for ClientHandshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientHandshake {
+    /// Construct handshake with random generated `SEC_WEBSOCKET_KEY` header value.
+    pub fn new() -> Self {
+        Self {
+            key: client_key(),
+            protocols: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Request sub protocols in preference order.
+    pub fn protocols(mut self, protocols: &[&str]) -> Self {
+        self.protocols.extend(protocols.iter().map(|p| String::from(*p)));
+        self
+    }
+
+    /// Request an extension. Parameters are separated from extension name with `;`.
+    pub fn extension(mut self, extension: &str) -> Self {
+        self.extensions.push(String::from(extension));
+        self
+    }
+
+    /// Request permessage-deflate extension. When server accepts it codec from
+    /// [Negotiated::codec] compresses and decompresses messages.
+    #[cfg(feature = "deflate")]
+    pub fn permessage_deflate(self) -> Self {
+        self.extension(PERMESSAGE_DEFLATE)
+    }
+
+    /// `SEC_WEBSOCKET_KEY` header value of handshake request.
+    pub fn key(&self) -> &HeaderValue {
+        &self.key
+    }
+
+    /// Create handshake request builder for given uri.
+    ///
+    /// # Panics
+    /// When requested sub protocol or extension is not valid header value.
+    pub fn request<T>(&self, uri: T) -> request::Builder
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        let mut builder = Request::get(uri)
+            .header(header::UPGRADE, "websocket")
+            .header(header::CONNECTION, "upgrade")
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+            .header(header::SEC_WEBSOCKET_KEY, self.key.clone());
+
+        if !self.protocols.is_empty() {
+            let value = HeaderValue::from_str(&self.protocols.join(", ")).unwrap();
+            builder = builder.header(header::SEC_WEBSOCKET_PROTOCOL, value);
+        }
+
+        if !self.extensions.is_empty() {
+            let value = HeaderValue::from_str(&self.extensions.join(", ")).unwrap();
+            builder = builder.header(header::SEC_WEBSOCKET_EXTENSIONS, value);
+        }
+
+        builder
+    }
+
+    /// Verify server handshake response and return what is negotiated.
+    ///
+    /// Server can only select one of requested sub protocols and accept requested extensions.
+    /// Parameters of accepted permessage-deflate extension must be supported by
+    /// [DeflateConfig](crate::DeflateConfig) when `deflate` feature is enabled.
+    pub fn verify(&self, status: StatusCode, headers: &HeaderMap) -> Result<Negotiated, HandshakeError> {
+        verify_response(&self.key, status, headers)?;
+
+        let mut protocols = list(headers, header::SEC_WEBSOCKET_PROTOCOL);
+        let protocol = match (protocols.next(), protocols.next()) {
+            (None, _) => None,
+            (Some(p), None) if self.protocols.iter().any(|requested| requested == p) => Some(String::from(p)),
+            _ => return Err(HandshakeError::BadWebsocketProtocol),
+        };
+
+        let extensions = list(headers, header::SEC_WEBSOCKET_EXTENSIONS)
+            .map(|ext| {
+                let name = extension_name(ext);
+                if self
+                    .extensions
+                    .iter()
+                    .any(|requested| extension_name(requested) == name)
+                {
+                    Ok(String::from(ext))
+                } else {
+                    Err(HandshakeError::BadWebsocketExtension)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        #[cfg(feature = "deflate")]
+        let deflate = {
+            let mut deflate = None;
+            for ext in extensions
+                .iter()
+                .filter(|ext| extension_name(ext) == PERMESSAGE_DEFLATE)
+            {
+                // extension can only be accepted once.
+                if deflate.is_some() {
+                    return Err(HandshakeError::BadWebsocketExtension);
+                }
+                deflate = Some(DeflateConfig::from_response(ext)?);
+            }
+            deflate
+        };
+
+        Ok(Negotiated {
+            protocol,
+            extensions,
+            #[cfg(feature = "deflate")]
+            deflate,
+        })
+    }
+}
+
+fn extension_name(extension: &str) -> &str {
+    extension.split(';').next().unwrap_or("").trim()
+}
+
+/// Result of a successful client WebSocket handshake.
+#[derive(Debug)]
+pub struct Negotiated {
+    protocol: Option<String>,
+    extensions: Vec<String>,
+    #[cfg(feature = "deflate")]
+    deflate: Option<DeflateConfig>,
+}
+
+impl Negotiated {
+    /// Sub protocol selected by server.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Extensions accepted by server with their parameters.
+    pub fn extensions(&self) -> impl Iterator<Item = &str> {
+        self.extensions.iter().map(String::as_str)
+    }
+
+    /// Parameters of permessage-deflate extension accepted by server.
+    #[cfg(feature = "deflate")]
+    pub fn deflate(&self) -> Option<DeflateConfig> {
+        self.deflate
+    }
+
+    /// Codec for decoding server messages and encoding client messages with accepted extensions.
+    pub fn codec(&self) -> Codec {
+        #[cfg(feature = "deflate")]
+        if let Some(config) = self.deflate {
+            return Codec::client()
+                .permessage_deflate(true)
+                .no_context_takeover(config.client_no_context_takeover);
+        }
+
+        Codec::client()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handshake() {
+        let req = Request::builder().method(Method::POST).body(()).unwrap();
+        assert_eq!(
+            HandshakeError::GetMethodRequired,
+            verify_handshake(req.method(), req.headers()).unwrap_err(),
+        );
+
+        let req = Request::builder().body(()).unwrap();
+        assert_eq!(
+            HandshakeError::NoWebsocketUpgrade,
+            verify_handshake(req.method(), req.headers()).unwrap_err(),
+        );
+
+        let req = Request::builder()
+            .header(header::UPGRADE, header::HeaderValue::from_static("test"))
+            .body(())
+            .unwrap();
+        assert_eq!(
+            HandshakeError::NoWebsocketUpgrade,
+            verify_handshake(req.method(), req.headers()).unwrap_err(),
+        );
+
+        let req = Request::builder()
+            .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
+            .body(())
+            .unwrap();
+        assert_eq!(
+            HandshakeError::NoConnectionUpgrade,
+            verify_handshake(req.method(), req.headers()).unwrap_err(),
+        );
+
+        let req = Request::builder()
+            .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
+            .header(header::CONNECTION, header::HeaderValue::from_static("upgrade"))
+            .body(())
+            .unwrap();
+        assert_eq!(
+            HandshakeError::NoVersionHeader,
+            verify_handshake(req.method(), req.headers()).unwrap_err(),
+        );
+
+        let req = Request::builder()
+            .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
+            .header(header::CONNECTION, header::HeaderValue::from_static("upgrade"))
+            .header(header::SEC_WEBSOCKET_VERSION, header::HeaderValue::from_static("5"))
+            .body(())
+            .unwrap();
+        assert_eq!(
+            HandshakeError::UnsupportedVersion,
+            verify_handshake(req.method(), req.headers()).unwrap_err(),
+        );
+
+        let builder = || {
+            Request::builder()
+                .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
+                .header(header::CONNECTION, header::HeaderValue::from_static("upgrade"))
+                .header(header::SEC_WEBSOCKET_VERSION, header::HeaderValue::from_static("13"))
+        };
+
+        let req = builder().body(()).unwrap();
+        assert_eq!(
+            HandshakeError::BadWebsocketKey,
+            verify_handshake(req.method(), req.headers()).unwrap_err(),
+        );
+
+        let req = builder()
+            .header(header::SEC_WEBSOCKET_KEY, header::HeaderValue::from_static("13"))
+            .body(())
+            .unwrap();
+        let key = verify_handshake(req.method(), req.headers()).unwrap();
+        assert_eq!(
+            StatusCode::SWITCHING_PROTOCOLS,
+            handshake_response(key).body(()).unwrap().status()
+        );
+    }
+
+    #[test]
+    fn test_client_handshake() {
+        let (builder, key) = client_handshake("ws://localhost/");
+        let req = builder.body(()).unwrap();
+        assert_eq!(req.headers()[header::SEC_WEBSOCKET_KEY], key);
+        assert_eq!(key.len(), 24);
+
+        let res = handshake(req.method(), req.headers()).unwrap().body(()).unwrap();
+        assert!(verify_response(&key, res.status(), res.headers()).is_ok());
+
+        let (_, other) = client_handshake("ws://localhost/");
+        assert_ne!(key, other);
+        assert_eq!(
+            HandshakeError::BadWebsocketAccept,
+            verify_response(&other, res.status(), res.headers()).unwrap_err(),
+        );
+        assert_eq!(
+            HandshakeError::SwitchingProtocolsRequired,
+            verify_response(&key, StatusCode::OK, res.headers()).unwrap_err(),
+        );
+        assert_eq!(
+            HandshakeError::NoWebsocketUpgrade,
+            verify_response(&key, res.status(), &HeaderMap::new()).unwrap_err(),
+        );
+
+        let mut headers = res.headers().clone();
+        headers.remove(header::SEC_WEBSOCKET_ACCEPT);
+        assert_eq!(
+            HandshakeError::NoWebsocketAccept,
+            verify_response(&key, res.status(), &headers).unwrap_err(),
+        );
+    }
+
+    #[test]
+    fn test_client_negotiate() {
+        let handshake = ClientHandshake::new()
+            .protocols(&["chat", "superchat"])
+            .extension("permessage-deflate; client_max_window_bits");
+        let req = handshake.request("ws://localhost/").body(()).unwrap();
+        assert_eq!(req.headers()[header::SEC_WEBSOCKET_PROTOCOL], "chat, superchat");
+        assert_eq!(
+            req.headers()[header::SEC_WEBSOCKET_EXTENSIONS],
+            "permessage-deflate; client_max_window_bits"
+        );
+
+        let mut parts = verify_request(&req).unwrap();
+        parts.select_protocol(&["superchat"]);
+        let res = build_response(parts).body(()).unwrap();
+
+        let negotiated = handshake.verify(res.status(), res.headers()).unwrap();
+        assert_eq!(negotiated.protocol(), Some("superchat"));
+        assert_eq!(negotiated.extensions().count(), 0);
+
+        let verify = |name, value| {
+            let mut headers = res.headers().clone();
+            headers.insert(name, header::HeaderValue::from_static(value));
+            handshake.verify(res.status(), &headers)
+        };
+
+        assert_eq!(
+            HandshakeError::BadWebsocketProtocol,
+            verify(header::SEC_WEBSOCKET_PROTOCOL, "v3").unwrap_err(),
+        );
+        assert_eq!(
+            HandshakeError::BadWebsocketProtocol,
+            verify(header::SEC_WEBSOCKET_PROTOCOL, "chat, superchat").unwrap_err(),
+        );
+        assert_eq!(
+            HandshakeError::BadWebsocketExtension,
+            verify(header::SEC_WEBSOCKET_EXTENSIONS, "x-webkit-deflate-frame").unwrap_err(),
+        );
+
+        let negotiated = verify(
+            header::SEC_WEBSOCKET_EXTENSIONS,
+            "permessage-deflate; server_no_context_takeover",
+        )
+        .unwrap();
+        assert_eq!(
+            negotiated.extensions().collect::<Vec<_>>(),
+            vec!["permessage-deflate; server_no_context_takeover"]
+        );
+
+        let handshake = ClientHandshake::new();
+        let req = handshake.request("ws://localhost/").body(()).unwrap();
+        let res = handshake_response(req.headers()[header::SEC_WEBSOCKET_KEY].as_bytes())
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "chat")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            HandshakeError::BadWebsocketProtocol,
+            handshake.verify(res.status(), res.headers()).unwrap_err(),
+        );
+    }
+
+    #[test]
+    fn test_verify_request() {
+        let builder = || {
+            Request::builder()
+                .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
+                .header(header::CONNECTION, header::HeaderValue::from_static("upgrade"))
+                .header(header::SEC_WEBSOCKET_VERSION, header::HeaderValue::from_static("13"))
+                .header(
+                    header::SEC_WEBSOCKET_KEY,
+                    header::HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="),
+                )
+        };
+
+        let req = builder().method(Method::POST).body(()).unwrap();
+        assert_eq!(HandshakeError::GetMethodRequired, verify_request(&req).unwrap_err());
+
+        let req = builder()
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "chat, superchat")
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "v3")
+            .body(())
+            .unwrap();
+        let mut parts = verify_request(&req).unwrap();
+        assert_eq!(parts.accept(), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(parts.protocols().collect::<Vec<_>>(), vec!["chat", "superchat", "v3"]);
+
+        assert_eq!(parts.select_protocol(&["v4"]), None);
+        assert_eq!(parts.select_protocol(&["v4", "v3", "chat"]), Some("v3"));
+
+        let res = build_response(parts).body(()).unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            res.headers()[header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(res.headers()[header::SEC_WEBSOCKET_PROTOCOL], "v3");
+
+        let res = build_response(verify_request(&builder().body(()).unwrap()).unwrap())
+            .body(())
+            .unwrap();
+        assert!(res.headers().get(header::SEC_WEBSOCKET_PROTOCOL).is_none());
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn test_negotiate_deflate() {
+        let handshake = ClientHandshake::new().permessage_deflate();
+        let req = handshake
+            .request("ws://localhost/")
+            .header(
+                header::SEC_WEBSOCKET_EXTENSIONS,
+                "permessage-deflate; server_max_window_bits=10, permessage-deflate; client_no_context_takeover",
+            )
+            .body(())
+            .unwrap();
+
+        let mut parts = verify_request(&req).unwrap();
+        assert_eq!(parts.extensions().count(), 3);
+        // offer asking for smaller server window is skipped.
+        assert_eq!(parts.accept_deflate(), Some(DeflateConfig::default()));
+
+        let server = parts.codec();
+        let res = build_response(parts).body(()).unwrap();
+        assert_eq!(res.headers()[header::SEC_WEBSOCKET_EXTENSIONS], "permessage-deflate");
+
+        let negotiated = handshake.verify(res.status(), res.headers()).unwrap();
+        assert_eq!(negotiated.deflate(), Some(DeflateConfig::default()));
+
+        let client = negotiated.codec();
+        let text = "Hello, Hello, Hello, Hello, Hello".repeat(4);
+        let mut buf = bytes::BytesMut::new();
+        client.encode(text.as_str().into(), &mut buf).unwrap();
+        assert!(buf.len() < text.len());
+        assert_eq!(server.decode(&mut buf).unwrap(), Some(text.as_str().into()));
+
+        let mut parts = verify_request(&ClientHandshake::new().request("ws://localhost/").body(()).unwrap()).unwrap();
+        assert_eq!(parts.accept_deflate(), None);
+
+        // extension is not in response when it's not accepted.
+        let parts = verify_request(&handshake.request("ws://localhost/").body(()).unwrap()).unwrap();
+        let res = build_response(parts).body(()).unwrap();
+        assert!(res.headers().get(header::SEC_WEBSOCKET_EXTENSIONS).is_none());
+        assert_eq!(handshake.verify(res.status(), res.headers()).unwrap().deflate(), None);
+
+        let verify = |value| {
+            let mut headers = res.headers().clone();
+            headers.insert(
+                header::SEC_WEBSOCKET_EXTENSIONS,
+                header::HeaderValue::from_static(value),
+            );
+            handshake.verify(res.status(), &headers)
+        };
+
+        assert_eq!(
+            verify("permessage-deflate; client_max_window_bits=10").unwrap_err(),
+            HandshakeError::BadWebsocketExtension
+        );
+        assert_eq!(
+            verify("permessage-deflate, permessage-deflate").unwrap_err(),
+            HandshakeError::BadWebsocketExtension
+        );
+        let negotiated = verify("permessage-deflate; client_no_context_takeover").unwrap();
+        assert_eq!(
+            negotiated.deflate(),
+            Some(DeflateConfig {
+                server_no_context_takeover: false,
+                client_no_context_takeover: true,
+            })
+        );
+    }
+}
@@ -5,6 +5,13 @@
 //! To setup a WebSocket, first perform the WebSocket handshake then on success convert request's
 //! body into a `DecodeStream` stream and then use `EncodeStream` to communicate with the peer.
 //!
+//! [verify_request] and [build_response] can be used instead of `handshake` when sub protocol
+//! is negotiated.
+//!
+//! For client side send request from `client_handshake`, check the response with `verify_response`
+//! and construct the streams with `Codec::client`. `ClientHandshake` does the same with sub protocol
+//! and extension negotiation.
+//!
 //! # Examples:
 //! ```rust
 //! # use std::pin::Pin;
@@ -72,20 +79,36 @@
 //! ```
 
 use http::{
-    header::{self, HeaderValue},
+    header,
     response::{Builder, Response},
-    HeaderMap, Method, StatusCode,
+    StatusCode,
 };
 
 mod codec;
+#[cfg(feature = "deflate")]
+mod deflate;
 mod error;
 mod frame;
+mod handshake;
 mod mask;
 mod proto;
+mod stats;
+mod string;
+mod utf8;
 
 pub use self::codec::{Codec, Item, Message};
 pub use self::error::{HandshakeError, ProtocolError};
+pub use self::frame::{FrameDecoder, FrameEncoder, FrameHeader, FrameItem, RSV1};
+pub use self::handshake::{
+    accept_key, build_response, client_handshake, handshake, verify_request, verify_response, ClientHandshake,
+    HandshakeParts, Negotiated,
+};
 pub use self::proto::{hash_key, CloseCode, CloseReason, OpCode};
+pub use self::stats::{OnSessionClose, OpCodeCounts, SessionStats, Traffic};
+pub use self::string::ByteString;
+
+#[cfg(feature = "deflate")]
+pub use self::deflate::DeflateConfig;
 
 impl From<HandshakeError> for Builder {
     fn from(e: HandshakeError) -> Self {
@@ -99,81 +122,19 @@ impl From<HandshakeError> for Builder {
     }
 }
 
-/// Verify WebSocket handshake request and create handshake response.
-pub fn handshake(method: &Method, headers: &HeaderMap) -> Result<Builder, HandshakeError> {
-    let key = verify_handshake(method, headers)?;
-    let builder = handshake_response(key);
-    Ok(builder)
-}
-
-/// Verify WebSocket handshake request and return `SEC_WEBSOCKET_KEY` header value as `&[u8]`
-fn verify_handshake<'a>(method: &'a Method, headers: &'a HeaderMap) -> Result<&'a [u8], HandshakeError> {
-    // WebSocket accepts only GET
-    if method != Method::GET {
-        return Err(HandshakeError::GetMethodRequired);
-    }
-
-    // Check for "Upgrade" header
-    let has_upgrade_hd = headers
-        .get(header::UPGRADE)
-        .and_then(|hdr| hdr.to_str().ok())
-        .filter(|s| s.to_ascii_lowercase().contains("websocket"))
-        .is_some();
-
-    if !has_upgrade_hd {
-        return Err(HandshakeError::NoWebsocketUpgrade);
-    }
-
-    // Check for "Connection" header
-    let has_connection_hd = headers
-        .get(header::CONNECTION)
-        .and_then(|hdr| hdr.to_str().ok())
-        .filter(|s| s.to_ascii_lowercase().contains("upgrade"))
-        .is_some();
-
-    if !has_connection_hd {
-        return Err(HandshakeError::NoConnectionUpgrade);
-    }
-
-    // check supported version
-    let value = headers
-        .get(header::SEC_WEBSOCKET_VERSION)
-        .ok_or(HandshakeError::NoVersionHeader)?;
-
-    if value != "13" && value != "8" && value != "7" {
-        return Err(HandshakeError::UnsupportedVersion);
-    }
-
-    // check client handshake for validity
-    let value = headers
-        .get(header::SEC_WEBSOCKET_KEY)
-        .ok_or(HandshakeError::BadWebsocketKey)?;
-
-    Ok(value.as_bytes())
-}
-
-/// Create WebSocket handshake response.
-///
-/// This function returns handshake `http::response::Builder`, ready to send to peer.
-fn handshake_response(key: &[u8]) -> Builder {
-    let key = proto::hash_key(key);
-
-    Response::builder()
-        .status(StatusCode::SWITCHING_PROTOCOLS)
-        .header(header::UPGRADE, "websocket")
-        .header(header::CONNECTION, "upgrade")
-        .header(
-            header::SEC_WEBSOCKET_ACCEPT,
-            // key is known to be header value safe ascii
-            HeaderValue::from_bytes(&key).unwrap(),
-        )
-}
-
+#[cfg(feature = "stream")]
+mod listen;
+#[cfg(feature = "stream")]
+mod sink;
 #[cfg(feature = "stream")]
 mod stream;
 
 #[cfg(feature = "stream")]
-pub use self::stream::{DecodeError, DecodeStream, EncodeStream};
+pub use self::listen::{listen, Event, Listen, ListenConfig};
+#[cfg(feature = "stream")]
+pub use self::sink::MessageSender;
+#[cfg(feature = "stream")]
+pub use self::stream::{DecodeError, DecodeStream, EncodeStream, FrameStream, SharedCodec};
 
 #[cfg(feature = "stream")]
 pub type WsOutput<B> = (
@@ -189,7 +150,7 @@ where
     B: futures_core::Stream<Item = Result<T, E>>,
     T: AsRef<[u8]>,
 {
-    let builder = handshake(req.method(), req.headers())?;
+    let builder = build_response(verify_request(&req)?);
 
     let body = req.into_body();
 
@@ -207,85 +168,6 @@ where
 mod tests {
     use super::*;
 
-    use http::Request;
-
-    #[test]
-    fn test_handshake() {
-        let req = Request::builder().method(Method::POST).body(()).unwrap();
-        assert_eq!(
-            HandshakeError::GetMethodRequired,
-            verify_handshake(req.method(), req.headers()).unwrap_err(),
-        );
-
-        let req = Request::builder().body(()).unwrap();
-        assert_eq!(
-            HandshakeError::NoWebsocketUpgrade,
-            verify_handshake(req.method(), req.headers()).unwrap_err(),
-        );
-
-        let req = Request::builder()
-            .header(header::UPGRADE, header::HeaderValue::from_static("test"))
-            .body(())
-            .unwrap();
-        assert_eq!(
-            HandshakeError::NoWebsocketUpgrade,
-            verify_handshake(req.method(), req.headers()).unwrap_err(),
-        );
-
-        let req = Request::builder()
-            .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
-            .body(())
-            .unwrap();
-        assert_eq!(
-            HandshakeError::NoConnectionUpgrade,
-            verify_handshake(req.method(), req.headers()).unwrap_err(),
-        );
-
-        let req = Request::builder()
-            .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
-            .header(header::CONNECTION, header::HeaderValue::from_static("upgrade"))
-            .body(())
-            .unwrap();
-        assert_eq!(
-            HandshakeError::NoVersionHeader,
-            verify_handshake(req.method(), req.headers()).unwrap_err(),
-        );
-
-        let req = Request::builder()
-            .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
-            .header(header::CONNECTION, header::HeaderValue::from_static("upgrade"))
-            .header(header::SEC_WEBSOCKET_VERSION, header::HeaderValue::from_static("5"))
-            .body(())
-            .unwrap();
-        assert_eq!(
-            HandshakeError::UnsupportedVersion,
-            verify_handshake(req.method(), req.headers()).unwrap_err(),
-        );
-
-        let builder = || {
-            Request::builder()
-                .header(header::UPGRADE, header::HeaderValue::from_static("websocket"))
-                .header(header::CONNECTION, header::HeaderValue::from_static("upgrade"))
-                .header(header::SEC_WEBSOCKET_VERSION, header::HeaderValue::from_static("13"))
-        };
-
-        let req = builder().body(()).unwrap();
-        assert_eq!(
-            HandshakeError::BadWebsocketKey,
-            verify_handshake(req.method(), req.headers()).unwrap_err(),
-        );
-
-        let req = builder()
-            .header(header::SEC_WEBSOCKET_KEY, header::HeaderValue::from_static("13"))
-            .body(())
-            .unwrap();
-        let key = verify_handshake(req.method(), req.headers()).unwrap();
-        assert_eq!(
-            StatusCode::SWITCHING_PROTOCOLS,
-            handshake_response(key).body(()).unwrap().status()
-        );
-    }
-
     #[test]
     fn test_wserror_http_response() {
         let res = Builder::from(HandshakeError::GetMethodRequired).body(()).unwrap();
@@ -0,0 +1,288 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+use tokio::time::{sleep, Instant, Sleep};
+
+use super::codec::Message;
+use super::proto::CloseReason;
+use super::stream::Next;
+
+/// Item of [Listen] stream.
+#[derive(Debug, PartialEq)]
+pub enum Event<T> {
+    /// Message decoded from peer. Pong is consumed by [Listen] and close becomes [Event::Closed].
+    Message(Message),
+    /// Item of application stream.
+    App(T),
+    /// [ListenConfig::heartbeat] interval is passed.
+    HeartbeatDue,
+    /// Peer closed the connection or decode stream ended. This is always the last event.
+    Closed(Option<CloseReason>),
+}
+
+/// Configuration of [listen].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ListenConfig {
+    heartbeat: Option<Duration>,
+}
+
+impl ListenConfig {
+    pub const fn new() -> Self {
+        Self { heartbeat: None }
+    }
+
+    /// Yield [Event::HeartbeatDue] every given interval.
+    ///
+    /// By default no heartbeat event is yielded.
+    ///
+    /// # Panics
+    /// When `dur` is zero.
+    pub fn heartbeat(mut self, dur: Duration) -> Self {
+        assert!(dur > Duration::ZERO, "heartbeat interval can not be zero");
+        self.heartbeat = Some(dur);
+        self
+    }
+}
+
+/// Combine stream of decoded messages with stream of application items into one stream of
+/// [Event].
+///
+/// Both streams are polled in turn so a busy one can not starve the other. Ping and close
+/// are answered by the [Codec](crate::Codec) shared with [EncodeStream](crate::EncodeStream).
+/// When application stream ends only decoded messages are yielded.
+///
+/// # Examples:
+/// ```rust
+/// # use futures_core::Stream;
+/// use http_ws::{listen, Event, ListenConfig, Message};
+///
+/// # async fn run<D, A, E>(decode: D, app: A)
+/// # where
+/// #     D: Stream<Item = Result<Message, E>> + Unpin,
+/// #     A: Stream<Item = String> + Unpin,
+/// # {
+/// let mut events = listen(decode, app, ListenConfig::new());
+///
+/// while let Some(Ok(event)) = events.next().await {
+///     match event {
+///         Event::Message(msg) => {}
+///         Event::App(item) => {}
+///         Event::HeartbeatDue => {}
+///         Event::Closed(_) => break,
+///     }
+/// }
+/// # }
+/// ```
+pub fn listen<D, A, E>(decode: D, app: A, config: ListenConfig) -> Listen<D, A>
+where
+    D: Stream<Item = Result<Message, E>>,
+    A: Stream,
+{
+    Listen {
+        decode,
+        app: Some(app),
+        interval: config.heartbeat,
+        timer: None,
+        app_first: false,
+        closed: false,
+    }
+}
+
+pin_project! {
+    /// Stream returned by [listen].
+    pub struct Listen<D, A> {
+        #[pin]
+        decode: D,
+        #[pin]
+        app: Option<A>,
+        interval: Option<Duration>,
+        timer: Option<Pin<Box<Sleep>>>,
+        app_first: bool,
+        closed: bool
+    }
+}
+
+impl<D, A> Listen<D, A> {
+    #[allow(clippy::should_implement_trait)]
+    #[inline]
+    pub fn next(&mut self) -> Next<'_, Self> {
+        Next { stream: self }
+    }
+}
+
+impl<D, A, E> Stream for Listen<D, A>
+where
+    D: Stream<Item = Result<Message, E>>,
+    A: Stream,
+{
+    type Item = Result<Event<A::Item>, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if *this.closed {
+            return Poll::Ready(None);
+        }
+
+        if let Some(interval) = *this.interval {
+            let timer = this.timer.get_or_insert_with(|| Box::pin(sleep(interval)));
+            if timer.as_mut().poll(cx).is_ready() {
+                timer.as_mut().reset(Instant::now() + interval);
+                return Poll::Ready(Some(Ok(Event::HeartbeatDue)));
+            }
+        }
+
+        // alternate which stream goes first.
+        *this.app_first = !*this.app_first;
+
+        if *this.app_first {
+            if let Poll::Ready(item) = poll_app(this.app.as_mut(), cx) {
+                return Poll::Ready(Some(Ok(Event::App(item))));
+            }
+        }
+
+        loop {
+            match this.decode.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(Message::Pong(_)))) => continue,
+                Poll::Ready(Some(Ok(Message::Close(reason)))) => {
+                    *this.closed = true;
+                    return Poll::Ready(Some(Ok(Event::Closed(reason))));
+                }
+                Poll::Ready(Some(Ok(msg))) => return Poll::Ready(Some(Ok(Event::Message(msg)))),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    *this.closed = true;
+                    return Poll::Ready(Some(Ok(Event::Closed(None))));
+                }
+                Poll::Pending => break,
+            }
+        }
+
+        if !*this.app_first {
+            if let Poll::Ready(item) = poll_app(this.app.as_mut(), cx) {
+                return Poll::Ready(Some(Ok(Event::App(item))));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+// ended application stream is dropped and never polled again.
+fn poll_app<A: Stream>(mut app: Pin<&mut Option<A>>, cx: &mut Context<'_>) -> Poll<A::Item> {
+    match app.as_mut().as_pin_mut().map(|app| app.poll_next(cx)) {
+        Some(Poll::Ready(Some(item))) => Poll::Ready(item),
+        Some(Poll::Ready(None)) => {
+            app.set(None);
+            Poll::Pending
+        }
+        Some(Poll::Pending) | None => Poll::Pending,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::VecDeque;
+
+    use bytes::{Bytes, BytesMut};
+
+    use crate::{ByteString, CloseCode, Codec, DecodeError, DecodeStream};
+
+    // stream yielding given items and stay pending afterwards.
+    struct Input<T>(VecDeque<T>);
+
+    impl<T: Unpin> Stream for Input<T> {
+        type Item = T;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            match self.get_mut().0.pop_front() {
+                Some(item) => Poll::Ready(Some(item)),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    fn client_frames(msgs: Vec<Message>) -> VecDeque<Result<Bytes, ()>> {
+        let codec = Codec::new().client_mode();
+        msgs.into_iter()
+            .map(|msg| {
+                let mut buf = BytesMut::new();
+                codec.encode(msg, &mut buf).unwrap();
+                Ok(buf.freeze())
+            })
+            .collect()
+    }
+
+    fn text(s: &'static str) -> Message {
+        Message::Text(ByteString::from_static(s))
+    }
+
+    #[tokio::test]
+    async fn fair() {
+        let frames = client_frames(vec![
+            text("1"),
+            text("2"),
+            Message::Pong(Bytes::new()),
+            text("3"),
+            Message::Close(Some(CloseCode::Normal.into())),
+        ]);
+        let decode = DecodeStream::new(Input(frames));
+        let app = Input(vec![1, 2].into());
+
+        let mut events = listen(decode, app, ListenConfig::new());
+
+        let mut res = Vec::new();
+        while let Some(event) = events.next().await {
+            res.push(event.unwrap());
+        }
+
+        assert_eq!(
+            res,
+            vec![
+                Event::App(1),
+                Event::Message(text("1")),
+                Event::App(2),
+                Event::Message(text("2")),
+                Event::Message(text("3")),
+                Event::Closed(Some(CloseCode::Normal.into())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn heartbeat() {
+        let codec = Codec::new().close_timeout(Duration::from_millis(30));
+        let decode = DecodeStream::with_codec(Input(VecDeque::<Result<Bytes, ()>>::new()), codec);
+        let (tx, mut encode) = decode.encode_stream();
+        let app = Input(VecDeque::<()>::new());
+
+        let config = ListenConfig::new().heartbeat(Duration::from_millis(10));
+        let mut events = listen(decode, app, config);
+
+        assert_eq!(events.next().await.unwrap().unwrap(), Event::HeartbeatDue);
+        assert_eq!(events.next().await.unwrap().unwrap(), Event::HeartbeatDue);
+
+        // peer does not answer local close.
+        tx.send(Message::Close(None)).await.unwrap();
+        assert!(encode.next().await.unwrap().is_ok());
+
+        loop {
+            match events.next().await.unwrap() {
+                Ok(Event::HeartbeatDue) => {}
+                Err(DecodeError::Timeout) => break,
+                res => panic!("unexpected event {:?}", res),
+            }
+        }
+
+        assert_eq!(events.next().await.unwrap().unwrap(), Event::Closed(None));
+        assert!(events.next().await.is_none());
+    }
+}
@@ -1,9 +1,16 @@
 //! Copy from [tungstenite-rs](https://github.com/snapview/tungstenite-rs)
 
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    convert::TryInto,
+    hash::{BuildHasher, Hasher},
+};
+
 /// Mask/unmask a frame.
 #[inline]
 pub fn apply_mask(buf: &mut [u8], mask: [u8; 4]) {
-    apply_mask_fast32(buf, mask)
+    apply_mask_fast64(buf, mask)
 }
 
 /// A safe unoptimized mask application.
@@ -14,30 +21,46 @@ fn apply_mask_fallback(buf: &mut [u8], mask: [u8; 4]) {
     }
 }
 
-/// Faster version of `apply_mask()` which operates on 4-byte blocks.
+/// Faster version of `apply_mask()` which operates on 8-byte blocks.
 #[inline]
-pub fn apply_mask_fast32(buf: &mut [u8], mask: [u8; 4]) {
-    let mask_u32 = u32::from_ne_bytes(mask);
-
-    // SAFETY:
-    //
-    // https://github.com/snapview/tungstenite-rs/pull/126
-    let (mut prefix, words, mut suffix) = unsafe { buf.align_to_mut::<u32>() };
-    apply_mask_fallback(&mut prefix, mask);
-    let head = prefix.len() & 3;
-    let mask_u32 = if head > 0 {
-        if cfg!(target_endian = "big") {
-            mask_u32.rotate_left(8 * head as u32)
-        } else {
-            mask_u32.rotate_right(8 * head as u32)
-        }
-    } else {
-        mask_u32
-    };
-    for word in words.iter_mut() {
-        *word ^= mask_u32;
+pub fn apply_mask_fast64(buf: &mut [u8], mask: [u8; 4]) {
+    let mask_u64 = u64::from_ne_bytes([mask[0], mask[1], mask[2], mask[3], mask[0], mask[1], mask[2], mask[3]]);
+
+    let mut chunks = buf.chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        let word = u64::from_ne_bytes(chunk.try_into().unwrap()) ^ mask_u64;
+        chunk.copy_from_slice(&word.to_ne_bytes());
     }
-    apply_mask_fallback(&mut suffix, mask_u32.to_ne_bytes());
+
+    // remainder starts at a multiple of 8 so mask is still aligned.
+    apply_mask_fallback(chunks.into_remainder(), mask);
+}
+
+thread_local! {
+    static RNG: Cell<u64> = Cell::new(seed());
+}
+
+// RandomState is randomly keyed per thread which is enough for seeding.
+fn seed() -> u64 {
+    RandomState::new().build_hasher().finish() | 1
+}
+
+/// Lightweight xorshift64* generator for frame mask and handshake key.
+pub fn random_u64() -> u64 {
+    RNG.with(|rng| {
+        let mut x = rng.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        rng.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    })
+}
+
+/// Generate a random mask for outgoing frame.
+#[inline]
+pub fn random_mask() -> [u8; 4] {
+    ((random_u64() >> 32) as u32).to_ne_bytes()
 }
 
 #[cfg(test)]
@@ -48,7 +71,8 @@ mod tests {
     fn test_apply_mask() {
         let mask = [0x6d, 0xb6, 0xb2, 0x80];
         let unmasked = vec![
-            0xf3, 0x00, 0x01, 0x02, 0x03, 0x80, 0x81, 0x82, 0xff, 0xfe, 0x00, 0x17, 0x74, 0xf9, 0x12, 0x03,
+            0xf3, 0x00, 0x01, 0x02, 0x03, 0x80, 0x81, 0x82, 0xff, 0xfe, 0x00, 0x17, 0x74, 0xf9, 0x12, 0x03, 0x21, 0x42,
+            0x63,
         ];
 
         for data_len in 0..=unmasked.len() {
@@ -62,10 +86,16 @@ mod tests {
                 apply_mask_fallback(&mut masked[off..], mask);
 
                 let mut masked_fast = unmasked.to_vec();
-                apply_mask_fast32(&mut masked_fast[off..], mask);
+                apply_mask_fast64(&mut masked_fast[off..], mask);
 
                 assert_eq!(masked, masked_fast);
             }
         }
     }
+
+    #[test]
+    fn test_random_mask() {
+        let masks = (0..8).map(|_| random_mask()).collect::<Vec<_>>();
+        assert!(masks.windows(2).any(|w| w[0] != w[1]));
+    }
 }
@@ -141,13 +141,25 @@ pub enum CloseCode {
     /// an action.
     Again,
 
-    #[doc(hidden)]
+    /// Indicates that the connection was closed due to a failure to perform a TLS handshake.
+    /// Like [CloseCode::Abnormal] it's reserved and must not be sent in a close frame.
     Tls,
 
-    #[doc(hidden)]
+    /// Codes not listed above. `3000..=3999` are registered with IANA by libraries and
+    /// frameworks and `4000..=4999` are for private use of applications.
     Other(u16),
 }
 
+impl CloseCode {
+    /// Check if the code is allowed in a close frame.
+    ///
+    /// Codes below 1000, reserved codes (1004, 1005, 1006 and 1015), unassigned codes of
+    /// `1016..=2999` and codes from 5000 are not allowed. Receiving one is a protocol error.
+    pub fn is_allowed(&self) -> bool {
+        matches!(u16::from(*self), 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
 impl From<CloseCode> for u16 {
     fn from(code: CloseCode) -> u16 {
         use self::CloseCode::*;
@@ -333,6 +345,20 @@ mod test {
         assert_eq!(CloseCode::from(2000u16), CloseCode::Other(2000));
     }
 
+    #[test]
+    fn close_code_allowed() {
+        assert!(CloseCode::Normal.is_allowed());
+        assert!(CloseCode::Again.is_allowed());
+        assert!(CloseCode::Other(3000).is_allowed());
+        assert!(CloseCode::Other(4999).is_allowed());
+        assert!(!CloseCode::Abnormal.is_allowed());
+        assert!(!CloseCode::Tls.is_allowed());
+        assert!(!CloseCode::Other(999).is_allowed());
+        assert!(!CloseCode::Other(1005).is_allowed());
+        assert!(!CloseCode::Other(2999).is_allowed());
+        assert!(!CloseCode::Other(5000).is_allowed());
+    }
+
     #[test]
     fn close_code_into_u16() {
         assert_eq!(1000u16, Into::<u16>::into(CloseCode::Normal));
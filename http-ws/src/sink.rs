@@ -0,0 +1,148 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_sink::Sink;
+use tokio::sync::mpsc::{error::SendError, OwnedPermit, Sender};
+
+use super::codec::Message;
+use super::error::ProtocolError;
+
+type Reserve = Pin<Box<dyn Future<Output = Result<OwnedPermit<Message>, SendError<()>>> + Send>>;
+
+/// Sender of [Message] to [EncodeStream](crate::EncodeStream) implementing [Sink] trait.
+///
+/// Capacity of the channel is visible through [Sink::poll_ready] so a slow write of encode
+/// stream is back pressure to caller.
+pub struct MessageSender {
+    tx: Option<Sender<Message>>,
+    reserve: Option<Reserve>,
+    permit: Option<OwnedPermit<Message>>,
+    closed: bool,
+}
+
+impl MessageSender {
+    pub fn new(tx: Sender<Message>) -> Self {
+        Self {
+            tx: Some(tx),
+            reserve: None,
+            permit: None,
+            closed: false,
+        }
+    }
+}
+
+impl From<Sender<Message>> for MessageSender {
+    fn from(tx: Sender<Message>) -> Self {
+        Self::new(tx)
+    }
+}
+
+impl Sink<Message> for MessageSender {
+    type Error = ProtocolError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        if this.permit.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+
+        if this.reserve.is_none() {
+            let tx = this.tx.clone().ok_or_else(closed)?;
+            this.reserve = Some(Box::pin(tx.reserve_owned()));
+        }
+
+        let res = futures_core::ready!(this.reserve.as_mut().unwrap().as_mut().poll(cx));
+        this.reserve = None;
+        this.permit = Some(res.map_err(|_| closed())?);
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let permit = this
+            .permit
+            .take()
+            .expect("Sink::poll_ready must return Poll::Ready(Ok(())) before Sink::start_send");
+
+        if let Message::Close(_) = item {
+            this.closed = true;
+        }
+
+        permit.send(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // start close handshake when no close message is sent yet.
+        if !self.closed && self.tx.is_some() {
+            futures_core::ready!(self.as_mut().poll_ready(cx))?;
+            self.as_mut().start_send(Message::Close(None))?;
+        }
+
+        let this = self.get_mut();
+        this.tx = None;
+        this.reserve = None;
+        this.permit = None;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+// encode stream is gone or not accepting message after close.
+fn closed() -> ProtocolError {
+    ProtocolError::Io(io::ErrorKind::BrokenPipe.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{future::poll_fn, rc::Rc};
+
+    use bytes::BytesMut;
+
+    use crate::{ByteString, Codec, EncodeStream};
+
+    async fn send(sink: &mut MessageSender, msg: Message) -> Result<(), ProtocolError> {
+        poll_fn(|cx| Pin::new(&mut *sink).poll_ready(cx)).await?;
+        Pin::new(&mut *sink).start_send(msg)
+    }
+
+    #[tokio::test]
+    async fn sink() {
+        let codec = Codec::new().set_capacity(1);
+        let (tx, mut encode) = EncodeStream::new(Rc::new(codec));
+        let mut sink = MessageSender::new(tx);
+
+        send(&mut sink, Message::Text(ByteString::from_static("996")))
+            .await
+            .unwrap();
+
+        // channel is full until encode stream makes progress.
+        assert!(poll_fn(|cx| Poll::Ready(Pin::new(&mut sink).poll_ready(cx).is_pending())).await);
+
+        let mut buf = BytesMut::from(&encode.next().await.unwrap().unwrap()[..]);
+        let client = Codec::client();
+        assert_eq!(
+            client.decode(&mut buf).unwrap().unwrap(),
+            Message::Text(ByteString::from_static("996"))
+        );
+
+        poll_fn(|cx| Pin::new(&mut sink).poll_close(cx)).await.unwrap();
+
+        let mut buf = BytesMut::from(&encode.next().await.unwrap().unwrap()[..]);
+        assert_eq!(client.decode(&mut buf).unwrap().unwrap(), Message::Close(None));
+
+        assert!(send(&mut sink, Message::Nop).await.is_err());
+    }
+}
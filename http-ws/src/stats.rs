@@ -0,0 +1,234 @@
+//! Per session counters maintained by [Codec](crate::Codec).
+//!
+//! Every frame encoded and decoded by codec is counted, including pong and close messages sent by
+//! [EncodeStream](crate::EncodeStream) on its own.
+
+use std::{
+    cell::Cell,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::proto::{CloseCode, OpCode};
+
+/// Callback called with [SessionStats] once a session is closed.
+pub type OnSessionClose = Arc<dyn Fn(SessionStats) + Send + Sync>;
+
+/// Statistics of a websocket session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    /// Frames and bytes sent to peer.
+    pub sent: Traffic,
+    /// Frames and bytes received from peer.
+    pub received: Traffic,
+    /// Code of the first close message sent or received. `None` when no close message is seen or
+    /// it comes without a code.
+    pub close_code: Option<CloseCode>,
+    /// Time from session started to the snapshot taken. Zero when codec is not used by
+    /// [DecodeStream](crate::DecodeStream) or [EncodeStream](crate::EncodeStream).
+    pub duration: Duration,
+}
+
+/// Frames and bytes of one direction grouped by opcode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Traffic {
+    /// Number of frames.
+    pub frames: OpCodeCounts,
+    /// Bytes of frames including headers.
+    pub bytes: OpCodeCounts,
+    /// Payload bytes of messages compressed with permessage-deflate as they are on the wire.
+    pub compressed: u64,
+    /// Payload bytes of the same messages before compression or after decompression.
+    pub uncompressed: u64,
+}
+
+impl Traffic {
+    /// Ratio of uncompressed to compressed payload bytes. `None` when no message is compressed.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.compressed == 0 {
+            None
+        } else {
+            Some(self.uncompressed as f64 / self.compressed as f64)
+        }
+    }
+}
+
+/// Counts by frame opcode. First fragment of a message is counted as text or binary.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpCodeCounts {
+    pub continuation: u64,
+    pub text: u64,
+    pub binary: u64,
+    pub close: u64,
+    pub ping: u64,
+    pub pong: u64,
+}
+
+impl OpCodeCounts {
+    /// Sum of all opcodes.
+    pub fn total(&self) -> u64 {
+        self.continuation + self.text + self.binary + self.close + self.ping + self.pong
+    }
+
+    fn from_cells(cells: &[Cell<u64>; 6]) -> Self {
+        Self {
+            continuation: cells[0].get(),
+            text: cells[1].get(),
+            binary: cells[2].get(),
+            close: cells[3].get(),
+            ping: cells[4].get(),
+            pong: cells[5].get(),
+        }
+    }
+}
+
+/// Counters of one codec. Counters are not shared between cloned codecs.
+pub(crate) struct Recorder {
+    start: Cell<Option<Instant>>,
+    sent: Counters,
+    received: Counters,
+    // code of first close message. outer option is set once any close is seen.
+    close_code: Cell<Option<Option<CloseCode>>>,
+    pub(crate) on_close: Option<OnSessionClose>,
+}
+
+struct Counters {
+    // indexed by opcode. continuation at 0.
+    frames: [Cell<u64>; 6],
+    bytes: [Cell<u64>; 6],
+    compressed: Cell<u64>,
+    uncompressed: Cell<u64>,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: Cell<u64> = Cell::new(0);
+        Self {
+            frames: [ZERO; 6],
+            bytes: [ZERO; 6],
+            compressed: ZERO,
+            uncompressed: ZERO,
+        }
+    }
+
+    #[inline]
+    fn add(&self, opcode: OpCode, n: usize) {
+        let idx = match opcode {
+            OpCode::Continue => 0,
+            OpCode::Text => 1,
+            OpCode::Binary => 2,
+            OpCode::Close => 3,
+            OpCode::Ping => 4,
+            OpCode::Pong => 5,
+            OpCode::Bad => return,
+        };
+        add(&self.frames[idx], 1);
+        add(&self.bytes[idx], n as u64);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[inline]
+    fn add_compressed(&self, compressed: usize, uncompressed: usize) {
+        add(&self.compressed, compressed as u64);
+        add(&self.uncompressed, uncompressed as u64);
+    }
+
+    fn snapshot(&self) -> Traffic {
+        Traffic {
+            frames: OpCodeCounts::from_cells(&self.frames),
+            bytes: OpCodeCounts::from_cells(&self.bytes),
+            compressed: self.compressed.get(),
+            uncompressed: self.uncompressed.get(),
+        }
+    }
+}
+
+impl Recorder {
+    pub(crate) const fn new() -> Self {
+        Self {
+            start: Cell::new(None),
+            sent: Counters::new(),
+            received: Counters::new(),
+            close_code: Cell::new(None),
+            on_close: None,
+        }
+    }
+
+    /// Mark the session as started. [OnSessionClose] is only called for a started session.
+    #[cfg(feature = "stream")]
+    pub(crate) fn start(&self) {
+        if self.start.get().is_none() {
+            self.start.set(Some(Instant::now()));
+        }
+    }
+
+    #[inline]
+    pub(crate) fn sent(&self, opcode: OpCode, n: usize) {
+        self.sent.add(opcode, n);
+    }
+
+    #[inline]
+    pub(crate) fn received(&self, opcode: OpCode, n: usize) {
+        self.received.add(opcode, n);
+    }
+
+    /// Count payload of compressed message sent with its size before compression.
+    #[cfg(feature = "deflate")]
+    #[inline]
+    pub(crate) fn sent_compressed(&self, compressed: usize, uncompressed: usize) {
+        self.sent.add_compressed(compressed, uncompressed);
+    }
+
+    /// Count payload of compressed frame received with its size after decompression.
+    #[cfg(feature = "deflate")]
+    #[inline]
+    pub(crate) fn received_compressed(&self, compressed: usize, uncompressed: usize) {
+        self.received.add_compressed(compressed, uncompressed);
+    }
+
+    #[inline]
+    pub(crate) fn close(&self, code: Option<CloseCode>) {
+        if self.close_code.get().is_none() {
+            self.close_code.set(Some(code));
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> SessionStats {
+        SessionStats {
+            sent: self.sent.snapshot(),
+            received: self.received.snapshot(),
+            close_code: self.close_code.get().flatten(),
+            duration: self.start.get().map(|start| start.elapsed()).unwrap_or_default(),
+        }
+    }
+}
+
+// state is not shared between cloned codecs.
+impl Clone for Recorder {
+    fn clone(&self) -> Self {
+        let mut this = Self::new();
+        this.on_close = self.on_close.clone();
+        this
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let (Some(on_close), Some(_)) = (self.on_close.as_ref(), self.start.get()) {
+            on_close(self.snapshot());
+        }
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.snapshot(), f)
+    }
+}
+
+#[inline(always)]
+fn add(cell: &Cell<u64>, n: u64) {
+    cell.set(cell.get().wrapping_add(n));
+}
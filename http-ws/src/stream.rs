@@ -1,28 +1,50 @@
 use std::{
+    cell::Cell,
+    convert::TryFrom,
     fmt,
     future::Future,
+    io,
+    ops::Deref,
     pin::Pin,
     rc::Rc,
-    task::{Context, Poll},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+    time::Duration,
 };
 
 use bytes::{Bytes, BytesMut};
 use futures_core::Stream;
 use pin_project_lite::pin_project;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::{
+    sync::mpsc::{channel, Receiver, Sender},
+    time::{sleep, sleep_until, Instant, Sleep},
+};
 
-use super::codec::{Codec, Message};
+use super::codec::{Codec, Item, Message};
 use super::error::ProtocolError;
+use super::frame::{FrameDecoder, FrameItem};
+use super::proto::{CloseCode, CloseReason};
+use super::stats::SessionStats;
+use super::string::ByteString;
 
 pin_project! {
     /// Decode `S` type into Stream of websocket [Message](super::codec::Message).
     /// `S` type must impl `Stream` trait and output `Result<T, E>` as `Stream::Item`
     /// where `T` type impl `AsRef<[u8]>` trait. (`&[u8]` is needed for parsing messages)
-    pub struct DecodeStream<S> {
+    ///
+    /// Codec is shared with [EncodeStream] through `C` which is `Rc<Codec>` by default.
+    /// Construct with [DecodeStream::new_send] for a pair of streams that are `Send`.
+    ///
+    /// Read-ahead is bounded. `S` is only polled when buffered bytes do not make a complete frame
+    /// and a frame larger than [Codec::max_size] fails as soon as its header is read, so at most
+    /// one max sized frame and one item of `S` are buffered.
+    pub struct DecodeStream<S, C = Rc<Codec>> {
         #[pin]
         stream: Option<S>,
         buf: BytesMut,
-        codec: Rc<Codec>
+        codec: C,
+        partial: Option<Partial>,
+        timer: Option<Pin<Box<Sleep>>>
     }
 }
 
@@ -37,19 +59,66 @@ where
     }
 
     pub fn with_codec(stream: S, codec: Codec) -> Self {
+        Self::with_shared(stream, Rc::new(codec))
+    }
+}
+
+impl<S, T, E> DecodeStream<S, Arc<Mutex<Codec>>>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    /// Construct with codec shared through `Arc<Mutex<Codec>>`. Decode stream and the encode
+    /// stream made from it are `Send` when `S` is `Send`.
+    pub fn new_send(stream: S, codec: Codec) -> Self {
+        Self::with_shared(stream, Arc::new(Mutex::new(codec)))
+    }
+}
+
+impl<S, C, T, E> DecodeStream<S, C>
+where
+    S: Stream<Item = Result<T, E>>,
+    C: SharedCodec,
+    T: AsRef<[u8]>,
+{
+    fn with_shared(stream: S, codec: C) -> Self {
+        codec.get().stats.start();
         Self {
             stream: Some(stream),
             buf: BytesMut::new(),
-            codec: Rc::new(codec),
+            codec,
+            partial: None,
+            timer: None,
         }
     }
 
+    /// Construct from parts returned by [DecodeStream::into_parts]. Bytes in `buf` are decoded
+    /// before reading from `stream`.
+    pub fn from_parts(stream: S, buf: BytesMut, codec: C) -> Self {
+        let mut this = Self::with_shared(stream, codec);
+        this.buf = buf;
+        this
+    }
+
+    /// Destruct into inner stream, bytes read from it but not decoded yet and codec.
+    ///
+    /// Stream is `None` when it's ended or decoding stopped on error or peer close. When called
+    /// in the middle of a frame the bytes of partial frame are left in buffer while fragments of
+    /// a message aggregated so far are dropped.
+    pub fn into_parts(self) -> (Option<S>, BytesMut, C) {
+        (self.stream, self.buf, self.codec)
+    }
+
     /// Make an [EncodeStream] from current DecodeStream.
     ///
     /// This API is to share the same codec for both decode and encode stream.
-    pub fn encode_stream(&self) -> (Sender<Message>, EncodeStream) {
-        let codec = self.codec.clone();
-        EncodeStream::new(codec)
+    pub fn encode_stream(&self) -> (Sender<Message>, EncodeStream<C>) {
+        EncodeStream::with_shared(self.codec.clone())
+    }
+
+    /// Snapshot of frames and bytes of the session. See [Codec::stats].
+    pub fn stats(&self) -> SessionStats {
+        self.codec.get().stats()
     }
 
     #[allow(clippy::should_implement_trait)]
@@ -62,27 +131,43 @@ where
 pub enum DecodeError<E> {
     Protocol(ProtocolError),
     Stream(E),
+    /// No pong received in [Codec::pong_timeout] after ping is sent or no close received in
+    /// [Codec::close_timeout] after local side closed.
+    Timeout,
 }
 
-impl<E> fmt::Debug for DecodeError<E> {
+impl<E: fmt::Debug> fmt::Debug for DecodeError<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            Self::Protocol(ref e) => write!(f, "{:?}", e),
-            Self::Stream(..) => write!(f, "Input Stream error"),
+            Self::Protocol(ref e) => f.debug_tuple("Protocol").field(e).finish(),
+            Self::Stream(ref e) => f.debug_tuple("Stream").field(e).finish(),
+            Self::Timeout => f.write_str("Timeout"),
         }
     }
 }
 
-impl<E> fmt::Display for DecodeError<E> {
+impl<E: fmt::Display> fmt::Display for DecodeError<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            Self::Protocol(ref e) => write!(f, "{:?}", e),
-            Self::Stream(..) => write!(f, "Input Stream error"),
+            Self::Protocol(ref e) => fmt::Display::fmt(e, f),
+            Self::Stream(ref e) => write!(f, "Input stream error: {}", e),
+            Self::Timeout => write!(f, "Timeout waiting for pong or close from peer."),
         }
     }
 }
 
-impl<E> std::error::Error for DecodeError<E> {}
+impl<E> std::error::Error for DecodeError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Self::Protocol(ref e) => Some(e),
+            Self::Stream(ref e) => Some(e),
+            Self::Timeout => None,
+        }
+    }
+}
 
 impl<E> From<ProtocolError> for DecodeError<E> {
     fn from(e: ProtocolError) -> Self {
@@ -90,9 +175,23 @@ impl<E> From<ProtocolError> for DecodeError<E> {
     }
 }
 
-impl<S, T, E> Stream for DecodeStream<S>
+impl<E> From<DecodeError<E>> for io::Error
+where
+    E: Into<io::Error>,
+{
+    fn from(e: DecodeError<E>) -> Self {
+        match e {
+            DecodeError::Protocol(e) => e.into(),
+            DecodeError::Stream(e) => e.into(),
+            DecodeError::Timeout => io::ErrorKind::TimedOut.into(),
+        }
+    }
+}
+
+impl<S, C, T, E> Stream for DecodeStream<S, C>
 where
     S: Stream<Item = Result<T, E>>,
+    C: SharedCodec,
     T: AsRef<[u8]>,
 {
     type Item = Result<Message, DecodeError<E>>;
@@ -100,51 +199,313 @@ where
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self.project();
 
-        while let Some(stream) = this.stream.as_mut().as_pin_mut() {
-            match stream.poll_next(cx) {
-                Poll::Ready(Some(Ok(item))) => this.buf.extend_from_slice(item.as_ref()),
-                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(DecodeError::Stream(e)))),
-                Poll::Ready(None) => this.stream.set(None),
-                Poll::Pending => break,
+        if this.stream.is_some() {
+            let codec = this.codec.get();
+            let shared = codec.shared();
+
+            register(&shared.decode_waker, cx);
+
+            let deadline = if shared.close_sent.get() {
+                shared.close_deadline.get()
+            } else {
+                shared.deadline.get()
+            };
+
+            if poll_deadline(this.timer, deadline, cx) {
+                this.stream.set(None);
+                this.buf.clear();
+                return Poll::Ready(Some(Err(DecodeError::Timeout)));
+            }
+        }
+
+        // decode buffered bytes first and only read from stream when they do not make a complete
+        // message. a burst of frames read at once is yielded without polling stream again.
+        loop {
+            // codec is not held while polling stream.
+            let codec = this.codec.get();
+            let shared = codec.shared();
+            match decode(&codec, this.buf, this.partial) {
+                // peer close is the last message and is echoed when local side did not close first.
+                Ok(Some(Message::Close(reason))) => {
+                    shared.close_received(&reason);
+                    this.stream.set(None);
+                    this.buf.clear();
+                    return Poll::Ready(Some(Ok(Message::Close(reason))));
+                }
+                Ok(Some(msg)) => return Poll::Ready(Some(Ok(msg))),
+                Ok(None) => {}
+                // close with code of the error and stop reading.
+                Err(e) => {
+                    shared.protocol_error(&e);
+                    this.stream.set(None);
+                    this.buf.clear();
+                    *this.partial = None;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+            }
+            drop(codec);
+
+            match this.stream.as_mut().as_pin_mut() {
+                Some(stream) => match stream.poll_next(cx) {
+                    Poll::Ready(Some(Ok(item))) => this.buf.extend_from_slice(item.as_ref()),
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(DecodeError::Stream(e)))),
+                    Poll::Ready(None) => this.stream.set(None),
+                    Poll::Pending => return Poll::Pending,
+                },
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+// decode next message that should be yielded from DecodeStream.
+fn decode(codec: &Codec, buf: &mut BytesMut, partial: &mut Option<Partial>) -> Result<Option<Message>, ProtocolError> {
+    let shared = codec.shared();
+
+    loop {
+        let msg = codec.decode(buf)?;
+
+        // any frame from peer proves it's alive.
+        if msg.is_some() {
+            shared.deadline.set(None);
+        }
+
+        match msg {
+            Some(Message::Ping(ping)) if shared.auto_pong => {
+                shared.pong(ping.clone());
+                if shared.forward_ping {
+                    return Ok(Some(Message::Ping(ping)));
+                }
             }
+            Some(Message::Continuation(item)) if codec.aggregate => {
+                if let Some(msg) = aggregate(partial, item, codec.limits.continuation)? {
+                    return Ok(Some(msg));
+                }
+            }
+            // new data message can not start before fragmented one is finished.
+            Some(Message::Text(_)) | Some(Message::Binary(_)) if partial.is_some() => {
+                return Err(ProtocolError::ContinuationStarted);
+            }
+            Some(msg) => return Ok(Some(msg)),
+            None => return Ok(None),
+        }
+    }
+}
+
+pin_project! {
+    /// Decode `S` type into Stream of frame header and payload chunks with [FrameDecoder].
+    ///
+    /// Unlike [DecodeStream] message is not buffered and frame size is not limited. Control
+    /// frames, close handshake and keep-alive are left to caller.
+    pub struct FrameStream<S> {
+        #[pin]
+        stream: Option<S>,
+        buf: BytesMut,
+        decoder: FrameDecoder,
+    }
+}
+
+impl<S, T, E> FrameStream<S>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    /// Construct stream decoding frames from client when `server` is true and from server
+    /// otherwise.
+    pub fn new(stream: S, server: bool) -> Self {
+        Self {
+            stream: Some(stream),
+            buf: BytesMut::new(),
+            decoder: FrameDecoder::new(server),
         }
+    }
 
-        match this.codec.decode(this.buf)? {
-            Some(msg) => Poll::Ready(Some(Ok(msg))),
-            None => {
-                if this.stream.is_none() {
-                    Poll::Ready(None)
-                } else {
-                    Poll::Pending
+    #[allow(clippy::should_implement_trait)]
+    #[inline]
+    pub fn next(&mut self) -> Next<'_, Self> {
+        Next { stream: self }
+    }
+}
+
+impl<S, T, E> Stream for FrameStream<S>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    type Item = Result<FrameItem, DecodeError<E>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            match this.decoder.decode(this.buf) {
+                Ok(Some(item)) => return Poll::Ready(Some(Ok(item))),
+                Ok(None) => {}
+                Err(e) => {
+                    this.stream.set(None);
+                    this.buf.clear();
+                    return Poll::Ready(Some(Err(e.into())));
                 }
             }
+
+            match this.stream.as_mut().as_pin_mut() {
+                Some(stream) => match stream.poll_next(cx) {
+                    Poll::Ready(Some(Ok(item))) => this.buf.extend_from_slice(item.as_ref()),
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(DecodeError::Stream(e)))),
+                    Poll::Ready(None) => this.stream.set(None),
+                    Poll::Pending => return Poll::Pending,
+                },
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Fragmented text or binary message being aggregated.
+struct Partial {
+    text: bool,
+    buf: BytesMut,
+}
+
+impl Partial {
+    fn new(text: bool, bytes: &[u8], max: usize) -> Result<Self, ProtocolError> {
+        let mut partial = Self {
+            text,
+            buf: BytesMut::new(),
+        };
+        partial.push(bytes, max)?;
+        Ok(partial)
+    }
+
+    fn push(&mut self, bytes: &[u8], max: usize) -> Result<(), ProtocolError> {
+        let size = self.buf.len() + bytes.len();
+        if size > max {
+            return Err(ProtocolError::ContinuationTooLarge(size));
         }
+        self.buf.extend_from_slice(bytes);
+        Ok(())
     }
 }
 
+fn aggregate(partial: &mut Option<Partial>, item: Item, max: usize) -> Result<Option<Message>, ProtocolError> {
+    match item {
+        Item::FirstText(bytes) => *partial = Some(Partial::new(true, &bytes, max)?),
+        Item::FirstBinary(bytes) => *partial = Some(Partial::new(false, &bytes, max)?),
+        Item::Continue(bytes) => partial
+            .as_mut()
+            .ok_or(ProtocolError::ContinuationNotStarted)?
+            .push(&bytes, max)?,
+        Item::Last(bytes) => {
+            let mut partial = partial.take().ok_or(ProtocolError::ContinuationNotStarted)?;
+            partial.push(&bytes, max)?;
+
+            let bytes = partial.buf.freeze();
+            let msg = if partial.text {
+                Message::Text(ByteString::try_from(bytes).map_err(|_| ProtocolError::InvalidUtf8)?)
+            } else {
+                Message::Binary(bytes)
+            };
+
+            return Ok(Some(msg));
+        }
+    }
+
+    Ok(None)
+}
+
 /// Encode a stream of [Message](super::codec::Message) into [Bytes](bytes::Bytes).
-pub struct EncodeStream {
-    codec: Rc<Codec>,
+///
+/// Stream ends only after all encoded bytes are yielded. Once a close message is encoded the
+/// channel is closed and messages still queued in it are dropped with a warning log.
+pub struct EncodeStream<C = Rc<Codec>> {
+    codec: C,
     buf: BytesMut,
     rx: Option<Receiver<Message>>,
+    fragment: Option<Fragment>,
+    next: Option<Message>,
+    idle: Option<Pin<Box<Sleep>>>,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+/// Remaining payload of a large text or binary message being split into fragments.
+struct Fragment {
+    text: bool,
+    first: bool,
+    payload: Bytes,
+}
+
+impl Fragment {
+    fn new(text: bool, payload: Bytes) -> Self {
+        Self {
+            text,
+            first: true,
+            payload,
+        }
+    }
+
+    fn next(&mut self, size: usize) -> Message {
+        let chunk = self.payload.split_to(size.min(self.payload.len()));
+
+        let item = if self.first {
+            self.first = false;
+            if self.text {
+                Item::FirstText(chunk)
+            } else {
+                Item::FirstBinary(chunk)
+            }
+        } else if self.payload.is_empty() {
+            Item::Last(chunk)
+        } else {
+            Item::Continue(chunk)
+        };
+
+        Message::Continuation(item)
+    }
 }
 
 impl EncodeStream {
     /// Construct new stream with given codec.
     #[inline]
     pub fn new(codec: Rc<Codec>) -> (Sender<Message>, Self) {
-        let cap = codec.capacity();
+        Self::with_shared(codec)
+    }
+}
+
+impl EncodeStream<Arc<Mutex<Codec>>> {
+    /// Construct new stream with given codec. The stream is `Send`.
+    #[inline]
+    pub fn new_send(codec: Arc<Mutex<Codec>>) -> (Sender<Message>, Self) {
+        Self::with_shared(codec)
+    }
+}
+
+impl<C: SharedCodec> EncodeStream<C> {
+    fn with_shared(codec: C) -> (Sender<Message>, Self) {
+        let cap = {
+            let codec = codec.get();
+            codec.stats.start();
+            codec.capacity()
+        };
         let (tx, rx) = channel(cap);
 
         let stream = EncodeStream {
             codec,
             buf: BytesMut::new(),
             rx: Some(rx),
+            fragment: None,
+            next: None,
+            idle: None,
+            timer: None,
         };
 
         (tx, stream)
     }
 
+    /// Snapshot of frames and bytes of the session. See [Codec::stats].
+    pub fn stats(&self) -> SessionStats {
+        self.codec.get().stats()
+    }
+
     #[allow(clippy::should_implement_trait)]
     #[inline]
     pub fn next(&mut self) -> Next<'_, Self> {
@@ -152,34 +513,348 @@ impl EncodeStream {
     }
 }
 
-impl Stream for EncodeStream {
+impl<C: SharedCodec> EncodeStream<C> {
+    fn poll_heartbeat(&mut self, cx: &mut Context<'_>) -> Result<(), ProtocolError> {
+        let codec = self.codec.get();
+        let shared = codec.shared();
+
+        if shared.auto_pong {
+            register(&shared.encode_waker, cx);
+            if let Some(pong) = shared.pong.take() {
+                codec.encode(Message::Pong(pong), &mut self.buf)?;
+            }
+        }
+
+        if poll_deadline(&mut self.timer, shared.deadline.get(), cx) {
+            let reason = Some(CloseCode::Away.into());
+            codec.encode(Message::Close(reason), &mut self.buf)?;
+            discard(&mut self.rx, cx);
+            // peer is not responding. do not wait for its close.
+            shared.close_sent.set(true);
+            shared.close_deadline.set(Some(Instant::now()));
+            return Ok(());
+        }
+
+        if let Some(interval) = shared.ping_interval {
+            let idle = self.idle.get_or_insert_with(|| Box::pin(sleep(interval)));
+
+            // any outgoing message counts as activity. when idle timer fires the ping message
+            // makes buf non empty and the timer is polled again on next call.
+            if self.buf.is_empty() {
+                if idle.as_mut().poll(cx).is_pending() {
+                    return Ok(());
+                }
+                codec.encode(Message::Ping(Bytes::new()), &mut self.buf)?;
+                shared.ping_sent();
+            }
+
+            idle.as_mut().reset(Instant::now() + interval);
+        }
+
+        Ok(())
+    }
+}
+
+// close channel after close message is encoded. messages already queued can not be sent.
+fn discard(rx: &mut Option<Receiver<Message>>, cx: &mut Context<'_>) {
+    if let Some(mut rx) = rx.take() {
+        rx.close();
+        let mut dropped = 0;
+        while let Poll::Ready(Some(_)) = rx.poll_recv(cx) {
+            dropped += 1;
+        }
+        if dropped > 0 {
+            log::warn!("WebSocket dropped {} message(s) queued after close", dropped);
+        }
+    }
+}
+
+impl<C: SharedCodec> Stream for EncodeStream<C> {
     type Item = Result<Bytes, ProtocolError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
+        let codec = this.codec.get();
+        let shared = codec.shared();
+
+        // echo peer close or reply to protocol error. messages not yet sent are dropped.
+        if let Some(reason) = shared.echo.take() {
+            discard(&mut this.rx, cx);
+            this.fragment = None;
+            this.next = None;
+            codec.encode(Message::Close(reason), &mut this.buf)?;
+        }
 
-        while let Some(rx) = this.rx.as_mut() {
-            match rx.poll_recv(cx) {
-                Poll::Ready(Some(msg)) => this.codec.encode(msg, &mut this.buf)?,
-                Poll::Ready(None) => this.rx = None,
-                Poll::Pending => break,
+        loop {
+            // leave the rest in channel as back pressure to sender.
+            if this.buf.len() >= shared.write_buffer_size {
+                break;
             }
+
+            // encode one fragment at a time. only ping and pong can be sent between fragments
+            // and other messages wait until the last fragment is encoded.
+            if let Some(ref mut fragment) = this.fragment {
+                while let (None, Some(rx)) = (this.next.as_ref(), this.rx.as_mut()) {
+                    match rx.poll_recv(cx) {
+                        Poll::Ready(Some(msg @ Message::Ping(_))) | Poll::Ready(Some(msg @ Message::Pong(_))) => {
+                            codec.encode(msg, &mut this.buf)?
+                        }
+                        Poll::Ready(Some(msg)) => this.next = Some(msg),
+                        Poll::Ready(None) => this.rx = None,
+                        Poll::Pending => break,
+                    }
+                }
+
+                let msg = fragment.next(shared.fragment_size);
+                if let Message::Continuation(Item::Last(_)) = msg {
+                    this.fragment = None;
+                }
+                codec.encode(msg, &mut this.buf)?;
+                break;
+            }
+
+            let msg = match this.next.take() {
+                Some(msg) => msg,
+                None => match this.rx.as_mut().map(|rx| rx.poll_recv(cx)) {
+                    Some(Poll::Ready(Some(msg))) => msg,
+                    Some(Poll::Ready(None)) => {
+                        this.rx = None;
+                        break;
+                    }
+                    Some(Poll::Pending) | None => break,
+                },
+            };
+
+            match msg {
+                Message::Text(payload) if payload.len() > shared.fragment_size => {
+                    this.fragment = Some(Fragment::new(true, payload.into_bytes()));
+                }
+                Message::Binary(payload) | Message::BinaryUncompressed(payload)
+                    if payload.len() > shared.fragment_size =>
+                {
+                    this.fragment = Some(Fragment::new(false, payload));
+                }
+                msg => {
+                    let close = matches!(msg, Message::Close(_));
+                    codec.encode(msg, &mut this.buf)?;
+                    // nothing can be sent after close.
+                    if close {
+                        discard(&mut this.rx, cx);
+                        shared.close_sent();
+                    }
+                }
+            }
+        }
+
+        drop(codec);
+
+        if this.rx.is_some() && this.fragment.is_none() {
+            this.poll_heartbeat(cx)?;
         }
 
         if !this.buf.is_empty() {
-            Poll::Ready(Some(Ok(this.buf.split().freeze())))
-        } else if this.rx.is_none() {
-            Poll::Ready(None)
-        } else {
-            Poll::Pending
+            return Poll::Ready(Some(Ok(this.buf.split().freeze())));
+        }
+
+        if this.rx.is_some() {
+            return Poll::Pending;
         }
+
+        // wait for peer close after local side closed.
+        let codec = this.codec.get();
+        let shared = codec.shared();
+        if shared.close_sent.get() && !shared.close_received.get() {
+            register(&shared.encode_waker, cx);
+            if !poll_deadline(&mut this.timer, shared.close_deadline.get(), cx) {
+                return Poll::Pending;
+            }
+        }
+
+        Poll::Ready(None)
+    }
+}
+
+/// Handle of [Codec] shared by [DecodeStream] and [EncodeStream].
+///
+/// `Rc<Codec>` is the default. `Arc<Mutex<Codec>>` makes the streams `Send` at the cost of
+/// locking the codec on every poll.
+pub trait SharedCodec: Clone + Unpin {
+    type Ref<'a>: Deref<Target = Codec>
+    where
+        Self: 'a;
+
+    fn get(&self) -> Self::Ref<'_>;
+}
+
+impl SharedCodec for Rc<Codec> {
+    type Ref<'a> = &'a Codec;
+
+    #[inline]
+    fn get(&self) -> Self::Ref<'_> {
+        self
+    }
+}
+
+impl SharedCodec for Arc<Mutex<Codec>> {
+    type Ref<'a> = MutexGuard<'a, Codec>;
+
+    #[inline]
+    fn get(&self) -> Self::Ref<'_> {
+        // codec state stays consistent between frames. a panic elsewhere does not poison it.
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Ping/pong keep-alive and close handshake configuration and the state shared by [DecodeStream]
+/// and [EncodeStream] through their [Codec].
+pub(crate) struct Shared {
+    pub(crate) ping_interval: Option<Duration>,
+    pub(crate) pong_timeout: Option<Duration>,
+    pub(crate) auto_pong: bool,
+    pub(crate) forward_ping: bool,
+    pub(crate) close_timeout: Duration,
+    pub(crate) fragment_size: usize,
+    pub(crate) write_buffer_size: usize,
+    pub(crate) close_code: fn(&ProtocolError) -> CloseCode,
+    // payload of latest ping waiting to be answered.
+    pong: Cell<Option<Bytes>>,
+    // deadline of pong after a ping is sent.
+    deadline: Cell<Option<Instant>>,
+    // close message waiting to be sent. an echo of peer close or a reply to protocol error.
+    echo: Cell<Option<Option<CloseReason>>>,
+    close_sent: Cell<bool>,
+    close_received: Cell<bool>,
+    // deadline of peer close after local side closed.
+    close_deadline: Cell<Option<Instant>>,
+    encode_waker: Cell<Option<Waker>>,
+    decode_waker: Cell<Option<Waker>>,
+}
+
+impl Shared {
+    pub(crate) const fn new() -> Self {
+        Self {
+            ping_interval: None,
+            pong_timeout: None,
+            auto_pong: false,
+            forward_ping: true,
+            close_timeout: Duration::from_secs(5),
+            fragment_size: 65_536,
+            write_buffer_size: 65_536,
+            close_code: ProtocolError::close_code,
+            pong: Cell::new(None),
+            deadline: Cell::new(None),
+            echo: Cell::new(None),
+            close_sent: Cell::new(false),
+            close_received: Cell::new(false),
+            close_deadline: Cell::new(None),
+            encode_waker: Cell::new(None),
+            decode_waker: Cell::new(None),
+        }
+    }
+
+    fn pong(&self, ping: Bytes) {
+        self.pong.set(Some(ping));
+        wake(&self.encode_waker);
+    }
+
+    fn ping_sent(&self) {
+        if let Some(timeout) = self.pong_timeout {
+            if self.deadline.get().is_none() {
+                self.deadline.set(Some(Instant::now() + timeout));
+                wake(&self.decode_waker);
+            }
+        }
+    }
+
+    fn close_sent(&self) {
+        self.close_sent.set(true);
+        self.close_deadline.set(Some(Instant::now() + self.close_timeout));
+        wake(&self.decode_waker);
+    }
+
+    fn close_received(&self, reason: &Option<CloseReason>) {
+        self.close_received.set(true);
+        if !self.close_sent.replace(true) {
+            // echo the code only.
+            let reason = reason.as_ref().map(|reason| reason.code.into());
+            self.echo.set(Some(reason));
+        }
+        wake(&self.encode_waker);
+    }
+
+    fn protocol_error(&self, e: &ProtocolError) {
+        if !self.close_sent.replace(true) {
+            self.echo.set(Some(Some((self.close_code)(e).into())));
+        }
+        // peer close would not be read anymore. do not wait for it.
+        self.close_deadline.set(Some(Instant::now()));
+        wake(&self.encode_waker);
+    }
+}
+
+// state is not shared between cloned codecs.
+impl Clone for Shared {
+    fn clone(&self) -> Self {
+        Self {
+            ping_interval: self.ping_interval,
+            pong_timeout: self.pong_timeout,
+            auto_pong: self.auto_pong,
+            forward_ping: self.forward_ping,
+            close_timeout: self.close_timeout,
+            fragment_size: self.fragment_size,
+            write_buffer_size: self.write_buffer_size,
+            close_code: self.close_code,
+            ..Self::new()
+        }
+    }
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("ping_interval", &self.ping_interval)
+            .field("pong_timeout", &self.pong_timeout)
+            .field("auto_pong", &self.auto_pong)
+            .field("forward_ping", &self.forward_ping)
+            .field("close_timeout", &self.close_timeout)
+            .field("fragment_size", &self.fragment_size)
+            .field("write_buffer_size", &self.write_buffer_size)
+            .finish()
+    }
+}
+
+fn register(slot: &Cell<Option<Waker>>, cx: &Context<'_>) {
+    let waker = match slot.take() {
+        Some(waker) if waker.will_wake(cx.waker()) => waker,
+        _ => cx.waker().clone(),
+    };
+    slot.set(Some(waker));
+}
+
+fn wake(slot: &Cell<Option<Waker>>) {
+    if let Some(waker) = slot.take() {
+        waker.wake();
+    }
+}
+
+// poll timer towards given deadline. timer is lazily constructed and reset when deadline changes.
+fn poll_deadline(timer: &mut Option<Pin<Box<Sleep>>>, deadline: Option<Instant>, cx: &mut Context<'_>) -> bool {
+    match deadline {
+        Some(deadline) => {
+            let timer = timer.get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+            if timer.deadline() != deadline {
+                timer.as_mut().reset(deadline);
+            }
+            timer.as_mut().poll(cx).is_ready()
+        }
+        None => false,
     }
 }
 
 pin_project! {
     pub struct Next<'a, S> {
         #[pin]
-        stream: &'a mut S
+        pub(crate) stream: &'a mut S
     }
 }
 
@@ -193,3 +868,699 @@ where
         Pin::new(&mut self.get_mut().stream).poll_next(cx)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::VecDeque;
+
+    use crate::frame::{FrameEncoder, FrameHeader, Parser};
+    use crate::proto::OpCode;
+
+    // input stream yielding given frames and stay pending afterwards.
+    struct Input(VecDeque<Bytes>);
+
+    impl Stream for Input {
+        type Item = Result<Bytes, ()>;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            match self.get_mut().0.pop_front() {
+                Some(frame) => Poll::Ready(Some(Ok(frame))),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    fn client_frame(msg: Message) -> Bytes {
+        let mut buf = BytesMut::new();
+        Codec::new().client_mode().encode(msg, &mut buf).unwrap();
+        buf.freeze()
+    }
+
+    fn client_frames(msgs: Vec<Message>) -> VecDeque<Bytes> {
+        let codec = Codec::new().client_mode();
+        msgs.into_iter()
+            .map(|msg| {
+                let mut buf = BytesMut::new();
+                codec.encode(msg, &mut buf).unwrap();
+                buf.freeze()
+            })
+            .collect()
+    }
+
+    fn client_decode(frame: Bytes) -> Message {
+        let mut buf = BytesMut::from(&frame[..]);
+        Codec::new().client_mode().decode(&mut buf).unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn aggregate_continuation() {
+        let frames = || {
+            client_frames(vec![
+                Message::Continuation(Item::FirstText(Bytes::from_static(b"99"))),
+                Message::Ping(Bytes::new()),
+                Message::Continuation(Item::Continue(Bytes::from_static(b"6"))),
+                Message::Continuation(Item::Last(Bytes::from_static(b"!"))),
+            ])
+        };
+
+        let mut decode = DecodeStream::new(Input(frames()));
+        assert_eq!(decode.next().await.unwrap().unwrap(), Message::Ping(Bytes::new()));
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Text(ByteString::from_static("996!"))
+        );
+
+        let mut decode = DecodeStream::with_codec(Input(frames()), Codec::new().max_message_size(3));
+        assert_eq!(decode.next().await.unwrap().unwrap(), Message::Ping(Bytes::new()));
+        assert!(matches!(
+            decode.next().await,
+            Some(Err(DecodeError::Protocol(ProtocolError::ContinuationTooLarge(4))))
+        ));
+
+        let mut decode = DecodeStream::with_codec(Input(frames()), Codec::new().aggregate(false));
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Continuation(Item::FirstText(Bytes::from_static(b"99")))
+        );
+
+        let mut frame = BytesMut::new();
+        Parser::write_message(&mut frame, &b"6"[..], OpCode::Continue, false, true);
+        let mut decode = DecodeStream::new(Input(vec![frame.freeze()].into()));
+        assert!(matches!(
+            decode.next().await,
+            Some(Err(DecodeError::Protocol(ProtocolError::ContinuationNotStarted)))
+        ));
+
+        let frames = client_frames(vec![
+            Message::Continuation(Item::FirstBinary(Bytes::from_static(b"99"))),
+            Message::Binary(Bytes::from_static(b"6")),
+        ]);
+        let mut decode = DecodeStream::new(Input(frames));
+        assert!(matches!(
+            decode.next().await,
+            Some(Err(DecodeError::Protocol(ProtocolError::ContinuationStarted)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn ping_idle() {
+        let codec = Codec::new().ping_interval(Duration::from_millis(10));
+        let (tx, mut encode) = EncodeStream::new(Rc::new(codec));
+
+        let start = Instant::now();
+        tx.send(Message::Text(ByteString::from_static("996"))).await.unwrap();
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Text(ByteString::from_static("996")));
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Ping(Bytes::new()));
+        assert!(start.elapsed() >= Duration::from_millis(10));
+
+        drop(tx);
+        assert!(encode.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn auto_pong() {
+        let frames = [
+            client_frame(Message::Ping(Bytes::from_static(b"1"))),
+            client_frame(Message::Ping(Bytes::from_static(b"2"))),
+        ];
+
+        let codec = Codec::new().auto_pong(true);
+        let mut decode = DecodeStream::with_codec(Input(frames.iter().cloned().collect()), codec);
+        let (_tx, mut encode) = decode.encode_stream();
+
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Ping(Bytes::from_static(b"1"))
+        );
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Pong(Bytes::from_static(b"1")));
+
+        let codec = Codec::new().auto_pong(true).forward_ping(false);
+        let mut frames = frames.iter().cloned().collect::<VecDeque<_>>();
+        frames.push_back(client_frame(Message::Text(ByteString::from_static("996"))));
+        let mut decode = DecodeStream::with_codec(Input(frames), codec);
+        let (_tx, mut encode) = decode.encode_stream();
+
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Text(ByteString::from_static("996"))
+        );
+        // only latest ping is answered.
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Pong(Bytes::from_static(b"2")));
+    }
+
+    #[tokio::test]
+    async fn pong() {
+        let ping = Bytes::from((0..125).collect::<Vec<u8>>());
+        let mut frames = client_frames(vec![
+            // unsolicited pong is yielded as it is.
+            Message::Pong(Bytes::from_static(b"996")),
+            Message::Ping(ping.clone()),
+        ]);
+        let mut buf = BytesMut::new();
+        Parser::write_message(&mut buf, vec![0; 126], OpCode::Ping, true, true);
+        frames.push_back(buf.freeze());
+
+        let codec = Codec::new().auto_pong(true);
+        let mut decode = DecodeStream::with_codec(Input(frames), codec);
+        let (_tx, mut encode) = decode.encode_stream();
+
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Pong(Bytes::from_static(b"996"))
+        );
+        assert_eq!(decode.next().await.unwrap().unwrap(), Message::Ping(ping.clone()));
+
+        // pong echoes the whole payload of ping.
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Pong(ping));
+
+        assert!(matches!(
+            decode.next().await,
+            Some(Err(DecodeError::Protocol(ProtocolError::ControlTooLarge(126))))
+        ));
+    }
+
+    #[tokio::test]
+    async fn parts() {
+        let mut frames = client_frames(vec![
+            Message::Text(ByteString::from_static("996")),
+            Message::Binary(Bytes::from_static(b"996")),
+        ]);
+        let first = frames.pop_front().unwrap();
+        let second = frames.pop_front().unwrap();
+
+        // first frame and half of second one are read at once.
+        let mut buf = BytesMut::from(&first[..]);
+        buf.extend_from_slice(&second[..3]);
+        frames.push_back(buf.freeze());
+        frames.push_back(second.slice(3..));
+
+        let mut decode = DecodeStream::new(Input(frames));
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Text(ByteString::from_static("996"))
+        );
+
+        let (stream, buf, codec) = decode.into_parts();
+        assert_eq!(buf, second[..3]);
+
+        let mut decode = DecodeStream::from_parts(stream.unwrap(), buf, codec);
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Binary(Bytes::from_static(b"996"))
+        );
+    }
+
+    #[tokio::test]
+    async fn send() {
+        let frames = client_frames(vec![
+            Message::Ping(Bytes::from_static(b"996")),
+            Message::Text(ByteString::from_static("996")),
+        ]);
+
+        let codec = Codec::new().auto_pong(true).forward_ping(false);
+        let mut decode = DecodeStream::new_send(Input(frames), codec);
+        let (_tx, mut encode) = decode.encode_stream();
+
+        // tokio::spawn requires Send future.
+        let encode = tokio::spawn(async move { (encode.next().await.unwrap().unwrap(), encode) });
+        let decode = tokio::spawn(async move { (decode.next().await.unwrap().unwrap(), decode) });
+
+        let (msg, _decode) = decode.await.unwrap();
+        assert_eq!(msg, Message::Text(ByteString::from_static("996")));
+
+        let (frame, _encode) = encode.await.unwrap();
+        assert_eq!(client_decode(frame), Message::Pong(Bytes::from_static(b"996")));
+    }
+
+    #[tokio::test]
+    async fn pong_timeout() {
+        let codec = Codec::new()
+            .ping_interval(Duration::from_millis(10))
+            .pong_timeout(Duration::from_millis(10));
+
+        let mut decode = DecodeStream::with_codec(Input(VecDeque::new()), codec.clone());
+        let (tx, mut encode) = decode.encode_stream();
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Ping(Bytes::new()));
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Close(Some(CloseCode::Away.into())));
+        assert!(encode.next().await.is_none());
+        assert!(tx.send(Message::Nop).await.is_err());
+
+        assert!(matches!(decode.next().await, Some(Err(DecodeError::Timeout))));
+        assert!(decode.next().await.is_none());
+
+        // pong received in time clears the deadline.
+        let frames = [client_frame(Message::Pong(Bytes::new()))];
+        let mut decode = DecodeStream::with_codec(Input(frames.iter().cloned().collect()), codec);
+        let (_tx, mut encode) = decode.encode_stream();
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Ping(Bytes::new()));
+        assert_eq!(decode.next().await.unwrap().unwrap(), Message::Pong(Bytes::new()));
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Ping(Bytes::new()));
+
+        // any other frame from peer clears the deadline too.
+        let codec = Codec::new().heartbeat(Duration::from_millis(10), Duration::from_millis(10));
+        let frames = [client_frame(Message::Text(ByteString::from_static("996")))];
+        let mut decode = DecodeStream::with_codec(Input(frames.iter().cloned().collect()), codec);
+        let (_tx, mut encode) = decode.encode_stream();
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Ping(Bytes::new()));
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Text(ByteString::from_static("996"))
+        );
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Ping(Bytes::new()));
+    }
+
+    #[tokio::test]
+    async fn close_by_peer() {
+        let reason = CloseReason {
+            code: CloseCode::Normal,
+            description: Some("bye".into()),
+        };
+        let frames = client_frames(vec![
+            Message::Close(Some(reason.clone())),
+            Message::Text(ByteString::from_static("996")),
+        ]);
+
+        let mut decode = DecodeStream::new(Input(frames));
+        let (tx, mut encode) = decode.encode_stream();
+
+        assert_eq!(decode.next().await.unwrap().unwrap(), Message::Close(Some(reason)));
+        assert!(decode.next().await.is_none());
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Close(Some(CloseCode::Normal.into())));
+        assert!(encode.next().await.is_none());
+        assert!(tx.send(Message::Text(ByteString::from_static("996"))).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn close_by_local() {
+        let frames = client_frames(vec![Message::Close(Some(CloseCode::Normal.into()))]);
+
+        let mut decode = DecodeStream::new(Input(frames));
+        let (tx, mut encode) = decode.encode_stream();
+
+        tx.send(Message::Close(Some(CloseCode::Away.into()))).await.unwrap();
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Close(Some(CloseCode::Away.into())));
+        assert!(tx.send(Message::Text(ByteString::from_static("996"))).await.is_err());
+
+        // encode stream waits for peer close and it's not echoed.
+        let (msg, end) = tokio::join!(decode.next(), encode.next());
+        assert_eq!(msg.unwrap().unwrap(), Message::Close(Some(CloseCode::Normal.into())));
+        assert!(end.is_none());
+        assert!(decode.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn close_timeout() {
+        let codec = Codec::new().close_timeout(Duration::from_millis(10));
+        let mut decode = DecodeStream::with_codec(Input(VecDeque::new()), codec);
+        let (tx, mut encode) = decode.encode_stream();
+
+        tx.send(Message::Close(None)).await.unwrap();
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Close(None));
+
+        let start = Instant::now();
+        assert!(encode.next().await.is_none());
+        assert!(start.elapsed() >= Duration::from_millis(10));
+
+        assert!(matches!(decode.next().await, Some(Err(DecodeError::Timeout))));
+        assert!(decode.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn drop_after_close() {
+        for size in [1, 4096] {
+            let codec = Codec::new()
+                .write_buffer_size(size)
+                .close_timeout(Duration::from_millis(10));
+            let (tx, mut encode) = EncodeStream::new(Rc::new(codec));
+
+            tx.send(Message::Text(ByteString::from_static("996"))).await.unwrap();
+            tx.send(Message::Close(None)).await.unwrap();
+            tx.send(Message::Text(ByteString::from_static("251"))).await.unwrap();
+            drop(tx);
+
+            // everything encoded before stream end must be yielded in order.
+            let mut buf = BytesMut::new();
+            while let Some(chunk) = encode.next().await {
+                buf.extend_from_slice(&chunk.unwrap());
+            }
+
+            let client = Codec::new().client_mode();
+            assert_eq!(
+                client.decode(&mut buf).unwrap().unwrap(),
+                Message::Text(ByteString::from_static("996"))
+            );
+            assert_eq!(client.decode(&mut buf).unwrap().unwrap(), Message::Close(None));
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn fragment() {
+        let codec = Codec::new().fragment_size(4);
+        let (tx, mut encode) = EncodeStream::new(Rc::new(codec));
+
+        tx.send(Message::Binary(Bytes::from_static(b"0123456789")))
+            .await
+            .unwrap();
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&encode.next().await.unwrap().unwrap());
+
+        // ping is sent between fragments and text waits for the last fragment.
+        tx.send(Message::Ping(Bytes::new())).await.unwrap();
+        tx.send(Message::Text(ByteString::from_static("996"))).await.unwrap();
+        drop(tx);
+
+        while let Some(frame) = encode.next().await {
+            buf.extend_from_slice(&frame.unwrap());
+        }
+
+        let client = Codec::client();
+        let mut msgs = Vec::new();
+        while let Some(msg) = client.decode(&mut buf).unwrap() {
+            msgs.push(msg);
+        }
+
+        assert_eq!(
+            msgs,
+            vec![
+                Message::Continuation(Item::FirstBinary(Bytes::from_static(b"0123"))),
+                Message::Ping(Bytes::new()),
+                Message::Continuation(Item::Continue(Bytes::from_static(b"4567"))),
+                Message::Continuation(Item::Last(Bytes::from_static(b"89"))),
+                Message::Text(ByteString::from_static("996")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let frames = client_frames(vec![
+            Message::Text(ByteString::from_static("996")),
+            Message::Ping(Bytes::from_static(b"1")),
+            Message::Close(Some(CloseCode::Normal.into())),
+        ]);
+
+        let closed = Arc::new(Mutex::new(None));
+        let codec = Codec::new().auto_pong(true).on_session_close({
+            let closed = closed.clone();
+            move |stats| *closed.lock().unwrap() = Some(stats)
+        });
+
+        let mut decode = DecodeStream::with_codec(Input(frames), codec);
+        let (tx, mut encode) = decode.encode_stream();
+
+        decode.next().await.unwrap().unwrap();
+        decode.next().await.unwrap().unwrap();
+
+        // pong and close echo are sent without application.
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Pong(Bytes::from_static(b"1")));
+
+        decode.next().await.unwrap().unwrap();
+        assert!(decode.next().await.is_none());
+        while let Some(chunk) = encode.next().await {
+            chunk.unwrap();
+        }
+
+        let stats = decode.stats();
+        assert_eq!(stats.sent, encode.stats().sent);
+        assert_eq!(stats.close_code, Some(CloseCode::Normal));
+
+        let received = stats.received;
+        assert_eq!(
+            (received.frames.text, received.frames.ping, received.frames.close),
+            (1, 1, 1)
+        );
+        assert_eq!(received.frames.total(), 3);
+        // masked frames from client.
+        assert_eq!(
+            (received.bytes.text, received.bytes.ping, received.bytes.close),
+            (9, 7, 8)
+        );
+
+        let sent = stats.sent;
+        assert_eq!((sent.frames.pong, sent.frames.close), (1, 1));
+        assert_eq!(sent.frames.total(), 2);
+        assert_eq!((sent.bytes.pong, sent.bytes.close), (3, 4));
+
+        assert!(closed.lock().unwrap().is_none());
+        drop((decode, encode, tx));
+
+        let res = closed.lock().unwrap().take().unwrap();
+        assert_eq!(
+            (res.sent, res.received, res.close_code),
+            (sent, received, stats.close_code)
+        );
+
+        // codec not used by any stream is not a session.
+        let closed = Arc::new(Mutex::new(None));
+        let codec = Codec::new().on_session_close({
+            let closed = closed.clone();
+            move |stats| *closed.lock().unwrap() = Some(stats)
+        });
+        drop(codec.clone());
+        drop(codec);
+        assert!(closed.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn close_on_error() {
+        let mut frames = client_frames(vec![Message::Text(ByteString::from_static("996"))]);
+        let mut frame = BytesMut::new();
+        Parser::write_message(&mut frame, &b"6"[..], OpCode::Continue, false, true);
+        frames.push_back(frame.freeze());
+        frames.push_back(client_frame(Message::Text(ByteString::from_static("996"))));
+
+        let mut decode = DecodeStream::new(Input(frames.clone()));
+        let (tx, mut encode) = decode.encode_stream();
+
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Text(ByteString::from_static("996"))
+        );
+        assert!(matches!(
+            decode.next().await,
+            Some(Err(DecodeError::Protocol(ProtocolError::ContinuationNotStarted)))
+        ));
+        // remaining input is not read.
+        assert!(decode.next().await.is_none());
+
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Close(Some(CloseCode::Protocol.into())));
+        assert!(encode.next().await.is_none());
+        assert!(tx.send(Message::Nop).await.is_err());
+
+        let codec = Codec::new().error_close_code(|_| CloseCode::Policy);
+        let mut decode = DecodeStream::with_codec(Input(frames), codec);
+        let (_tx, mut encode) = decode.encode_stream();
+
+        decode.next().await.unwrap().unwrap();
+        assert!(decode.next().await.unwrap().is_err());
+        let frame = encode.next().await.unwrap().unwrap();
+        assert_eq!(client_decode(frame), Message::Close(Some(CloseCode::Policy.into())));
+        assert!(encode.next().await.is_none());
+    }
+
+    // input stream counting how many times it's polled.
+    struct Counted<'a>(Input, &'a Cell<usize>);
+
+    impl Stream for Counted<'_> {
+        type Item = Result<Bytes, ()>;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.get_mut();
+            this.1.set(this.1.get() + 1);
+            Pin::new(&mut this.0).poll_next(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn read_ahead() {
+        let polls = Cell::new(0);
+
+        // frame larger than max size fails on its header. the rest of it is never read.
+        let mut header = BytesMut::new();
+        Parser::write_message(&mut header, vec![0; 1024], OpCode::Binary, true, true);
+        header.truncate(8);
+        let input = vec![header.freeze(), Bytes::from(vec![0; 1024])];
+        let mut decode = DecodeStream::with_codec(Counted(Input(input.into()), &polls), Codec::new().max_size(1023));
+
+        assert!(matches!(
+            decode.next().await,
+            Some(Err(DecodeError::Protocol(ProtocolError::Overflow(1024))))
+        ));
+        assert!(decode.next().await.is_none());
+        assert_eq!(polls.get(), 1);
+
+        // bytes of incomplete frame are buffered only up to the frame size.
+        let polls = Cell::new(0);
+        let frame = client_frame(Message::Binary(Bytes::from(vec![1; 1024])));
+        let mut input = frame.chunks(100).map(Bytes::copy_from_slice).collect::<VecDeque<_>>();
+        input.push_back(Bytes::from(vec![0; 4096]));
+        let chunks = input.len();
+        let mut decode = DecodeStream::with_codec(Counted(Input(input), &polls), Codec::new().max_size(1024));
+
+        assert_eq!(
+            decode.next().await.unwrap().unwrap(),
+            Message::Binary(Bytes::from(vec![1; 1024]))
+        );
+        assert_eq!(polls.get(), chunks - 1);
+        let (_, buf, _) = decode.into_parts();
+        assert_eq!(buf.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn burst() {
+        let mut msgs = (0..100u8)
+            .map(|i| Message::Binary(Bytes::copy_from_slice(&[i])))
+            .collect::<Vec<_>>();
+        msgs.insert(50, Message::Ping(Bytes::new()));
+
+        let burst = client_frames(msgs).into_iter().flatten().collect::<Vec<_>>();
+
+        let polls = Cell::new(0);
+        let mut decode = DecodeStream::new(Counted(Input(vec![Bytes::from(burst)].into()), &polls));
+
+        for i in 0..100u8 {
+            if i == 50 {
+                assert_eq!(decode.next().await.unwrap().unwrap(), Message::Ping(Bytes::new()));
+            }
+            assert_eq!(
+                decode.next().await.unwrap().unwrap(),
+                Message::Binary(Bytes::copy_from_slice(&[i]))
+            );
+        }
+
+        assert_eq!(polls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn write_buffer() {
+        let codec = Codec::new().set_capacity(64).write_buffer_size(4096);
+        let (tx, mut encode) = EncodeStream::new(Rc::new(codec));
+
+        let payload = Bytes::from(vec![0; 1024]);
+        for _ in 0..64 {
+            tx.send(Message::Binary(payload.clone())).await.unwrap();
+        }
+        assert!(tx.try_send(Message::Nop).is_err());
+
+        // every chunk stops at the first message reaching size limit.
+        let frame_size = client_frame(Message::Binary(payload.clone())).len() - 4;
+        let chunk = encode.next().await.unwrap().unwrap();
+        assert_eq!(chunk.len(), frame_size * 4);
+
+        // drained messages free channel capacity only.
+        assert_eq!(tx.capacity(), 4);
+
+        let mut total = chunk.len();
+        while total < frame_size * 64 {
+            let chunk = encode.next().await.unwrap().unwrap();
+            assert!(chunk.len() <= frame_size * 4);
+            total += chunk.len();
+        }
+    }
+
+    #[tokio::test]
+    async fn frame_stream() {
+        let payload = (0..=255u8).cycle().take(100_000).collect::<Vec<_>>();
+
+        // stream payload of one frame in chunks.
+        let header = FrameHeader {
+            fin: true,
+            rsv: 0,
+            opcode: OpCode::Binary,
+            len: payload.len() as u64,
+            mask: Some([1, 2, 3, 4]),
+        };
+        let mut encoder = FrameEncoder::new();
+        let mut buf = BytesMut::new();
+        encoder.header(&header, &mut buf);
+        let mut input = VecDeque::new();
+        input.push_back(buf.split().freeze());
+        for chunk in payload.chunks(7_777) {
+            encoder.payload(chunk, &mut buf);
+            input.push_back(buf.split().freeze());
+        }
+        input.extend(client_frames(vec![Message::Ping(Bytes::new())]));
+
+        let mut frames = FrameStream::new(Input(input), true);
+        assert_eq!(frames.next().await.unwrap().unwrap(), FrameItem::Header(header));
+
+        let mut received = Vec::new();
+        loop {
+            match frames.next().await.unwrap().unwrap() {
+                FrameItem::Payload { bytes, last } => {
+                    assert!(bytes.len() <= 7_777);
+                    received.extend_from_slice(&bytes);
+                    if last {
+                        break;
+                    }
+                }
+                item => panic!("unexpected {:?}", item),
+            }
+        }
+        assert_eq!(received, payload);
+
+        match frames.next().await.unwrap().unwrap() {
+            FrameItem::Header(header) => {
+                assert_eq!(header.opcode, OpCode::Ping);
+                assert_eq!(header.len, 0);
+            }
+            item => panic!("unexpected {:?}", item),
+        }
+
+        // server frames must not be masked.
+        let mut frames = FrameStream::new(
+            Input(client_frames(vec![Message::Nop, Message::Ping(Bytes::new())])),
+            false,
+        );
+        assert!(matches!(
+            frames.next().await,
+            Some(Err(DecodeError::Protocol(ProtocolError::MaskedFrame)))
+        ));
+        assert!(frames.next().await.is_none());
+    }
+
+    #[test]
+    fn decode_error() {
+        use std::error::Error;
+
+        let e = DecodeError::<io::Error>::from(ProtocolError::InvalidOpcode(0x0B));
+        assert_eq!(e.to_string(), "Encountered invalid OpCode: 0x0b.");
+        assert!(e.source().unwrap().is::<ProtocolError>());
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::InvalidData);
+
+        let e = DecodeError::Stream(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(e.source().unwrap().is::<io::Error>());
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::ConnectionReset);
+
+        let e = DecodeError::<io::Error>::Timeout;
+        assert!(e.source().is_none());
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{borrow::Borrow, convert::TryFrom, fmt, hash, ops::Deref, str};
+
+use bytes::{Bytes, BytesMut};
+
+/// A cheaply cloneable UTF-8 string backed by [Bytes].
+///
+/// Payload of text message is validated once by [Codec](crate::Codec) and shared as it is.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteString(Bytes);
+
+impl ByteString {
+    /// Construct an empty string.
+    pub const fn new() -> Self {
+        Self(Bytes::new())
+    }
+
+    /// Construct from a static string without copying.
+    pub const fn from_static(s: &'static str) -> Self {
+        Self(Bytes::from_static(s.as_bytes()))
+    }
+
+    /// Construct from bytes without checking they are valid UTF-8.
+    ///
+    /// # Safety
+    /// Given bytes must be valid UTF-8.
+    pub unsafe fn from_bytes_unchecked(bytes: Bytes) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // SAFETY: ByteString is only constructed from valid UTF-8.
+        unsafe { str::from_utf8_unchecked(&self.0) }
+    }
+
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for ByteString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for ByteString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<[u8]> for ByteString {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Borrow<str> for ByteString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl hash::Hash for ByteString {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for ByteString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ByteString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl From<String> for ByteString {
+    fn from(s: String) -> Self {
+        Self(Bytes::from(s))
+    }
+}
+
+impl From<&str> for ByteString {
+    fn from(s: &str) -> Self {
+        Self(Bytes::copy_from_slice(s.as_bytes()))
+    }
+}
+
+impl TryFrom<Bytes> for ByteString {
+    type Error = str::Utf8Error;
+
+    fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
+        str::from_utf8(&bytes)?;
+        Ok(Self(bytes))
+    }
+}
+
+impl TryFrom<BytesMut> for ByteString {
+    type Error = str::Utf8Error;
+
+    fn try_from(bytes: BytesMut) -> Result<Self, Self::Error> {
+        Self::try_from(bytes.freeze())
+    }
+}
+
+impl From<ByteString> for Bytes {
+    fn from(s: ByteString) -> Self {
+        s.0
+    }
+}
+
+impl fmt::Debug for ByteString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for ByteString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let s = ByteString::from_static("996");
+        assert_eq!(s, "996");
+        assert_eq!(s, ByteString::from(String::from("996")));
+        assert_eq!(Bytes::from(s.clone()), Bytes::from_static(b"996"));
+        assert_eq!(ByteString::try_from(Bytes::from_static(b"996")).unwrap(), s);
+        assert!(ByteString::try_from(Bytes::from_static(&[0xFF])).is_err());
+        assert_eq!(format!("{:?}", s), "\"996\"");
+    }
+}
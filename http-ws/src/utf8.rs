@@ -0,0 +1,105 @@
+use std::str;
+
+use super::error::ProtocolError;
+
+/// Incremental UTF-8 validator of text message split into fragments.
+///
+/// Keeps the bytes of a code point cut off at the end of previous fragment.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Utf8 {
+    buf: [u8; 4],
+    len: usize,
+}
+
+impl Utf8 {
+    pub(crate) const fn new() -> Self {
+        Self { buf: [0; 4], len: 0 }
+    }
+
+    /// Validate next fragment. `last` indicates the end of message where no incomplete code point
+    /// is allowed.
+    pub(crate) fn validate(&mut self, mut payload: &[u8], last: bool) -> Result<(), ProtocolError> {
+        if self.len > 0 {
+            let width = width(self.buf[0]);
+            let take = (width - self.len).min(payload.len());
+            self.buf[self.len..self.len + take].copy_from_slice(&payload[..take]);
+            self.len += take;
+            payload = &payload[take..];
+
+            if let Err(e) = str::from_utf8(&self.buf[..self.len]) {
+                // code point is still incomplete when payload is exhausted.
+                return if e.error_len().is_none() && !last {
+                    Ok(())
+                } else {
+                    Err(ProtocolError::InvalidUtf8)
+                };
+            }
+
+            self.len = 0;
+        }
+
+        match str::from_utf8(payload) {
+            Ok(_) => Ok(()),
+            // incomplete code point at the end of fragment.
+            Err(e) if e.error_len().is_none() && !last => {
+                let rest = &payload[e.valid_up_to()..];
+                self.buf[..rest.len()].copy_from_slice(rest);
+                self.len = rest.len();
+                Ok(())
+            }
+            Err(_) => Err(ProtocolError::InvalidUtf8),
+        }
+    }
+}
+
+// width of code point from its leading byte. only called with valid leading bytes.
+fn width(byte: u8) -> usize {
+    match byte {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fragments() {
+        let text = "a€😀b".as_bytes();
+
+        // split at every possible position.
+        for i in 0..=text.len() {
+            for j in i..=text.len() {
+                let mut utf8 = Utf8::new();
+                utf8.validate(&text[..i], false).unwrap();
+                utf8.validate(&text[i..j], false).unwrap();
+                utf8.validate(&text[j..], true).unwrap();
+            }
+        }
+
+        let mut utf8 = Utf8::new();
+        utf8.validate(&text[..2], false).unwrap();
+        assert!(matches!(
+            utf8.validate(&text[2..3], true),
+            Err(ProtocolError::InvalidUtf8)
+        ));
+
+        let mut utf8 = Utf8::new();
+        utf8.validate(&[0xF0, 0x9F], false).unwrap();
+        assert!(matches!(utf8.validate(&[0x41], false), Err(ProtocolError::InvalidUtf8)));
+
+        let mut utf8 = Utf8::new();
+        assert!(matches!(
+            utf8.validate(&[0x61, 0xFF], false),
+            Err(ProtocolError::InvalidUtf8)
+        ));
+
+        let mut utf8 = Utf8::new();
+        assert!(matches!(
+            utf8.validate(&[0xED, 0xA0, 0x80], false),
+            Err(ProtocolError::InvalidUtf8)
+        ));
+    }
+}